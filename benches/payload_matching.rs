@@ -0,0 +1,57 @@
+//! Payload prefilter throughput against the number of patterns.
+//!
+//! Run with `cargo bench --bench payload_matching`. Patterns are random
+//! 4-24 byte strings (half of them case-insensitive); the payload is random
+//! bytes scanned in 1460-byte segments through the stream path, as TCP
+//! traffic would be.
+
+use netguard::detection::payload::{PatternSetBuilder, StreamState};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::time::Instant;
+
+const PAYLOAD_BYTES: usize = 64 * 1024 * 1024;
+const SEGMENT_BYTES: usize = 1460;
+const PATTERN_COUNTS: [usize; 6] = [10, 100, 1_000, 10_000, 50_000, 100_000];
+
+fn main() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let payload: Vec<u8> = (0..PAYLOAD_BYTES)
+        .map(|_| rng.gen_range(b' '..=b'~'))
+        .collect();
+
+    println!(
+        "{:>10} {:>12} {:>12} {:>10} {:>12}",
+        "patterns", "build (ms)", "memory (KB)", "MB/s", "matches"
+    );
+    for count in PATTERN_COUNTS {
+        let mut builder = PatternSetBuilder::new();
+        for i in 0..count {
+            let len = rng.gen_range(4..=24);
+            let pattern: Vec<u8> = (0..len).map(|_| rng.gen_range(b' '..=b'~')).collect();
+            builder.add(&pattern, i % 2 == 0);
+        }
+
+        let started = Instant::now();
+        let patterns = builder.build().expect("patterns compile");
+        let build_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+        let mut stream = StreamState::new(patterns.max_pattern_len());
+        let mut matches = 0u64;
+        let started = Instant::now();
+        for segment in payload.chunks(SEGMENT_BYTES) {
+            patterns.scan_stream(&stream, segment, |_| matches += 1);
+            stream.push(segment);
+        }
+        let seconds = started.elapsed().as_secs_f64();
+
+        println!(
+            "{:>10} {:>12.1} {:>12} {:>10.1} {:>12}",
+            count,
+            build_ms,
+            patterns.memory_usage() / 1024,
+            PAYLOAD_BYTES as f64 / (1024.0 * 1024.0) / seconds,
+            matches
+        );
+    }
+}
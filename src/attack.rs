@@ -0,0 +1,321 @@
+//! MITRE ATT&CK tactics and techniques for alerts.
+//!
+//! Built-in detectors tag their alerts with fixed techniques; signature
+//! rules take theirs from `mitre_tactic_id`/`mitre_technique_id` metadata
+//! (or their classtype), Sigma rules from `attack.*` tags. Stored alerts can
+//! be summarised as ATT&CK Navigator layers, one per matrix.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::detection::Alert;
+
+/// Enterprise tactics and the ICS ones our detectors use: ID, Navigator
+/// short name.
+const TACTICS: &[(&str, &str)] = &[
+    ("TA0043", "reconnaissance"),
+    ("TA0042", "resource-development"),
+    ("TA0001", "initial-access"),
+    ("TA0002", "execution"),
+    ("TA0003", "persistence"),
+    ("TA0004", "privilege-escalation"),
+    ("TA0005", "defense-evasion"),
+    ("TA0006", "credential-access"),
+    ("TA0007", "discovery"),
+    ("TA0008", "lateral-movement"),
+    ("TA0009", "collection"),
+    ("TA0011", "command-and-control"),
+    ("TA0010", "exfiltration"),
+    ("TA0040", "impact"),
+    ("TA0106", "impair-process-control"),
+];
+
+/// A technique (`T1046`, `T1071.004`) and the tactic it serves here, if
+/// known.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Technique {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tactic: Option<String>,
+    pub technique: String,
+}
+
+impl fmt::Display for Technique {
+    /// `TA0007/T1046`, or just the technique when the tactic is unknown.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.tactic {
+            Some(tactic) => write!(f, "{}/{}", tactic, self.technique),
+            None => f.write_str(&self.technique),
+        }
+    }
+}
+
+/// Techniques from `(tactic, technique)` ID pairs.
+pub fn techniques(pairs: &[(&str, &str)]) -> Vec<Technique> {
+    pairs
+        .iter()
+        .map(|(tactic, technique)| Technique {
+            tactic: Some(tactic.to_string()),
+            technique: technique.to_string(),
+        })
+        .collect()
+}
+
+/// Technique IDs paired with tactic IDs in declaration order: the first
+/// technique with the first tactic, and so on. A lone tactic applies to
+/// every technique and a lone technique to every tactic; techniques past
+/// the last tactic get none. Both are uppercased.
+pub fn pair(tactics: &[String], technique_ids: &[String]) -> Vec<Technique> {
+    let combinations: Vec<(Option<&String>, &String)> = match (tactics, technique_ids) {
+        ([tactic], _) => technique_ids.iter().map(|t| (Some(tactic), t)).collect(),
+        (_, [technique]) if !tactics.is_empty() => tactics
+            .iter()
+            .map(|tactic| (Some(tactic), technique))
+            .collect(),
+        _ => technique_ids
+            .iter()
+            .enumerate()
+            .map(|(i, technique)| (tactics.get(i), technique))
+            .collect(),
+    };
+    let mut pairs = Vec::new();
+    for (tactic, technique) in combinations {
+        let technique = Technique {
+            tactic: tactic.map(|tactic| tactic.to_ascii_uppercase()),
+            technique: technique.to_ascii_uppercase(),
+        };
+        if !pairs.contains(&technique) {
+            pairs.push(technique);
+        }
+    }
+    pairs
+}
+
+/// Techniques from Sigma tags: `attack.t1090.003` names a technique,
+/// `attack.command_and_control` or `attack.ta0011` a tactic.
+pub fn from_sigma_tags(tags: &[String]) -> Vec<Technique> {
+    let mut tactics = Vec::new();
+    let mut technique_ids = Vec::new();
+    for tag in tags {
+        let Some(name) = tag
+            .to_ascii_lowercase()
+            .strip_prefix("attack.")
+            .map(str::to_string)
+        else {
+            continue;
+        };
+        if name.len() > 2 && name.starts_with("ta") && name[2..].chars().all(|c| c.is_ascii_digit())
+        {
+            tactics.push(name);
+        } else if name.starts_with('t') && name[1..].starts_with(|c: char| c.is_ascii_digit()) {
+            technique_ids.push(name);
+        } else if let Some(id) = tactic_id(&name.replace('_', "-")) {
+            tactics.push(id.to_string());
+        }
+    }
+    pair(&tactics, &technique_ids)
+}
+
+fn tactic_id(short_name: &str) -> Option<&'static str> {
+    TACTICS
+        .iter()
+        .find(|(_, name)| *name == short_name)
+        .map(|(id, _)| *id)
+}
+
+fn tactic_short_name(id: &str) -> Option<&'static str> {
+    TACTICS
+        .iter()
+        .find(|(tactic, _)| tactic.eq_ignore_ascii_case(id))
+        .map(|(_, name)| *name)
+}
+
+/// ATT&CK matrices, which Navigator shows in separate layers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Domain {
+    Enterprise,
+    Ics,
+}
+
+impl Domain {
+    /// The domain of a technique ID: ICS techniques are numbered `T0xxx`.
+    pub fn of(technique: &str) -> Self {
+        if technique.to_ascii_uppercase().starts_with("T0") {
+            Domain::Ics
+        } else {
+            Domain::Enterprise
+        }
+    }
+
+    /// Navigator's name for the domain.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Domain::Enterprise => "enterprise-attack",
+            Domain::Ics => "ics-attack",
+        }
+    }
+}
+
+/// An ATT&CK Navigator layer scoring each observed technique of `domain`
+/// by the number of alerts tagged with it, with the alert types as the
+/// comment. Techniques of other domains are left out.
+pub fn navigator_layer(
+    name: &str,
+    description: &str,
+    domain: Domain,
+    alerts: &[Alert],
+) -> serde_json::Value {
+    // (technique, tactic) -> alert type -> count
+    let mut observed: BTreeMap<(&str, Option<&str>), BTreeMap<&str, u64>> = BTreeMap::new();
+    for alert in alerts {
+        for tagged in alert
+            .attack
+            .iter()
+            .filter(|tagged| Domain::of(&tagged.technique) == domain)
+        {
+            let tactic = tagged.tactic.as_deref().and_then(tactic_short_name);
+            *observed
+                .entry((tagged.technique.as_str(), tactic))
+                .or_default()
+                .entry(alert.alert_type.as_str())
+                .or_insert(0) += 1;
+        }
+    }
+
+    let mut max_score = 0;
+    let techniques: Vec<serde_json::Value> = observed
+        .into_iter()
+        .map(|((technique, tactic), types)| {
+            let score: u64 = types.values().sum();
+            max_score = max_score.max(score);
+            let comment: Vec<String> = types
+                .iter()
+                .map(|(alert_type, count)| format!("{} ({})", alert_type, count))
+                .collect();
+            let mut entry = serde_json::json!({
+                "techniqueID": technique,
+                "score": score,
+                "comment": comment.join(", "),
+                "enabled": true,
+            });
+            if let Some(tactic) = tactic {
+                entry["tactic"] = tactic.into();
+            }
+            entry
+        })
+        .collect();
+
+    serde_json::json!({
+        "name": name,
+        "versions": { "layer": "4.5", "navigator": "4.9.1" },
+        "domain": domain.name(),
+        "description": description,
+        "techniques": techniques,
+        "gradient": {
+            "colors": ["#ffe766", "#ff6666"],
+            "minValue": 1,
+            "maxValue": max_score.max(1),
+        },
+        "metadata": [{ "name": "alerts", "value": alerts.len().to_string() }],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    fn rendered(techniques: &[Technique]) -> Vec<String> {
+        techniques.iter().map(Technique::to_string).collect()
+    }
+
+    #[test]
+    fn pairs_follow_declaration_order() {
+        let pairs = pair(&ids(&["ta0011", "TA0010"]), &ids(&["t1071", "T1048"]));
+        assert_eq!(rendered(&pairs), ["TA0011/T1071", "TA0010/T1048"]);
+        let pairs = pair(&ids(&["TA0011"]), &ids(&["T1071", "T1571"]));
+        assert_eq!(rendered(&pairs), ["TA0011/T1071", "TA0011/T1571"]);
+        let pairs = pair(&ids(&["TA0006", "TA0009"]), &ids(&["T1557"]));
+        assert_eq!(rendered(&pairs), ["TA0006/T1557", "TA0009/T1557"]);
+        let pairs = pair(
+            &ids(&["TA0011", "TA0010"]),
+            &ids(&["T1071", "T1048", "T1095"]),
+        );
+        assert_eq!(rendered(&pairs), ["TA0011/T1071", "TA0010/T1048", "T1095"]);
+        assert_eq!(rendered(&pair(&[], &ids(&["T1046"]))), ["T1046"]);
+        assert!(pair(&ids(&["TA0007"]), &[]).is_empty());
+        let pairs = pair(&ids(&["TA0011", "TA0011"]), &ids(&["T1071", "t1071"]));
+        assert_eq!(rendered(&pairs), ["TA0011/T1071"]);
+    }
+
+    #[test]
+    fn sigma_tags() {
+        let tags = ids(&[
+            "attack.command_and_control",
+            "attack.t1090.003",
+            "attack.exfiltration",
+            "attack.t1048",
+            "car.2016-04-005",
+        ]);
+        assert_eq!(
+            rendered(&from_sigma_tags(&tags)),
+            ["TA0011/T1090.003", "TA0010/T1048"]
+        );
+        let tags = ids(&[
+            "attack.ta0005",
+            "attack.privilege_escalation",
+            "attack.t1055",
+        ]);
+        assert_eq!(
+            rendered(&from_sigma_tags(&tags)),
+            ["TA0005/T1055", "TA0004/T1055"]
+        );
+    }
+
+    fn alert(alert_type: &str, attack: &[(&str, &str)]) -> Alert {
+        Alert::new(alert_type, "high", "10.0.0.1", None, String::new())
+            .with_attack(techniques(attack))
+    }
+
+    #[test]
+    fn layers_are_split_by_domain() {
+        let alerts = [
+            alert("port_scan", &[("TA0007", "T1046")]),
+            alert("port_scan", &[("TA0007", "T1046")]),
+            alert("modbus_write", &[("TA0106", "T0855")]),
+            alert("beacon", &[("TA0011", "T1071")]),
+        ];
+        let enterprise = navigator_layer("test", "", Domain::Enterprise, &alerts);
+        assert_eq!(enterprise["domain"], "enterprise-attack");
+        assert_eq!(
+            enterprise["techniques"],
+            serde_json::json!([
+                {
+                    "techniqueID": "T1046",
+                    "tactic": "discovery",
+                    "score": 2,
+                    "comment": "port_scan (2)",
+                    "enabled": true,
+                },
+                {
+                    "techniqueID": "T1071",
+                    "tactic": "command-and-control",
+                    "score": 1,
+                    "comment": "beacon (1)",
+                    "enabled": true,
+                },
+            ])
+        );
+        assert_eq!(enterprise["gradient"]["maxValue"], 2);
+
+        let ics = navigator_layer("test", "", Domain::Ics, &alerts);
+        assert_eq!(ics["domain"], "ics-attack");
+        let techniques = ics["techniques"].as_array().unwrap();
+        assert_eq!(techniques.len(), 1);
+        assert_eq!(techniques[0]["techniqueID"], "T0855");
+        assert_eq!(techniques[0]["tactic"], "impair-process-control");
+    }
+}
@@ -0,0 +1,40 @@
+//! Bounds-checked integer readers shared by the protocol dissectors.
+
+pub fn u8_at(buf: &[u8], offset: usize) -> Option<u8> {
+    buf.get(offset).copied()
+}
+
+pub fn le_u16(buf: &[u8], offset: usize) -> Option<u16> {
+    let bytes = buf.get(offset..offset + 2)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+pub fn le_u32(buf: &[u8], offset: usize) -> Option<u32> {
+    let bytes = buf.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?))
+}
+
+pub fn le_u64(buf: &[u8], offset: usize) -> Option<u64> {
+    let bytes = buf.get(offset..offset + 8)?;
+    Some(u64::from_le_bytes(bytes.try_into().ok()?))
+}
+
+pub fn be_u16(buf: &[u8], offset: usize) -> Option<u16> {
+    let bytes = buf.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+pub fn be_u32(buf: &[u8], offset: usize) -> Option<u32> {
+    let bytes = buf.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes(bytes.try_into().ok()?))
+}
+
+/// Decodes a UTF-16LE string, as used by SMB2 for paths and file names.
+pub fn utf16le_string(buf: &[u8], offset: usize, len: usize) -> Option<String> {
+    let bytes = buf.get(offset..offset + len)?;
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+    Some(String::from_utf16_lossy(&units))
+}
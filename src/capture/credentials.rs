@@ -0,0 +1,302 @@
+//! Lightweight dissectors for protocols that send credentials in cleartext:
+//! FTP, Telnet, HTTP Basic authentication, POP3 and IMAP.
+//!
+//! Passwords are never retained; only their length is reported so alerts can
+//! show a masked value.
+
+use base64::Engine;
+use std::borrow::Cow;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CleartextProtocol {
+    Ftp,
+    Telnet,
+    Http,
+    Pop3,
+    Imap,
+}
+
+impl CleartextProtocol {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CleartextProtocol::Ftp => "FTP",
+            CleartextProtocol::Telnet => "Telnet",
+            CleartextProtocol::Http => "HTTP",
+            CleartextProtocol::Pop3 => "POP3",
+            CleartextProtocol::Imap => "IMAP",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TelnetPrompt {
+    Login,
+    Password,
+}
+
+#[derive(Debug, Clone)]
+pub enum CredentialEvent {
+    /// A username sent on its own (FTP/POP3 `USER`).
+    User(String),
+    /// A password sent on its own (FTP/POP3 `PASS`).
+    Pass { length: usize },
+    /// Username and password in one message (HTTP Basic, IMAP `LOGIN`).
+    Login {
+        username: String,
+        password_length: usize,
+    },
+    /// Login or password prompt sent by a Telnet server.
+    TelnetPrompt(TelnetPrompt),
+    /// Keystrokes sent by a Telnet client, option negotiation removed.
+    TelnetInput(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct CleartextAuth {
+    pub protocol: CleartextProtocol,
+    pub events: Vec<CredentialEvent>,
+}
+
+const HTTP_METHODS: &[&str] = &[
+    "GET ", "POST ", "PUT ", "HEAD ", "DELETE ", "OPTIONS ", "PATCH ",
+];
+
+/// Dispatches a TCP payload to the matching cleartext dissector by port.
+pub fn parse(source_port: u16, destination_port: u16, payload: &[u8]) -> Option<CleartextAuth> {
+    let (protocol, events) = match (source_port, destination_port) {
+        (_, 21) => (CleartextProtocol::Ftp, parse_user_pass(payload)),
+        (_, 110) => (CleartextProtocol::Pop3, parse_user_pass(payload)),
+        (_, 143) => (CleartextProtocol::Imap, parse_imap(payload)),
+        (23, _) => (CleartextProtocol::Telnet, parse_telnet_server(payload)),
+        (_, 23) => (CleartextProtocol::Telnet, parse_telnet_client(payload)),
+        _ if HTTP_METHODS
+            .iter()
+            .any(|m| payload.starts_with(m.as_bytes())) =>
+        {
+            (CleartextProtocol::Http, parse_http(payload))
+        }
+        _ => return None,
+    };
+
+    if events.is_empty() {
+        None
+    } else {
+        Some(CleartextAuth { protocol, events })
+    }
+}
+
+/// Lines ending in LF or CRLF, each decoded on its own so that binary data
+/// elsewhere in the segment (a body, an IMAP literal) doesn't hide them.
+fn lines(payload: &[u8]) -> impl Iterator<Item = Cow<'_, str>> {
+    payload
+        .split(|byte| *byte == b'\n')
+        .map(|line| String::from_utf8_lossy(line.strip_suffix(b"\r").unwrap_or(line)))
+}
+
+/// `USER <name>` / `PASS <secret>` as used by FTP and POP3.
+fn parse_user_pass(payload: &[u8]) -> Vec<CredentialEvent> {
+    let mut events = Vec::new();
+    for line in lines(payload) {
+        let line = line.as_ref();
+        let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+        if command.eq_ignore_ascii_case("USER") && !argument.is_empty() {
+            events.push(CredentialEvent::User(argument.trim().to_string()));
+        } else if command.eq_ignore_ascii_case("PASS") {
+            events.push(CredentialEvent::Pass {
+                length: argument.trim_end().len(),
+            });
+        }
+    }
+    events
+}
+
+/// `<tag> LOGIN <user> <password>`, with atoms or quoted strings.
+fn parse_imap(payload: &[u8]) -> Vec<CredentialEvent> {
+    let mut events = Vec::new();
+    for line in lines(payload) {
+        let mut args = imap_arguments(&line).into_iter();
+        let (Some(_tag), Some(command)) = (args.next(), args.next()) else {
+            continue;
+        };
+        if !command.eq_ignore_ascii_case("LOGIN") {
+            continue;
+        }
+        if let (Some(username), Some(password)) = (args.next(), args.next()) {
+            events.push(CredentialEvent::Login {
+                username,
+                password_length: password.len(),
+            });
+        }
+    }
+    events
+}
+
+fn imap_arguments(line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut chars = line.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c == ' ' {
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            let mut arg = String::new();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => arg.extend(chars.next()),
+                    '"' => break,
+                    c => arg.push(c),
+                }
+            }
+            args.push(arg);
+        } else {
+            let mut arg = String::new();
+            while let Some(&c) = chars.peek() {
+                if c == ' ' {
+                    break;
+                }
+                arg.push(c);
+                chars.next();
+            }
+            args.push(arg);
+        }
+    }
+    args
+}
+
+/// `Authorization: Basic` and `Proxy-Authorization: Basic` request headers.
+fn parse_http(payload: &[u8]) -> Vec<CredentialEvent> {
+    let mut events = Vec::new();
+    for line in lines(payload).skip(1) {
+        if line.is_empty() {
+            break;
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        if !name.eq_ignore_ascii_case("authorization")
+            && !name.eq_ignore_ascii_case("proxy-authorization")
+        {
+            continue;
+        }
+        let Some((scheme, token)) = value.trim().split_once(' ') else {
+            continue;
+        };
+        if !scheme.eq_ignore_ascii_case("basic") {
+            continue;
+        }
+        let Ok(decoded) = base64::engine::general_purpose::STANDARD.decode(token.trim()) else {
+            continue;
+        };
+        let decoded = String::from_utf8_lossy(&decoded);
+        let (username, password) = decoded.split_once(':').unwrap_or((&decoded, ""));
+        events.push(CredentialEvent::Login {
+            username: username.to_string(),
+            password_length: password.len(),
+        });
+    }
+    events
+}
+
+fn parse_telnet_server(payload: &[u8]) -> Vec<CredentialEvent> {
+    let text = String::from_utf8_lossy(&strip_telnet_commands(payload)).to_lowercase();
+    let text = text.trim_end();
+
+    if text.ends_with("password:") {
+        vec![CredentialEvent::TelnetPrompt(TelnetPrompt::Password)]
+    } else if text.ends_with("login:") || text.ends_with("username:") {
+        vec![CredentialEvent::TelnetPrompt(TelnetPrompt::Login)]
+    } else {
+        Vec::new()
+    }
+}
+
+fn parse_telnet_client(payload: &[u8]) -> Vec<CredentialEvent> {
+    let input = strip_telnet_commands(payload);
+    if input.is_empty() {
+        Vec::new()
+    } else {
+        vec![CredentialEvent::TelnetInput(
+            String::from_utf8_lossy(&input).into_owned(),
+        )]
+    }
+}
+
+/// Removes IAC option negotiation and subnegotiation sequences.
+fn strip_telnet_commands(payload: &[u8]) -> Vec<u8> {
+    const IAC: u8 = 255;
+    const SB: u8 = 250;
+    const SE: u8 = 240;
+
+    let mut data = Vec::with_capacity(payload.len());
+    let mut i = 0;
+    while i < payload.len() {
+        if payload[i] != IAC {
+            data.push(payload[i]);
+            i += 1;
+            continue;
+        }
+        match payload.get(i + 1) {
+            Some(&IAC) => {
+                data.push(IAC);
+                i += 2;
+            }
+            Some(&SB) => {
+                i += 2;
+                while i + 1 < payload.len() && !(payload[i] == IAC && payload[i + 1] == SE) {
+                    i += 1;
+                }
+                i += 2;
+            }
+            // WILL/WONT/DO/DONT carry an option byte; other commands do not.
+            Some(251..=254) => i += 3,
+            _ => i += 2,
+        }
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events(destination_port: u16, payload: &[u8]) -> Vec<CredentialEvent> {
+        parse(50000, destination_port, payload).map_or_else(Vec::new, |auth| auth.events)
+    }
+
+    fn login(events: &[CredentialEvent]) -> Option<(&str, usize)> {
+        events.iter().find_map(|event| match event {
+            CredentialEvent::Login {
+                username,
+                password_length,
+            } => Some((username.as_str(), *password_length)),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn http_basic_with_a_binary_body() {
+        // alice:secret
+        let mut payload = b"POST /upload HTTP/1.1\r\nHost: example.com\r\n\
+            Authorization: Basic YWxpY2U6c2VjcmV0\r\n\r\n"
+            .to_vec();
+        payload.extend_from_slice(&[0x89, b'P', b'N', b'G', 0xff, 0xfe, b'\n', 0x00]);
+        assert_eq!(login(&events(80, &payload)), Some(("alice", 6)));
+    }
+
+    #[test]
+    fn imap_login_next_to_a_binary_literal() {
+        let mut payload = b"a1 APPEND INBOX {4}\r\n".to_vec();
+        payload.extend_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+        payload.extend_from_slice(b"\r\na2 LOGIN \"bob\" \"p@ss word\"\r\n");
+        assert_eq!(login(&events(143, &payload)), Some(("bob", 9)));
+    }
+
+    #[test]
+    fn ftp_user_and_pass_lines() {
+        let events = events(21, b"USER carol\nPASS \xe9t\xe9\r\n");
+        assert!(matches!(&events[0], CredentialEvent::User(name) if name == "carol"));
+        assert!(matches!(events[1], CredentialEvent::Pass { length } if length > 0));
+        assert!(parse(50000, 21, b"SYST\r\n").is_none());
+    }
+}
@@ -0,0 +1,120 @@
+//! DCE-RPC (MS-RPCE) connection-oriented PDU parsing.
+//!
+//! DCE-RPC shows up either directly over TCP (port 135 and dynamic ports) or
+//! tunnelled through SMB2 named pipes, so this parser works on a bare PDU and
+//! is called from both the TCP dispatcher and the SMB dissector.
+
+use super::bytes::{le_u16, le_u32, u8_at};
+
+const PTYPE_REQUEST: u8 = 0;
+const PTYPE_BIND: u8 = 11;
+const PTYPE_ALTER_CONTEXT: u8 = 14;
+
+/// Well-known interfaces that matter for lateral movement.
+const KNOWN_INTERFACES: &[(&str, &str)] = &[
+    ("367abb81-9844-35f1-ad32-98f038001003", "svcctl"),
+    ("1ff70682-0a51-30e8-076d-740be8cee98b", "atsvc"),
+    (
+        "86d35949-83c9-4044-b424-db363231fd0c",
+        "ITaskSchedulerService",
+    ),
+    ("12345778-1234-abcd-ef00-0123456789ac", "samr"),
+    ("12345778-1234-abcd-ef00-0123456789ab", "lsarpc"),
+    ("4b324fc8-1670-01d3-1278-5a47bf6ee188", "srvsvc"),
+    ("338cd001-2244-31f1-aaaa-900038001003", "winreg"),
+    ("e3514235-4b06-11d1-ab04-00c04fc2dcd2", "drsuapi"),
+    ("e1af8308-5d1f-11c9-91a4-08002b14a0fa", "epmapper"),
+];
+
+#[derive(Debug, Clone)]
+pub enum DceRpcPdu {
+    /// Bind or alter-context: the presentation contexts the client proposes.
+    Bind {
+        contexts: Vec<PresentationContext>,
+    },
+    Request {
+        context_id: u16,
+        opnum: u16,
+    },
+    Other,
+}
+
+#[derive(Debug, Clone)]
+pub struct PresentationContext {
+    pub context_id: u16,
+    pub interface_uuid: String,
+    pub interface_version: u16,
+}
+
+impl PresentationContext {
+    /// Short interface name (e.g. `svcctl`) if the UUID is a known one.
+    pub fn interface_name(&self) -> Option<&'static str> {
+        interface_name(&self.interface_uuid)
+    }
+}
+
+pub fn interface_name(uuid: &str) -> Option<&'static str> {
+    KNOWN_INTERFACES
+        .iter()
+        .find(|(known, _)| *known == uuid)
+        .map(|(_, name)| *name)
+}
+
+pub fn parse(data: &[u8]) -> Option<DceRpcPdu> {
+    // Common header: version 5.0, little-endian data representation only.
+    if u8_at(data, 0)? != 5 || u8_at(data, 1)? != 0 {
+        return None;
+    }
+    let packet_type = u8_at(data, 2)?;
+    if u8_at(data, 4)? & 0x10 == 0 {
+        return None;
+    }
+    let frag_len = le_u16(data, 8)? as usize;
+    if frag_len < 16 {
+        return None;
+    }
+
+    match packet_type {
+        PTYPE_BIND | PTYPE_ALTER_CONTEXT => {
+            let num_contexts = u8_at(data, 24)? as usize;
+            let mut offset = 28;
+            let mut contexts = Vec::with_capacity(num_contexts);
+
+            for _ in 0..num_contexts {
+                let context_id = le_u16(data, offset)?;
+                let num_transfer = u8_at(data, offset + 2)? as usize;
+                let interface_uuid = format_uuid(data.get(offset + 4..offset + 20)?)?;
+                let interface_version = le_u16(data, offset + 20)?;
+                contexts.push(PresentationContext {
+                    context_id,
+                    interface_uuid,
+                    interface_version,
+                });
+                offset += 24 + num_transfer * 20;
+            }
+
+            Some(DceRpcPdu::Bind { contexts })
+        }
+        PTYPE_REQUEST => Some(DceRpcPdu::Request {
+            context_id: le_u16(data, 20)?,
+            opnum: le_u16(data, 22)?,
+        }),
+        _ => Some(DceRpcPdu::Other),
+    }
+}
+
+/// Formats a UUID stored in DCE little-endian wire order.
+fn format_uuid(raw: &[u8]) -> Option<String> {
+    let d1 = le_u32(raw, 0)?;
+    let d2 = le_u16(raw, 4)?;
+    let d3 = le_u16(raw, 6)?;
+    let tail = raw.get(8..16)?;
+    Some(format!(
+        "{:08x}-{:04x}-{:04x}-{}-{}",
+        d1,
+        d2,
+        d3,
+        hex::encode(&tail[..2]),
+        hex::encode(&tail[2..])
+    ))
+}
@@ -0,0 +1,140 @@
+//! DNS message parsing (RFC 1035).
+//!
+//! Only the header and question section are decoded: query names and types
+//! are what tunneling and DGA detection look at, and the response code tells
+//! whether a name existed.
+
+use super::bytes::{be_u16, u8_at};
+
+const HEADER_LEN: usize = 12;
+const MAX_QUESTIONS: usize = 16;
+const MAX_NAME_LEN: usize = 255;
+/// Compression pointers followed per name before giving up on a loop.
+const MAX_POINTERS: usize = 16;
+
+pub const TYPE_A: u16 = 1;
+pub const TYPE_NULL: u16 = 10;
+pub const TYPE_TXT: u16 = 16;
+pub const TYPE_AAAA: u16 = 28;
+
+pub const RCODE_NXDOMAIN: u8 = 3;
+
+#[derive(Debug, Clone)]
+pub struct DnsMessage {
+    pub id: u16,
+    pub response: bool,
+    pub rcode: u8,
+    pub questions: Vec<DnsQuestion>,
+    pub answer_count: u16,
+}
+
+#[derive(Debug, Clone)]
+pub struct DnsQuestion {
+    /// Lower-cased name without the trailing dot.
+    pub name: String,
+    pub qtype: u16,
+}
+
+/// Human-readable record type.
+pub fn type_name(qtype: u16) -> String {
+    match qtype {
+        TYPE_A => "A".to_string(),
+        2 => "NS".to_string(),
+        5 => "CNAME".to_string(),
+        6 => "SOA".to_string(),
+        TYPE_NULL => "NULL".to_string(),
+        12 => "PTR".to_string(),
+        15 => "MX".to_string(),
+        TYPE_TXT => "TXT".to_string(),
+        TYPE_AAAA => "AAAA".to_string(),
+        33 => "SRV".to_string(),
+        65 => "HTTPS".to_string(),
+        255 => "ANY".to_string(),
+        other => format!("TYPE{}", other),
+    }
+}
+
+/// Parses a DNS message carried in a UDP datagram.
+pub fn parse_udp(datagram: &[u8]) -> Option<DnsMessage> {
+    parse(datagram)
+}
+
+/// Parses the first DNS message in a TCP segment, which is preceded by a
+/// two-byte length.
+pub fn parse_tcp(segment: &[u8]) -> Option<DnsMessage> {
+    let len = be_u16(segment, 0)? as usize;
+    let message = segment.get(2..)?;
+    parse(message.get(..len).unwrap_or(message))
+}
+
+fn parse(message: &[u8]) -> Option<DnsMessage> {
+    if message.len() < HEADER_LEN {
+        return None;
+    }
+    let flags = be_u16(message, 2)?;
+    let opcode = (flags >> 11) & 0x0f;
+    // Standard queries only; anything else on port 53 is not worth guessing at.
+    if opcode != 0 {
+        return None;
+    }
+    let question_count = be_u16(message, 4)? as usize;
+    if question_count == 0 || question_count > MAX_QUESTIONS {
+        return None;
+    }
+
+    let mut offset = HEADER_LEN;
+    let mut questions = Vec::with_capacity(question_count);
+    for _ in 0..question_count {
+        let (name, next) = read_name(message, offset)?;
+        let qtype = be_u16(message, next)?;
+        be_u16(message, next + 2)?;
+        offset = next + 4;
+        questions.push(DnsQuestion { name, qtype });
+    }
+
+    Some(DnsMessage {
+        id: be_u16(message, 0)?,
+        response: flags & 0x8000 != 0,
+        rcode: (flags & 0x000f) as u8,
+        questions,
+        answer_count: be_u16(message, 6)?,
+    })
+}
+
+/// Reads a possibly compressed name at `offset`, returning it and the offset
+/// just past it in the original position.
+fn read_name(message: &[u8], mut offset: usize) -> Option<(String, usize)> {
+    let mut name = String::new();
+    let mut end = None;
+    let mut pointers = 0;
+
+    loop {
+        let len = u8_at(message, offset)?;
+        match len & 0xc0 {
+            0x00 if len == 0 => {
+                return Some((name, end.unwrap_or(offset + 1)));
+            }
+            0x00 => {
+                let label = message.get(offset + 1..offset + 1 + len as usize)?;
+                if !name.is_empty() {
+                    name.push('.');
+                }
+                name.extend(label.iter().map(|b| b.to_ascii_lowercase() as char));
+                if name.len() > MAX_NAME_LEN {
+                    return None;
+                }
+                offset += 1 + len as usize;
+            }
+            0xc0 => {
+                pointers += 1;
+                if pointers > MAX_POINTERS {
+                    return None;
+                }
+                let target = (be_u16(message, offset)? & 0x3fff) as usize;
+                end.get_or_insert(offset + 2);
+                offset = target;
+            }
+            _ => return None,
+        }
+    }
+}
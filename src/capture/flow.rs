@@ -0,0 +1,311 @@
+//! Flow tracking: aggregates packets into bidirectional flow records.
+//!
+//! A flow is keyed by its 5-tuple and oriented from the initiator (the first
+//! packet seen, or the SYN side of a TCP handshake). Records are emitted once
+//! the flow goes idle or, for TCP, shortly after a FIN or RST. When the table
+//! is full, the least recently active flow is emitted early to make room.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::parser::{AppLayer, ParsedPacket};
+use crate::detection::state::LruMap;
+use crate::geoip::GeoInfo;
+use crate::networks::Direction;
+
+const TCP_FIN: u8 = 0x01;
+const TCP_SYN: u8 = 0x02;
+const TCP_RST: u8 = 0x04;
+const TCP_ACK: u8 = 0x10;
+
+/// How long a closed TCP flow lingers for trailing ACKs before it is emitted.
+const CLOSED_LINGER_SECONDS: i64 = 2;
+const MAX_FLOWS: usize = 100_000;
+/// Rough size of an address string, heap included.
+const IP_STRING_BYTES: usize = 40;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct FlowKey {
+    source_ip: String,
+    destination_ip: String,
+    source_port: Option<u16>,
+    destination_port: Option<u16>,
+    protocol: String,
+}
+
+impl FlowKey {
+    fn reversed(&self) -> Self {
+        Self {
+            source_ip: self.destination_ip.clone(),
+            destination_ip: self.source_ip.clone(),
+            source_port: self.destination_port,
+            destination_port: self.source_port,
+            protocol: self.protocol.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlowRecord {
+    /// Initiator of the flow.
+    pub source_ip: String,
+    pub destination_ip: String,
+    pub source_port: Option<u16>,
+    pub destination_port: Option<u16>,
+    pub protocol: String,
+    /// Packets and bytes sent by the initiator.
+    pub packets_sent: u64,
+    pub bytes_sent: u64,
+    /// Packets and bytes sent by the responder.
+    pub packets_received: u64,
+    pub bytes_received: u64,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    /// Union of all TCP flags seen in either direction.
+    pub tcp_flags: u8,
+    /// Server name from a TLS or QUIC ClientHello seen in the flow.
+    pub server_name: Option<String>,
+    /// Filled in from the GeoIP databases when the flow is closed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_geo: Option<GeoInfo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination_geo: Option<GeoInfo>,
+    /// Relative to the configured home networks; set when the flow is
+    /// closed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub direction: Option<Direction>,
+}
+
+impl FlowRecord {
+    fn new(key: &FlowKey, now: DateTime<Utc>) -> Self {
+        Self {
+            source_ip: key.source_ip.clone(),
+            destination_ip: key.destination_ip.clone(),
+            source_port: key.source_port,
+            destination_port: key.destination_port,
+            protocol: key.protocol.clone(),
+            packets_sent: 0,
+            bytes_sent: 0,
+            packets_received: 0,
+            bytes_received: 0,
+            first_seen: now,
+            last_seen: now,
+            tcp_flags: 0,
+            server_name: None,
+            source_geo: None,
+            destination_geo: None,
+            direction: None,
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.last_seen - self.first_seen
+    }
+
+    fn is_closed(&self) -> bool {
+        self.tcp_flags & (TCP_FIN | TCP_RST) != 0
+    }
+}
+
+pub struct FlowTable {
+    flows: LruMap<FlowKey, FlowRecord>,
+    /// Flows pushed out of a full table, emitted by the next `expire`.
+    evicted: Vec<FlowRecord>,
+    idle_timeout: Duration,
+}
+
+impl FlowTable {
+    pub fn new(idle_timeout_seconds: u64) -> Self {
+        Self::with_capacity(idle_timeout_seconds, MAX_FLOWS)
+    }
+
+    fn with_capacity(idle_timeout_seconds: u64, capacity: usize) -> Self {
+        Self {
+            flows: LruMap::new(capacity),
+            evicted: Vec::new(),
+            idle_timeout: Duration::seconds(idle_timeout_seconds as i64),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.flows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.flows.is_empty()
+    }
+
+    /// Accounts a packet to its flow, creating the flow if needed.
+    pub fn update(&mut self, packet: &ParsedPacket, now: DateTime<Utc>) {
+        if packet.protocol == "ARP" {
+            return;
+        }
+
+        let key = FlowKey {
+            source_ip: packet.source_ip.clone(),
+            destination_ip: packet.destination_ip.clone(),
+            source_port: packet.source_port,
+            destination_port: packet.destination_port,
+            protocol: packet.protocol.clone(),
+        };
+        let flags = packet.tcp_flags.unwrap_or(0);
+        let size = packet.size as u64;
+
+        let reversed = key.reversed();
+        let (key, from_initiator) = if self.flows.contains_key(&key) {
+            (key, true)
+        } else if self.flows.contains_key(&reversed) {
+            (reversed, false)
+        } else {
+            if self.flows.len() >= self.flows.capacity() {
+                if let Some((_, flow)) = self.flows.pop_oldest() {
+                    self.evicted.push(flow);
+                }
+            }
+            // A SYN-ACK as the first packet means we missed the SYN; the
+            // destination is the real initiator.
+            let (key, from_initiator) = if flags & (TCP_SYN | TCP_ACK) == TCP_SYN | TCP_ACK {
+                (reversed, false)
+            } else {
+                (key, true)
+            };
+            self.flows.insert(key.clone(), FlowRecord::new(&key, now));
+            (key, from_initiator)
+        };
+
+        let Some(flow) = self.flows.get_mut(&key) else {
+            return;
+        };
+        if from_initiator {
+            flow.packets_sent += 1;
+            flow.bytes_sent += size;
+        } else {
+            flow.packets_received += 1;
+            flow.bytes_received += size;
+        }
+        flow.last_seen = now;
+        flow.tcp_flags |= flags;
+
+        if flow.server_name.is_none() {
+            flow.server_name = match &packet.app {
+                Some(AppLayer::Tls(hello)) => hello.server_name.clone(),
+                Some(AppLayer::Quic(initial)) => initial
+                    .client_hello
+                    .as_ref()
+                    .and_then(|hello| hello.server_name.clone()),
+                _ => None,
+            };
+        }
+    }
+
+    /// Removes and returns flows that have gone idle or been closed, and
+    /// those evicted since the last call.
+    pub fn expire(&mut self, now: DateTime<Utc>) -> Vec<FlowRecord> {
+        let idle_timeout = self.idle_timeout;
+        let linger = Duration::seconds(CLOSED_LINGER_SECONDS);
+
+        let expired: Vec<FlowKey> = self
+            .flows
+            .iter()
+            .filter(|(_, flow)| {
+                let idle = now - flow.last_seen;
+                idle >= idle_timeout || (flow.is_closed() && idle >= linger)
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        let mut records = std::mem::take(&mut self.evicted);
+        records.extend(
+            expired
+                .into_iter()
+                .filter_map(|key| self.flows.remove(&key)),
+        );
+        records
+    }
+
+    /// Approximate bytes held by the table.
+    pub fn memory_usage(&self) -> usize {
+        let names: usize = self
+            .flows
+            .values()
+            .map(|flow| flow.server_name.as_ref().map_or(0, String::capacity))
+            .sum();
+        (self.flows.len() + self.evicted.len())
+            * (LruMap::<FlowKey, FlowRecord>::entry_overhead() + 4 * IP_STRING_BYTES)
+            + names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(
+        source: &str,
+        destination: &str,
+        source_port: u16,
+        destination_port: u16,
+        flags: u8,
+    ) -> ParsedPacket {
+        ParsedPacket {
+            source_ip: source.to_string(),
+            destination_ip: destination.to_string(),
+            source_port: Some(source_port),
+            destination_port: Some(destination_port),
+            protocol: "TCP".to_string(),
+            size: 100,
+            tcp_flags: Some(flags),
+            app: None,
+            payload: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn flows_are_oriented_from_the_initiator() {
+        let mut table = FlowTable::new(60);
+        let now = Utc::now();
+        // SYN-ACK seen first: the server's peer started the flow.
+        table.update(
+            &packet("10.0.0.2", "10.0.0.1", 443, 50000, TCP_SYN | TCP_ACK),
+            now,
+        );
+        table.update(&packet("10.0.0.1", "10.0.0.2", 50000, 443, TCP_ACK), now);
+        table.update(
+            &packet("10.0.0.1", "10.0.0.2", 50000, 443, TCP_FIN | TCP_ACK),
+            now,
+        );
+        assert_eq!(table.len(), 1);
+
+        assert!(table.expire(now).is_empty());
+        let flows = table.expire(now + Duration::seconds(CLOSED_LINGER_SECONDS));
+        assert_eq!(flows.len(), 1);
+        assert_eq!(flows[0].source_ip, "10.0.0.1");
+        assert_eq!(flows[0].destination_port, Some(443));
+        assert_eq!((flows[0].packets_sent, flows[0].packets_received), (2, 1));
+    }
+
+    #[test]
+    fn full_table_emits_the_least_recently_active_flow() {
+        let mut table = FlowTable::with_capacity(60, 2);
+        let now = Utc::now();
+        table.update(&packet("10.0.0.1", "10.0.0.9", 40001, 80, TCP_SYN), now);
+        table.update(&packet("10.0.0.2", "10.0.0.9", 40002, 80, TCP_SYN), now);
+        // The first flow is active again, so the second is the oldest.
+        table.update(&packet("10.0.0.9", "10.0.0.1", 80, 40001, TCP_ACK), now);
+        let usage = table.memory_usage();
+        table.update(&packet("10.0.0.3", "10.0.0.9", 40003, 80, TCP_SYN), now);
+        assert_eq!(table.len(), 2);
+        assert!(table.memory_usage() > usage);
+
+        let flows = table.expire(now);
+        assert_eq!(flows.len(), 1);
+        assert_eq!(flows[0].source_ip, "10.0.0.2");
+        assert_eq!(table.memory_usage(), usage);
+
+        let flows = table.expire(now + Duration::seconds(60));
+        let mut sources: Vec<&str> = flows.iter().map(|flow| flow.source_ip.as_str()).collect();
+        sources.sort_unstable();
+        assert_eq!(sources, ["10.0.0.1", "10.0.0.3"]);
+        assert!(table.is_empty());
+    }
+}
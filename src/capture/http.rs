@@ -0,0 +1,60 @@
+//! HTTP/1.x request line and headers.
+//!
+//! Only the first segment of a request is looked at; headers continued in a
+//! later segment are not seen. The body is ignored.
+
+const METHODS: &[&str] = &[
+    "GET", "POST", "PUT", "HEAD", "DELETE", "OPTIONS", "PATCH", "CONNECT", "TRACE",
+];
+
+#[derive(Debug, Clone)]
+pub struct HttpRequest {
+    pub method: String,
+    pub uri: String,
+    pub version: String,
+    /// Headers in order, names lowercased.
+    pub headers: Vec<(String, String)>,
+}
+
+impl HttpRequest {
+    /// First value of a header, by lowercase name.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Parses a request from the start of a TCP payload.
+pub fn parse_request(payload: &[u8]) -> Option<HttpRequest> {
+    let head_end = payload
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .unwrap_or(payload.len());
+    let head = String::from_utf8_lossy(&payload[..head_end]);
+    let mut lines = head.split("\r\n");
+
+    let mut request_line = lines.next()?.split(' ');
+    let method = request_line.next()?;
+    if !METHODS.contains(&method) {
+        return None;
+    }
+    let uri = request_line.next()?;
+    let version = request_line.next()?;
+    if !version.starts_with("HTTP/") || request_line.next().is_some() {
+        return None;
+    }
+
+    let headers = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+
+    Some(HttpRequest {
+        method: method.to_string(),
+        uri: uri.to_string(),
+        version: version.to_string(),
+        headers,
+    })
+}
@@ -0,0 +1,104 @@
+//! ICMP and ICMPv6 echo and destination-unreachable messages.
+//!
+//! Unreachable messages quote the header of the packet that triggered them,
+//! which tells us which probe a closed UDP port was answering.
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use super::bytes::{be_u16, u8_at};
+
+const ICMP_ECHO_REPLY: u8 = 0;
+const ICMP_UNREACHABLE: u8 = 3;
+const ICMP_ECHO_REQUEST: u8 = 8;
+const ICMP_PORT_UNREACHABLE: u8 = 3;
+
+const ICMPV6_UNREACHABLE: u8 = 1;
+const ICMPV6_ECHO_REQUEST: u8 = 128;
+const ICMPV6_ECHO_REPLY: u8 = 129;
+const ICMPV6_PORT_UNREACHABLE: u8 = 4;
+
+const IPPROTO_TCP: u8 = 6;
+const IPPROTO_UDP: u8 = 17;
+
+#[derive(Debug, Clone)]
+pub enum IcmpMessage {
+    EchoRequest,
+    EchoReply,
+    Unreachable {
+        /// True for port unreachable, the answer to a probe of a closed UDP port.
+        port_unreachable: bool,
+        original: Option<QuotedPacket>,
+    },
+}
+
+/// The offending packet quoted in an ICMP error.
+#[derive(Debug, Clone)]
+pub struct QuotedPacket {
+    pub destination_ip: String,
+    pub destination_port: Option<u16>,
+    pub protocol: &'static str,
+}
+
+pub fn parse_v4(icmp: &[u8]) -> Option<IcmpMessage> {
+    match u8_at(icmp, 0)? {
+        ICMP_ECHO_REQUEST => Some(IcmpMessage::EchoRequest),
+        ICMP_ECHO_REPLY => Some(IcmpMessage::EchoReply),
+        ICMP_UNREACHABLE => {
+            let code = u8_at(icmp, 1)?;
+            Some(IcmpMessage::Unreachable {
+                port_unreachable: code == ICMP_PORT_UNREACHABLE,
+                original: quoted_v4(icmp.get(8..)?),
+            })
+        }
+        _ => None,
+    }
+}
+
+pub fn parse_v6(icmpv6: &[u8]) -> Option<IcmpMessage> {
+    match u8_at(icmpv6, 0)? {
+        ICMPV6_ECHO_REQUEST => Some(IcmpMessage::EchoRequest),
+        ICMPV6_ECHO_REPLY => Some(IcmpMessage::EchoReply),
+        ICMPV6_UNREACHABLE => {
+            let code = u8_at(icmpv6, 1)?;
+            Some(IcmpMessage::Unreachable {
+                port_unreachable: code == ICMPV6_PORT_UNREACHABLE,
+                original: quoted_v6(icmpv6.get(8..)?),
+            })
+        }
+        _ => None,
+    }
+}
+
+fn quoted_v4(ip: &[u8]) -> Option<QuotedPacket> {
+    let header_len = (u8_at(ip, 0)? & 0x0f) as usize * 4;
+    let bytes: [u8; 4] = ip.get(16..20)?.try_into().ok()?;
+    quoted(
+        Ipv4Addr::from(bytes).to_string(),
+        u8_at(ip, 9)?,
+        ip.get(header_len..)?,
+    )
+}
+
+/// Extension headers between the IPv6 header and transport are not walked;
+/// probes rarely carry them.
+fn quoted_v6(ip: &[u8]) -> Option<QuotedPacket> {
+    let bytes: [u8; 16] = ip.get(24..40)?.try_into().ok()?;
+    quoted(
+        Ipv6Addr::from(bytes).to_string(),
+        u8_at(ip, 6)?,
+        ip.get(40..)?,
+    )
+}
+
+fn quoted(destination_ip: String, next_header: u8, transport: &[u8]) -> Option<QuotedPacket> {
+    let protocol = match next_header {
+        IPPROTO_TCP => "TCP",
+        IPPROTO_UDP => "UDP",
+        _ => return None,
+    };
+    Some(QuotedPacket {
+        destination_ip,
+        destination_port: be_u16(transport, 2),
+        protocol,
+    })
+}
@@ -0,0 +1,243 @@
+//! Industrial control protocol dissectors: Modbus/TCP and DNP3.
+//!
+//! Both decode to a common [`IcsCommand`] so detection can reason about
+//! "who told which outstation to do what" without caring about the protocol.
+
+use super::bytes::{be_u16, le_u16, u8_at};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IcsProtocol {
+    Modbus,
+    Dnp3,
+}
+
+impl IcsProtocol {
+    pub fn name(&self) -> &'static str {
+        match self {
+            IcsProtocol::Modbus => "Modbus",
+            IcsProtocol::Dnp3 => "DNP3",
+        }
+    }
+}
+
+/// What a function code does to the field device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IcsCategory {
+    Read,
+    Write,
+    Diagnostic,
+    Firmware,
+    Other,
+}
+
+#[derive(Debug, Clone)]
+pub struct IcsCommand {
+    pub protocol: IcsProtocol,
+    /// True for master -> outstation requests.
+    pub is_request: bool,
+    pub function_code: u8,
+    pub function_name: &'static str,
+    pub category: IcsCategory,
+    /// Modbus unit ID, or DNP3 destination address.
+    pub unit_id: u16,
+    /// First and last register/coil/point index addressed, when known.
+    pub range: Option<(u16, u16)>,
+}
+
+const MODBUS_HEADER_LEN: usize = 7;
+
+/// Parses every Modbus/TCP ADU in a TCP payload.
+pub fn parse_modbus(payload: &[u8], is_request: bool) -> Option<Vec<IcsCommand>> {
+    let mut commands = Vec::new();
+    let mut offset = 0;
+
+    while offset + MODBUS_HEADER_LEN < payload.len() {
+        // MBAP: transaction ID, protocol ID (0), length, unit ID.
+        if be_u16(payload, offset + 2)? != 0 {
+            break;
+        }
+        let length = be_u16(payload, offset + 4)? as usize;
+        if length < 2 {
+            break;
+        }
+        let unit_id = u8_at(payload, offset + 6)? as u16;
+        let pdu_start = offset + MODBUS_HEADER_LEN;
+        let pdu_end = (offset + 6 + length).min(payload.len());
+        let pdu = &payload[pdu_start..pdu_end];
+
+        // Exception responses set the high bit of the function code.
+        let function_code = *pdu.first()? & 0x7f;
+        let (function_name, category) = modbus_function(function_code, pdu);
+
+        let range = if is_request {
+            match function_code {
+                // Start address and quantity.
+                1..=4 | 15 | 16 | 23 => {
+                    let start = be_u16(pdu, 1)?;
+                    let quantity = be_u16(pdu, 3)?.max(1);
+                    Some((start, start.saturating_add(quantity - 1)))
+                }
+                // Single address.
+                5 | 6 | 22 => be_u16(pdu, 1).map(|address| (address, address)),
+                _ => None,
+            }
+        } else {
+            None
+        };
+
+        commands.push(IcsCommand {
+            protocol: IcsProtocol::Modbus,
+            is_request,
+            function_code,
+            function_name,
+            category,
+            unit_id,
+            range,
+        });
+        offset += 6 + length;
+    }
+
+    if commands.is_empty() {
+        None
+    } else {
+        Some(commands)
+    }
+}
+
+fn modbus_function(code: u8, pdu: &[u8]) -> (&'static str, IcsCategory) {
+    match code {
+        1 => ("Read Coils", IcsCategory::Read),
+        2 => ("Read Discrete Inputs", IcsCategory::Read),
+        3 => ("Read Holding Registers", IcsCategory::Read),
+        4 => ("Read Input Registers", IcsCategory::Read),
+        5 => ("Write Single Coil", IcsCategory::Write),
+        6 => ("Write Single Register", IcsCategory::Write),
+        7 => ("Read Exception Status", IcsCategory::Diagnostic),
+        8 => (
+            modbus_diagnostic_name(be_u16(pdu, 1).unwrap_or(0)),
+            IcsCategory::Diagnostic,
+        ),
+        11 => ("Get Comm Event Counter", IcsCategory::Diagnostic),
+        12 => ("Get Comm Event Log", IcsCategory::Diagnostic),
+        15 => ("Write Multiple Coils", IcsCategory::Write),
+        16 => ("Write Multiple Registers", IcsCategory::Write),
+        17 => ("Report Server ID", IcsCategory::Diagnostic),
+        20 => ("Read File Record", IcsCategory::Read),
+        21 => ("Write File Record", IcsCategory::Write),
+        22 => ("Mask Write Register", IcsCategory::Write),
+        23 => ("Read/Write Multiple Registers", IcsCategory::Write),
+        24 => ("Read FIFO Queue", IcsCategory::Read),
+        43 => ("Read Device Identification", IcsCategory::Diagnostic),
+        // Vendor program/firmware transfer (Schneider UMAS, Modicon download).
+        90 => ("UMAS Program/Firmware", IcsCategory::Firmware),
+        125 => ("Program Download", IcsCategory::Firmware),
+        126 => ("Program Upload", IcsCategory::Firmware),
+        _ => ("Unknown", IcsCategory::Other),
+    }
+}
+
+fn modbus_diagnostic_name(sub_function: u16) -> &'static str {
+    match sub_function {
+        0x00 => "Diagnostics: Return Query Data",
+        0x01 => "Diagnostics: Restart Communications",
+        0x04 => "Diagnostics: Force Listen Only Mode",
+        0x0a => "Diagnostics: Clear Counters",
+        _ => "Diagnostics",
+    }
+}
+
+const DNP3_START: [u8; 2] = [0x05, 0x64];
+const DNP3_LINK_HEADER_LEN: usize = 10;
+const DNP3_DIR_FLAG: u8 = 0x80;
+
+/// Parses a DNP3 link frame and the application header it carries.
+pub fn parse_dnp3(payload: &[u8]) -> Option<Vec<IcsCommand>> {
+    if !payload.starts_with(&DNP3_START) {
+        return None;
+    }
+    let length = u8_at(payload, 2)? as usize;
+    let control = u8_at(payload, 3)?;
+    let destination = le_u16(payload, 4)?;
+
+    // User data follows the header CRC in 16-byte blocks, each with a CRC.
+    let user_len = length.checked_sub(5)?;
+    let mut user_data = Vec::with_capacity(user_len);
+    let mut offset = DNP3_LINK_HEADER_LEN;
+    while user_data.len() < user_len {
+        let take = (user_len - user_data.len()).min(16);
+        user_data.extend_from_slice(payload.get(offset..offset + take)?);
+        offset += take + 2;
+    }
+
+    // Transport header (FIR must be set for the application header to be
+    // present), then application control and function code.
+    let transport = *user_data.first()?;
+    if transport & 0x40 == 0 {
+        return None;
+    }
+    let function_code = *user_data.get(2)?;
+    let (function_name, category) = dnp3_function(function_code);
+    let is_request = control & DNP3_DIR_FLAG != 0 && function_code < 129;
+
+    // Requests carry object headers right after the function code; report
+    // the point range of the first one when it uses a start/stop qualifier.
+    let range = if is_request {
+        dnp3_range(user_data.get(3..).unwrap_or(&[]))
+    } else {
+        None
+    };
+
+    Some(vec![IcsCommand {
+        protocol: IcsProtocol::Dnp3,
+        is_request,
+        function_code,
+        function_name,
+        category,
+        unit_id: destination,
+        range,
+    }])
+}
+
+fn dnp3_function(code: u8) -> (&'static str, IcsCategory) {
+    match code {
+        0 => ("Confirm", IcsCategory::Other),
+        1 => ("Read", IcsCategory::Read),
+        2 => ("Write", IcsCategory::Write),
+        3 => ("Select", IcsCategory::Write),
+        4 => ("Operate", IcsCategory::Write),
+        5 => ("Direct Operate", IcsCategory::Write),
+        6 => ("Direct Operate No Ack", IcsCategory::Write),
+        7..=12 => ("Freeze", IcsCategory::Write),
+        13 => ("Cold Restart", IcsCategory::Diagnostic),
+        14 => ("Warm Restart", IcsCategory::Diagnostic),
+        15 => ("Initialize Data", IcsCategory::Diagnostic),
+        16 => ("Initialize Application", IcsCategory::Firmware),
+        17 => ("Start Application", IcsCategory::Firmware),
+        18 => ("Stop Application", IcsCategory::Firmware),
+        19 => ("Save Configuration", IcsCategory::Firmware),
+        20 => ("Enable Unsolicited", IcsCategory::Other),
+        21 => ("Disable Unsolicited", IcsCategory::Diagnostic),
+        22 => ("Assign Class", IcsCategory::Other),
+        23 => ("Delay Measure", IcsCategory::Other),
+        24 => ("Record Current Time", IcsCategory::Other),
+        25 => ("Open File", IcsCategory::Firmware),
+        26 => ("Close File", IcsCategory::Firmware),
+        27 => ("Delete File", IcsCategory::Firmware),
+        28 => ("Get File Info", IcsCategory::Read),
+        29 => ("Authenticate File", IcsCategory::Firmware),
+        30 => ("Abort File", IcsCategory::Firmware),
+        129 => ("Response", IcsCategory::Other),
+        130 => ("Unsolicited Response", IcsCategory::Other),
+        _ => ("Unknown", IcsCategory::Other),
+    }
+}
+
+fn dnp3_range(objects: &[u8]) -> Option<(u16, u16)> {
+    // Object header: group, variation, qualifier, then the range field.
+    let qualifier = u8_at(objects, 2)? & 0x0f;
+    match qualifier {
+        0x00 => Some((u8_at(objects, 3)? as u16, u8_at(objects, 4)? as u16)),
+        0x01 => Some((le_u16(objects, 3)?, le_u16(objects, 5)?)),
+        _ => None,
+    }
+}
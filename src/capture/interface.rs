@@ -15,11 +15,7 @@ pub fn list_interfaces() -> Result<Vec<InterfaceInfo>> {
     let info: Vec<InterfaceInfo> = interfaces
         .into_iter()
         .map(|iface| {
-            let mac = if !iface.mac.is_none() {
-                Some(format!("{}", iface.mac.unwrap()))
-            } else {
-                None
-            };
+            let mac = iface.mac.map(|mac| mac.to_string());
             
             let ips: Vec<String> = iface
                 .ips
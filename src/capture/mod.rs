@@ -1,5 +1,17 @@
+mod bytes;
+pub mod credentials;
+pub mod dcerpc;
+pub mod dns;
+pub mod flow;
+pub mod http;
+pub mod icmp;
+pub mod ics;
 mod interface;
+pub mod neighbor;
 pub mod parser;
+pub mod quic;
+pub mod smb;
+pub mod tls;
 
 pub use interface::list_interfaces;
 
@@ -9,8 +21,9 @@ use std::sync::Arc;
 use tokio::sync::Mutex;
 
 use crate::config::Config;
-use crate::detection::DetectionEngine;
+use crate::detection::{DetectionEngine, DetectorRegistry};
 use crate::error::NetGuardError;
+use crate::stats::CaptureStats;
 use crate::storage::Storage;
 
 pub struct Monitor {
@@ -18,6 +31,7 @@ pub struct Monitor {
     config: Config,
     storage: Option<Arc<Mutex<Storage>>>,
     verbose: bool,
+    detectors: DetectorRegistry,
 }
 
 impl Monitor {
@@ -33,7 +47,7 @@ impl Monitor {
             interfaces
                 .into_iter()
                 .find(|iface| iface.name == name)
-                .ok_or_else(|| NetGuardError::InterfaceNotFound(name))?
+                .ok_or(NetGuardError::InterfaceNotFound(name))?
         } else {
             interfaces
                 .into_iter()
@@ -48,9 +62,17 @@ impl Monitor {
             config,
             storage,
             verbose,
+            detectors: DetectorRegistry::with_builtin(),
         })
     }
     
+    /// Replaces the detector registry, e.g. one with third-party detectors
+    /// registered on top of the built-ins.
+    pub fn with_detectors(mut self, detectors: DetectorRegistry) -> Self {
+        self.detectors = detectors;
+        self
+    }
+    
     pub async fn start(self) -> Result<()> {
         use colored::Colorize;
         
@@ -58,7 +80,12 @@ impl Monitor {
         println!();
         
         // Create detection engine
-        let detection_engine = DetectionEngine::new(self.config.detection.clone());
+        let detection_engine =
+            DetectionEngine::with_registry(self.config.detection.clone(), &self.detectors)?
+                .with_interface(&self.interface.name);
+        if let Some(storage) = &self.storage {
+            detection_engine.restore_risk(storage.lock().await.get_host_risks()?);
+        }
         
         // Create a channel to receive packets
         let (_, mut rx) = match datalink::channel(&self.interface, Default::default()) {
@@ -68,6 +95,7 @@ impl Monitor {
         };
         
         let mut packet_count = 0u64;
+        let mut capture_stats = CaptureStats::default();
         
         loop {
             match rx.next() {
@@ -76,13 +104,23 @@ impl Monitor {
                     
                     // Parse packet
                     if let Some(parsed) = parser::parse_packet(packet) {
+                        capture_stats.record(&parsed);
+                        
+                        if self.verbose {
+                            print_handshake(&parsed);
+                        }
+                        
                         // Run detection
-                        if let Some(alert) = detection_engine.check_packet(&parsed) {
+                        for alert in detection_engine.check_packet(&parsed) {
                             self.handle_alert(alert).await?;
                         }
+                        self.store_risk(&detection_engine).await?;
                         
-                        if self.verbose && packet_count % 100 == 0 {
+                        if self.verbose && packet_count.is_multiple_of(100) {
                             println!("📦 Packets captured: {}", packet_count);
+                            capture_stats.display();
+                            print_detector_memory(&detection_engine);
+                            print_suppressed_hits(&detection_engine);
                         }
                     }
                 }
@@ -93,6 +131,21 @@ impl Monitor {
         }
     }
     
+    /// Persists host risk scores that changed.
+    async fn store_risk(&self, engine: &DetectionEngine) -> Result<()> {
+        let updates = engine.take_risk_updates();
+        if updates.is_empty() {
+            return Ok(());
+        }
+        if let Some(storage) = &self.storage {
+            let mut storage = storage.lock().await;
+            for risk in &updates {
+                storage.store_host_risk(risk)?;
+            }
+        }
+        Ok(())
+    }
+    
     async fn handle_alert(&self, alert: crate::detection::Alert) -> Result<()> {
         use colored::Colorize;
         
@@ -106,9 +159,9 @@ impl Monitor {
         
         println!("\n🚨 {} {}", "ALERT:".bright_red().bold(), severity_color);
         println!("   Type: {}", alert.alert_type);
-        println!("   Source: {}", alert.source_ip);
+        println!("   Source: {}", crate::geoip::describe(&alert.source_ip, alert.source_geo.as_ref()));
         if let Some(dest) = &alert.destination_ip {
-            println!("   Destination: {}", dest);
+            println!("   Destination: {}", crate::geoip::describe(dest, alert.destination_geo.as_ref()));
         }
         println!("   Details: {}", alert.details);
         println!("   Time: {}", alert.timestamp);
@@ -123,3 +176,44 @@ impl Monitor {
         Ok(())
     }
 }
+
+/// Prints the hostname, ALPN and JA4 of TLS and QUIC client hellos.
+fn print_handshake(packet: &parser::ParsedPacket) {
+    let (transport, hello) = match &packet.app {
+        Some(parser::AppLayer::Tls(hello)) => ("TLS".to_string(), hello),
+        Some(parser::AppLayer::Quic(quic::QuicInitial {
+            version,
+            client_hello: Some(hello),
+        })) => (format!("QUIC {}", quic::version_name(*version)), hello),
+        _ => return,
+    };
+    
+    println!(
+        "🔒 {} {} -> {} sni={} alpn={} ja4={}",
+        transport,
+        packet.source_ip,
+        packet.destination_ip,
+        hello.server_name.as_deref().unwrap_or("-"),
+        if hello.alpn.is_empty() { "-".to_string() } else { hello.alpn.join(",") },
+        hello.ja4.as_deref().unwrap_or("-")
+    );
+}
+
+/// Prints the approximate memory held by each detector.
+fn print_detector_memory(engine: &DetectionEngine) {
+    let usage = engine.memory_usage();
+    let total: usize = usage.iter().map(|(_, bytes)| bytes).sum();
+    let parts: Vec<String> = usage
+        .iter()
+        .filter(|(_, bytes)| *bytes > 0)
+        .map(|(name, bytes)| format!("{} {} KiB", name, bytes / 1024))
+        .collect();
+    println!("🧠 Detector state: {} KiB ({})", total / 1024, parts.join(", "));
+}
+
+/// Prints how much traffic each allowlist entry has hidden, per detector.
+fn print_suppressed_hits(engine: &DetectionEngine) {
+    for (reason, detector, count) in engine.suppressed_hits() {
+        println!("🙈 Allowlisted ({}): {} hidden from {}", reason, count, detector);
+    }
+}
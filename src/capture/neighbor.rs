@@ -0,0 +1,144 @@
+//! Address resolution: ARP and ICMPv6 Neighbor Discovery (RFC 4861).
+//!
+//! Both protocols announce "this IP lives at this MAC" and neither is
+//! authenticated, which is what spoofing detection watches for.
+
+use pnet::packet::arp::{ArpOperations, ArpPacket};
+use pnet::util::MacAddr;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use super::bytes::{be_u16, u8_at};
+
+const ICMPV6_ROUTER_ADVERTISEMENT: u8 = 134;
+const ICMPV6_NEIGHBOR_SOLICITATION: u8 = 135;
+const ICMPV6_NEIGHBOR_ADVERTISEMENT: u8 = 136;
+
+const OPTION_SOURCE_LINK_ADDR: u8 = 1;
+const OPTION_TARGET_LINK_ADDR: u8 = 2;
+const OPTION_PREFIX_INFO: u8 = 3;
+
+#[derive(Debug, Clone)]
+pub struct ArpMessage {
+    pub is_reply: bool,
+    pub sender_mac: String,
+    pub sender_ip: String,
+    pub target_ip: String,
+}
+
+impl ArpMessage {
+    /// A gratuitous ARP announces the sender's own address: the target IP
+    /// equals the sender IP.
+    pub fn is_gratuitous(&self) -> bool {
+        self.sender_ip == self.target_ip
+    }
+
+    /// ARP probes (RFC 5227) use an all-zero sender IP and bind nothing.
+    pub fn is_probe(&self) -> bool {
+        self.sender_ip == Ipv4Addr::UNSPECIFIED.to_string()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum NdpMessage {
+    NeighborSolicitation {
+        /// MAC of the soliciting host, from the source link-layer option.
+        source_link_addr: Option<String>,
+    },
+    NeighborAdvertisement {
+        target: String,
+        /// MAC of the target, from the option or the Ethernet source.
+        target_link_addr: String,
+        is_router: bool,
+        is_override: bool,
+    },
+    RouterAdvertisement {
+        router_lifetime: u16,
+        /// MAC of the router, from the option or the Ethernet source.
+        source_link_addr: String,
+        prefixes: Vec<String>,
+    },
+}
+
+pub fn parse_arp(payload: &[u8]) -> Option<ArpMessage> {
+    let arp = ArpPacket::new(payload)?;
+    let is_reply = match arp.get_operation() {
+        ArpOperations::Request => false,
+        ArpOperations::Reply => true,
+        _ => return None,
+    };
+
+    Some(ArpMessage {
+        is_reply,
+        sender_mac: arp.get_sender_hw_addr().to_string(),
+        sender_ip: arp.get_sender_proto_addr().to_string(),
+        target_ip: arp.get_target_proto_addr().to_string(),
+    })
+}
+
+/// Parses an ICMPv6 Neighbor Discovery message. `ethernet_source` is used
+/// when the message carries no link-layer address option.
+pub fn parse_ndp(icmpv6: &[u8], ethernet_source: MacAddr) -> Option<NdpMessage> {
+    match u8_at(icmpv6, 0)? {
+        ICMPV6_NEIGHBOR_SOLICITATION => Some(NdpMessage::NeighborSolicitation {
+            source_link_addr: link_addr_option(icmpv6.get(24..)?, OPTION_SOURCE_LINK_ADDR),
+        }),
+        ICMPV6_NEIGHBOR_ADVERTISEMENT => {
+            let flags = u8_at(icmpv6, 4)?;
+            Some(NdpMessage::NeighborAdvertisement {
+                target: ipv6_at(icmpv6, 8)?.to_string(),
+                target_link_addr: link_addr_option(icmpv6.get(24..)?, OPTION_TARGET_LINK_ADDR)
+                    .unwrap_or_else(|| ethernet_source.to_string()),
+                is_router: flags & 0x80 != 0,
+                is_override: flags & 0x20 != 0,
+            })
+        }
+        ICMPV6_ROUTER_ADVERTISEMENT => {
+            let options = icmpv6.get(16..)?;
+            Some(NdpMessage::RouterAdvertisement {
+                router_lifetime: be_u16(icmpv6, 6)?,
+                source_link_addr: link_addr_option(options, OPTION_SOURCE_LINK_ADDR)
+                    .unwrap_or_else(|| ethernet_source.to_string()),
+                prefixes: prefix_options(options),
+            })
+        }
+        _ => None,
+    }
+}
+
+fn ipv6_at(buf: &[u8], offset: usize) -> Option<Ipv6Addr> {
+    let bytes: [u8; 16] = buf.get(offset..offset + 16)?.try_into().ok()?;
+    Some(Ipv6Addr::from(bytes))
+}
+
+/// Iterates ND options as (type, body) pairs; lengths are in 8-byte units.
+fn options(mut data: &[u8]) -> impl Iterator<Item = (u8, &[u8])> {
+    std::iter::from_fn(move || {
+        let option_type = *data.first()?;
+        let len = *data.get(1)? as usize * 8;
+        if len == 0 || len > data.len() {
+            return None;
+        }
+        let (option, rest) = data.split_at(len);
+        data = rest;
+        Some((option_type, option))
+    })
+}
+
+fn link_addr_option(data: &[u8], wanted: u8) -> Option<String> {
+    options(data)
+        .find(|(option_type, _)| *option_type == wanted)
+        .and_then(|(_, option)| {
+            let mac = option.get(2..8)?;
+            Some(MacAddr::new(mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]).to_string())
+        })
+}
+
+fn prefix_options(data: &[u8]) -> Vec<String> {
+    options(data)
+        .filter(|(option_type, _)| *option_type == OPTION_PREFIX_INFO)
+        .filter_map(|(_, option)| {
+            let prefix_len = u8_at(option, 2)?;
+            Some(format!("{}/{}", ipv6_at(option, 16)?, prefix_len))
+        })
+        .collect()
+}
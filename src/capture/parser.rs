@@ -1,9 +1,21 @@
 use pnet::packet::ethernet::{EthernetPacket, EtherTypes};
-use pnet::packet::ip::{IpNextHeaderProtocols};
+use pnet::packet::ip::{IpNextHeaderProtocol, IpNextHeaderProtocols};
 use pnet::packet::ipv4::Ipv4Packet;
+use pnet::packet::ipv6::Ipv6Packet;
 use pnet::packet::tcp::TcpPacket;
 use pnet::packet::udp::UdpPacket;
 use pnet::packet::Packet;
+use pnet::util::MacAddr;
+
+use super::credentials::{self, CleartextAuth};
+use super::dcerpc::{self, DceRpcPdu};
+use super::dns::{self, DnsMessage};
+use super::icmp::{self, IcmpMessage};
+use super::ics::{self, IcsCommand};
+use super::neighbor::{self, ArpMessage, NdpMessage};
+use super::quic::{self, QuicInitial};
+use super::smb::{self, SmbMessage};
+use super::tls::{self, ClientHello};
 
 #[derive(Debug, Clone)]
 pub struct ParsedPacket {
@@ -13,6 +25,27 @@ pub struct ParsedPacket {
     pub destination_port: Option<u16>,
     pub protocol: String,
     pub size: usize,
+    /// TCP flag bits (FIN 0x01, SYN 0x02, RST 0x04, PSH 0x08, ACK 0x10, URG 0x20).
+    pub tcp_flags: Option<u8>,
+    /// Application-layer data decoded by one of the protocol dissectors.
+    pub app: Option<AppLayer>,
+    /// Raw transport payload (TCP/UDP data, or the ICMP body after its
+    /// header), for signature matching.
+    pub payload: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub enum AppLayer {
+    Smb(Vec<SmbMessage>),
+    DceRpc(DceRpcPdu),
+    Tls(ClientHello),
+    Quic(QuicInitial),
+    Cleartext(CleartextAuth),
+    Ics(Vec<IcsCommand>),
+    Arp(ArpMessage),
+    Ndp(NdpMessage),
+    Icmp(IcmpMessage),
+    Dns(DnsMessage),
 }
 
 pub fn parse_packet(packet: &[u8]) -> Option<ParsedPacket> {
@@ -21,52 +54,190 @@ pub fn parse_packet(packet: &[u8]) -> Option<ParsedPacket> {
     match ethernet.get_ethertype() {
         EtherTypes::Ipv4 => {
             let ipv4 = Ipv4Packet::new(ethernet.payload())?;
-            let source_ip = ipv4.get_source().to_string();
-            let destination_ip = ipv4.get_destination().to_string();
-            
-            match ipv4.get_next_level_protocol() {
-                IpNextHeaderProtocols::Tcp => {
-                    let tcp = TcpPacket::new(ipv4.payload())?;
-                    Some(ParsedPacket {
-                        source_ip,
-                        destination_ip,
-                        source_port: Some(tcp.get_source()),
-                        destination_port: Some(tcp.get_destination()),
-                        protocol: "TCP".to_string(),
-                        size: packet.len(),
-                    })
-                }
-                IpNextHeaderProtocols::Udp => {
-                    let udp = UdpPacket::new(ipv4.payload())?;
-                    Some(ParsedPacket {
-                        source_ip,
-                        destination_ip,
-                        source_port: Some(udp.get_source()),
-                        destination_port: Some(udp.get_destination()),
-                        protocol: "UDP".to_string(),
-                        size: packet.len(),
-                    })
-                }
-                IpNextHeaderProtocols::Icmp => {
-                    Some(ParsedPacket {
-                        source_ip,
-                        destination_ip,
-                        source_port: None,
-                        destination_port: None,
-                        protocol: "ICMP".to_string(),
-                        size: packet.len(),
-                    })
-                }
-                _ => Some(ParsedPacket {
-                    source_ip,
-                    destination_ip,
-                    source_port: None,
-                    destination_port: None,
-                    protocol: "OTHER".to_string(),
-                    size: packet.len(),
-                }),
-            }
+            parse_transport(
+                ipv4.get_source().to_string(),
+                ipv4.get_destination().to_string(),
+                ipv4.get_next_level_protocol(),
+                ipv4.payload(),
+                packet.len(),
+                ethernet.get_source(),
+            )
+        }
+        EtherTypes::Ipv6 => {
+            let ipv6 = Ipv6Packet::new(ethernet.payload())?;
+            parse_transport(
+                ipv6.get_source().to_string(),
+                ipv6.get_destination().to_string(),
+                ipv6.get_next_header(),
+                ipv6.payload(),
+                packet.len(),
+                ethernet.get_source(),
+            )
+        }
+        EtherTypes::Arp => {
+            let arp = neighbor::parse_arp(ethernet.payload())?;
+            Some(ParsedPacket {
+                source_ip: arp.sender_ip.clone(),
+                destination_ip: arp.target_ip.clone(),
+                source_port: None,
+                destination_port: None,
+                protocol: "ARP".to_string(),
+                size: packet.len(),
+                tcp_flags: None,
+                app: Some(AppLayer::Arp(arp)),
+                payload: Vec::new(),
+            })
         }
         _ => None,
     }
 }
+
+fn parse_transport(
+    source_ip: String,
+    destination_ip: String,
+    next_header: IpNextHeaderProtocol,
+    payload: &[u8],
+    size: usize,
+    ethernet_source: MacAddr,
+) -> Option<ParsedPacket> {
+    let mut tcp_flags = None;
+    let mut data = Vec::new();
+    let (source_port, destination_port, protocol, app) = match next_header {
+        IpNextHeaderProtocols::Tcp => {
+            let tcp = TcpPacket::new(payload)?;
+            tcp_flags = Some(tcp.get_flags());
+            data = tcp.payload().to_vec();
+            let app = dissect_tcp(tcp.get_source(), tcp.get_destination(), tcp.payload());
+            (Some(tcp.get_source()), Some(tcp.get_destination()), "TCP", app)
+        }
+        IpNextHeaderProtocols::Udp => {
+            let udp = UdpPacket::new(payload)?;
+            data = udp.payload().to_vec();
+            let app = dissect_udp(udp.get_source(), udp.get_destination(), udp.payload());
+            (Some(udp.get_source()), Some(udp.get_destination()), "UDP", app)
+        }
+        IpNextHeaderProtocols::Icmp => {
+            data = payload.get(8..).unwrap_or_default().to_vec();
+            let app = icmp::parse_v4(payload).map(AppLayer::Icmp);
+            (None, None, "ICMP", app)
+        }
+        IpNextHeaderProtocols::Icmpv6 => {
+            data = payload.get(8..).unwrap_or_default().to_vec();
+            let app = neighbor::parse_ndp(payload, ethernet_source)
+                .map(AppLayer::Ndp)
+                .or_else(|| icmp::parse_v6(payload).map(AppLayer::Icmp));
+            (None, None, "ICMPv6", app)
+        }
+        _ => (None, None, "OTHER", None),
+    };
+    
+    Some(ParsedPacket {
+        source_ip,
+        destination_ip,
+        source_port,
+        destination_port,
+        protocol: protocol.to_string(),
+        size,
+        tcp_flags,
+        app,
+        payload: data,
+    })
+}
+
+fn dissect_tcp(source_port: u16, destination_port: u16, payload: &[u8]) -> Option<AppLayer> {
+    if payload.is_empty() {
+        return None;
+    }
+
+    let is_port = |port: u16| source_port == port || destination_port == port;
+
+    let by_port = if is_port(53) {
+        dns::parse_tcp(payload).map(AppLayer::Dns)
+    } else if is_port(445) || is_port(139) {
+        smb::parse(payload).map(AppLayer::Smb)
+    } else if is_port(502) {
+        ics::parse_modbus(payload, destination_port == 502).map(AppLayer::Ics)
+    } else if is_port(20000) {
+        ics::parse_dnp3(payload).map(AppLayer::Ics)
+    } else {
+        None
+    };
+
+    // TLS runs on any port, so it only gets what the port's own protocol
+    // didn't decode: a Modbus transaction ID or DNS length can start with
+    // the handshake record type too.
+    by_port
+        .or_else(|| {
+            (payload[0] == 0x16)
+                .then(|| tls::parse_record(payload))
+                .flatten()
+                .map(AppLayer::Tls)
+        })
+        .or_else(|| {
+            // Endpoint mapper, or DCE-RPC on a dynamically assigned port.
+            (is_port(135) || payload.starts_with(&[5, 0]))
+                .then(|| dcerpc::parse(payload))
+                .flatten()
+                .map(AppLayer::DceRpc)
+        })
+        .or_else(|| {
+            credentials::parse(source_port, destination_port, payload).map(AppLayer::Cleartext)
+        })
+}
+
+fn dissect_udp(source_port: u16, destination_port: u16, payload: &[u8]) -> Option<AppLayer> {
+    if payload.is_empty() {
+        return None;
+    }
+
+    if destination_port == 53 || source_port == 53 {
+        dns::parse_udp(payload).map(AppLayer::Dns)
+    } else if destination_port == 443 || source_port == 443 {
+        quic::parse(payload).map(AppLayer::Quic)
+    } else if destination_port == 20000 || source_port == 20000 {
+        ics::parse_dnp3(payload).map(AppLayer::Ics)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn port_dissectors_run_before_tls() {
+        // Write Single Register, transaction ID 0x1603.
+        let modbus = [0x16, 0x03, 0, 0, 0, 6, 1, 6, 0, 10, 0, 42];
+        match dissect_tcp(49152, 502, &modbus) {
+            Some(AppLayer::Ics(commands)) => {
+                assert_eq!(commands.len(), 1);
+                assert_eq!(commands[0].function_code, 6);
+                assert_eq!(commands[0].range, Some((10, 10)));
+            }
+            other => panic!("expected Modbus, got {:?}", other),
+        }
+
+        // A long DNS over TCP message: its length prefix starts with 0x16.
+        let mut dns = vec![0x16, 0x00, 0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+        dns.extend_from_slice(b"\x07example\x03com\x00\x00\x01\x00\x01");
+        assert!(matches!(
+            dissect_tcp(49152, 53, &dns),
+            Some(AppLayer::Dns(_))
+        ));
+    }
+
+    #[test]
+    fn tls_on_an_ics_port_still_decodes() {
+        let mut body = vec![0x03, 0x03];
+        body.extend_from_slice(&[0; 32]);
+        body.extend_from_slice(&[0, 0, 2, 0x13, 0x01, 1, 0, 0, 0]);
+        let mut hello = vec![0x16, 0x03, 0x01, 0, body.len() as u8 + 4, 1, 0, 0];
+        hello.push(body.len() as u8);
+        hello.extend_from_slice(&body);
+        assert!(matches!(
+            dissect_tcp(49152, 502, &hello),
+            Some(AppLayer::Tls(_))
+        ));
+    }
+}
@@ -0,0 +1,295 @@
+//! QUIC Initial packet decryption (RFC 9001 section 5).
+//!
+//! Initial packets are protected with keys derived from the client's
+//! destination connection ID and a version-specific public salt, so a passive
+//! observer can remove the protection and read the TLS ClientHello carried in
+//! CRYPTO frames. Only QUIC v1 and draft-29 are supported.
+
+use aes::cipher::{generic_array::GenericArray, BlockEncrypt};
+use aes::Aes128;
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes128Gcm, Nonce};
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+use super::bytes::{be_u32, u8_at};
+use super::tls::{self, ClientHello, Transport};
+
+pub const VERSION_1: u32 = 0x0000_0001;
+pub const VERSION_DRAFT_29: u32 = 0xff00_001d;
+
+const INITIAL_SALT_V1: [u8; 20] = [
+    0x38, 0x76, 0x2c, 0xf7, 0xf5, 0x59, 0x34, 0xb3, 0x4d, 0x17, 0x9a, 0xe6, 0xa4, 0xc8, 0x0c, 0xad,
+    0xcc, 0xbb, 0x7f, 0x0a,
+];
+const INITIAL_SALT_DRAFT_29: [u8; 20] = [
+    0xaf, 0xbf, 0xec, 0x28, 0x99, 0x93, 0xd2, 0x4c, 0x9e, 0x97, 0x86, 0xf1, 0x9c, 0x61, 0x11, 0xe0,
+    0x43, 0x90, 0xa8, 0x99,
+];
+
+const FRAME_PADDING: u64 = 0x00;
+const FRAME_PING: u64 = 0x01;
+const FRAME_ACK: u64 = 0x02;
+const FRAME_ACK_ECN: u64 = 0x03;
+const FRAME_CRYPTO: u64 = 0x06;
+
+#[derive(Debug, Clone)]
+pub struct QuicInitial {
+    pub version: u32,
+    /// ClientHello from the CRYPTO frames, when the Initial could be
+    /// decrypted and the hello starts in this packet.
+    pub client_hello: Option<ClientHello>,
+}
+
+/// Human-readable QUIC version, used for version statistics.
+pub fn version_name(version: u32) -> String {
+    match version {
+        VERSION_1 => "v1".to_string(),
+        VERSION_DRAFT_29 => "draft-29".to_string(),
+        0x6b33_43cf => "v2".to_string(),
+        0 => "version negotiation".to_string(),
+        v if v & 0xffff_ff00 == 0xff00_0000 => format!("draft-{}", v & 0xff),
+        v => format!("0x{:08x}", v),
+    }
+}
+
+/// Parses the first packet of a UDP datagram if it is a long-header QUIC
+/// packet. Versions other than v1/draft-29 are reported without a hello.
+pub fn parse(datagram: &[u8]) -> Option<QuicInitial> {
+    let first = u8_at(datagram, 0)?;
+    // Long header form and fixed bit.
+    if first & 0xc0 != 0xc0 {
+        return None;
+    }
+    let version = be_u32(datagram, 1)?;
+
+    let salt = match version {
+        VERSION_1 => &INITIAL_SALT_V1,
+        VERSION_DRAFT_29 => &INITIAL_SALT_DRAFT_29,
+        _ => {
+            return Some(QuicInitial {
+                version,
+                client_hello: None,
+            })
+        }
+    };
+
+    // Long packet type 0 is Initial for v1 and draft-29.
+    if (first & 0x30) >> 4 != 0 {
+        return Some(QuicInitial {
+            version,
+            client_hello: None,
+        });
+    }
+
+    Some(QuicInitial {
+        version,
+        client_hello: decrypt_initial(datagram, salt)
+            .and_then(|crypto| tls::parse_handshake(&crypto, Transport::Quic)),
+    })
+}
+
+/// Removes header and packet protection and returns the in-order CRYPTO
+/// stream bytes starting at offset 0.
+fn decrypt_initial(packet: &[u8], salt: &[u8]) -> Option<Vec<u8>> {
+    let mut offset = 5;
+    let dcid_len = u8_at(packet, offset)? as usize;
+    let dcid = packet.get(offset + 1..offset + 1 + dcid_len)?;
+    offset += 1 + dcid_len;
+    let scid_len = u8_at(packet, offset)? as usize;
+    offset += 1 + scid_len;
+    let (token_len, n) = varint(packet.get(offset..)?)?;
+    offset += n + token_len as usize;
+    let (length, n) = varint(packet.get(offset..)?)?;
+    offset += n;
+    let pn_offset = offset;
+    let packet_end = pn_offset.checked_add(length as usize)?;
+    // The Length covers the packet number and payload, so it must hold the
+    // header protection sample, which starts 4 bytes past the PN start and
+    // thereby also the longest packet number.
+    if packet_end > packet.len() || pn_offset + 20 > packet_end {
+        return None;
+    }
+
+    let (key, iv, hp) = client_initial_keys(salt, dcid)?;
+
+    // Header protection: mask from a 16-byte sample 4 bytes past the PN start.
+    let sample = packet.get(pn_offset + 4..pn_offset + 20)?;
+    let hp_cipher = Aes128::new(GenericArray::from_slice(&hp));
+    let mut mask = GenericArray::clone_from_slice(sample);
+    hp_cipher.encrypt_block(&mut mask);
+
+    let mut header = packet[..pn_offset].to_vec();
+    header[0] ^= mask[0] & 0x0f;
+    let pn_len = (header[0] & 0x03) as usize + 1;
+    if pn_offset + pn_len > packet_end {
+        return None;
+    }
+    let mut packet_number = 0u64;
+    for i in 0..pn_len {
+        let byte = packet.get(pn_offset + i)? ^ mask[1 + i];
+        header.push(byte);
+        packet_number = (packet_number << 8) | byte as u64;
+    }
+
+    let mut nonce = iv;
+    for (i, byte) in packet_number.to_be_bytes().iter().enumerate() {
+        nonce[4 + i] ^= byte;
+    }
+
+    let cipher = Aes128Gcm::new_from_slice(&key).ok()?;
+    let plaintext = cipher
+        .decrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: &packet[pn_offset + pn_len..packet_end],
+                aad: &header,
+            },
+        )
+        .ok()?;
+
+    crypto_stream(&plaintext)
+}
+
+fn client_initial_keys(salt: &[u8], dcid: &[u8]) -> Option<([u8; 16], [u8; 12], [u8; 16])> {
+    let (initial_secret, _) = Hkdf::<Sha256>::extract(Some(salt), dcid);
+    let mut client_secret = [0u8; 32];
+    expand_label(&initial_secret, b"client in", &mut client_secret)?;
+
+    let mut key = [0u8; 16];
+    let mut iv = [0u8; 12];
+    let mut hp = [0u8; 16];
+    expand_label(&client_secret, b"quic key", &mut key)?;
+    expand_label(&client_secret, b"quic iv", &mut iv)?;
+    expand_label(&client_secret, b"quic hp", &mut hp)?;
+    Some((key, iv, hp))
+}
+
+/// HKDF-Expand-Label from TLS 1.3 with an empty context.
+fn expand_label(secret: &[u8], label: &[u8], out: &mut [u8]) -> Option<()> {
+    let hkdf = Hkdf::<Sha256>::from_prk(secret).ok()?;
+    let mut info = Vec::with_capacity(4 + 6 + label.len());
+    info.extend_from_slice(&(out.len() as u16).to_be_bytes());
+    info.push((6 + label.len()) as u8);
+    info.extend_from_slice(b"tls13 ");
+    info.extend_from_slice(label);
+    info.push(0);
+    hkdf.expand(&info, out).ok()
+}
+
+/// Collects CRYPTO frame data into a contiguous stream from offset 0.
+/// Clients may split and reorder the ClientHello across frames.
+fn crypto_stream(payload: &[u8]) -> Option<Vec<u8>> {
+    let mut chunks: Vec<(u64, &[u8])> = Vec::new();
+    let mut offset = 0;
+
+    while offset < payload.len() {
+        let (frame_type, n) = varint(&payload[offset..])?;
+        offset += n;
+        match frame_type {
+            FRAME_PADDING | FRAME_PING => {}
+            FRAME_ACK | FRAME_ACK_ECN => {
+                // Largest acknowledged, delay, range count, first range.
+                let mut fields = [0u64; 4];
+                for field in fields.iter_mut() {
+                    let (value, n) = varint(payload.get(offset..)?)?;
+                    *field = value;
+                    offset += n;
+                }
+                let extra =
+                    fields[2] as usize * 2 + if frame_type == FRAME_ACK_ECN { 3 } else { 0 };
+                for _ in 0..extra {
+                    let (_, n) = varint(payload.get(offset..)?)?;
+                    offset += n;
+                }
+            }
+            FRAME_CRYPTO => {
+                let (data_offset, n) = varint(payload.get(offset..)?)?;
+                offset += n;
+                let (len, n) = varint(payload.get(offset..)?)?;
+                offset += n;
+                let data = payload.get(offset..offset + len as usize)?;
+                chunks.push((data_offset, data));
+                offset += len as usize;
+            }
+            _ => break,
+        }
+    }
+
+    chunks.sort_by_key(|(data_offset, _)| *data_offset);
+    let mut stream = Vec::new();
+    for (data_offset, data) in chunks {
+        let data_offset = data_offset as usize;
+        if data_offset > stream.len() {
+            break;
+        }
+        let skip = stream.len() - data_offset;
+        if skip < data.len() {
+            stream.extend_from_slice(&data[skip..]);
+        }
+    }
+
+    if stream.is_empty() {
+        None
+    } else {
+        Some(stream)
+    }
+}
+
+/// Decodes a QUIC variable-length integer, returning the value and its size.
+fn varint(data: &[u8]) -> Option<(u64, usize)> {
+    let first = *data.first()?;
+    let len = 1usize << (first >> 6);
+    let bytes = data.get(..len)?;
+    let mut value = (first & 0x3f) as u64;
+    for byte in &bytes[1..] {
+        value = (value << 8) | *byte as u64;
+    }
+    Some((value, len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DCID: [u8; 8] = [0x83, 0x94, 0xc8, 0xf0, 0x3e, 0x51, 0x57, 0x08];
+
+    /// An Initial packet header up to (not including) the Length field.
+    fn initial_header() -> Vec<u8> {
+        let mut packet = vec![0xc3];
+        packet.extend_from_slice(&VERSION_1.to_be_bytes());
+        packet.push(DCID.len() as u8);
+        packet.extend_from_slice(&DCID);
+        packet.push(0); // SCID length
+        packet.push(0); // token length
+        packet
+    }
+
+    #[test]
+    fn initial_keys_match_rfc9001() {
+        let (key, iv, hp) = client_initial_keys(&INITIAL_SALT_V1, &DCID).unwrap();
+        assert_eq!(hex::encode(key), "1f369613dd76d5467730efcbe3b1a22d");
+        assert_eq!(hex::encode(iv), "fa044b2f42a3fd3b46fb255c");
+        assert_eq!(hex::encode(hp), "9f50449e04a0e810283a1e9933adedd2");
+    }
+
+    #[test]
+    fn length_shorter_than_packet_number_is_rejected() {
+        for length in 0..24u8 {
+            let mut packet = initial_header();
+            packet.push(length);
+            packet.resize(1200, 0x5a);
+            let initial = parse(&packet).unwrap();
+            assert_eq!(initial.version, VERSION_1);
+            assert!(initial.client_hello.is_none());
+        }
+    }
+
+    #[test]
+    fn varints() {
+        assert_eq!(varint(&[0x25]), Some((37, 1)));
+        assert_eq!(varint(&[0x7b, 0xbd]), Some((15293, 2)));
+        assert_eq!(varint(&[0x9d, 0x7f, 0x3e, 0x7d]), Some((494878333, 4)));
+        assert_eq!(varint(&[0x7b]), None);
+    }
+}
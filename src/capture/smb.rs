@@ -0,0 +1,227 @@
+//! SMB2/3 dissector.
+//!
+//! Extracts the pieces that matter for lateral-movement visibility: tree
+//! connects (share names), file and named-pipe creates with their requested
+//! access, writes, and DCE-RPC PDUs carried over named pipes. Only messages
+//! that start within a single TCP segment are decoded.
+
+use super::bytes::{le_u16, le_u32, le_u64, utf16le_string};
+use super::dcerpc::{self, DceRpcPdu};
+
+const SMB2_HEADER_LEN: usize = 64;
+const SMB2_FLAGS_SERVER_TO_REDIR: u32 = 0x0000_0001;
+
+/// Access bits that allow modifying a file (FILE_WRITE_DATA, FILE_APPEND_DATA,
+/// WRITE_DAC, WRITE_OWNER, GENERIC_ALL, GENERIC_WRITE, DELETE).
+const WRITE_ACCESS_MASK: u32 =
+    0x0000_0002 | 0x0000_0004 | 0x0001_0000 | 0x0004_0000 | 0x0008_0000 | 0x1000_0000 | 0x4000_0000;
+
+const FSCTL_PIPE_TRANSCEIVE: u32 = 0x0011_C017;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmbCommand {
+    Negotiate,
+    SessionSetup,
+    TreeConnect,
+    TreeDisconnect,
+    Create,
+    Close,
+    Read,
+    Write,
+    Ioctl,
+    Other(u16),
+    /// SMB3 transform header; the payload is encrypted and opaque to us.
+    Encrypted,
+}
+
+impl SmbCommand {
+    fn from_code(code: u16) -> Self {
+        match code {
+            0x0000 => SmbCommand::Negotiate,
+            0x0001 => SmbCommand::SessionSetup,
+            0x0003 => SmbCommand::TreeConnect,
+            0x0004 => SmbCommand::TreeDisconnect,
+            0x0005 => SmbCommand::Create,
+            0x0006 => SmbCommand::Close,
+            0x0008 => SmbCommand::Read,
+            0x0009 => SmbCommand::Write,
+            0x000B => SmbCommand::Ioctl,
+            other => SmbCommand::Other(other),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SmbMessage {
+    pub command: SmbCommand,
+    pub is_response: bool,
+    pub status: u32,
+    pub message_id: u64,
+    pub session_id: u64,
+    pub tree_id: u32,
+    /// UNC path from a tree connect request, e.g. `\\host\ADMIN$`.
+    pub tree_path: Option<String>,
+    /// File or pipe name from a create request.
+    pub file_name: Option<String>,
+    pub desired_access: u32,
+    /// File ID from create responses and write/ioctl/close requests.
+    pub file_id: Option<u128>,
+    /// DCE-RPC PDU carried in a pipe write, read or transceive.
+    pub rpc: Option<DceRpcPdu>,
+}
+
+impl SmbMessage {
+    fn encrypted() -> Self {
+        Self {
+            command: SmbCommand::Encrypted,
+            is_response: false,
+            status: 0,
+            message_id: 0,
+            session_id: 0,
+            tree_id: 0,
+            tree_path: None,
+            file_name: None,
+            desired_access: 0,
+            file_id: None,
+            rpc: None,
+        }
+    }
+
+    pub fn requests_write_access(&self) -> bool {
+        self.desired_access & WRITE_ACCESS_MASK != 0
+    }
+}
+
+/// Parses every SMB2 message in a TCP payload (NetBIOS session framing,
+/// compound chains included).
+pub fn parse(payload: &[u8]) -> Option<Vec<SmbMessage>> {
+    let mut messages = Vec::new();
+    let mut offset = 0;
+
+    while offset + 4 <= payload.len() {
+        // NetBIOS session message: type 0x00 then 24-bit big-endian length.
+        if payload[offset] != 0x00 {
+            break;
+        }
+        let length = ((payload[offset + 1] as usize) << 16)
+            | ((payload[offset + 2] as usize) << 8)
+            | payload[offset + 3] as usize;
+        let start = offset + 4;
+        let end = (start + length).min(payload.len());
+        parse_frame(&payload[start..end], &mut messages);
+        offset = start + length;
+    }
+
+    if messages.is_empty() {
+        None
+    } else {
+        Some(messages)
+    }
+}
+
+fn parse_frame(frame: &[u8], messages: &mut Vec<SmbMessage>) {
+    if frame.starts_with(b"\xfdSMB") {
+        messages.push(SmbMessage::encrypted());
+        return;
+    }
+
+    let mut offset = 0;
+    while let Some(header) = frame.get(offset..) {
+        if !header.starts_with(b"\xfeSMB") {
+            break;
+        }
+        let next_command = le_u32(header, 20).unwrap_or(0) as usize;
+        let message_len = if next_command == 0 {
+            header.len()
+        } else {
+            next_command.min(header.len())
+        };
+        match parse_message(&header[..message_len]) {
+            Some(message) => messages.push(message),
+            None => break,
+        }
+        if next_command == 0 {
+            break;
+        }
+        offset += next_command;
+    }
+}
+
+fn parse_message(msg: &[u8]) -> Option<SmbMessage> {
+    if msg.len() < SMB2_HEADER_LEN {
+        return None;
+    }
+
+    let status = le_u32(msg, 8)?;
+    let command = SmbCommand::from_code(le_u16(msg, 12)?);
+    let flags = le_u32(msg, 16)?;
+    let is_response = flags & SMB2_FLAGS_SERVER_TO_REDIR != 0;
+
+    let mut message = SmbMessage {
+        command,
+        is_response,
+        status,
+        message_id: le_u64(msg, 24)?,
+        tree_id: le_u32(msg, 36)?,
+        session_id: le_u64(msg, 40)?,
+        tree_path: None,
+        file_name: None,
+        desired_access: 0,
+        file_id: None,
+        rpc: None,
+    };
+
+    let body = SMB2_HEADER_LEN;
+    match (command, is_response) {
+        (SmbCommand::TreeConnect, false) => {
+            let path_offset = le_u16(msg, body + 4)? as usize;
+            let path_len = le_u16(msg, body + 6)? as usize;
+            message.tree_path = utf16le_string(msg, path_offset, path_len);
+        }
+        (SmbCommand::Create, false) => {
+            message.desired_access = le_u32(msg, body + 24)?;
+            let name_offset = le_u16(msg, body + 44)? as usize;
+            let name_len = le_u16(msg, body + 46)? as usize;
+            message.file_name = utf16le_string(msg, name_offset, name_len);
+        }
+        (SmbCommand::Create, true) if status == 0 => {
+            message.file_id = file_id_at(msg, body + 64);
+        }
+        (SmbCommand::Close, false) => {
+            message.file_id = file_id_at(msg, body + 8);
+        }
+        (SmbCommand::Write, false) => {
+            let data_offset = le_u16(msg, body + 2)? as usize;
+            let data_len = le_u32(msg, body + 4)? as usize;
+            message.file_id = file_id_at(msg, body + 16);
+            message.rpc = rpc_at(msg, data_offset, data_len);
+        }
+        (SmbCommand::Read, true) => {
+            let data_offset = msg.get(body + 2).copied()? as usize;
+            let data_len = le_u32(msg, body + 4)? as usize;
+            message.rpc = rpc_at(msg, data_offset, data_len);
+        }
+        (SmbCommand::Ioctl, false) => {
+            message.file_id = file_id_at(msg, body + 8);
+            if le_u32(msg, body + 4)? == FSCTL_PIPE_TRANSCEIVE {
+                let input_offset = le_u32(msg, body + 24)? as usize;
+                let input_len = le_u32(msg, body + 28)? as usize;
+                message.rpc = rpc_at(msg, input_offset, input_len);
+            }
+        }
+        _ => {}
+    }
+
+    Some(message)
+}
+
+fn file_id_at(msg: &[u8], offset: usize) -> Option<u128> {
+    let persistent = le_u64(msg, offset)? as u128;
+    let volatile = le_u64(msg, offset + 8)? as u128;
+    Some((volatile << 64) | persistent)
+}
+
+fn rpc_at(msg: &[u8], offset: usize, len: usize) -> Option<DceRpcPdu> {
+    let end = offset.checked_add(len)?.min(msg.len());
+    dcerpc::parse(msg.get(offset..end)?)
+}
@@ -0,0 +1,302 @@
+//! TLS ClientHello parsing and JA3/JA4 fingerprinting.
+//!
+//! Shared by the TCP path (a handshake record at the start of a segment) and
+//! the QUIC path (the CRYPTO stream of a decrypted Initial packet).
+
+use md5::Md5;
+use sha2::{Digest, Sha256};
+
+use super::bytes::{be_u16, u8_at};
+
+const HANDSHAKE_CLIENT_HELLO: u8 = 1;
+const EXT_SERVER_NAME: u16 = 0x0000;
+const EXT_SUPPORTED_GROUPS: u16 = 0x000a;
+const EXT_EC_POINT_FORMATS: u16 = 0x000b;
+const EXT_SIGNATURE_ALGORITHMS: u16 = 0x000d;
+const EXT_ALPN: u16 = 0x0010;
+const EXT_SUPPORTED_VERSIONS: u16 = 0x002b;
+
+/// Transport the ClientHello was carried over; the first character of JA4.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    Tcp,
+    Quic,
+}
+
+#[derive(Debug, Clone)]
+pub struct ClientHello {
+    pub server_name: Option<String>,
+    pub alpn: Vec<String>,
+    /// JA4 fingerprint; `None` when the hello was truncated and the
+    /// cipher/extension lists are incomplete.
+    pub ja4: Option<String>,
+    /// JA3 fingerprint (MD5 of the JA3 string); `None` when truncated.
+    pub ja3: Option<String>,
+}
+
+/// Parses a TLS record carrying a ClientHello from the start of a TCP payload.
+pub fn parse_record(payload: &[u8]) -> Option<ClientHello> {
+    // Handshake record, TLS 1.0-1.3 record version.
+    if u8_at(payload, 0)? != 0x16 || u8_at(payload, 1)? != 0x03 {
+        return None;
+    }
+    parse_handshake(payload.get(5..)?, Transport::Tcp)
+}
+
+/// Parses a handshake message (type, 24-bit length, body) holding a ClientHello.
+pub fn parse_handshake(data: &[u8], transport: Transport) -> Option<ClientHello> {
+    if u8_at(data, 0)? != HANDSHAKE_CLIENT_HELLO {
+        return None;
+    }
+    let length = ((u8_at(data, 1)? as usize) << 16)
+        | ((u8_at(data, 2)? as usize) << 8)
+        | u8_at(data, 3)? as usize;
+    let body = data.get(4..)?;
+    let complete = body.len() >= length;
+    let body = &body[..length.min(body.len())];
+
+    let legacy_version = be_u16(body, 0)?;
+    let mut offset = 2 + 32;
+    let session_id_len = u8_at(body, offset)? as usize;
+    offset += 1 + session_id_len;
+
+    let ciphers_len = be_u16(body, offset)? as usize;
+    offset += 2;
+    let ciphers: Vec<u16> = (0..ciphers_len / 2)
+        .filter_map(|i| be_u16(body, offset + i * 2))
+        .collect();
+    offset += ciphers_len;
+
+    let compression_len = u8_at(body, offset)? as usize;
+    offset += 1 + compression_len;
+
+    let mut hello = ClientHello {
+        server_name: None,
+        alpn: Vec::new(),
+        ja4: None,
+        ja3: None,
+    };
+    let mut extensions = Vec::new();
+    let mut groups = Vec::new();
+    let mut point_formats = Vec::new();
+    let mut signature_algorithms = Vec::new();
+    let mut supported_versions = Vec::new();
+    let mut truncated = !complete;
+
+    let extensions_end = be_u16(body, offset)
+        .map(|len| offset + 2 + len as usize)
+        .unwrap_or(offset);
+    offset += 2;
+
+    while offset + 4 <= extensions_end {
+        let (Some(ext_type), Some(ext_len)) = (be_u16(body, offset), be_u16(body, offset + 2))
+        else {
+            truncated = true;
+            break;
+        };
+        let Some(ext) = body.get(offset + 4..offset + 4 + ext_len as usize) else {
+            truncated = true;
+            break;
+        };
+        extensions.push(ext_type);
+
+        match ext_type {
+            EXT_SERVER_NAME => hello.server_name = parse_server_name(ext),
+            EXT_ALPN => hello.alpn = parse_alpn(ext),
+            EXT_SIGNATURE_ALGORITHMS => signature_algorithms = u16_list(ext.get(2..)?),
+            EXT_SUPPORTED_VERSIONS => supported_versions = u16_list(ext.get(1..)?),
+            EXT_SUPPORTED_GROUPS => groups = u16_list(ext.get(2..)?),
+            EXT_EC_POINT_FORMATS => point_formats = ext.get(1..)?.to_vec(),
+            _ => {}
+        }
+        offset += 4 + ext_len as usize;
+    }
+    if extensions_end > body.len() {
+        truncated = true;
+    }
+
+    if !truncated {
+        let version = supported_versions
+            .iter()
+            .copied()
+            .filter(|v| !is_grease(*v))
+            .max()
+            .unwrap_or(legacy_version);
+        hello.ja4 = Some(ja4(
+            transport,
+            version,
+            hello.server_name.is_some(),
+            &ciphers,
+            &extensions,
+            hello.alpn.first().map(String::as_str),
+            &signature_algorithms,
+        ));
+        hello.ja3 = Some(ja3(
+            legacy_version,
+            &ciphers,
+            &extensions,
+            &groups,
+            &point_formats,
+        ));
+    }
+
+    Some(hello)
+}
+
+fn parse_server_name(ext: &[u8]) -> Option<String> {
+    // server_name_list length, then name_type (0 = host_name) and name.
+    if u8_at(ext, 2)? != 0 {
+        return None;
+    }
+    let len = be_u16(ext, 3)? as usize;
+    let name = ext.get(5..5 + len)?;
+    Some(String::from_utf8_lossy(name).into_owned())
+}
+
+fn parse_alpn(ext: &[u8]) -> Vec<String> {
+    let mut protocols = Vec::new();
+    let mut offset = 2;
+    while let Some(len) = u8_at(ext, offset) {
+        let Some(proto) = ext.get(offset + 1..offset + 1 + len as usize) else {
+            break;
+        };
+        protocols.push(String::from_utf8_lossy(proto).into_owned());
+        offset += 1 + len as usize;
+    }
+    protocols
+}
+
+fn u16_list(data: &[u8]) -> Vec<u16> {
+    data.chunks_exact(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+        .collect()
+}
+
+/// GREASE values (RFC 8701) are ignored by JA4.
+fn is_grease(value: u16) -> bool {
+    value & 0x0f0f == 0x0a0a && value >> 8 == value & 0xff
+}
+
+fn ja4(
+    transport: Transport,
+    version: u16,
+    has_sni: bool,
+    ciphers: &[u16],
+    extensions: &[u16],
+    alpn: Option<&str>,
+    signature_algorithms: &[u16],
+) -> String {
+    let protocol = match transport {
+        Transport::Tcp => 't',
+        Transport::Quic => 'q',
+    };
+    let version = match version {
+        0x0304 => "13",
+        0x0303 => "12",
+        0x0302 => "11",
+        0x0301 => "10",
+        0x0300 => "s3",
+        _ => "00",
+    };
+    let sni = if has_sni { 'd' } else { 'i' };
+
+    let mut ciphers: Vec<u16> = ciphers.iter().copied().filter(|c| !is_grease(*c)).collect();
+    let extension_count = extensions.iter().filter(|e| !is_grease(**e)).count();
+    let mut hashed_extensions: Vec<u16> = extensions
+        .iter()
+        .copied()
+        .filter(|e| !is_grease(*e) && *e != EXT_SERVER_NAME && *e != EXT_ALPN)
+        .collect();
+
+    let alpn = match alpn.map(str::as_bytes) {
+        Some([first, .., last])
+            if first.is_ascii_alphanumeric() && last.is_ascii_alphanumeric() =>
+        {
+            format!("{}{}", *first as char, *last as char)
+        }
+        Some([only]) if only.is_ascii_alphanumeric() => {
+            format!("{}{}", *only as char, *only as char)
+        }
+        Some(bytes) if !bytes.is_empty() => {
+            let hex = hex::encode(bytes);
+            format!("{}{}", &hex[..1], &hex[hex.len() - 1..])
+        }
+        _ => "00".to_string(),
+    };
+
+    let part_a = format!(
+        "{}{}{}{:02}{:02}{}",
+        protocol,
+        version,
+        sni,
+        ciphers.len().min(99),
+        extension_count.min(99),
+        alpn
+    );
+
+    ciphers.sort_unstable();
+    let part_b = truncated_hash(&hex_list(&ciphers));
+
+    hashed_extensions.sort_unstable();
+    let mut extension_string = hex_list(&hashed_extensions);
+    if !signature_algorithms.is_empty() {
+        extension_string.push('_');
+        extension_string.push_str(&hex_list(signature_algorithms));
+    }
+    let part_c = if hashed_extensions.is_empty() {
+        "000000000000".to_string()
+    } else {
+        truncated_hash(&extension_string)
+    };
+
+    format!("{}_{}_{}", part_a, part_b, part_c)
+}
+
+/// `version,ciphers,extensions,groups,point_formats` in decimal and wire
+/// order, GREASE removed, hashed with MD5.
+fn ja3(
+    version: u16,
+    ciphers: &[u16],
+    extensions: &[u16],
+    groups: &[u16],
+    point_formats: &[u8],
+) -> String {
+    let decimal_list = |values: &[u16]| {
+        values
+            .iter()
+            .filter(|v| !is_grease(**v))
+            .map(u16::to_string)
+            .collect::<Vec<_>>()
+            .join("-")
+    };
+    let point_formats = point_formats
+        .iter()
+        .map(u8::to_string)
+        .collect::<Vec<_>>()
+        .join("-");
+    let input = format!(
+        "{},{},{},{},{}",
+        version,
+        decimal_list(ciphers),
+        decimal_list(extensions),
+        decimal_list(groups),
+        point_formats
+    );
+    hex::encode(Md5::digest(input.as_bytes()))
+}
+
+fn hex_list(values: &[u16]) -> String {
+    values
+        .iter()
+        .map(|v| format!("{:04x}", v))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn truncated_hash(input: &str) -> String {
+    if input.is_empty() {
+        return "000000000000".to_string();
+    }
+    let digest = Sha256::digest(input.as_bytes());
+    hex::encode(digest)[..12].to_string()
+}
@@ -1,3 +1,4 @@
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, Utc};
 use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 
@@ -66,6 +67,11 @@ pub enum Commands {
         #[arg(short, long)]
         severity: Option<String>,
         
+        /// Filter by MITRE ATT&CK technique ID (e.g. T1046), including
+        /// its sub-techniques
+        #[arg(short, long)]
+        technique: Option<String>,
+        
         /// Export to JSON file
         #[arg(short, long)]
         export: Option<PathBuf>,
@@ -74,6 +80,71 @@ pub enum Commands {
         #[arg(short, long, default_value = "100")]
         limit: usize,
     },
+    
+    /// Rank hosts by risk score
+    Hosts {
+        /// Database path
+        #[arg(short, long)]
+        db_path: PathBuf,
+        
+        /// Number of hosts to show
+        #[arg(short, long, default_value = "10")]
+        top: usize,
+        
+        /// Configuration file path, for the score half-life
+        #[arg(short, long)]
+        config_file: Option<PathBuf>,
+    },
+    
+    /// Export observed MITRE ATT&CK techniques as a Navigator layer
+    AttackLayer {
+        /// Database path
+        #[arg(short, long)]
+        db_path: PathBuf,
+        
+        /// Start of the range: RFC 3339 time, date, or age like 24h or 7d
+        #[arg(short, long)]
+        from: Option<String>,
+        
+        /// End of the range: RFC 3339 time, date (inclusive), or age
+        #[arg(short, long)]
+        to: Option<String>,
+        
+        /// Layer JSON file to write; ICS techniques go to a second file
+        /// with -ics added to the name
+        #[arg(short, long, default_value = "netguard-layer.json")]
+        output: PathBuf,
+    },
+}
+
+/// Parses a time argument: an RFC 3339 time, a `YYYY-MM-DD` date (its
+/// start, or with `end_of_day` the start of the next day), or an age
+/// before now in hours (`24h`) or days (`7d`).
+pub fn parse_time(text: &str, end_of_day: bool) -> anyhow::Result<DateTime<Utc>> {
+    let text = text.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        let date = if end_of_day {
+            date.succ_opt().unwrap_or(date)
+        } else {
+            date
+        };
+        return Ok(date.and_time(NaiveTime::MIN).and_utc());
+    }
+    let age = |digits: &str| digits.parse::<i64>().ok();
+    let duration = if let Some(hours) = text.strip_suffix('h').and_then(age) {
+        Duration::hours(hours)
+    } else if let Some(days) = text.strip_suffix('d').and_then(age) {
+        Duration::days(days)
+    } else {
+        anyhow::bail!(
+            "invalid time '{}': expected RFC 3339, YYYY-MM-DD, or an age like 24h or 7d",
+            text
+        );
+    };
+    Ok(Utc::now() - duration)
 }
 
 #[derive(Subcommand)]
@@ -1,7 +1,10 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::Path;
 
+use crate::networks::{default_groups, Direction};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub interfaces: Vec<String>,
@@ -15,7 +18,278 @@ pub struct Config {
 pub struct DetectionConfig {
     pub port_scan: PortScanConfig,
     pub ddos: DdosConfig,
+    #[serde(default)]
+    pub syn_flood: SynFloodConfig,
+    #[serde(default)]
+    pub volumetric: VolumetricConfig,
     pub suspicious_ports: Vec<u16>,
+    #[serde(default)]
+    pub beacon: BeaconConfig,
+    #[serde(default)]
+    pub dns_tunneling: DnsTunnelingConfig,
+    #[serde(default)]
+    pub dga: DgaConfig,
+    #[serde(default)]
+    pub exfiltration: ExfiltrationConfig,
+    #[serde(default)]
+    pub rules: RulesConfig,
+    #[serde(default)]
+    pub sigma: SigmaConfig,
+    #[serde(default)]
+    pub intel: IntelConfig,
+    #[serde(default)]
+    pub smb: SmbConfig,
+    #[serde(default)]
+    pub cleartext_credentials: CleartextCredentialsConfig,
+    #[serde(default)]
+    pub ics: IcsConfig,
+    #[serde(default)]
+    pub spoofing: SpoofingConfig,
+    /// Named network groups (`HOME_NET`, `DMZ`, `EXTERNAL_NET`, ...), also
+    /// available to signature rules as address variables. `HOME_NET` and
+    /// `DMZ` decide whether traffic is inbound, outbound or internal.
+    /// `HOME_NET` (private and local ranges) and `EXTERNAL_NET`
+    /// (`!$HOME_NET`) keep their defaults unless redefined here.
+    #[serde(default = "default_groups")]
+    pub networks: HashMap<String, Vec<String>>,
+    /// Steps to raise (positive) or lower (negative) alert severities by,
+    /// per traffic direction. Detectors that already rate by direction, such
+    /// as the suspicious port check, are left alone.
+    #[serde(default)]
+    pub direction_severity: HashMap<Direction, i8>,
+    /// Exceptions: traffic that some or all detectors never see.
+    #[serde(default)]
+    pub allowlist: Vec<AllowlistEntry>,
+    #[serde(default)]
+    pub risk: RiskConfig,
+    #[serde(default)]
+    pub correlation: CorrelationConfig,
+    /// Detectors to run first, in this order; the rest follow in their
+    /// default order.
+    #[serde(default)]
+    pub order: Vec<String>,
+    /// Detectors to skip entirely.
+    #[serde(default)]
+    pub disabled: Vec<String>,
+    /// Config sections for detectors registered by other crates, keyed by
+    /// detector name.
+    #[serde(default)]
+    pub plugins: HashMap<String, serde_yaml::Value>,
+    /// Seconds of inactivity before a flow is closed and handed to the
+    /// detectors' flow hooks.
+    #[serde(default = "default_flow_timeout")]
+    pub flow_timeout_seconds: u64,
+    #[serde(default)]
+    pub aggregation: AggregationConfig,
+    #[serde(default)]
+    pub geoip: GeoIpConfig,
+}
+
+fn default_flow_timeout() -> u64 {
+    60
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AggregationConfig {
+    pub enabled: bool,
+    /// Repeats of the same alert (type, source, destination) within this
+    /// many seconds are suppressed and reported as one "still ongoing"
+    /// update per window.
+    pub suppression_seconds: u64,
+    /// Per alert type overrides of `suppression_seconds`.
+    #[serde(default)]
+    pub windows: HashMap<String, u64>,
+}
+
+impl Default for AggregationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            suppression_seconds: 60,
+            windows: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RiskConfig {
+    pub enabled: bool,
+    /// Hours for a host's score to halve without new alerts.
+    pub half_life_hours: f64,
+    /// Score at which a host is escalated.
+    pub threshold: f64,
+    /// Points per alert by severity.
+    pub severity_points: HashMap<String, f64>,
+    /// Multipliers by alert type; 1 for types not listed.
+    #[serde(default)]
+    pub type_weights: HashMap<String, f64>,
+    /// Multipliers by traffic direction; 1 for directions not listed.
+    #[serde(default)]
+    pub direction_weights: HashMap<Direction, f64>,
+    /// Multiplier for threat-intel matches.
+    pub intel_weight: f64,
+}
+
+impl Default for RiskConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            half_life_hours: 6.0,
+            threshold: 100.0,
+            severity_points: [("critical", 40.0), ("high", 20.0), ("medium", 8.0), ("low", 2.0)]
+                .into_iter()
+                .map(|(severity, points)| (severity.to_string(), points))
+                .collect(),
+            type_weights: HashMap::new(),
+            direction_weights: HashMap::from([
+                (Direction::Outbound, 1.5),
+                (Direction::Internal, 1.25),
+                (Direction::External, 0.5),
+            ]),
+            intel_weight: 2.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CorrelationConfig {
+    pub enabled: bool,
+    pub rules: Vec<CorrelationRule>,
+}
+
+impl Default for CorrelationConfig {
+    fn default() -> Self {
+        let stage = |alert_types: &[&str], host: StageHost| CorrelationStage {
+            alert_types: alert_types.iter().map(|t| t.to_string()).collect(),
+            min_severity: None,
+            host,
+        };
+        Self {
+            enabled: true,
+            rules: vec![
+                CorrelationRule {
+                    name: "Scan, Exploit and Beaconing".to_string(),
+                    severity: "critical".to_string(),
+                    window_seconds: 6 * 3600,
+                    key: CorrelationKey::Host,
+                    stages: vec![
+                        stage(&["Port Scan"], StageHost::Destination),
+                        stage(
+                            &["*exploit*", "SMB Admin Share Write", "Remote Service Creation"],
+                            StageHost::Destination,
+                        ),
+                        stage(
+                            &["Beaconing", "Data Exfiltration", "Threat Intel Match"],
+                            StageHost::Source,
+                        ),
+                    ],
+                },
+                CorrelationRule {
+                    name: "Remote Service Installation".to_string(),
+                    severity: "critical".to_string(),
+                    window_seconds: 600,
+                    key: CorrelationKey::Pair,
+                    stages: vec![
+                        stage(&["SMB Admin Share Write"], StageHost::Source),
+                        stage(&["Remote Service Creation"], StageHost::Source),
+                    ],
+                },
+            ],
+        }
+    }
+}
+
+/// Alerts matching each stage in turn, within `window_seconds` of the
+/// first, raise an incident alert linking them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CorrelationRule {
+    /// Also the incident's alert type.
+    pub name: String,
+    #[serde(default = "default_incident_severity")]
+    pub severity: String,
+    pub window_seconds: i64,
+    /// What the stages' alerts must have in common.
+    #[serde(default)]
+    pub key: CorrelationKey,
+    pub stages: Vec<CorrelationStage>,
+}
+
+fn default_incident_severity() -> String {
+    "critical".to_string()
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CorrelationKey {
+    /// The same host, taken from the end each stage names.
+    Host,
+    /// The same pair of hosts, the end each stage names first.
+    #[default]
+    Pair,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CorrelationStage {
+    /// Alert types, any case; `*` at either end matches any text. Any
+    /// type when empty.
+    #[serde(default)]
+    pub alert_types: Vec<String>,
+    #[serde(default)]
+    pub min_severity: Option<String>,
+    /// Which end of the alert is the correlated host.
+    #[serde(default)]
+    pub host: StageHost,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StageHost {
+    #[default]
+    Source,
+    Destination,
+}
+
+/// Traffic matching every condition set here is hidden from the listed
+/// detectors. Each suppressed packet or flow counts as a hit against the
+/// entry, for auditing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AllowlistEntry {
+    /// Why the exception exists.
+    pub reason: String,
+    /// Detectors the exception applies to; all of them when empty.
+    #[serde(default)]
+    pub detectors: Vec<String>,
+    /// Addresses or CIDRs, matching either end of the traffic.
+    #[serde(default)]
+    pub addresses: Vec<String>,
+    /// Ports, matching either end of the traffic.
+    #[serde(default)]
+    pub ports: Vec<u16>,
+    /// Protocol names as parsed (`TCP`, `UDP`, `ICMP`, ...), any case.
+    #[serde(default)]
+    pub protocols: Vec<String>,
+    /// Capture interfaces.
+    #[serde(default)]
+    pub interfaces: Vec<String>,
+    /// Local time-of-day window such as `22:00-06:00`; may wrap midnight.
+    #[serde(default)]
+    pub hours: Option<String>,
+    /// RFC 3339 time, or a date the exception lasts through.
+    #[serde(default)]
+    pub expires: Option<String>,
+}
+
+/// Local MaxMind databases (GeoLite2 or GeoIP2 format) used to enrich
+/// alerts and flows and to match signature and Sigma rules on country or
+/// ASN.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GeoIpConfig {
+    /// A City or Country database.
+    #[serde(default)]
+    pub city_database: Option<String>,
+    /// An ASN database.
+    #[serde(default)]
+    pub asn_database: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -23,6 +297,16 @@ pub struct PortScanConfig {
     pub enabled: bool,
     pub threshold: usize,
     pub window_seconds: u64,
+    /// Hosts probed on the same port (or pinged) by one source within the
+    /// window that count as a horizontal sweep.
+    #[serde(default = "default_sweep_threshold")]
+    pub sweep_threshold: usize,
+}
+
+fn default_sweep_threshold() -> usize {
+    // Browsers legitimately open connections to a few dozen hosts on 443
+    // within seconds of loading a page.
+    50
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -32,6 +316,406 @@ pub struct DdosConfig {
     pub window_seconds: u64,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SynFloodConfig {
+    pub enabled: bool,
+    pub window_seconds: u64,
+    /// SYNs to one destination IP and port within the window.
+    pub syn_threshold: usize,
+    /// Handshakes left open at the destination at the same time.
+    pub half_open_threshold: usize,
+    /// Seconds after which an unanswered SYN is no longer counted as
+    /// half-open.
+    pub half_open_timeout_seconds: u64,
+    /// Only alert when at most this fraction of SYNs in the window
+    /// completed the handshake.
+    pub max_completion_ratio: f64,
+}
+
+impl Default for SynFloodConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            window_seconds: 5,
+            syn_threshold: 500,
+            half_open_threshold: 200,
+            half_open_timeout_seconds: 30,
+            max_completion_ratio: 0.2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VolumetricConfig {
+    pub enabled: bool,
+    /// Prefix lengths used to aggregate destinations into networks.
+    pub prefix_v4: u8,
+    pub prefix_v6: u8,
+    /// Absolute limits per destination IP or prefix, regardless of baseline.
+    pub max_packets_per_second: u64,
+    pub max_bytes_per_second: u64,
+    /// Standard deviations above the hour-of-week mean that count as a spike.
+    pub spike_stddev: f64,
+    /// Spikes below this rate are ignored, however unusual.
+    pub min_packets_per_second: u64,
+    /// Weight of each new one-second sample in the baseline.
+    pub ewma_alpha: f64,
+    /// Samples an hour-of-week baseline needs before spikes are reported.
+    pub min_baseline_samples: u64,
+}
+
+impl Default for VolumetricConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            prefix_v4: 24,
+            prefix_v6: 64,
+            max_packets_per_second: 100_000,
+            max_bytes_per_second: 125_000_000,
+            spike_stddev: 4.0,
+            min_packets_per_second: 1_000,
+            ewma_alpha: 0.01,
+            min_baseline_samples: 300,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BeaconConfig {
+    pub enabled: bool,
+    /// Connections between a host and destination before scoring starts.
+    pub min_connections: usize,
+    /// Time the connections must span, so bursts are not mistaken for a timer.
+    pub min_span_seconds: u64,
+    /// Regularity score (0 to 1) at which a pair is reported.
+    pub score_threshold: f64,
+    /// Destination ports with legitimately periodic traffic (DNS, NTP).
+    pub exclude_ports: Vec<u16>,
+    /// Minimum seconds between alerts for the same pair.
+    pub realert_seconds: u64,
+}
+
+impl Default for BeaconConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_connections: 10,
+            min_span_seconds: 3600,
+            score_threshold: 0.8,
+            exclude_ports: vec![53, 123],
+            realert_seconds: 3600,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DnsTunnelingConfig {
+    pub enabled: bool,
+    pub window_seconds: u64,
+    /// Queries from one client for names under one domain within the window.
+    pub client_queries_per_domain: u64,
+    /// Distinct subdomains of one domain queried by one client.
+    pub client_unique_subdomains: u64,
+    /// Distinct subdomains of one domain queried by all clients together.
+    pub domain_unique_subdomains: u64,
+    /// TXT and NULL queries from one client for one domain.
+    pub txt_null_queries: u64,
+    /// Queries with an over-long label or high-entropy subdomain from one
+    /// client for one domain.
+    pub suspicious_queries: u64,
+    pub max_label_length: usize,
+    /// Shannon entropy (bits per character) above which a long subdomain
+    /// looks encoded.
+    pub entropy_threshold: f64,
+    /// Domains (and their subdomains) that legitimately encode data in
+    /// names, e.g. anti-virus reputation lookups.
+    pub ignored_domains: Vec<String>,
+}
+
+impl Default for DnsTunnelingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            window_seconds: 60,
+            client_queries_per_domain: 300,
+            client_unique_subdomains: 100,
+            domain_unique_subdomains: 500,
+            txt_null_queries: 50,
+            suspicious_queries: 20,
+            max_label_length: 50,
+            entropy_threshold: 3.9,
+            ignored_domains: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DgaConfig {
+    pub enabled: bool,
+    pub window_seconds: u64,
+    /// Distinct generated-looking domains one client must query within the
+    /// window.
+    pub client_threshold: u64,
+    /// Shorter registered labels are too short to judge.
+    pub min_length: usize,
+    /// Average bigram log10 probability below which a label looks generated.
+    pub score_threshold: f64,
+    pub ignored_domains: Vec<String>,
+}
+
+impl Default for DgaConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            window_seconds: 300,
+            client_threshold: 5,
+            min_length: 8,
+            score_threshold: -1.6,
+            ignored_domains: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExfiltrationConfig {
+    pub enabled: bool,
+    /// Short window over which uploads are summed.
+    pub window_seconds: u64,
+    /// Upload to one destination within the window that counts as a large
+    /// transfer.
+    pub transfer_bytes: u64,
+    /// Upload/download ratio a transfer must exceed; downloads that are
+    /// answered with similar volume (sync, backups with restores) are not
+    /// exfiltration-shaped.
+    pub ratio_threshold: f64,
+    /// Upload to one destination over 24 hours that counts as slow-and-low
+    /// exfiltration.
+    pub daily_bytes: u64,
+    /// Standard deviations above the host's usual window upload that count
+    /// as anomalous.
+    pub spike_stddev: f64,
+    /// Anomalous windows smaller than this are ignored.
+    pub min_anomaly_bytes: u64,
+    pub ewma_alpha: f64,
+    /// Windows of history a host needs before anomalies are reported.
+    pub min_baseline_windows: u64,
+    /// Minimum seconds between alerts for the same host or destination.
+    pub realert_seconds: u64,
+}
+
+impl Default for ExfiltrationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            window_seconds: 3600,
+            transfer_bytes: 500_000_000,
+            ratio_threshold: 5.0,
+            daily_bytes: 200_000_000,
+            spike_stddev: 4.0,
+            min_anomaly_bytes: 50_000_000,
+            ewma_alpha: 0.05,
+            min_baseline_windows: 24,
+            realert_seconds: 3600,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RulesConfig {
+    pub enabled: bool,
+    /// Suricata/Snort rule files, loaded in order.
+    pub files: Vec<String>,
+    /// Address and port variables used in rule headers (`$HTTP_PORTS`),
+    /// named without the `$`. The network groups are variables too, unless
+    /// redefined here.
+    #[serde(default = "default_rule_vars")]
+    pub vars: HashMap<String, String>,
+    /// Bytes of each TCP direction kept so that rules can match content
+    /// split across segments.
+    #[serde(default = "default_stream_window")]
+    pub stream_window_bytes: usize,
+}
+
+impl Default for RulesConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            files: Vec::new(),
+            vars: default_rule_vars(),
+            stream_window_bytes: default_stream_window(),
+        }
+    }
+}
+
+fn default_stream_window() -> usize {
+    256
+}
+
+/// The variables of Suricata's stock suricata.yaml, less `HOME_NET` and
+/// `EXTERNAL_NET`, which come from the network groups.
+fn default_rule_vars() -> HashMap<String, String> {
+    [
+        ("HTTP_SERVERS", "$HOME_NET"),
+        ("SMTP_SERVERS", "$HOME_NET"),
+        ("SQL_SERVERS", "$HOME_NET"),
+        ("DNS_SERVERS", "$HOME_NET"),
+        ("TELNET_SERVERS", "$HOME_NET"),
+        ("HTTP_PORTS", "80"),
+        ("SHELLCODE_PORTS", "!80"),
+        ("ORACLE_PORTS", "1521"),
+        ("SSH_PORTS", "22"),
+        ("FILE_DATA_PORTS", "[$HTTP_PORTS,110,143]"),
+        ("FTP_PORTS", "21"),
+    ]
+    .into_iter()
+    .map(|(name, value)| (name.to_string(), value.to_string()))
+    .collect()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SigmaConfig {
+    pub enabled: bool,
+    /// Sigma rule files, or directories searched recursively for `.yml`
+    /// and `.yaml` files.
+    pub files: Vec<String>,
+}
+
+impl Default for SigmaConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            files: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IntelConfig {
+    pub enabled: bool,
+    pub feeds: Vec<FeedConfig>,
+    /// How often feed files are checked for changes.
+    pub reload_seconds: u64,
+    /// Minimum seconds between alerts for the same indicator and hosts.
+    pub realert_seconds: u64,
+}
+
+impl Default for IntelConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            feeds: Vec::new(),
+            reload_seconds: 60,
+            realert_seconds: 3600,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeedConfig {
+    /// Shown in alerts.
+    pub name: String,
+    pub path: String,
+    /// Guessed from the extension and content when not set.
+    #[serde(default)]
+    pub format: Option<FeedFormat>,
+    /// Severity of matches, unless the feed gives one per indicator.
+    #[serde(default = "default_feed_severity")]
+    pub severity: String,
+}
+
+fn default_feed_severity() -> String {
+    "high".to_string()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FeedFormat {
+    /// One indicator per line, `#` comments.
+    Text,
+    /// A header row naming an indicator column, optionally type,
+    /// description, confidence and tags.
+    Csv,
+    /// A MISP event export.
+    Misp,
+    /// A STIX 2.1 bundle.
+    Stix,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SmbConfig {
+    pub enabled: bool,
+    /// Shares where write access is treated as lateral movement.
+    pub admin_shares: Vec<String>,
+}
+
+impl Default for SmbConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            admin_shares: vec!["ADMIN$".to_string(), "C$".to_string()],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CleartextCredentialsConfig {
+    pub enabled: bool,
+}
+
+impl Default for CleartextCredentialsConfig {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IcsConfig {
+    pub enabled: bool,
+    /// Hosts allowed to send write, diagnostic or firmware commands to
+    /// outstations. Empty allows any master.
+    pub allowed_masters: Vec<String>,
+    /// Modbus function codes permitted from allowed masters.
+    pub modbus_allowed_function_codes: Vec<u8>,
+    /// DNP3 function codes permitted from allowed masters.
+    pub dnp3_allowed_function_codes: Vec<u8>,
+}
+
+impl Default for IcsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            allowed_masters: vec![],
+            modbus_allowed_function_codes: vec![1, 2, 3, 4, 5, 6, 15, 16, 23],
+            dnp3_allowed_function_codes: vec![0, 1, 2, 3, 4, 5, 6, 20, 22, 23, 24],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpoofingConfig {
+    pub enabled: bool,
+    /// Addresses (typically gateways) whose MAC binding must not change.
+    pub protected_ips: Vec<String>,
+    /// Gratuitous ARPs from one MAC within the window that count as a storm.
+    pub gratuitous_threshold: usize,
+    pub window_seconds: u64,
+    /// Router IPs or MACs allowed to send IPv6 router advertisements. Empty
+    /// learns the first router seen.
+    pub allowed_routers: Vec<String>,
+}
+
+impl Default for SpoofingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            protected_ips: vec![],
+            gratuitous_threshold: 20,
+            window_seconds: 10,
+            allowed_routers: vec![],
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FirewallConfig {
     pub default_policy: String,
@@ -67,13 +751,38 @@ impl Default for Config {
                     enabled: true,
                     threshold: 10,
                     window_seconds: 5,
+                    sweep_threshold: default_sweep_threshold(),
                 },
                 ddos: DdosConfig {
                     enabled: true,
                     threshold: 1000,
                     window_seconds: 1,
                 },
+                syn_flood: SynFloodConfig::default(),
+                volumetric: VolumetricConfig::default(),
                 suspicious_ports: vec![23, 135, 445, 3389],
+                beacon: BeaconConfig::default(),
+                dns_tunneling: DnsTunnelingConfig::default(),
+                dga: DgaConfig::default(),
+                exfiltration: ExfiltrationConfig::default(),
+                rules: RulesConfig::default(),
+                sigma: SigmaConfig::default(),
+                intel: IntelConfig::default(),
+                smb: SmbConfig::default(),
+                cleartext_credentials: CleartextCredentialsConfig::default(),
+                ics: IcsConfig::default(),
+                spoofing: SpoofingConfig::default(),
+                networks: default_groups(),
+                direction_severity: HashMap::new(),
+                allowlist: Vec::new(),
+                risk: RiskConfig::default(),
+                correlation: CorrelationConfig::default(),
+                order: vec![],
+                disabled: vec![],
+                plugins: HashMap::new(),
+                flow_timeout_seconds: default_flow_timeout(),
+                aggregation: AggregationConfig::default(),
+                geoip: GeoIpConfig::default(),
             },
             firewall: FirewallConfig {
                 default_policy: "allow".to_string(),
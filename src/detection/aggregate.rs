@@ -0,0 +1,283 @@
+//! Alert deduplication and rate limiting.
+//!
+//! Detectors fire on every packet that matches, so a single scan or a busy
+//! SMB server produces thousands of identical alerts. The aggregator keys
+//! alerts by (type, source, destination), lets the first one through, and
+//! then only emits a "still ongoing" update with the suppressed count once
+//! per suppression window. When the table is full, the least recently seen
+//! entry is dropped to make room, with its final update.
+
+use chrono::{DateTime, Duration, Utc};
+
+use super::state::LruMap;
+use super::Alert;
+use crate::config::AggregationConfig;
+
+const MAX_ENTRIES: usize = 10_000;
+/// Rough size of a short string (alert type, address, severity), heap
+/// included.
+const STRING_BYTES: usize = 40;
+
+type AlertKey = (String, String, Option<String>);
+
+struct Aggregate {
+    /// Most recent (or most severe) alert seen for this key.
+    latest: Alert,
+    first_seen: DateTime<Utc>,
+    last_seen: DateTime<Utc>,
+    last_emitted: DateTime<Utc>,
+    count: u64,
+    /// Occurrences since the last emitted alert or update.
+    suppressed: u64,
+}
+
+pub struct AlertAggregator {
+    config: AggregationConfig,
+    entries: LruMap<AlertKey, Aggregate>,
+}
+
+impl AlertAggregator {
+    pub fn new(config: AggregationConfig) -> Self {
+        Self::with_capacity(config, MAX_ENTRIES)
+    }
+
+    fn with_capacity(config: AggregationConfig, capacity: usize) -> Self {
+        Self {
+            config,
+            entries: LruMap::new(capacity),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Passes through alerts that are new or due an update, suppressing the
+    /// rest.
+    pub fn process(&mut self, alerts: Vec<Alert>, now: DateTime<Utc>) -> Vec<Alert> {
+        let mut emitted = Vec::new();
+
+        for alert in alerts {
+            let window = window(&self.config, &alert.alert_type);
+            let key = (
+                alert.alert_type.clone(),
+                alert.source_ip.clone(),
+                alert.destination_ip.clone(),
+            );
+
+            // An entry that has been quiet for a whole window is over; the
+            // next occurrence starts a new incident.
+            if let Some(entry) = self.entries.get_mut(&key) {
+                if now - entry.last_seen >= window {
+                    if let Some(entry) = self.entries.remove(&key) {
+                        emitted.extend(ongoing(&entry));
+                    }
+                }
+            }
+
+            if !self.entries.contains_key(&key) && self.entries.len() >= self.entries.capacity() {
+                if let Some((_, oldest)) = self.entries.pop_oldest() {
+                    emitted.extend(ongoing(&oldest));
+                }
+            }
+
+            match self.entries.get_mut(&key) {
+                Some(entry) => {
+                    entry.count += 1;
+                    entry.suppressed += 1;
+                    entry.last_seen = now;
+                    if alert.priority() >= entry.latest.priority() {
+                        entry.latest = alert;
+                    }
+                    if now - entry.last_emitted >= window {
+                        emitted.extend(ongoing(entry));
+                        entry.last_emitted = now;
+                        entry.suppressed = 0;
+                    }
+                }
+                None => {
+                    self.entries.insert(
+                        key,
+                        Aggregate {
+                            latest: alert.clone(),
+                            first_seen: now,
+                            last_seen: now,
+                            last_emitted: now,
+                            count: 1,
+                            suppressed: 0,
+                        },
+                    );
+                    emitted.push(alert);
+                }
+            }
+        }
+
+        emitted
+    }
+
+    /// Emits final updates for incidents whose window has passed and drops
+    /// entries that have gone quiet. Called periodically so the last burst
+    /// of suppressed alerts is reported even when no new alert arrives.
+    pub fn flush(&mut self, now: DateTime<Utc>) -> Vec<Alert> {
+        let mut emitted = Vec::new();
+        let config = &self.config;
+
+        self.entries.retain(|key, entry| {
+            let window = window(config, &key.0);
+            if now - entry.last_emitted >= window {
+                emitted.extend(ongoing(entry));
+                entry.last_emitted = now;
+                entry.suppressed = 0;
+            }
+            now - entry.last_seen < window
+        });
+
+        emitted
+    }
+
+    /// Approximate bytes held by the tracked entries.
+    pub fn memory_usage(&self) -> usize {
+        let details: usize = self
+            .entries
+            .values()
+            .map(|entry| entry.latest.details.capacity())
+            .sum();
+        // Key: type and both addresses; alert: those again and the severity.
+        self.entries.len() * (LruMap::<AlertKey, Aggregate>::entry_overhead() + 7 * STRING_BYTES)
+            + details
+    }
+}
+
+fn window(config: &AggregationConfig, alert_type: &str) -> Duration {
+    let seconds = config
+        .windows
+        .get(alert_type)
+        .copied()
+        .unwrap_or(config.suppression_seconds);
+    Duration::seconds(seconds as i64)
+}
+
+/// A "still ongoing" update for an entry, if anything was suppressed.
+fn ongoing(entry: &Aggregate) -> Option<Alert> {
+    if entry.suppressed == 0 {
+        return None;
+    }
+
+    let mut alert = entry.latest.clone();
+    alert.details = format!(
+        "Still ongoing: {} occurrences ({} since last report), first seen {}, last seen {}. {}",
+        entry.count,
+        entry.suppressed,
+        entry.first_seen.format("%Y-%m-%d %H:%M:%S"),
+        entry.last_seen.format("%Y-%m-%d %H:%M:%S"),
+        alert.details
+    );
+    alert.timestamp = entry.last_seen;
+    alert.ongoing = true;
+    Some(alert)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alert(alert_type: &str, source_ip: &str) -> Alert {
+        Alert::new(alert_type, "medium", source_ip, None, "found")
+    }
+
+    fn aggregator(capacity: usize) -> AlertAggregator {
+        AlertAggregator::with_capacity(
+            AggregationConfig {
+                enabled: true,
+                suppression_seconds: 60,
+                windows: [("Port Scan".to_string(), 10)].into(),
+            },
+            capacity,
+        )
+    }
+
+    #[test]
+    fn repeats_are_reported_once_per_window() {
+        let mut aggregator = aggregator(10);
+        let start = Utc::now();
+        let scan = || vec![alert("Port Scan", "10.0.0.1")];
+        assert_eq!(aggregator.process(scan(), start).len(), 1);
+        assert!(aggregator
+            .process(scan(), start + Duration::seconds(5))
+            .is_empty());
+        assert!(aggregator
+            .process(scan(), start + Duration::seconds(9))
+            .is_empty());
+
+        let updates = aggregator.process(scan(), start + Duration::seconds(10));
+        assert_eq!(updates.len(), 1);
+        assert!(updates[0]
+            .details
+            .starts_with("Still ongoing: 4 occurrences (3 since last report)"));
+        assert!(aggregator.flush(start + Duration::seconds(15)).is_empty());
+        assert_eq!(aggregator.len(), 1);
+    }
+
+    #[test]
+    fn quiet_entries_are_flushed_and_restart() {
+        let mut aggregator = aggregator(10);
+        let start = Utc::now();
+        let scan = || vec![alert("Port Scan", "10.0.0.1")];
+        aggregator.process(scan(), start);
+        aggregator.process(scan(), start + Duration::seconds(1));
+
+        let updates = aggregator.flush(start + Duration::seconds(11));
+        assert_eq!(updates.len(), 1);
+        assert_eq!(aggregator.len(), 0);
+        let alerts = aggregator.process(scan(), start + Duration::seconds(12));
+        assert_eq!(alerts[0].details, "found");
+    }
+
+    #[test]
+    fn full_table_evicts_the_least_recently_seen_entry() {
+        let mut aggregator = aggregator(2);
+        let now = Utc::now();
+        aggregator.process(vec![alert("Beacon", "10.0.0.1")], now);
+        aggregator.process(vec![alert("Beacon", "10.0.0.1")], now);
+        aggregator.process(vec![alert("Beacon", "10.0.0.2")], now);
+        aggregator.process(vec![alert("Beacon", "10.0.0.2")], now);
+        let usage = aggregator.memory_usage();
+        assert!(usage > 0);
+
+        // A new key pushes out 10.0.0.1 with its pending update, and is
+        // itself tracked.
+        let alerts = aggregator.process(vec![alert("Beacon", "10.0.0.3")], now);
+        assert_eq!(alerts.len(), 2);
+        assert_eq!(alerts[0].source_ip, "10.0.0.1");
+        assert!(alerts[0]
+            .details
+            .starts_with("Still ongoing: 2 occurrences"));
+        assert_eq!(alerts[1].details, "found");
+        assert_eq!(aggregator.len(), 2);
+        assert_eq!(aggregator.memory_usage(), usage);
+        assert!(aggregator
+            .process(vec![alert("Beacon", "10.0.0.3")], now)
+            .is_empty());
+    }
+
+    #[test]
+    fn other_types_use_the_default_window_and_keep_the_worst_severity() {
+        let mut aggregator = aggregator(10);
+        let start = Utc::now();
+        let mut severe = alert("Beacon", "10.0.0.1");
+        severe.severity = "critical".to_string();
+        aggregator.process(vec![alert("Beacon", "10.0.0.1")], start);
+        aggregator.process(vec![severe], start + Duration::seconds(10));
+        aggregator.process(
+            vec![alert("Beacon", "10.0.0.1")],
+            start + Duration::seconds(20),
+        );
+
+        assert!(aggregator.flush(start + Duration::seconds(59)).is_empty());
+        let updates = aggregator.flush(start + Duration::seconds(60));
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].severity, "critical");
+        assert!(updates[0].ongoing);
+        assert_eq!(aggregator.len(), 1);
+    }
+}
@@ -0,0 +1,327 @@
+//! Configured exceptions that hide traffic from detectors.
+//!
+//! Each entry lists conditions (addresses, ports, protocols, interfaces, a
+//! time-of-day window) and the detectors it applies to. The engine asks
+//! before handing a packet or a closed flow to each detector; a match skips
+//! that detector and counts a hit against the entry, so what the
+//! exceptions hide can be audited from the engine snapshot. Expired entries
+//! stop matching but keep their counts.
+
+use anyhow::Result;
+use chrono::{DateTime, Local, NaiveDate, NaiveTime, Utc};
+use pnet::ipnetwork::IpNetwork;
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use crate::capture::flow::FlowRecord;
+use crate::capture::parser::ParsedPacket;
+use crate::config::AllowlistEntry;
+use crate::error::NetGuardError;
+
+/// The parts of a packet or flow that entries match on.
+pub struct Traffic<'a> {
+    source: Option<IpAddr>,
+    destination: Option<IpAddr>,
+    ports: [Option<u16>; 2],
+    protocol: &'a str,
+}
+
+impl<'a> Traffic<'a> {
+    pub fn packet(packet: &'a ParsedPacket) -> Self {
+        Self {
+            source: packet.source_ip.parse().ok(),
+            destination: packet.destination_ip.parse().ok(),
+            ports: [packet.source_port, packet.destination_port],
+            protocol: &packet.protocol,
+        }
+    }
+
+    pub fn flow(flow: &'a FlowRecord) -> Self {
+        Self {
+            source: flow.source_ip.parse().ok(),
+            destination: flow.destination_ip.parse().ok(),
+            ports: [flow.source_port, flow.destination_port],
+            protocol: &flow.protocol,
+        }
+    }
+}
+
+struct Entry {
+    reason: String,
+    detectors: Vec<String>,
+    addresses: Vec<IpNetwork>,
+    ports: Vec<u16>,
+    protocols: Vec<String>,
+    interfaces: Vec<String>,
+    hours: Option<(NaiveTime, NaiveTime)>,
+    expires: Option<DateTime<Utc>>,
+    /// Suppressed packets and flows, by detector.
+    hits: HashMap<&'static str, u64>,
+}
+
+impl Entry {
+    fn matches(
+        &self,
+        detector: &str,
+        traffic: &Traffic,
+        interface: Option<&str>,
+        now: DateTime<Utc>,
+    ) -> bool {
+        let addresses = [traffic.source, traffic.destination];
+        (self.detectors.is_empty() || self.detectors.iter().any(|name| name == detector))
+            && self.expires.is_none_or(|expires| now < expires)
+            && (self.addresses.is_empty()
+                || self
+                    .addresses
+                    .iter()
+                    .any(|network| addresses.iter().flatten().any(|ip| network.contains(*ip))))
+            && (self.ports.is_empty()
+                || traffic
+                    .ports
+                    .iter()
+                    .flatten()
+                    .any(|port| self.ports.contains(port)))
+            && (self.protocols.is_empty()
+                || self
+                    .protocols
+                    .iter()
+                    .any(|protocol| protocol.eq_ignore_ascii_case(traffic.protocol)))
+            && (self.interfaces.is_empty()
+                || interface
+                    .is_some_and(|interface| self.interfaces.iter().any(|name| name == interface)))
+            && self.hours.is_none_or(|(start, end)| {
+                let time = now.with_timezone(&Local).time();
+                if start < end {
+                    start <= time && time < end
+                } else {
+                    time >= start || time < end
+                }
+            })
+    }
+}
+
+#[derive(Default)]
+pub struct Allowlist {
+    entries: Vec<Entry>,
+}
+
+impl Allowlist {
+    /// Compiles the configured entries; values that do not parse are
+    /// config errors.
+    pub fn new(config: &[AllowlistEntry]) -> Result<Self> {
+        let now = Utc::now();
+        let mut entries = Vec::with_capacity(config.len());
+        for entry in config {
+            let invalid = |e: String| {
+                NetGuardError::ConfigError(format!("Allowlist entry '{}': {}", entry.reason, e))
+            };
+            let entry = Entry {
+                reason: entry.reason.clone(),
+                detectors: entry.detectors.clone(),
+                addresses: entry
+                    .addresses
+                    .iter()
+                    .map(|address| {
+                        address
+                            .parse()
+                            .map_err(|_| format!("invalid address '{}'", address))
+                    })
+                    .collect::<Result<_, _>>()
+                    .map_err(invalid)?,
+                ports: entry.ports.clone(),
+                protocols: entry.protocols.clone(),
+                interfaces: entry.interfaces.clone(),
+                hours: entry
+                    .hours
+                    .as_deref()
+                    .map(parse_hours)
+                    .transpose()
+                    .map_err(invalid)?,
+                expires: entry
+                    .expires
+                    .as_deref()
+                    .map(parse_expiry)
+                    .transpose()
+                    .map_err(invalid)?,
+                hits: HashMap::new(),
+            };
+            if entry.expires.is_some_and(|expires| expires <= now) {
+                log::warn!("Allowlist entry '{}' has expired", entry.reason);
+            }
+            entries.push(entry);
+        }
+        Ok(Self { entries })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Whether an entry hides the traffic from `detector`, counting the hit
+    /// against the first such entry.
+    pub fn suppresses(
+        &mut self,
+        detector: &'static str,
+        traffic: &Traffic,
+        interface: Option<&str>,
+        now: DateTime<Utc>,
+    ) -> bool {
+        match self
+            .entries
+            .iter_mut()
+            .find(|entry| entry.matches(detector, traffic, interface, now))
+        {
+            Some(entry) => {
+                *entry.hits.entry(detector).or_insert(0) += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// (reason, detector, hits) for every entry that suppressed something.
+    pub fn hits(&self) -> Vec<(&str, &'static str, u64)> {
+        let mut hits: Vec<(&str, &'static str, u64)> = self
+            .entries
+            .iter()
+            .flat_map(|entry| {
+                entry
+                    .hits
+                    .iter()
+                    .map(|(detector, count)| (entry.reason.as_str(), *detector, *count))
+            })
+            .collect();
+        hits.sort_unstable();
+        hits
+    }
+
+    pub fn snapshot(&self, now: DateTime<Utc>) -> serde_json::Value {
+        self.entries
+            .iter()
+            .map(|entry| {
+                serde_json::json!({
+                    "reason": entry.reason,
+                    "expired": entry.expires.is_some_and(|expires| expires <= now),
+                    "hits": entry.hits,
+                })
+            })
+            .collect()
+    }
+}
+
+/// `HH:MM-HH:MM`.
+fn parse_hours(text: &str) -> Result<(NaiveTime, NaiveTime), String> {
+    let (start, end) = text
+        .split_once('-')
+        .ok_or_else(|| format!("invalid hours '{}', expected HH:MM-HH:MM", text))?;
+    let time = |part: &str| {
+        NaiveTime::parse_from_str(part.trim(), "%H:%M")
+            .map_err(|_| format!("invalid time '{}'", part.trim()))
+    };
+    Ok((time(start)?, time(end)?))
+}
+
+/// An RFC 3339 time, or a date meaning the end of that day in UTC.
+fn parse_expiry(text: &str) -> Result<DateTime<Utc>, String> {
+    let text = text.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(text, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.succ_opt())
+        .and_then(|next| next.and_hms_opt(0, 0, 0))
+        .map(|midnight| midnight.and_utc())
+        .ok_or_else(|| format!("invalid expiry '{}'", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(reason: &str) -> AllowlistEntry {
+        AllowlistEntry {
+            reason: reason.to_string(),
+            detectors: Vec::new(),
+            addresses: Vec::new(),
+            ports: Vec::new(),
+            protocols: Vec::new(),
+            interfaces: Vec::new(),
+            hours: None,
+            expires: None,
+        }
+    }
+
+    fn packet(source: &str, destination: &str, destination_port: u16) -> ParsedPacket {
+        ParsedPacket {
+            source_ip: source.to_string(),
+            destination_ip: destination.to_string(),
+            source_port: Some(50000),
+            destination_port: Some(destination_port),
+            protocol: "TCP".to_string(),
+            size: 60,
+            tcp_flags: Some(0x02),
+            app: None,
+            payload: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn cidr_and_port_must_both_match_either_end() {
+        let mut allowlist = Allowlist::new(&[AllowlistEntry {
+            addresses: vec!["10.1.0.0/16".to_string(), "2001:db8::1".to_string()],
+            ports: vec![443],
+            protocols: vec!["tcp".to_string()],
+            ..entry("backup server")
+        }])
+        .unwrap();
+        let now = Utc::now();
+        let mut suppressed = |packet: ParsedPacket| {
+            allowlist.suppresses("port_scan", &Traffic::packet(&packet), None, now)
+        };
+
+        assert!(suppressed(packet("10.1.255.7", "203.0.113.5", 443)));
+        assert!(suppressed(packet("203.0.113.5", "10.1.0.1", 443)));
+        assert!(suppressed(packet("2001:db8::1", "2001:db8::2", 443)));
+        assert!(!suppressed(packet("10.2.0.1", "203.0.113.5", 443)));
+        assert!(!suppressed(packet("10.1.0.1", "203.0.113.5", 80)));
+        assert_eq!(allowlist.hits(), [("backup server", "port_scan", 3)]);
+    }
+
+    #[test]
+    fn entries_apply_only_to_their_detectors_until_they_expire() {
+        let mut allowlist = Allowlist::new(&[AllowlistEntry {
+            detectors: vec!["beacon".to_string()],
+            interfaces: vec!["eth1".to_string()],
+            expires: Some("2030-01-31".to_string()),
+            ..entry("monitoring")
+        }])
+        .unwrap();
+        let packet = packet("10.0.0.1", "10.0.0.2", 80);
+        let traffic = Traffic::packet(&packet);
+        let before = parse_expiry("2030-01-31T23:59:59Z").unwrap();
+        let after = parse_expiry("2030-02-01T00:00:00Z").unwrap();
+
+        assert!(allowlist.suppresses("beacon", &traffic, Some("eth1"), before));
+        assert!(!allowlist.suppresses("beacon", &traffic, Some("eth0"), before));
+        assert!(!allowlist.suppresses("beacon", &traffic, None, before));
+        assert!(!allowlist.suppresses("port_scan", &traffic, Some("eth1"), before));
+        assert!(!allowlist.suppresses("beacon", &traffic, Some("eth1"), after));
+    }
+
+    #[test]
+    fn invalid_values_are_config_errors() {
+        let bad_address = AllowlistEntry {
+            addresses: vec!["10.0.0.0/33".to_string()],
+            ..entry("typo")
+        };
+        assert!(Allowlist::new(&[bad_address]).is_err());
+        let bad_hours = AllowlistEntry {
+            hours: Some("22:00".to_string()),
+            ..entry("typo")
+        };
+        assert!(Allowlist::new(&[bad_hours]).is_err());
+        assert!(parse_hours("22:00-06:00").is_ok());
+        assert!(parse_expiry("tomorrow").is_err());
+    }
+}
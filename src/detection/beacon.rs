@@ -0,0 +1,299 @@
+//! Beaconing (C2 call-home) detection over flow records.
+//!
+//! Implants check in with their controller on a timer, usually with a
+//! little jitter and near-identical request sizes. For each internal host
+//! and external destination this keeps the start time and size of recent
+//! flows and scores how regular they are: the skew and dispersion (median
+//! absolute deviation) of the intervals between connections, and the same
+//! for connection sizes. Scores near 1 mean machine-like regularity.
+
+use chrono::{DateTime, Duration, Utc};
+use std::collections::VecDeque;
+use std::mem::size_of;
+
+use super::detector::Detector;
+use super::state::LruMap;
+use super::Alert;
+use crate::attack;
+use crate::capture::flow::FlowRecord;
+use crate::config::BeaconConfig;
+use crate::networks::{Direction, Networks};
+
+const MAX_PAIRS: usize = 50_000;
+const MAX_CONNECTIONS_PER_PAIR: usize = 256;
+
+/// (internal host, external destination, destination port)
+type PairKey = (String, String, u16);
+
+#[derive(Default)]
+struct PairHistory {
+    /// (flow start, bytes in both directions), in arrival order.
+    connections: VecDeque<(DateTime<Utc>, u64)>,
+    server_name: Option<String>,
+    last_alert: Option<DateTime<Utc>>,
+}
+
+struct Score {
+    period: f64,
+    jitter: f64,
+    interval_skew: f64,
+    size_dispersion: f64,
+    confidence: f64,
+}
+
+pub struct BeaconDetector {
+    config: BeaconConfig,
+    pairs: LruMap<PairKey, PairHistory>,
+    networks: Networks,
+}
+
+impl BeaconDetector {
+    pub fn new(config: BeaconConfig, networks: Networks) -> Self {
+        Self {
+            config,
+            networks,
+            pairs: LruMap::new(MAX_PAIRS),
+        }
+    }
+}
+
+impl Detector for BeaconDetector {
+    fn name(&self) -> &'static str {
+        "beacon"
+    }
+
+    fn on_flow(&mut self, flow: &FlowRecord) -> Vec<Alert> {
+        let Some(port) = flow.destination_port else {
+            return Vec::new();
+        };
+        let direction = flow.direction.unwrap_or_else(|| {
+            self.networks
+                .direction(&flow.source_ip, &flow.destination_ip)
+        });
+        if self.config.exclude_ports.contains(&port) || direction != Direction::Outbound {
+            return Vec::new();
+        }
+
+        let key = (flow.source_ip.clone(), flow.destination_ip.clone(), port);
+        let history = self.pairs.get_or_insert_with(key, PairHistory::default);
+        if history.connections.len() >= MAX_CONNECTIONS_PER_PAIR {
+            history.connections.pop_front();
+        }
+        history
+            .connections
+            .push_back((flow.first_seen, flow.bytes_sent + flow.bytes_received));
+        if flow.server_name.is_some() {
+            history.server_name = flow.server_name.clone();
+        }
+
+        if history.connections.len() < self.config.min_connections {
+            return Vec::new();
+        }
+        let realert = Duration::seconds(self.config.realert_seconds as i64);
+        if history
+            .last_alert
+            .is_some_and(|last| flow.last_seen - last < realert)
+        {
+            return Vec::new();
+        }
+
+        let mut starts: Vec<DateTime<Utc>> = history
+            .connections
+            .iter()
+            .map(|(start, _)| *start)
+            .collect();
+        starts.sort();
+        let span = *starts.last().unwrap() - starts[0];
+        if span < Duration::seconds(self.config.min_span_seconds as i64) {
+            return Vec::new();
+        }
+
+        let intervals: Vec<f64> = starts
+            .windows(2)
+            .map(|pair| (pair[1] - pair[0]).num_milliseconds() as f64 / 1000.0)
+            .collect();
+        let sizes: Vec<f64> = history
+            .connections
+            .iter()
+            .map(|(_, bytes)| *bytes as f64)
+            .collect();
+        let Some(score) = score(&intervals, &sizes) else {
+            return Vec::new();
+        };
+        if score.confidence < self.config.score_threshold {
+            return Vec::new();
+        }
+
+        history.last_alert = Some(flow.last_seen);
+        let destination = match &history.server_name {
+            Some(name) => format!("{}:{} ({})", flow.destination_ip, port, name),
+            None => format!("{}:{}", flow.destination_ip, port),
+        };
+
+        vec![Alert::new(
+            "Beaconing",
+            if score.confidence >= 0.9 { "high" } else { "medium" },
+            flow.source_ip.clone(),
+            Some(flow.destination_ip.clone()),
+            format!(
+                "Beacon to {} every ~{:.0}s: {} connections over {:.1}h, jitter {:.1}s, interval skew {:.2}, size dispersion {:.2}, confidence {:.2}",
+                destination,
+                score.period,
+                starts.len(),
+                span.num_seconds() as f64 / 3600.0,
+                score.jitter,
+                score.interval_skew,
+                score.size_dispersion,
+                score.confidence
+            ),
+        )
+        .with_attack(attack::techniques(&[("TA0011", "T1071")]))]
+    }
+
+    fn snapshot(&self) -> serde_json::Value {
+        serde_json::json!({ "tracked_pairs": self.pairs.len() })
+    }
+
+    fn memory_usage(&self) -> usize {
+        let connections: usize = self
+            .pairs
+            .values()
+            .map(|history| history.connections.capacity() * size_of::<(DateTime<Utc>, u64)>())
+            .sum();
+        self.pairs.len() * LruMap::<PairKey, PairHistory>::entry_overhead() + connections
+    }
+}
+
+/// Scores interval and size regularity, each from 0 (random) to 1 (fixed).
+/// Skew alone cannot tell a timer from uniformly random gaps, so dispersion
+/// counts double: a deviation of half the median already scores zero.
+fn score(intervals: &[f64], sizes: &[f64]) -> Option<Score> {
+    let (interval_skew, jitter, period) = dispersion(intervals)?;
+    let (size_skew, size_mad, size_median) = dispersion(sizes)?;
+    if period <= 0.0 {
+        return None;
+    }
+
+    let interval_skew_score = 1.0 - interval_skew.abs();
+    let jitter_score = (1.0 - 2.0 * jitter / period).max(0.0);
+    let size_skew_score = 1.0 - size_skew.abs();
+    let size_dispersion = if size_median > 0.0 {
+        size_mad / size_median
+    } else {
+        0.0
+    };
+    let size_dispersion_score = (1.0 - 2.0 * size_dispersion).max(0.0);
+
+    Some(Score {
+        period,
+        jitter,
+        interval_skew,
+        size_dispersion,
+        confidence: (interval_skew_score + jitter_score + size_skew_score + size_dispersion_score)
+            / 4.0,
+    })
+}
+
+/// Bowley skew, median absolute deviation and median of a sample.
+fn dispersion(values: &[f64]) -> Option<(f64, f64, f64)> {
+    if values.len() < 3 {
+        return None;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let q1 = quantile(&sorted, 0.25);
+    let median = quantile(&sorted, 0.5);
+    let q3 = quantile(&sorted, 0.75);
+    let skew = if q3 - q1 > 0.0 {
+        (q1 + q3 - 2.0 * median) / (q3 - q1)
+    } else {
+        0.0
+    };
+
+    let mut deviations: Vec<f64> = sorted.iter().map(|value| (value - median).abs()).collect();
+    deviations.sort_by(f64::total_cmp);
+    Some((skew, quantile(&deviations, 0.5), median))
+}
+
+fn quantile(sorted: &[f64], q: f64) -> f64 {
+    let position = q * (sorted.len() - 1) as f64;
+    let lower = position.floor() as usize;
+    let upper = position.ceil() as usize;
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (position - lower as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networks::default_groups;
+    use chrono::TimeZone;
+
+    fn detector() -> BeaconDetector {
+        BeaconDetector::new(
+            BeaconConfig::default(),
+            Networks::from_config(&default_groups()).unwrap(),
+        )
+    }
+
+    fn flow(destination_port: u16, start: i64, bytes: u64) -> FlowRecord {
+        let first_seen = Utc.timestamp_opt(1_700_000_000 + start, 0).unwrap();
+        FlowRecord {
+            source_ip: "10.0.0.5".to_string(),
+            destination_ip: "203.0.113.7".to_string(),
+            source_port: Some(50000),
+            destination_port: Some(destination_port),
+            protocol: "TCP".to_string(),
+            packets_sent: 5,
+            bytes_sent: bytes,
+            packets_received: 5,
+            bytes_received: 0,
+            first_seen,
+            last_seen: first_seen + Duration::seconds(1),
+            tcp_flags: 0,
+            server_name: None,
+            source_geo: None,
+            destination_geo: None,
+            direction: None,
+        }
+    }
+
+    #[test]
+    fn a_regular_timer_is_reported_once_enough_connections_span_the_minimum() {
+        let mut beacon = detector();
+        let jitter = [0, 0, -1, 0, -2, 0, 0, -1, 0, 0, 0];
+        let mut alerts = Vec::new();
+        for (index, offset) in jitter.iter().enumerate() {
+            let start = index as i64 * 600 + offset;
+            alerts.push(beacon.on_flow(&flow(443, start, 512)));
+        }
+
+        assert!(alerts[..9].iter().all(Vec::is_empty));
+        assert_eq!(alerts[9].len(), 1);
+        assert_eq!(alerts[9][0].severity, "high");
+        assert!(alerts[9][0]
+            .details
+            .starts_with("Beacon to 203.0.113.7:443 every ~600s: 10 connections"));
+        // Not again until realert_seconds have passed.
+        assert!(alerts[10].is_empty());
+    }
+
+    #[test]
+    fn irregular_or_excluded_traffic_is_not_a_beacon() {
+        let mut beacon = detector();
+        let starts = [
+            0, 40, 900, 1000, 2900, 3000, 3050, 4800, 5000, 7000, 7100, 9000,
+        ];
+        let sizes = [
+            300, 9000, 512, 45000, 800, 2000, 120, 64000, 700, 3000, 900, 15000,
+        ];
+        for (start, bytes) in starts.iter().zip(sizes) {
+            assert!(beacon.on_flow(&flow(443, *start, bytes)).is_empty());
+        }
+
+        let mut beacon = detector();
+        for index in 0..12 {
+            assert!(beacon.on_flow(&flow(53, index * 600, 80)).is_empty());
+        }
+        assert_eq!(beacon.pairs.len(), 0);
+    }
+}
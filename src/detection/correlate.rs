@@ -0,0 +1,382 @@
+//! Multi-stage incidents from the alert stream.
+//!
+//! Each rule lists stages, each matching alerts by type and severity. When
+//! alerts matching every stage arrive in order within the rule's window,
+//! all sharing the same host or host pair, the correlator raises an
+//! incident alert that links them by ID. An alert matching the stage a
+//! sequence last reached counts as a repeat of it, so a noisy stage does
+//! not also satisfy the next one; a sequence that runs out of time is
+//! dropped.
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use std::mem::size_of;
+
+use super::state::LruMap;
+use super::{severity_rank, Alert};
+use crate::attack::Technique;
+use crate::config::{CorrelationConfig, CorrelationKey, CorrelationStage, StageHost};
+use crate::error::NetGuardError;
+
+/// Sequences in progress across all rules.
+const MAX_SEQUENCES: usize = 50_000;
+
+struct Stage {
+    /// Lowercased patterns.
+    alert_types: Vec<String>,
+    min_rank: u8,
+    host: StageHost,
+}
+
+impl Stage {
+    fn matches(&self, alert: &Alert) -> bool {
+        alert.priority() >= self.min_rank
+            && (self.alert_types.is_empty() || {
+                let alert_type = alert.alert_type.to_ascii_lowercase();
+                self.alert_types
+                    .iter()
+                    .any(|pattern| type_matches(pattern, &alert_type))
+            })
+    }
+
+    /// The host or host pair the alert is correlated on, if it has the
+    /// ends the rule needs.
+    fn key(&self, alert: &Alert, key: CorrelationKey) -> Option<String> {
+        let (host, other) = match self.host {
+            StageHost::Source => (Some(&alert.source_ip), alert.destination_ip.as_ref()),
+            StageHost::Destination => (alert.destination_ip.as_ref(), Some(&alert.source_ip)),
+        };
+        match key {
+            CorrelationKey::Host => host.cloned(),
+            CorrelationKey::Pair => Some(format!("{} -> {}", host?, other?)),
+        }
+    }
+}
+
+/// `*` at either end of the pattern matches any text.
+fn type_matches(pattern: &str, alert_type: &str) -> bool {
+    match (pattern.strip_prefix('*'), pattern.strip_suffix('*')) {
+        _ if pattern == "*" => true,
+        (Some(rest), _) if rest.ends_with('*') => alert_type.contains(&rest[..rest.len() - 1]),
+        (Some(suffix), _) => alert_type.ends_with(suffix),
+        (None, Some(prefix)) => alert_type.starts_with(prefix),
+        (None, None) => alert_type == pattern,
+    }
+}
+
+struct Rule {
+    name: String,
+    severity: String,
+    window: Duration,
+    key: CorrelationKey,
+    stages: Vec<Stage>,
+}
+
+/// A rule's stages matched so far for one host or pair.
+struct Sequence {
+    started: DateTime<Utc>,
+    /// IDs and types of the alerts matching each stage reached.
+    alerts: Vec<(String, String)>,
+    /// Techniques of the alerts, for the incident.
+    attack: Vec<Technique>,
+    /// Ends of the first alert, which the incident reports.
+    source_ip: String,
+    destination_ip: Option<String>,
+}
+
+pub struct Correlator {
+    rules: Vec<Rule>,
+    /// Keyed by rule index and correlated host or pair.
+    sequences: LruMap<(usize, String), Sequence>,
+    incidents: u64,
+}
+
+impl Correlator {
+    /// Compiles the configured rules; rules without stages, windows or
+    /// with unknown severities are config errors.
+    pub fn new(config: &CorrelationConfig) -> Result<Self> {
+        let rules = config
+            .rules
+            .iter()
+            .map(|rule| {
+                let invalid = |e: &str| {
+                    NetGuardError::ConfigError(format!("Correlation rule '{}': {}", rule.name, e))
+                };
+                if rule.stages.is_empty() {
+                    return Err(invalid("no stages"));
+                }
+                if rule.window_seconds <= 0 {
+                    return Err(invalid("window_seconds must be positive"));
+                }
+                if severity_rank(&rule.severity) == 0 {
+                    return Err(invalid(&format!("unknown severity '{}'", rule.severity)));
+                }
+                Ok(Rule {
+                    name: rule.name.clone(),
+                    severity: rule.severity.clone(),
+                    window: Duration::seconds(rule.window_seconds),
+                    key: rule.key,
+                    stages: rule
+                        .stages
+                        .iter()
+                        .map(|stage| compile_stage(stage).map_err(|e| invalid(&e)))
+                        .collect::<Result<_, _>>()?,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            rules,
+            sequences: LruMap::new(MAX_SEQUENCES),
+            incidents: 0,
+        })
+    }
+
+    /// Advances sequences with the alerts, which must carry IDs, and
+    /// returns incidents for those that completed. Incidents themselves
+    /// are not correlated further, and neither are "still ongoing" updates,
+    /// which would restart a stage their alert already reached.
+    pub fn process(&mut self, alerts: &[Alert], now: DateTime<Utc>) -> Vec<Alert> {
+        let mut incidents = Vec::new();
+        for alert in alerts {
+            let Some(id) = &alert.id else {
+                continue;
+            };
+            if alert.ongoing || !alert.related.is_empty() {
+                continue;
+            }
+            for (index, rule) in self.rules.iter().enumerate() {
+                // Stages the alert matches, grouped by the host or pair
+                // each correlates it on.
+                let mut by_key: Vec<(String, Vec<usize>)> = Vec::new();
+                for (position, stage) in rule.stages.iter().enumerate() {
+                    let Some(key) = stage
+                        .matches(alert)
+                        .then(|| stage.key(alert, rule.key))
+                        .flatten()
+                    else {
+                        continue;
+                    };
+                    match by_key.iter_mut().find(|(existing, _)| *existing == key) {
+                        Some((_, positions)) => positions.push(position),
+                        None => by_key.push((key, vec![position])),
+                    }
+                }
+                for (key, positions) in by_key {
+                    let entry = (index, key);
+                    if let Some(incident) =
+                        advance(&mut self.sequences, rule, entry, &positions, alert, id, now)
+                    {
+                        incidents.push(incident);
+                        self.incidents += 1;
+                    }
+                }
+            }
+        }
+        incidents
+    }
+
+    pub fn snapshot(&self) -> serde_json::Value {
+        serde_json::json!({
+            "rules": self.rules.len(),
+            "sequences": self.sequences.len(),
+            "incidents": self.incidents,
+        })
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        let ids: usize = self
+            .sequences
+            .values()
+            .map(|sequence| {
+                sequence.source_ip.capacity()
+                    + sequence.attack.capacity() * size_of::<Technique>()
+                    + sequence
+                        .alerts
+                        .iter()
+                        .map(|(id, alert_type)| {
+                            id.capacity() + alert_type.capacity() + size_of::<(String, String)>()
+                        })
+                        .sum::<usize>()
+            })
+            .sum();
+        self.sequences.len() * LruMap::<(usize, String), Sequence>::entry_overhead() + ids
+    }
+}
+
+impl Sequence {
+    fn start(alert: &Alert, id: &str, now: DateTime<Utc>) -> Self {
+        Self {
+            started: now,
+            alerts: vec![(id.to_string(), alert.alert_type.clone())],
+            attack: alert.attack.clone(),
+            source_ip: alert.source_ip.clone(),
+            destination_ip: alert.destination_ip.clone(),
+        }
+    }
+}
+
+/// Moves the rule's sequence for `entry` on with an alert matching the
+/// stages at `positions`, returning the incident if it completed.
+fn advance(
+    sequences: &mut LruMap<(usize, String), Sequence>,
+    rule: &Rule,
+    entry: (usize, String),
+    positions: &[usize],
+    alert: &Alert,
+    id: &str,
+    now: DateTime<Utc>,
+) -> Option<Alert> {
+    let reached = sequences
+        .get_mut(&entry)
+        .filter(|sequence| now - sequence.started <= rule.window)
+        .map(|sequence| sequence.alerts.len());
+    match reached {
+        // A repeat of the stage reached last; before the sequence went
+        // further, a repeat of the first stage restarts the window.
+        Some(1) if positions.contains(&0) => {
+            sequences.insert(entry, Sequence::start(alert, id, now));
+            None
+        }
+        Some(reached) if positions.contains(&(reached - 1)) => None,
+        Some(reached) if positions.contains(&reached) => {
+            let sequence = sequences.get_mut(&entry)?;
+            sequence
+                .alerts
+                .push((id.to_string(), alert.alert_type.clone()));
+            sequence.attack.extend(alert.attack.iter().cloned());
+            if sequence.alerts.len() < rule.stages.len() {
+                return None;
+            }
+            let sequence = sequences.remove(&entry)?;
+            Some(incident_alert(rule, &entry.1, sequence, now))
+        }
+        Some(_) => None,
+        None if positions.contains(&0) => {
+            let sequence = Sequence::start(alert, id, now);
+            if rule.stages.len() == 1 {
+                sequences.remove(&entry);
+                return Some(incident_alert(rule, &entry.1, sequence, now));
+            }
+            sequences.insert(entry, sequence);
+            None
+        }
+        None => {
+            sequences.remove(&entry);
+            None
+        }
+    }
+}
+
+fn compile_stage(stage: &CorrelationStage) -> Result<Stage, String> {
+    let min_rank = match &stage.min_severity {
+        Some(severity) => match severity_rank(severity) {
+            0 => return Err(format!("unknown severity '{}'", severity)),
+            rank => rank,
+        },
+        None => 0,
+    };
+    Ok(Stage {
+        alert_types: stage
+            .alert_types
+            .iter()
+            .map(|alert_type| alert_type.to_ascii_lowercase())
+            .collect(),
+        min_rank,
+        host: stage.host,
+    })
+}
+
+fn incident_alert(rule: &Rule, key: &str, sequence: Sequence, now: DateTime<Utc>) -> Alert {
+    let (ids, types): (Vec<String>, Vec<String>) = sequence.alerts.into_iter().unzip();
+    let minutes = (now - sequence.started).num_minutes();
+    let mut attack = sequence.attack;
+    attack.sort();
+    attack.dedup();
+    let mut alert = Alert::new(
+        rule.name.clone(),
+        rule.severity.clone(),
+        sequence.source_ip,
+        sequence.destination_ip,
+        format!(
+            "Incident on {}: {} within {} [alerts: {}]",
+            key,
+            types.join(" -> "),
+            if minutes > 0 {
+                format!("{}m", minutes)
+            } else {
+                format!("{}s", (now - sequence.started).num_seconds())
+            },
+            ids.join(", ")
+        ),
+    )
+    .at(now)
+    .with_attack(attack);
+    alert.related = ids;
+    alert
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CorrelationRule;
+
+    fn correlator() -> Correlator {
+        let stage = |alert_type: &str| CorrelationStage {
+            alert_types: vec![alert_type.to_string()],
+            min_severity: None,
+            host: StageHost::Source,
+        };
+        Correlator::new(&CorrelationConfig {
+            enabled: true,
+            rules: vec![CorrelationRule {
+                name: "Scan then Exploit".to_string(),
+                severity: "critical".to_string(),
+                window_seconds: 60,
+                key: CorrelationKey::Host,
+                stages: vec![stage("Port Scan"), stage("Exploit*")],
+            }],
+        })
+        .unwrap()
+    }
+
+    fn alert(alert_type: &str, id: &str) -> Alert {
+        let mut alert = Alert::new(alert_type, "high", "10.0.0.5", None, "");
+        alert.id = Some(id.to_string());
+        alert
+    }
+
+    #[test]
+    fn stages_in_order_within_the_window_raise_an_incident() {
+        let mut correlator = correlator();
+        let now = Utc::now();
+        assert!(correlator
+            .process(&[alert("Port Scan", "a")], now)
+            .is_empty());
+        let incidents = correlator.process(
+            &[alert("Exploit Attempt", "b")],
+            now + Duration::seconds(60),
+        );
+        assert_eq!(incidents.len(), 1);
+        assert_eq!(incidents[0].related, ["a", "b"]);
+        assert!(correlator
+            .process(&[alert("Exploit Attempt", "c")], now)
+            .is_empty());
+    }
+
+    #[test]
+    fn ongoing_updates_do_not_restart_a_stage() {
+        let mut correlator = correlator();
+        let now = Utc::now();
+        correlator.process(&[alert("Port Scan", "a")], now);
+        let mut update = alert("Port Scan", "b");
+        update.ongoing = true;
+        correlator.process(&[update], now + Duration::seconds(50));
+
+        let late = correlator.process(
+            &[alert("Exploit Attempt", "c")],
+            now + Duration::seconds(90),
+        );
+        assert!(late.is_empty());
+        assert_eq!(correlator.sequences.len(), 0);
+    }
+}
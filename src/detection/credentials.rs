@@ -0,0 +1,282 @@
+//! Cleartext credential detection.
+//!
+//! FTP and POP3 send the username and password in separate commands, and
+//! Telnet logins arrive as keystrokes after server prompts, so this tracker
+//! keeps per-connection state until a complete login has been seen.
+
+use super::detector::Detector;
+use super::state::LruMap;
+use super::Alert;
+use crate::attack;
+use crate::capture::credentials::{CleartextProtocol, CredentialEvent, TelnetPrompt};
+use crate::capture::parser::{AppLayer, ParsedPacket};
+
+const MAX_CONNECTIONS: usize = 10_000;
+const MASKED_PASSWORD: &str = "********";
+/// Rough size of a connection key's text, `a.b.c.d:port->e.f.g.h:port`.
+const CONNECTION_BYTES: usize = 48;
+
+enum TelnetState {
+    Idle,
+    Username(String),
+    Password { username: String, length: usize },
+}
+
+/// Logins in progress are kept for at most `MAX_CONNECTIONS` connections
+/// per protocol family; the least recently active are dropped first.
+pub struct CredentialTracker {
+    pending_users: LruMap<String, String>,
+    telnet: LruMap<String, TelnetState>,
+}
+
+impl Default for CredentialTracker {
+    fn default() -> Self {
+        Self {
+            pending_users: LruMap::new(MAX_CONNECTIONS),
+            telnet: LruMap::new(MAX_CONNECTIONS),
+        }
+    }
+}
+
+impl CredentialTracker {
+    fn inspect(&mut self, packet: &ParsedPacket) -> Vec<Alert> {
+        let Some(AppLayer::Cleartext(auth)) = &packet.app else {
+            return Vec::new();
+        };
+
+        auth.events
+            .iter()
+            .filter_map(|event| self.inspect_event(packet, auth.protocol, event))
+            .collect()
+    }
+
+    fn inspect_event(
+        &mut self,
+        packet: &ParsedPacket,
+        protocol: CleartextProtocol,
+        event: &CredentialEvent,
+    ) -> Option<Alert> {
+        let client_to_server = connection_key(packet, false);
+
+        match event {
+            CredentialEvent::User(username) => {
+                self.pending_users
+                    .insert(client_to_server, username.clone());
+                None
+            }
+            CredentialEvent::Pass { length } => {
+                let username = self
+                    .pending_users
+                    .remove(&client_to_server)
+                    .unwrap_or_else(|| "<unknown>".to_string());
+                Some(credentials_alert(packet, protocol, &username, *length))
+            }
+            CredentialEvent::Login {
+                username,
+                password_length,
+            } => Some(credentials_alert(
+                packet,
+                protocol,
+                username,
+                *password_length,
+            )),
+            CredentialEvent::TelnetPrompt(prompt) => {
+                // Prompts come from the server; key state by the client side.
+                let key = connection_key(packet, true);
+                let state = self.telnet.remove(&key).unwrap_or(TelnetState::Idle);
+                let next = match (prompt, state) {
+                    (TelnetPrompt::Login, _) => TelnetState::Username(String::new()),
+                    (TelnetPrompt::Password, TelnetState::Username(username)) => {
+                        TelnetState::Password {
+                            username: username.trim().to_string(),
+                            length: 0,
+                        }
+                    }
+                    (TelnetPrompt::Password, _) => TelnetState::Password {
+                        username: "<unknown>".to_string(),
+                        length: 0,
+                    },
+                };
+                self.telnet.insert(key, next);
+                None
+            }
+            CredentialEvent::TelnetInput(input) => {
+                let state = self.telnet.get_mut(&client_to_server)?;
+                let submitted = input.contains(['\r', '\n']);
+                let typed = input.trim_end_matches(['\r', '\n', '\0']);
+
+                match state {
+                    TelnetState::Idle => None,
+                    TelnetState::Username(username) => {
+                        username.push_str(typed);
+                        None
+                    }
+                    TelnetState::Password { username, length } => {
+                        *length += typed.len();
+                        if !submitted || *length == 0 {
+                            return None;
+                        }
+                        let username = std::mem::take(username);
+                        let length = *length;
+                        self.telnet.insert(client_to_server, TelnetState::Idle);
+                        Some(credentials_alert(packet, protocol, &username, length))
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn credentials_alert(
+    packet: &ParsedPacket,
+    protocol: CleartextProtocol,
+    username: &str,
+    password_length: usize,
+) -> Alert {
+    let password = if password_length == 0 {
+        "<empty>"
+    } else {
+        MASKED_PASSWORD
+    };
+
+    Alert::new(
+        "Cleartext Credentials",
+        "high",
+        packet.source_ip.clone(),
+        Some(packet.destination_ip.clone()),
+        format!(
+            "{} login sent in cleartext: username={} password={}",
+            protocol.name(),
+            username,
+            password
+        ),
+    )
+    .with_attack(attack::techniques(&[("TA0006", "T1552")]))
+}
+
+/// Connection key oriented client -> server. `from_server` flips the
+/// packet's direction for server-originated traffic.
+fn connection_key(packet: &ParsedPacket, from_server: bool) -> String {
+    let source = format!("{}:{}", packet.source_ip, packet.source_port.unwrap_or(0));
+    let destination = format!(
+        "{}:{}",
+        packet.destination_ip,
+        packet.destination_port.unwrap_or(0)
+    );
+    if from_server {
+        format!("{}->{}", destination, source)
+    } else {
+        format!("{}->{}", source, destination)
+    }
+}
+
+impl Detector for CredentialTracker {
+    fn name(&self) -> &'static str {
+        "cleartext_credentials"
+    }
+
+    fn on_packet(&mut self, packet: &ParsedPacket) -> Vec<Alert> {
+        if packet.app.is_none() {
+            return Vec::new();
+        }
+        self.inspect(packet)
+    }
+
+    fn memory_usage(&self) -> usize {
+        let telnet: usize = self
+            .telnet
+            .values()
+            .map(|state| match state {
+                TelnetState::Idle => 0,
+                TelnetState::Username(username) | TelnetState::Password { username, .. } => {
+                    username.capacity()
+                }
+            })
+            .sum();
+        self.pending_users.len() * (LruMap::<String, String>::entry_overhead() + CONNECTION_BYTES)
+            + self
+                .pending_users
+                .values()
+                .map(String::capacity)
+                .sum::<usize>()
+            + self.telnet.len()
+                * (LruMap::<String, TelnetState>::entry_overhead() + CONNECTION_BYTES)
+            + telnet
+    }
+
+    fn snapshot(&self) -> serde_json::Value {
+        serde_json::json!({
+            "pending_users": self.pending_users.len(),
+            "telnet_sessions": self.telnet.len(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::credentials::CleartextAuth;
+
+    fn ftp(
+        tracker: &mut CredentialTracker,
+        client_port: u16,
+        event: CredentialEvent,
+    ) -> Vec<Alert> {
+        tracker.on_packet(&ParsedPacket {
+            source_ip: "10.0.0.1".to_string(),
+            destination_ip: "10.0.0.2".to_string(),
+            source_port: Some(client_port),
+            destination_port: Some(21),
+            protocol: "TCP".to_string(),
+            size: 0,
+            tcp_flags: None,
+            app: Some(AppLayer::Cleartext(CleartextAuth {
+                protocol: CleartextProtocol::Ftp,
+                events: vec![event],
+            })),
+            payload: Vec::new(),
+        })
+    }
+
+    #[test]
+    fn user_and_pass_are_joined_per_connection() {
+        let mut tracker = CredentialTracker::default();
+        ftp(
+            &mut tracker,
+            40000,
+            CredentialEvent::User("alice".to_string()),
+        );
+        ftp(
+            &mut tracker,
+            40001,
+            CredentialEvent::User("bob".to_string()),
+        );
+        let alerts = ftp(&mut tracker, 40000, CredentialEvent::Pass { length: 6 });
+        assert_eq!(alerts.len(), 1);
+        assert!(alerts[0]
+            .details
+            .contains("username=alice password=********"));
+        assert_eq!(tracker.pending_users.len(), 1);
+    }
+
+    #[test]
+    fn full_table_evicts_the_oldest_login_only() {
+        let mut tracker = CredentialTracker::default();
+        for port in 0..=MAX_CONNECTIONS as u16 {
+            ftp(
+                &mut tracker,
+                port,
+                CredentialEvent::User(format!("user{}", port)),
+            );
+        }
+        assert_eq!(tracker.pending_users.len(), MAX_CONNECTIONS);
+        let usage = tracker.memory_usage();
+        assert!(usage >= MAX_CONNECTIONS * CONNECTION_BYTES);
+
+        let evicted = ftp(&mut tracker, 0, CredentialEvent::Pass { length: 6 });
+        assert!(evicted[0].details.contains("username=<unknown>"));
+        let kept = ftp(&mut tracker, 1, CredentialEvent::Pass { length: 6 });
+        assert!(kept[0].details.contains("username=user1 "));
+        assert!(tracker.memory_usage() < usage);
+    }
+}
@@ -0,0 +1,72 @@
+//! Packet-rate flood detection per source address.
+//!
+//! Per-source counts live in a ring of count-min sketches, so memory stays
+//! fixed however many (possibly spoofed) sources send traffic.
+
+use chrono::Utc;
+
+use super::detector::Detector;
+use super::state::{CountMinSketch, Ring};
+use super::Alert;
+use crate::attack;
+use crate::capture::parser::ParsedPacket;
+use crate::config::DdosConfig;
+
+const BUCKETS: usize = 4;
+
+pub struct DdosDetector {
+    config: DdosConfig,
+    packet_rate_tracker: Ring<CountMinSketch>,
+}
+
+impl DdosDetector {
+    pub fn new(config: DdosConfig) -> Self {
+        let window_ms = config.window_seconds as i64 * 1000;
+        Self {
+            config,
+            packet_rate_tracker: Ring::new(window_ms, BUCKETS),
+        }
+    }
+}
+
+impl Detector for DdosDetector {
+    fn name(&self) -> &'static str {
+        "ddos"
+    }
+
+    fn on_packet(&mut self, packet: &ParsedPacket) -> Vec<Alert> {
+        let now = Utc::now();
+
+        // Add current packet
+        self.packet_rate_tracker
+            .current(now)
+            .add(packet.source_ip.as_str(), 1);
+        let packets = self
+            .packet_rate_tracker
+            .count(packet.source_ip.as_str(), now);
+
+        // Check if threshold exceeded
+        if packets >= self.config.threshold as u64 {
+            let rate = packets as f64 / self.config.window_seconds as f64;
+
+            return vec![Alert::new(
+                "Possible DDoS",
+                "critical",
+                packet.source_ip.clone(),
+                Some(packet.destination_ip.clone()),
+                format!(
+                    "High packet rate detected: {:.0} packets/second (threshold: {})",
+                    rate, self.config.threshold
+                ),
+            )
+            .at(now)
+            .with_attack(attack::techniques(&[("TA0040", "T1498")]))];
+        }
+
+        Vec::new()
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.packet_rate_tracker.len() * CountMinSketch::memory_usage()
+    }
+}
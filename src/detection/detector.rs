@@ -0,0 +1,337 @@
+//! The `Detector` trait and the registry that builds detectors from config.
+//!
+//! Built-in detectors are registered under the name of their section in
+//! `detection:`. Third-party crates register a factory under their own name
+//! and receive the matching entry from `detection.plugins`.
+
+use anyhow::Result;
+
+use super::Alert;
+use crate::capture::flow::FlowRecord;
+use crate::capture::parser::ParsedPacket;
+use crate::config::DetectionConfig;
+use crate::error::NetGuardError;
+use crate::geoip::GeoIp;
+use crate::networks::Networks;
+
+use super::beacon::BeaconDetector;
+use super::credentials::CredentialTracker;
+use super::ddos::DdosDetector;
+use super::dns::{DgaDetector, DnsTunnelDetector};
+use super::exfiltration::ExfiltrationDetector;
+use super::ics::IcsDetector;
+use super::intel::IntelDetector;
+use super::port_scan::PortScanDetector;
+use super::rules::RuleDetector;
+use super::sigma::SigmaDetector;
+use super::smb::SmbTracker;
+use super::spoofing::SpoofingTracker;
+use super::suspicious_port::SuspiciousPortDetector;
+use super::syn_flood::SynFloodDetector;
+use super::volumetric::VolumetricDetector;
+
+pub trait Detector: Send {
+    /// Name shown in logs and state snapshots.
+    fn name(&self) -> &'static str;
+
+    /// Called for every parsed packet. Returns every finding, not just the
+    /// most severe; the engine merges and ranks them.
+    fn on_packet(&mut self, _packet: &ParsedPacket) -> Vec<Alert> {
+        Vec::new()
+    }
+
+    /// Called once for each flow when it closes or goes idle.
+    fn on_flow(&mut self, _flow: &FlowRecord) -> Vec<Alert> {
+        Vec::new()
+    }
+
+    /// Called for every alert the packet and flow hooks raised, before
+    /// merging. Alerts returned here are not fed back to this hook.
+    fn on_alert(&mut self, _alert: &Alert) -> Vec<Alert> {
+        Vec::new()
+    }
+
+    /// Whether the detector already sets severities by traffic direction,
+    /// in which case the engine's `direction_severity` shift leaves its
+    /// alerts alone.
+    fn rates_direction(&self) -> bool {
+        false
+    }
+
+    /// Approximate bytes of state held, for status output.
+    fn memory_usage(&self) -> usize {
+        0
+    }
+
+    /// Summary of internal state, for debugging and status output.
+    fn snapshot(&self) -> serde_json::Value {
+        serde_json::Value::Null
+    }
+}
+
+/// Builds a detector from the detection config and, for plugins, its own
+/// section under `detection.plugins`. Returns `None` when disabled.
+pub type DetectorFactory =
+    fn(&DetectionConfig, Option<&serde_yaml::Value>) -> Result<Option<Box<dyn Detector>>>;
+
+pub struct DetectorRegistry {
+    factories: Vec<(String, DetectorFactory)>,
+}
+
+impl Default for DetectorRegistry {
+    fn default() -> Self {
+        Self::with_builtin()
+    }
+}
+
+impl DetectorRegistry {
+    /// An empty registry.
+    pub fn new() -> Self {
+        Self {
+            factories: Vec::new(),
+        }
+    }
+
+    /// A registry holding every built-in detector, in the default order.
+    pub fn with_builtin() -> Self {
+        let mut registry = Self::new();
+        registry.register("spoofing", |config, _| {
+            enabled(config.spoofing.enabled, || {
+                Ok(SpoofingTracker::new(config.spoofing.clone()))
+            })
+        });
+        registry.register("port_scan", |config, _| {
+            enabled(config.port_scan.enabled, || {
+                Ok(PortScanDetector::new(config.port_scan.clone()))
+            })
+        });
+        registry.register("ddos", |config, _| {
+            enabled(config.ddos.enabled, || {
+                Ok(DdosDetector::new(config.ddos.clone()))
+            })
+        });
+        registry.register("syn_flood", |config, _| {
+            enabled(config.syn_flood.enabled, || {
+                Ok(SynFloodDetector::new(config.syn_flood.clone()))
+            })
+        });
+        registry.register("volumetric", |config, _| {
+            enabled(config.volumetric.enabled, || {
+                Ok(VolumetricDetector::new(config.volumetric.clone()))
+            })
+        });
+        registry.register("beacon", |config, _| {
+            enabled(config.beacon.enabled, || {
+                Ok(BeaconDetector::new(
+                    config.beacon.clone(),
+                    Networks::from_config(&config.networks)?,
+                ))
+            })
+        });
+        registry.register("dns_tunneling", |config, _| {
+            enabled(config.dns_tunneling.enabled, || {
+                Ok(DnsTunnelDetector::new(config.dns_tunneling.clone()))
+            })
+        });
+        registry.register("dga", |config, _| {
+            enabled(config.dga.enabled, || {
+                Ok(DgaDetector::new(config.dga.clone()))
+            })
+        });
+        registry.register("exfiltration", |config, _| {
+            enabled(config.exfiltration.enabled, || {
+                Ok(ExfiltrationDetector::new(
+                    config.exfiltration.clone(),
+                    Networks::from_config(&config.networks)?,
+                ))
+            })
+        });
+        registry.register("rules", |config, _| {
+            if !config.rules.enabled || config.rules.files.is_empty() {
+                return Ok(None);
+            }
+            let geoip = GeoIp::shared(&config.geoip)?;
+            let networks = Networks::from_config(&config.networks)?;
+            Ok(Some(Box::new(RuleDetector::load(
+                &config.rules,
+                &networks,
+                geoip,
+            )?)))
+        });
+        registry.register("sigma", |config, _| {
+            if !config.sigma.enabled || config.sigma.files.is_empty() {
+                return Ok(None);
+            }
+            let geoip = GeoIp::shared(&config.geoip)?;
+            let networks = Networks::from_config(&config.networks)?;
+            Ok(Some(Box::new(SigmaDetector::load(
+                &config.sigma,
+                networks,
+                geoip,
+            )?)))
+        });
+        registry.register("intel", |config, _| {
+            if !config.intel.enabled || config.intel.feeds.is_empty() {
+                return Ok(None);
+            }
+            Ok(Some(Box::new(IntelDetector::load(&config.intel)?)))
+        });
+        registry.register("smb", |config, _| {
+            enabled(config.smb.enabled, || {
+                Ok(SmbTracker::new(config.smb.clone()))
+            })
+        });
+        registry.register("cleartext_credentials", |config, _| {
+            enabled(config.cleartext_credentials.enabled, || {
+                Ok(CredentialTracker::default())
+            })
+        });
+        registry.register("ics", |config, _| {
+            enabled(config.ics.enabled, || {
+                Ok(IcsDetector::new(config.ics.clone()))
+            })
+        });
+        registry.register("suspicious_port", |config, _| {
+            enabled(!config.suspicious_ports.is_empty(), || {
+                Ok(SuspiciousPortDetector::new(
+                    config.suspicious_ports.clone(),
+                    Networks::from_config(&config.networks)?,
+                ))
+            })
+        });
+        registry
+    }
+
+    /// Registers a detector factory. Registering an existing name replaces
+    /// the previous factory, which lets plugins override a built-in.
+    pub fn register(&mut self, name: &str, factory: DetectorFactory) {
+        if let Some(entry) = self
+            .factories
+            .iter_mut()
+            .find(|(existing, _)| existing == name)
+        {
+            entry.1 = factory;
+        } else {
+            self.factories.push((name.to_string(), factory));
+        }
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.factories.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Instantiates the enabled detectors. Names listed in `detection.order`
+    /// run first, in that order; the rest follow in registration order. A
+    /// name listed twice is a config error.
+    pub fn build(&self, config: &DetectionConfig) -> Result<Vec<Box<dyn Detector>>> {
+        for name in config.order.iter().chain(&config.disabled) {
+            if !self.names().any(|registered| registered == name) {
+                return Err(
+                    NetGuardError::ConfigError(format!("Unknown detector: {}", name)).into(),
+                );
+            }
+        }
+        for (index, name) in config.order.iter().enumerate() {
+            if config.order[..index].contains(name) {
+                return Err(NetGuardError::ConfigError(format!(
+                    "Detector {} is listed more than once in detection.order",
+                    name
+                ))
+                .into());
+            }
+        }
+
+        let mut ordered: Vec<&(String, DetectorFactory)> = config
+            .order
+            .iter()
+            .filter_map(|name| {
+                self.factories
+                    .iter()
+                    .find(|(registered, _)| registered == name)
+            })
+            .collect();
+        for entry in &self.factories {
+            if !config.order.contains(&entry.0) {
+                ordered.push(entry);
+            }
+        }
+
+        let mut detectors = Vec::new();
+        for (name, factory) in ordered {
+            if config.disabled.contains(name) {
+                continue;
+            }
+
+            let section = config.plugins.get(name);
+            if section
+                .and_then(|section| section.get("enabled"))
+                .and_then(serde_yaml::Value::as_bool)
+                == Some(false)
+            {
+                continue;
+            }
+
+            let detector = factory(config, section)
+                .map_err(|e| NetGuardError::ConfigError(format!("Detector {}: {}", name, e)))?;
+            if let Some(detector) = detector {
+                log::debug!("Loaded detector {}", name);
+                detectors.push(detector);
+            }
+        }
+
+        Ok(detectors)
+    }
+}
+
+/// Builds the detector only when `on`, so a disabled detector never
+/// allocates its state or reads its config.
+fn enabled<D: Detector + 'static>(
+    on: bool,
+    detector: impl FnOnce() -> Result<D>,
+) -> Result<Option<Box<dyn Detector>>> {
+    if !on {
+        return Ok(None);
+    }
+    Ok(Some(Box::new(detector()?)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn names(detectors: &[Box<dyn Detector>]) -> Vec<&'static str> {
+        detectors.iter().map(|detector| detector.name()).collect()
+    }
+
+    #[test]
+    fn listed_detectors_run_first() {
+        let mut config = Config::default().detection;
+        config.order = vec!["suspicious_port".to_string(), "ddos".to_string()];
+        let detectors = DetectorRegistry::with_builtin().build(&config).unwrap();
+        let names = names(&detectors);
+        assert_eq!(names[..2], ["suspicious_port", "ddos"]);
+        assert_eq!(names.iter().filter(|name| **name == "ddos").count(), 1);
+    }
+
+    #[test]
+    fn a_name_listed_twice_is_rejected() {
+        let mut config = Config::default().detection;
+        config.order = vec![
+            "ddos".to_string(),
+            "port_scan".to_string(),
+            "ddos".to_string(),
+        ];
+        let Err(error) = DetectorRegistry::with_builtin().build(&config) else {
+            panic!("duplicate accepted");
+        };
+        assert!(error.to_string().contains("ddos"));
+    }
+
+    #[test]
+    fn disabled_detectors_are_never_built() {
+        let built = enabled::<PortScanDetector>(false, || anyhow::bail!("constructed")).unwrap();
+        assert!(built.is_none());
+        assert!(enabled::<PortScanDetector>(true, || anyhow::bail!("constructed")).is_err());
+    }
+}
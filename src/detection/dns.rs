@@ -0,0 +1,522 @@
+//! DNS tunneling and DGA (algorithmically generated domain) detection.
+//!
+//! Tunnels encode data in query names under a domain the attacker controls,
+//! which shows up as many queries with long, high-entropy and mostly unique
+//! subdomains, often asking for TXT or NULL records to get data back. This
+//! is checked per client and domain, and per domain across all clients.
+//!
+//! DGA malware looks up many pseudo-random domains hoping one is registered.
+//! Names are scored against a character bigram model trained on common
+//! domain labels; a client resolving several names the model finds
+//! implausible within the window is reported.
+
+use chrono::{DateTime, Duration, Utc};
+use std::collections::VecDeque;
+use std::mem::size_of;
+
+use super::detector::Detector;
+use super::state::{HyperLogLog, LruMap, Ring};
+use super::Alert;
+use crate::attack;
+use crate::capture::dns::{self, DnsMessage, RCODE_NXDOMAIN, TYPE_NULL, TYPE_TXT};
+use crate::capture::parser::{AppLayer, ParsedPacket};
+use crate::config::{DgaConfig, DnsTunnelingConfig};
+
+const MAX_CLIENT_DOMAINS: usize = 10_000;
+const MAX_DOMAINS: usize = 10_000;
+const MAX_CLIENTS: usize = 10_000;
+const WINDOW_BUCKETS: usize = 4;
+/// Subdomains shorter than this are not judged on entropy.
+const MIN_ENTROPY_LENGTH: usize = 24;
+/// Suspicious names kept per client for alert details.
+const MAX_SAMPLES: usize = 5;
+/// Typical heap size of an address or domain string, for memory estimates.
+const STRING_BYTES: usize = 40;
+
+/// Labels of popular sites, plus the hosting and dynamic DNS providers in the
+/// private section of the Public Suffix List. Against the default threshold
+/// of -1.6, legitimate labels held out of training scored no lower than
+/// -1.54, while about 90% of random 8-24 character labels scored below it.
+const CORPUS: &str = include_str!("data/domain_corpus.txt");
+
+/// Second-level labels under which registrations happen on many ccTLDs
+/// (example.co.uk, example.com.au).
+const SECOND_LEVEL_LABELS: &[&str] = &["ac", "co", "com", "edu", "gov", "net", "org"];
+
+/// Names that are never sent to a public resolver or carry encoded data by
+/// design.
+const IGNORED_SUFFIXES: &[&str] = &["arpa", "local", "localhost", "internal", "lan", "home"];
+
+struct ClientDomain {
+    queries: Ring<u64>,
+    subdomains: Ring<HyperLogLog>,
+    txt_null: Ring<u64>,
+    /// Queries with an over-long label or a high-entropy subdomain.
+    suspicious: Ring<u64>,
+    example: Option<String>,
+}
+
+pub struct DnsTunnelDetector {
+    config: DnsTunnelingConfig,
+    clients: LruMap<(String, String), ClientDomain>,
+    domains: LruMap<String, Ring<HyperLogLog>>,
+}
+
+impl DnsTunnelDetector {
+    pub fn new(config: DnsTunnelingConfig) -> Self {
+        Self {
+            config,
+            clients: LruMap::new(MAX_CLIENT_DOMAINS),
+            domains: LruMap::new(MAX_DOMAINS),
+        }
+    }
+
+    fn window_ms(&self) -> i64 {
+        Duration::seconds(self.config.window_seconds as i64).num_milliseconds()
+    }
+
+    fn on_query(&mut self, packet: &ParsedPacket, name: &str, qtype: u16) -> Vec<Alert> {
+        let Some(domain) = registered_domain(name) else {
+            return Vec::new();
+        };
+        if is_ignored(name, &self.config.ignored_domains) {
+            return Vec::new();
+        }
+        let subdomain = name
+            .strip_suffix(domain.as_str())
+            .unwrap_or("")
+            .trim_end_matches('.');
+        let now = Utc::now();
+        let window_ms = self.window_ms();
+        let suspicious = subdomain
+            .split('.')
+            .any(|label| label.len() >= self.config.max_label_length)
+            || (subdomain.len() >= MIN_ENTROPY_LENGTH
+                && entropy(&subdomain.replace('.', "")) >= self.config.entropy_threshold);
+
+        let mut alerts = Vec::new();
+        let entry =
+            self.clients
+                .get_or_insert_with((packet.source_ip.clone(), domain.clone()), || {
+                    ClientDomain {
+                        queries: Ring::new(window_ms, WINDOW_BUCKETS),
+                        subdomains: Ring::new(window_ms, WINDOW_BUCKETS),
+                        txt_null: Ring::new(window_ms, WINDOW_BUCKETS),
+                        suspicious: Ring::new(window_ms, WINDOW_BUCKETS),
+                        example: None,
+                    }
+                });
+        entry.queries.add(now, 1);
+        if !subdomain.is_empty() {
+            entry.subdomains.current(now).insert(subdomain);
+        }
+        if qtype == TYPE_TXT || qtype == TYPE_NULL {
+            entry.txt_null.add(now, 1);
+        }
+        if suspicious {
+            entry.suspicious.add(now, 1);
+            entry.example = Some(name.to_string());
+        }
+
+        let queries = entry.queries.total(now);
+        let unique = entry.subdomains.distinct(now);
+        let txt_null = entry.txt_null.total(now);
+        let suspicious_queries = entry.suspicious.total(now);
+
+        let mut reasons = Vec::new();
+        if queries >= self.config.client_queries_per_domain {
+            reasons.push(format!("{} queries", queries));
+        }
+        if unique >= self.config.client_unique_subdomains {
+            reasons.push(format!("~{} unique subdomains", unique));
+        }
+        if txt_null >= self.config.txt_null_queries {
+            reasons.push(format!("{} TXT/NULL queries", txt_null));
+        }
+        if suspicious_queries >= self.config.suspicious_queries {
+            reasons.push(format!("{} long or high-entropy names", suspicious_queries));
+        }
+        if !reasons.is_empty() {
+            let example = entry.example.as_deref().unwrap_or(name);
+            alerts.push(
+                Alert::new(
+                    "DNS Tunneling",
+                    if reasons.len() > 1 { "high" } else { "medium" },
+                    packet.source_ip.clone(),
+                    Some(packet.destination_ip.clone()),
+                    format!(
+                        "Possible DNS tunnel via {} in the last {}s: {} (e.g. {} {})",
+                        domain,
+                        self.config.window_seconds,
+                        reasons.join(", "),
+                        dns::type_name(qtype),
+                        example
+                    ),
+                )
+                .at(now)
+                .with_attack(attack::techniques(&[
+                    ("TA0011", "T1071.004"),
+                    ("TA0010", "T1048"),
+                ])),
+            );
+        }
+
+        if !subdomain.is_empty() {
+            let ring = self
+                .domains
+                .get_or_insert_with(domain.clone(), || Ring::new(window_ms, WINDOW_BUCKETS));
+            ring.current(now).insert(subdomain);
+            let unique = ring.distinct(now);
+            if unique >= self.config.domain_unique_subdomains {
+                alerts.push(
+                    Alert::new(
+                        "DNS Tunneling",
+                        "high",
+                        "multiple",
+                        None,
+                        format!(
+                        "~{} unique subdomains of {} queried across all clients in the last {}s",
+                        unique, domain, self.config.window_seconds
+                    ),
+                    )
+                    .at(now)
+                    .with_attack(attack::techniques(&[
+                        ("TA0011", "T1071.004"),
+                        ("TA0010", "T1048"),
+                    ])),
+                );
+            }
+        }
+
+        alerts
+    }
+}
+
+impl Detector for DnsTunnelDetector {
+    fn name(&self) -> &'static str {
+        "dns_tunneling"
+    }
+
+    fn on_packet(&mut self, packet: &ParsedPacket) -> Vec<Alert> {
+        let Some(AppLayer::Dns(message)) = &packet.app else {
+            return Vec::new();
+        };
+        if message.response {
+            return Vec::new();
+        }
+        match message.questions.first() {
+            Some(question) => self.on_query(packet, &question.name, question.qtype),
+            None => Vec::new(),
+        }
+    }
+
+    fn snapshot(&self) -> serde_json::Value {
+        serde_json::json!({
+            "tracked_client_domains": self.clients.len(),
+            "tracked_domains": self.domains.len(),
+        })
+    }
+
+    fn memory_usage(&self) -> usize {
+        let client = LruMap::<(String, String), ClientDomain>::entry_overhead()
+            + 2 * STRING_BYTES
+            + WINDOW_BUCKETS * (3 * size_of::<(i64, u64)>() + size_of::<(i64, HyperLogLog)>());
+        let domain = LruMap::<String, Ring<HyperLogLog>>::entry_overhead()
+            + STRING_BYTES
+            + WINDOW_BUCKETS * size_of::<(i64, HyperLogLog)>();
+        self.clients.len() * client + self.domains.len() * domain
+    }
+}
+
+struct ClientNames {
+    domains: Ring<HyperLogLog>,
+    nxdomain: Ring<u64>,
+    samples: VecDeque<String>,
+}
+
+pub struct DgaDetector {
+    config: DgaConfig,
+    model: BigramModel,
+    clients: LruMap<String, ClientNames>,
+}
+
+impl DgaDetector {
+    pub fn new(config: DgaConfig) -> Self {
+        Self {
+            config,
+            model: BigramModel::train(CORPUS),
+            clients: LruMap::new(MAX_CLIENTS),
+        }
+    }
+
+    /// The label that was registered (`example` in `www.example.co.uk`) if
+    /// it scores as generated.
+    fn generated_label(&self, name: &str) -> Option<(String, f64)> {
+        let domain = registered_domain(name)?;
+        if is_ignored(name, &self.config.ignored_domains) {
+            return None;
+        }
+        let label = domain.split('.').next()?;
+        if label.len() < self.config.min_length {
+            return None;
+        }
+        let score = self.model.score(label);
+        (score < self.config.score_threshold).then(|| (domain.clone(), score))
+    }
+
+    fn record(
+        &mut self,
+        client: &str,
+        packet: &ParsedPacket,
+        domain: String,
+        score: f64,
+        nxdomain: bool,
+        now: DateTime<Utc>,
+    ) -> Option<Alert> {
+        let window_ms = Duration::seconds(self.config.window_seconds as i64).num_milliseconds();
+        let entry = self
+            .clients
+            .get_or_insert_with(client.to_string(), || ClientNames {
+                domains: Ring::new(window_ms, WINDOW_BUCKETS),
+                nxdomain: Ring::new(window_ms, WINDOW_BUCKETS),
+                samples: VecDeque::new(),
+            });
+        if nxdomain {
+            entry.nxdomain.add(now, 1);
+            return None;
+        }
+        entry.domains.current(now).insert(&domain);
+        if !entry.samples.contains(&domain) {
+            if entry.samples.len() >= MAX_SAMPLES {
+                entry.samples.pop_front();
+            }
+            entry.samples.push_back(domain);
+        }
+
+        let distinct = entry.domains.distinct(now);
+        if distinct < self.config.client_threshold {
+            return None;
+        }
+        let samples: Vec<&str> = entry.samples.iter().map(String::as_str).collect();
+        Some(Alert::new(
+            "DGA Domains",
+            "high",
+            client,
+            Some(packet.destination_ip.clone()),
+            format!(
+                "~{} algorithmically generated-looking domains queried in the last {}s ({} NXDOMAIN), latest score {:.2}: {}",
+                distinct,
+                self.config.window_seconds,
+                entry.nxdomain.total(now),
+                score,
+                samples.join(", ")
+            ),
+        )
+        .at(now)
+        .with_attack(attack::techniques(&[("TA0011", "T1568.002")])))
+    }
+
+    fn inspect(&mut self, packet: &ParsedPacket, message: &DnsMessage) -> Option<Alert> {
+        let question = message.questions.first()?;
+        let (domain, score) = self.generated_label(&question.name)?;
+        // Failed lookups are the usual fate of generated names; the client
+        // is the destination of the response.
+        if message.response && message.rcode != RCODE_NXDOMAIN {
+            return None;
+        }
+        let client = if message.response {
+            &packet.destination_ip
+        } else {
+            &packet.source_ip
+        };
+        self.record(client, packet, domain, score, message.response, Utc::now())
+    }
+}
+
+impl Detector for DgaDetector {
+    fn name(&self) -> &'static str {
+        "dga"
+    }
+
+    fn on_packet(&mut self, packet: &ParsedPacket) -> Vec<Alert> {
+        match &packet.app {
+            Some(AppLayer::Dns(message)) => self.inspect(packet, message).into_iter().collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn snapshot(&self) -> serde_json::Value {
+        serde_json::json!({ "tracked_clients": self.clients.len() })
+    }
+
+    fn memory_usage(&self) -> usize {
+        let client = LruMap::<String, ClientNames>::entry_overhead()
+            + STRING_BYTES
+            + WINDOW_BUCKETS * (size_of::<(i64, u64)>() + size_of::<(i64, HyperLogLog)>())
+            + MAX_SAMPLES * (size_of::<String>() + STRING_BYTES);
+        self.clients.len() * client
+    }
+}
+
+/// Symbols the model knows: a-z, 0-9, '-', and a boundary marker.
+const SYMBOLS: usize = 38;
+const BOUNDARY: usize = SYMBOLS - 1;
+
+fn symbol(c: char) -> Option<usize> {
+    match c {
+        'a'..='z' => Some(c as usize - 'a' as usize),
+        '0'..='9' => Some(26 + c as usize - '0' as usize),
+        '-' => Some(36),
+        _ => None,
+    }
+}
+
+/// Character bigram model with add-one smoothing.
+struct BigramModel {
+    log_probability: Vec<f64>,
+}
+
+impl BigramModel {
+    fn train(corpus: &str) -> Self {
+        let mut counts = vec![1u32; SYMBOLS * SYMBOLS];
+        for word in corpus.split_whitespace() {
+            let mut previous = BOUNDARY;
+            for current in word.chars().filter_map(symbol).chain([BOUNDARY]) {
+                counts[previous * SYMBOLS + current] += 1;
+                previous = current;
+            }
+        }
+        let mut log_probability = vec![0.0; SYMBOLS * SYMBOLS];
+        for row in 0..SYMBOLS {
+            let cells = &counts[row * SYMBOLS..(row + 1) * SYMBOLS];
+            let total: u32 = cells.iter().sum();
+            for (column, count) in cells.iter().enumerate() {
+                log_probability[row * SYMBOLS + column] = (*count as f64 / total as f64).log10();
+            }
+        }
+        Self { log_probability }
+    }
+
+    /// Average log10 probability per transition; lower is less word-like.
+    fn score(&self, label: &str) -> f64 {
+        let mut previous = BOUNDARY;
+        let mut sum = 0.0;
+        let mut transitions = 0;
+        for current in label.chars().filter_map(symbol).chain([BOUNDARY]) {
+            sum += self.log_probability[previous * SYMBOLS + current];
+            transitions += 1;
+            previous = current;
+        }
+        sum / transitions as f64
+    }
+}
+
+/// Shannon entropy in bits per character.
+fn entropy(text: &str) -> f64 {
+    let mut counts = [0u32; 256];
+    for byte in text.bytes() {
+        counts[byte as usize] += 1;
+    }
+    let len = text.len() as f64;
+    counts
+        .iter()
+        .filter(|count| **count > 0)
+        .map(|count| {
+            let p = *count as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// The registrable domain of a name (`example.com`, `example.co.uk`), or
+/// None for single-label names such as browser NXDOMAIN probes.
+fn registered_domain(name: &str) -> Option<String> {
+    let labels: Vec<&str> = name.trim_end_matches('.').split('.').collect();
+    if labels.len() < 2 || labels.iter().any(|label| label.is_empty()) {
+        return None;
+    }
+    let tld = labels[labels.len() - 1];
+    if IGNORED_SUFFIXES.contains(&tld) {
+        return None;
+    }
+    let keep = if labels.len() >= 3
+        && tld.len() == 2
+        && SECOND_LEVEL_LABELS.contains(&labels[labels.len() - 2])
+    {
+        3
+    } else {
+        2
+    };
+    Some(labels[labels.len().saturating_sub(keep)..].join("."))
+}
+
+/// Whether `name` is one of the configured domains or a subdomain of one.
+fn is_ignored(name: &str, ignored: &[String]) -> bool {
+    ignored.iter().any(|suffix| {
+        name == suffix
+            || name
+                .strip_suffix(suffix.as_str())
+                .is_some_and(|rest| rest.ends_with('.'))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bigram_model_separates_real_and_generated_labels() {
+        let model = BigramModel::train(CORPUS);
+        let threshold = DgaConfig::default().score_threshold;
+        for label in [
+            "google",
+            "microsoft",
+            "cloudflare",
+            "duckduckgo",
+            "office365",
+            "wolframalpha",
+            "raspberrypi",
+            "instructure",
+        ] {
+            let score = model.score(label);
+            assert!(score > threshold, "{} scored {}", label, score);
+        }
+        for label in [
+            "kq3x9vz1pl",
+            "xjwqkzpv",
+            "qwxzvbnmlk",
+            "4a9f0c2e71bd",
+            "vgtwlpqzrkhd",
+        ] {
+            let score = model.score(label);
+            assert!(score < threshold, "{} scored {}", label, score);
+        }
+    }
+
+    #[test]
+    fn generated_label_judges_the_registered_label() {
+        let detector = DgaDetector::new(DgaConfig::default());
+        assert!(detector.generated_label("www.kq3x9vz1pl.com").is_some());
+        assert!(detector.generated_label("kq3x9vz1pl.example.com").is_none());
+        assert!(detector.generated_label("www.duckduckgo.com").is_none());
+        // Too short to judge.
+        assert!(detector.generated_label("xjwqkzp.net").is_none());
+    }
+
+    #[test]
+    fn registered_domains() {
+        assert_eq!(
+            registered_domain("www.example.com").as_deref(),
+            Some("example.com")
+        );
+        assert_eq!(
+            registered_domain("a.b.example.co.uk").as_deref(),
+            Some("example.co.uk")
+        );
+        assert_eq!(
+            registered_domain("example.co").as_deref(),
+            Some("example.co")
+        );
+        assert_eq!(registered_domain("localhost"), None);
+    }
+}
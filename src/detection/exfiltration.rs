@@ -0,0 +1,356 @@
+//! Data exfiltration detection from outbound volume.
+//!
+//! Bytes are counted per internal host and external destination in both
+//! directions, over a short window and over a day. A host is reported when
+//! it uploads a large amount to one destination while downloading little
+//! back, when its total upload in the window is far above its own history,
+//! or when a lopsided upload to one destination accumulates over the day
+//! without any single window standing out (slow-and-low exfiltration).
+
+use chrono::{DateTime, Duration, Utc};
+use std::mem::size_of;
+
+use super::detector::Detector;
+use super::state::{Ewma, LruMap, Ring};
+use super::Alert;
+use crate::attack;
+use crate::capture::parser::ParsedPacket;
+use crate::networks::{Direction, Networks};
+use crate::config::ExfiltrationConfig;
+
+const MAX_PAIRS: usize = 10_000;
+const MAX_HOSTS: usize = 10_000;
+const WINDOW_BUCKETS: usize = 6;
+const DAY_BUCKETS: usize = 24;
+const DAY_SECONDS: i64 = 86_400;
+/// Idle windows fed to the baseline as zero samples after a gap.
+const MAX_IDLE_WINDOWS: i64 = 24;
+/// Typical heap size of an address string, for memory estimates.
+const IP_STRING_BYTES: usize = 40;
+
+/// Traffic between one internal host and one external destination.
+struct Pair {
+    upload: Ring<u64>,
+    download: Ring<u64>,
+    day_upload: Ring<u64>,
+    day_download: Ring<u64>,
+    last_alert: Option<DateTime<Utc>>,
+}
+
+/// One internal host's total upload, against its own history.
+#[derive(Default)]
+struct Host {
+    /// Index of the window `current` belongs to.
+    window: i64,
+    current: u64,
+    baseline: Ewma,
+    last_alert: Option<DateTime<Utc>>,
+}
+
+pub struct ExfiltrationDetector {
+    config: ExfiltrationConfig,
+    pairs: LruMap<(String, String), Pair>,
+    hosts: LruMap<String, Host>,
+    networks: Networks,
+}
+
+impl ExfiltrationDetector {
+    pub fn new(config: ExfiltrationConfig, networks: Networks) -> Self {
+        Self {
+            config,
+            networks,
+            pairs: LruMap::new(MAX_PAIRS),
+            hosts: LruMap::new(MAX_HOSTS),
+        }
+    }
+
+    fn on_upload(&mut self, packet: &ParsedPacket, now: DateTime<Utc>) -> Vec<Alert> {
+        let bytes = packet.size as u64;
+        let host = packet.source_ip.as_str();
+        let remote = packet.destination_ip.as_str();
+        let config = &self.config;
+        let realert = Duration::seconds(config.realert_seconds as i64);
+        let mut alerts = Vec::new();
+
+        let pair = pair(&mut self.pairs, config, host, remote);
+        pair.upload.add(now, bytes);
+        pair.day_upload.add(now, bytes);
+        let ready = pair.last_alert.is_none_or(|last| now - last >= realert);
+        if ready {
+            let upload = pair.upload.total(now);
+            let download = pair.download.total(now);
+            let day_upload = pair.day_upload.total(now);
+            let day_download = pair.day_download.total(now);
+
+            let details = if upload >= config.transfer_bytes
+                && ratio(upload, download) >= config.ratio_threshold
+            {
+                Some(format!(
+                    "Uploaded {} to {} in the last {}s while downloading {} (ratio {:.1})",
+                    format_bytes(upload),
+                    remote,
+                    config.window_seconds,
+                    format_bytes(download),
+                    ratio(upload, download)
+                ))
+            } else if day_upload >= config.daily_bytes
+                && ratio(day_upload, day_download) >= config.ratio_threshold
+            {
+                Some(format!(
+                    "Slow upload to {}: {} over the last 24h while downloading {} (ratio {:.1})",
+                    remote,
+                    format_bytes(day_upload),
+                    format_bytes(day_download),
+                    ratio(day_upload, day_download)
+                ))
+            } else {
+                None
+            };
+
+            if let Some(details) = details {
+                pair.last_alert = Some(now);
+                alerts.push(exfiltration_alert(host, Some(remote), "high", details, now));
+            }
+        }
+
+        if let Some(alert) = self.check_host(host, bytes, now) {
+            alerts.push(alert);
+        }
+        alerts
+    }
+
+    /// Adds upload bytes to the host's current window, folding finished
+    /// windows into its baseline, and checks the window against it.
+    fn check_host(&mut self, host: &str, bytes: u64, now: DateTime<Utc>) -> Option<Alert> {
+        let config = &self.config;
+        let window_seconds = config.window_seconds.max(1) as i64;
+        let window = now.timestamp().div_euclid(window_seconds);
+        let entry = self.hosts.get_or_insert_with(host.to_string(), || Host {
+            window,
+            ..Host::default()
+        });
+
+        if entry.window != window {
+            let finished = entry.current as f64;
+            if !is_anomalous(config, &entry.baseline, entry.current) {
+                entry.baseline.update(finished, config.ewma_alpha);
+            }
+            let idle = (window - entry.window - 1).clamp(0, MAX_IDLE_WINDOWS);
+            for _ in 0..idle {
+                entry.baseline.update(0.0, config.ewma_alpha);
+            }
+            entry.window = window;
+            entry.current = 0;
+        }
+        entry.current += bytes;
+
+        let realert = Duration::seconds(config.realert_seconds as i64);
+        if !is_anomalous(config, &entry.baseline, entry.current)
+            || entry.last_alert.is_some_and(|last| now - last < realert)
+        {
+            return None;
+        }
+        entry.last_alert = Some(now);
+        Some(exfiltration_alert(
+            host,
+            None,
+            "medium",
+            format!(
+                "Uploaded {} in the current {}s window, against a usual {} (+/- {})",
+                format_bytes(entry.current),
+                config.window_seconds,
+                format_bytes(entry.baseline.mean as u64),
+                format_bytes(entry.baseline.stddev() as u64)
+            ),
+            now,
+        ))
+    }
+}
+
+impl Detector for ExfiltrationDetector {
+    fn name(&self) -> &'static str {
+        "exfiltration"
+    }
+
+    fn on_packet(&mut self, packet: &ParsedPacket) -> Vec<Alert> {
+        let now = Utc::now();
+        match self
+            .networks
+            .direction(&packet.source_ip, &packet.destination_ip)
+        {
+            Direction::Outbound => self.on_upload(packet, now),
+            Direction::Inbound => {
+                let bytes = packet.size as u64;
+                let pair = pair(
+                    &mut self.pairs,
+                    &self.config,
+                    &packet.destination_ip,
+                    &packet.source_ip,
+                );
+                pair.download.add(now, bytes);
+                pair.day_download.add(now, bytes);
+                Vec::new()
+            }
+            Direction::Internal | Direction::External => Vec::new(),
+        }
+    }
+
+    fn snapshot(&self) -> serde_json::Value {
+        serde_json::json!({
+            "tracked_pairs": self.pairs.len(),
+            "tracked_hosts": self.hosts.len(),
+        })
+    }
+
+    fn memory_usage(&self) -> usize {
+        let pair = LruMap::<(String, String), Pair>::entry_overhead()
+            + 2 * IP_STRING_BYTES
+            + 2 * (WINDOW_BUCKETS + DAY_BUCKETS) * size_of::<(i64, u64)>();
+        let host = LruMap::<String, Host>::entry_overhead() + IP_STRING_BYTES;
+        self.pairs.len() * pair + self.hosts.len() * host
+    }
+}
+
+fn pair<'a>(
+    pairs: &'a mut LruMap<(String, String), Pair>,
+    config: &ExfiltrationConfig,
+    host: &str,
+    remote: &str,
+) -> &'a mut Pair {
+    let window_ms = Duration::seconds(config.window_seconds as i64).num_milliseconds();
+    let day_ms = Duration::seconds(DAY_SECONDS).num_milliseconds();
+    pairs.get_or_insert_with((host.to_string(), remote.to_string()), || Pair {
+        upload: Ring::new(window_ms, WINDOW_BUCKETS),
+        download: Ring::new(window_ms, WINDOW_BUCKETS),
+        day_upload: Ring::new(day_ms, DAY_BUCKETS),
+        day_download: Ring::new(day_ms, DAY_BUCKETS),
+        last_alert: None,
+    })
+}
+
+/// Whether a window's upload is far above the host's learned baseline.
+fn is_anomalous(config: &ExfiltrationConfig, baseline: &Ewma, bytes: u64) -> bool {
+    baseline.samples >= config.min_baseline_windows
+        && bytes >= config.min_anomaly_bytes
+        && bytes as f64 > baseline.mean + config.spike_stddev * baseline.stddev().max(1.0)
+}
+
+fn ratio(upload: u64, download: u64) -> f64 {
+    upload as f64 / download.max(1) as f64
+}
+
+fn exfiltration_alert(
+    host: &str,
+    remote: Option<&str>,
+    severity: &str,
+    details: String,
+    now: DateTime<Utc>,
+) -> Alert {
+    Alert::new(
+        "Data Exfiltration",
+        severity,
+        host,
+        remote.map(str::to_string),
+        details,
+    )
+    .at(now)
+    .with_attack(attack::techniques(&[("TA0010", "T1048")]))
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networks::default_groups;
+    use chrono::TimeZone;
+
+    fn detector(config: ExfiltrationConfig) -> ExfiltrationDetector {
+        ExfiltrationDetector::new(config, Networks::from_config(&default_groups()).unwrap())
+    }
+
+    fn config() -> ExfiltrationConfig {
+        ExfiltrationConfig {
+            window_seconds: 60,
+            transfer_bytes: 1_000,
+            daily_bytes: u64::MAX,
+            min_anomaly_bytes: 100,
+            ewma_alpha: 0.5,
+            min_baseline_windows: 3,
+            ..ExfiltrationConfig::default()
+        }
+    }
+
+    fn at(seconds: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(1_700_000_040 + seconds, 0).unwrap()
+    }
+
+    fn upload(size: usize) -> ParsedPacket {
+        ParsedPacket {
+            source_ip: "10.0.0.5".to_string(),
+            destination_ip: "203.0.113.7".to_string(),
+            source_port: Some(50000),
+            destination_port: Some(443),
+            protocol: "TCP".to_string(),
+            size,
+            tcp_flags: Some(0x18),
+            app: None,
+            payload: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn lopsided_upload_is_reported_at_the_transfer_size() {
+        let mut exfiltration = detector(config());
+        assert!(exfiltration.on_upload(&upload(999), at(0)).is_empty());
+        let alerts = exfiltration.on_upload(&upload(1), at(1));
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].severity, "high");
+        assert!(alerts[0]
+            .details
+            .starts_with("Uploaded 1000 B to 203.0.113.7 in the last 60s"));
+        assert!(exfiltration.on_upload(&upload(5_000), at(2)).is_empty());
+
+        let mut exfiltration = detector(config());
+        let config = config();
+        pair(&mut exfiltration.pairs, &config, "10.0.0.5", "203.0.113.7")
+            .download
+            .add(at(0), 300);
+        assert!(exfiltration.on_upload(&upload(1_000), at(0)).is_empty());
+    }
+
+    #[test]
+    fn upload_far_above_the_hosts_history_is_reported() {
+        let mut exfiltration = detector(ExfiltrationConfig {
+            transfer_bytes: u64::MAX,
+            ..config()
+        });
+        for window in 0..4 {
+            assert!(exfiltration
+                .on_upload(&upload(10), at(window * 60))
+                .is_empty());
+        }
+        assert!(exfiltration.on_upload(&upload(99), at(240)).is_empty());
+
+        let alerts = exfiltration.on_upload(&upload(1), at(241));
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].severity, "medium");
+        assert_eq!(alerts[0].destination_ip, None);
+        assert!(alerts[0]
+            .details
+            .starts_with("Uploaded 100 B in the current 60s window, against a usual 10 B"));
+    }
+}
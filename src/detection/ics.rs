@@ -0,0 +1,92 @@
+//! Unauthorized command detection for Modbus/TCP and DNP3.
+//!
+//! On OT segments the dangerous traffic is a valid command from the wrong
+//! host, or a command nobody should be sending at all. Reads are never
+//! alerted on; writes, diagnostics and firmware/program operations are
+//! checked against the configured masters and function-code allowlists.
+
+use super::detector::Detector;
+use super::Alert;
+use crate::attack;
+use crate::capture::ics::{IcsCategory, IcsCommand, IcsProtocol};
+use crate::capture::parser::{AppLayer, ParsedPacket};
+use crate::config::IcsConfig;
+
+pub struct IcsDetector {
+    config: IcsConfig,
+}
+
+impl IcsDetector {
+    pub fn new(config: IcsConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl Detector for IcsDetector {
+    fn name(&self) -> &'static str {
+        "ics"
+    }
+
+    fn on_packet(&mut self, packet: &ParsedPacket) -> Vec<Alert> {
+        let Some(AppLayer::Ics(commands)) = &packet.app else {
+            return Vec::new();
+        };
+
+        commands
+            .iter()
+            .filter(|command| command.is_request)
+            .filter_map(|command| check_command(&self.config, packet, command))
+            .collect()
+    }
+}
+
+fn check_command(config: &IcsConfig, packet: &ParsedPacket, command: &IcsCommand) -> Option<Alert> {
+    let severity = match command.category {
+        IcsCategory::Firmware => "critical",
+        IcsCategory::Write => "high",
+        IcsCategory::Diagnostic => "medium",
+        IcsCategory::Read | IcsCategory::Other => return None,
+    };
+
+    let master_allowed =
+        config.allowed_masters.is_empty() || config.allowed_masters.contains(&packet.source_ip);
+    let allowed_codes = match command.protocol {
+        IcsProtocol::Modbus => &config.modbus_allowed_function_codes,
+        IcsProtocol::Dnp3 => &config.dnp3_allowed_function_codes,
+    };
+    let code_allowed = allowed_codes.contains(&command.function_code);
+
+    let reason = match (master_allowed, code_allowed) {
+        (true, true) => return None,
+        (false, _) => "unauthorized master",
+        (true, false) => "function code not allowed",
+    };
+
+    let unit = match command.protocol {
+        IcsProtocol::Modbus => format!("unit {}", command.unit_id),
+        IcsProtocol::Dnp3 => format!("outstation {}", command.unit_id),
+    };
+    let range = command
+        .range
+        .map(|(start, end)| format!(", points {}-{}", start, end))
+        .unwrap_or_default();
+
+    Some(
+        Alert::new(
+            "Unauthorized ICS Command",
+            severity,
+            packet.source_ip.clone(),
+            Some(packet.destination_ip.clone()),
+            format!(
+                "{} {} (function {}) to {}{}: {}",
+                command.protocol.name(),
+                command.function_name,
+                command.function_code,
+                unit,
+                range,
+                reason
+            ),
+        )
+        .with_attack(attack::techniques(&[("TA0106", "T0855")])),
+    )
+}
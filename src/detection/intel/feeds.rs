@@ -0,0 +1,638 @@
+//! Feed file formats: plain text, CSV, MISP event exports and STIX 2.1
+//! bundles.
+//!
+//! Indicator types a network monitor cannot observe (URLs, email addresses,
+//! registry keys, ...) are skipped. NetGuard does not carve files out of
+//! traffic, so file hashes are loaded but never match a packet. A JA3
+//! fingerprint is an MD5 too, so it must be typed as one (a `ja3` column or
+//! type, or MISP's `ja3-fingerprint-md5`) to match TLS clients.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+use super::store::{Indicator, IndicatorKind};
+use crate::config::{FeedConfig, FeedFormat};
+use crate::error::NetGuardError;
+
+/// Indicators read from one feed, and how many entries were skipped.
+pub struct FeedContents {
+    pub indicators: Vec<Indicator>,
+    pub skipped: usize,
+}
+
+impl FeedContents {
+    fn new() -> Self {
+        Self {
+            indicators: Vec::new(),
+            skipped: 0,
+        }
+    }
+
+    /// Records an entry, skipping it when the kind is unknown.
+    fn push(
+        &mut self,
+        feed: &FeedConfig,
+        kind: Option<IndicatorKind>,
+        value: &str,
+    ) -> Option<&mut Indicator> {
+        let value = value.trim();
+        let Some(kind) = kind.filter(|_| !value.is_empty()) else {
+            self.skipped += 1;
+            return None;
+        };
+        self.indicators.push(Indicator {
+            kind,
+            value: value.to_string(),
+            feed: feed.name.clone(),
+            description: None,
+            tags: Vec::new(),
+            confidence: None,
+            severity: feed.severity.clone(),
+        });
+        self.indicators.last_mut()
+    }
+}
+
+pub fn load_feed(feed: &FeedConfig) -> Result<FeedContents> {
+    let text = fs::read_to_string(&feed.path).map_err(|e| {
+        NetGuardError::ConfigError(format!(
+            "Cannot read feed {} ({}): {}",
+            feed.name, feed.path, e
+        ))
+    })?;
+    let format = feed
+        .format
+        .unwrap_or_else(|| guess_format(Path::new(&feed.path), &text));
+    let invalid = |e: String| NetGuardError::ParseError(format!("Feed {}: {}", feed.name, e));
+
+    match format {
+        FeedFormat::Text => Ok(parse_text(feed, &text)),
+        FeedFormat::Csv => parse_csv(feed, &text).map_err(|e| invalid(e).into()),
+        FeedFormat::Misp | FeedFormat::Stix => {
+            let json: Value = serde_json::from_str(&text).map_err(|e| invalid(e.to_string()))?;
+            if format == FeedFormat::Misp {
+                parse_misp(feed, &json).map_err(|e| invalid(e).into())
+            } else {
+                parse_stix(feed, &json, Utc::now()).map_err(|e| invalid(e).into())
+            }
+        }
+    }
+}
+
+fn guess_format(path: &Path, text: &str) -> FeedFormat {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("csv") => FeedFormat::Csv,
+        Some("json") => {
+            if text.contains("\"type\"") && text.contains("\"bundle\"") {
+                FeedFormat::Stix
+            } else {
+                FeedFormat::Misp
+            }
+        }
+        _ => FeedFormat::Text,
+    }
+}
+
+/// One value per line; text after it is taken as the description.
+fn parse_text(feed: &FeedConfig, text: &str) -> FeedContents {
+    let mut contents = FeedContents::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("//") {
+            continue;
+        }
+        let (value, rest) = line
+            .split_once(|c: char| c.is_whitespace() || c == ',' || c == ';')
+            .unwrap_or((line, ""));
+        let description = rest
+            .trim()
+            .trim_start_matches(['#', ',', ';'])
+            .trim()
+            .to_string();
+        if let Some(indicator) = contents.push(feed, IndicatorKind::infer(value), value) {
+            indicator.description = (!description.is_empty()).then_some(description);
+        }
+    }
+    contents
+}
+
+/// Column names holding the indicator, with the kind they imply.
+const VALUE_COLUMNS: &[(&str, Option<IndicatorKind>)] = &[
+    ("indicator", None),
+    ("ioc", None),
+    ("value", None),
+    ("ip", Some(IndicatorKind::Ip)),
+    ("ip_address", Some(IndicatorKind::Ip)),
+    ("dst_ip", Some(IndicatorKind::Ip)),
+    ("cidr", Some(IndicatorKind::Ip)),
+    ("domain", Some(IndicatorKind::Domain)),
+    ("hostname", Some(IndicatorKind::Domain)),
+    ("ja3", Some(IndicatorKind::Ja3)),
+    ("ja3_md5", Some(IndicatorKind::Ja3)),
+    ("ja4", Some(IndicatorKind::Ja4)),
+    ("md5", Some(IndicatorKind::Hash)),
+    ("sha1", Some(IndicatorKind::Hash)),
+    ("sha256", Some(IndicatorKind::Hash)),
+    ("hash", Some(IndicatorKind::Hash)),
+];
+
+fn parse_csv(feed: &FeedConfig, text: &str) -> Result<FeedContents, String> {
+    let mut rows = text
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(split_csv_line);
+    let header: Vec<String> = rows
+        .next()
+        .ok_or("no header row")?
+        .iter()
+        .map(|name| name.trim().to_ascii_lowercase())
+        .collect();
+    let column = |names: &[&str]| {
+        header
+            .iter()
+            .position(|name| names.contains(&name.as_str()))
+    };
+
+    let (value_column, implied_kind) = VALUE_COLUMNS
+        .iter()
+        .find_map(|(name, kind)| column(&[name]).map(|index| (index, *kind)))
+        .ok_or("no indicator column in the header")?;
+    let type_column = column(&["type", "indicator_type", "ioc_type"]);
+    let description_column = column(&["description", "comment", "malware", "threat"]);
+    let confidence_column = column(&["confidence", "confidence_level"]);
+    let tags_column = column(&["tags", "tag", "labels"]);
+
+    let mut contents = FeedContents::new();
+    for row in rows {
+        let cell = |index: Option<usize>| {
+            index
+                .and_then(|index| row.get(index))
+                .map(|cell| cell.trim())
+                .filter(|cell| !cell.is_empty())
+        };
+        let Some(value) = cell(Some(value_column)) else {
+            contents.skipped += 1;
+            continue;
+        };
+        let kind = match cell(type_column) {
+            Some(kind) => kind_from_name(kind),
+            None => implied_kind.or_else(|| IndicatorKind::infer(value)),
+        };
+        if let Some(indicator) = contents.push(feed, kind, value) {
+            indicator.description = cell(description_column).map(str::to_string);
+            indicator.confidence = cell(confidence_column).and_then(|c| c.parse().ok());
+            indicator.tags = cell(tags_column)
+                .map(|tags| {
+                    tags.split(['|', ';'])
+                        .map(|tag| tag.trim().to_string())
+                        .filter(|tag| !tag.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default();
+        }
+    }
+    Ok(contents)
+}
+
+/// Splits a CSV line, honouring double-quoted fields with `""` escapes.
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// Kind named in a type column: our own names, MISP attribute types and
+/// common aliases.
+fn kind_from_name(name: &str) -> Option<IndicatorKind> {
+    match name.to_ascii_lowercase().as_str() {
+        "ip" | "ipv4" | "ipv6" | "ip-src" | "ip-dst" | "cidr" | "ipv4-addr" | "ipv6-addr" => {
+            Some(IndicatorKind::Ip)
+        }
+        "domain" | "hostname" | "fqdn" | "domain-name" => Some(IndicatorKind::Domain),
+        "ja3" | "ja3-fingerprint-md5" => Some(IndicatorKind::Ja3),
+        "ja4" | "ja4-fingerprint" => Some(IndicatorKind::Ja4),
+        "md5" | "sha1" | "sha256" | "hash" | "filehash" => Some(IndicatorKind::Hash),
+        _ => None,
+    }
+}
+
+/// A MISP event export: `{"Event": {...}}`, `{"response": [{"Event": ...}]}`
+/// or a list of either. Attributes flagged `to_ids: false` are skipped.
+fn parse_misp(feed: &FeedConfig, json: &Value) -> Result<FeedContents, String> {
+    let mut events = Vec::new();
+    collect_misp_events(json, &mut events);
+    if events.is_empty() {
+        return Err("no MISP event found".to_string());
+    }
+
+    let mut contents = FeedContents::new();
+    for event in events {
+        let info = event.get("info").and_then(Value::as_str);
+        let severity = match json_string(event.get("threat_level_id")).as_deref() {
+            Some("1") => Some("high"),
+            Some("2") => Some("medium"),
+            Some("3") => Some("low"),
+            _ => None,
+        };
+        let event_tags = misp_tags(event);
+
+        let objects = event
+            .get("Object")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|object| object.get("Attribute").and_then(Value::as_array));
+        let attributes = event
+            .get("Attribute")
+            .and_then(Value::as_array)
+            .into_iter()
+            .chain(objects)
+            .flatten();
+
+        for attribute in attributes {
+            if attribute.get("to_ids").and_then(Value::as_bool) == Some(false) {
+                continue;
+            }
+            let kind_name = attribute.get("type").and_then(Value::as_str).unwrap_or("");
+            let value = attribute.get("value").and_then(Value::as_str).unwrap_or("");
+
+            // Composite types: "ip-dst|port" keeps the address, "domain|ip"
+            // yields both.
+            let parts: Vec<(Option<IndicatorKind>, &str)> = match kind_name.split_once('|') {
+                Some(("domain", "ip")) | Some(("hostname", "ip")) => {
+                    let (domain, ip) = value.split_once('|').unwrap_or((value, ""));
+                    vec![
+                        (Some(IndicatorKind::Domain), domain),
+                        (Some(IndicatorKind::Ip), ip),
+                    ]
+                }
+                Some((first, _)) => {
+                    vec![(kind_from_name(first), value.split('|').next().unwrap_or(""))]
+                }
+                None => vec![(kind_from_name(kind_name), value)],
+            };
+
+            for (kind, value) in parts {
+                if let Some(indicator) = contents.push(feed, kind, value) {
+                    indicator.description = attribute
+                        .get("comment")
+                        .and_then(Value::as_str)
+                        .filter(|comment| !comment.is_empty())
+                        .or(info)
+                        .map(str::to_string);
+                    indicator.tags = event_tags
+                        .iter()
+                        .cloned()
+                        .chain(misp_tags(attribute))
+                        .collect();
+                    if let Some(severity) = severity {
+                        indicator.severity = severity.to_string();
+                    }
+                }
+            }
+        }
+    }
+    Ok(contents)
+}
+
+fn collect_misp_events<'a>(json: &'a Value, events: &mut Vec<&'a Value>) {
+    match json {
+        Value::Array(items) => {
+            for item in items {
+                collect_misp_events(item, events);
+            }
+        }
+        Value::Object(map) => {
+            if let Some(event) = map.get("Event") {
+                events.push(event);
+            } else if let Some(response) = map.get("response") {
+                collect_misp_events(response, events);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn misp_tags(item: &Value) -> Vec<String> {
+    item.get("Tag")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|tag| tag.get("name").and_then(Value::as_str))
+        .map(str::to_string)
+        .collect()
+}
+
+/// A STIX 2.1 bundle. Indicators with a STIX pattern made of `=` comparisons
+/// joined by `OR` are taken; revoked and expired indicators, and patterns
+/// with `AND` or temporal qualifiers, are skipped.
+fn parse_stix(feed: &FeedConfig, json: &Value, now: DateTime<Utc>) -> Result<FeedContents, String> {
+    if json.get("type").and_then(Value::as_str) != Some("bundle") {
+        return Err("not a STIX bundle".to_string());
+    }
+    let comparison = Regex::new(r"([a-z0-9-]+):([A-Za-z0-9_.'-]+)\s*=\s*'((?:[^'\\]|\\.)*)'")
+        .map_err(|e| e.to_string())?;
+    let quoted = Regex::new(r"'(?:[^'\\]|\\.)*'").map_err(|e| e.to_string())?;
+
+    let mut contents = FeedContents::new();
+    let objects = json
+        .get("objects")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten();
+    for object in
+        objects.filter(|object| object.get("type").and_then(Value::as_str) == Some("indicator"))
+    {
+        let pattern_type = object
+            .get("pattern_type")
+            .and_then(Value::as_str)
+            .unwrap_or("stix");
+        let pattern = object.get("pattern").and_then(Value::as_str).unwrap_or("");
+        let revoked = object.get("revoked").and_then(Value::as_bool) == Some(true);
+        let expired = object
+            .get("valid_until")
+            .and_then(Value::as_str)
+            .and_then(|until| DateTime::parse_from_rfc3339(until).ok())
+            .is_some_and(|until| until < now);
+        if revoked || expired {
+            continue;
+        }
+        let unquoted = quoted.replace_all(pattern, "''").to_ascii_uppercase();
+        if pattern_type != "stix"
+            || ["AND", "FOLLOWEDBY", "WITHIN", "REPEATS", "START"]
+                .iter()
+                .any(|keyword| {
+                    unquoted
+                        .split(|c: char| !c.is_ascii_alphabetic())
+                        .any(|word| word == *keyword)
+                })
+        {
+            contents.skipped += 1;
+            continue;
+        }
+
+        let description = object
+            .get("description")
+            .or_else(|| object.get("name"))
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        let tags: Vec<String> = ["labels", "indicator_types"]
+            .iter()
+            .filter_map(|key| object.get(*key).and_then(Value::as_array))
+            .flatten()
+            .filter_map(Value::as_str)
+            .map(str::to_string)
+            .collect();
+        let confidence = object
+            .get("confidence")
+            .and_then(Value::as_u64)
+            .map(|c| c.min(100) as u8);
+
+        for capture in comparison.captures_iter(pattern) {
+            let kind = match (&capture[1], &capture[2]) {
+                ("ipv4-addr" | "ipv6-addr", "value") => Some(IndicatorKind::Ip),
+                ("domain-name", "value") => Some(IndicatorKind::Domain),
+                ("file", path) if path.starts_with("hashes.") => Some(IndicatorKind::Hash),
+                _ => None,
+            };
+            let value = capture[3].replace("\\'", "'").replace("\\\\", "\\");
+            if let Some(indicator) = contents.push(feed, kind, &value) {
+                indicator.description = description.clone();
+                indicator.tags = tags.clone();
+                indicator.confidence = confidence;
+            }
+        }
+    }
+    Ok(contents)
+}
+
+/// A JSON string or number as text; MISP uses both for ids.
+fn json_string(value: Option<&Value>) -> Option<String> {
+    match value? {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(format: Option<FeedFormat>) -> FeedConfig {
+        FeedConfig {
+            name: "test".to_string(),
+            path: "feed".to_string(),
+            format,
+            severity: "high".to_string(),
+        }
+    }
+
+    /// (kind, value) of every indicator read.
+    fn values(contents: &FeedContents) -> Vec<(IndicatorKind, &str)> {
+        contents
+            .indicators
+            .iter()
+            .map(|indicator| (indicator.kind, indicator.value.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn text_infers_kinds_and_descriptions() {
+        let contents = parse_text(
+            &feed(None),
+            "# comment\n\
+             // another\n\
+             203.0.113.7 # scanner\n\
+             198.51.100.0/24, botnet\n\
+             *.evil.example.\n\
+             d41d8cd98f00b204e9800998ecf8427e\n\
+             t13d1516h2_8daaf6152771_02713d6af862\n\
+             http://not-an-indicator/path\n\
+             \n",
+        );
+        assert_eq!(
+            values(&contents),
+            [
+                (IndicatorKind::Ip, "203.0.113.7"),
+                (IndicatorKind::Ip, "198.51.100.0/24"),
+                (IndicatorKind::Domain, "*.evil.example."),
+                (IndicatorKind::Hash, "d41d8cd98f00b204e9800998ecf8427e"),
+                (IndicatorKind::Ja4, "t13d1516h2_8daaf6152771_02713d6af862"),
+            ]
+        );
+        assert_eq!(contents.skipped, 1);
+        assert_eq!(
+            contents.indicators[0].description.as_deref(),
+            Some("scanner")
+        );
+        assert_eq!(
+            contents.indicators[1].description.as_deref(),
+            Some("botnet")
+        );
+        assert_eq!(contents.indicators[2].description, None);
+    }
+
+    #[test]
+    fn csv_reads_typed_and_implied_columns() {
+        let contents = parse_csv(
+            &feed(Some(FeedFormat::Csv)),
+            "# abuse.ch style\n\
+             ja3_md5,Listingdate,Comment\n\
+             e7d705a3286e19ea42f587b344ee6865,2024-01-01,\"Dridex, \"\"v4\"\"\"\n\
+             ,2024-01-02,missing\n",
+        )
+        .unwrap();
+        assert_eq!(
+            values(&contents),
+            [(IndicatorKind::Ja3, "e7d705a3286e19ea42f587b344ee6865")]
+        );
+        assert_eq!(
+            contents.indicators[0].description.as_deref(),
+            Some("Dridex, \"v4\"")
+        );
+        assert_eq!(contents.skipped, 1);
+
+        let contents = parse_csv(
+            &feed(None),
+            "indicator,type,confidence,tags\n\
+             evil.example,domain,80,c2|apt\n\
+             203.0.113.9,ip-dst,x,\n\
+             example.org,url,,\n",
+        )
+        .unwrap();
+        assert_eq!(
+            values(&contents),
+            [
+                (IndicatorKind::Domain, "evil.example"),
+                (IndicatorKind::Ip, "203.0.113.9"),
+            ]
+        );
+        assert_eq!(contents.indicators[0].confidence, Some(80));
+        assert_eq!(contents.indicators[0].tags, ["c2", "apt"]);
+        assert_eq!(contents.indicators[1].confidence, None);
+        assert_eq!(contents.skipped, 1);
+
+        assert!(parse_csv(&feed(None), "").is_err());
+        assert!(parse_csv(&feed(None), "first,second\n1,2\n").is_err());
+    }
+
+    #[test]
+    fn misp_reads_attributes_objects_and_composites() {
+        let json: Value = serde_json::from_str(
+            r#"{"response": [{"Event": {
+                "info": "Campaign",
+                "threat_level_id": "3",
+                "Tag": [{"name": "tlp:amber"}],
+                "Attribute": [
+                    {"type": "ip-dst|port", "value": "203.0.113.5|443", "to_ids": true},
+                    {"type": "domain|ip", "value": "evil.example|198.51.100.4"},
+                    {"type": "ja3-fingerprint-md5", "value": "E7D705A3286E19EA42F587B344EE6865",
+                     "comment": "Dridex", "Tag": [{"name": "malware"}]},
+                    {"type": "hostname", "value": "skip.example", "to_ids": false},
+                    {"type": "url", "value": "http://evil.example/x"}
+                ],
+                "Object": [{"Attribute": [{"type": "sha256", "value": "ab"}]}]
+            }}]}"#,
+        )
+        .unwrap();
+        let contents = parse_misp(&feed(None), &json).unwrap();
+        assert_eq!(
+            values(&contents),
+            [
+                (IndicatorKind::Ip, "203.0.113.5"),
+                (IndicatorKind::Domain, "evil.example"),
+                (IndicatorKind::Ip, "198.51.100.4"),
+                (IndicatorKind::Ja3, "E7D705A3286E19EA42F587B344EE6865"),
+                (IndicatorKind::Hash, "ab"),
+            ]
+        );
+        assert_eq!(contents.skipped, 1);
+        let ja3 = &contents.indicators[3];
+        assert_eq!(ja3.description.as_deref(), Some("Dridex"));
+        assert_eq!(ja3.tags, ["tlp:amber", "malware"]);
+        assert_eq!(ja3.severity, "low");
+        assert_eq!(
+            contents.indicators[0].description.as_deref(),
+            Some("Campaign")
+        );
+
+        let empty: Value = serde_json::from_str(r#"{"events": []}"#).unwrap();
+        assert!(parse_misp(&feed(None), &empty).is_err());
+    }
+
+    #[test]
+    fn stix_reads_simple_patterns_only() {
+        let json: Value = serde_json::from_str(
+            r#"{"type": "bundle", "objects": [
+                {"type": "indicator", "name": "C2",
+                 "pattern": "[ipv4-addr:value = '203.0.113.1'] OR [domain-name:value = 'it\\'s.example']",
+                 "labels": ["c2"], "confidence": 150},
+                {"type": "indicator",
+                 "pattern": "[file:hashes.'SHA-256' = 'aa']"},
+                {"type": "indicator",
+                 "pattern": "[ipv4-addr:value = '203.0.113.2' AND network-traffic:dst_port = 80]"},
+                {"type": "indicator", "pattern": "[domain-name:value = 'and.example']"},
+                {"type": "indicator", "revoked": true,
+                 "pattern": "[ipv4-addr:value = '203.0.113.3']"},
+                {"type": "indicator", "valid_until": "2020-01-01T00:00:00Z",
+                 "pattern": "[ipv4-addr:value = '203.0.113.4']"},
+                {"type": "indicator", "pattern_type": "snort", "pattern": "alert ip any any"},
+                {"type": "malware", "name": "ignored"}
+            ]}"#,
+        )
+        .unwrap();
+        let contents = parse_stix(&feed(None), &json, Utc::now()).unwrap();
+        assert_eq!(
+            values(&contents),
+            [
+                (IndicatorKind::Ip, "203.0.113.1"),
+                (IndicatorKind::Domain, "it's.example"),
+                (IndicatorKind::Hash, "aa"),
+                (IndicatorKind::Domain, "and.example"),
+            ]
+        );
+        assert_eq!(contents.skipped, 2);
+        assert_eq!(contents.indicators[0].description.as_deref(), Some("C2"));
+        assert_eq!(contents.indicators[0].tags, ["c2"]);
+        assert_eq!(contents.indicators[0].confidence, Some(100));
+
+        let not_bundle: Value = serde_json::from_str(r#"{"type": "indicator"}"#).unwrap();
+        assert!(parse_stix(&feed(None), &not_bundle, Utc::now()).is_err());
+    }
+
+    #[test]
+    fn format_is_guessed_from_extension_and_content() {
+        let guess = |path: &str, text: &str| guess_format(Path::new(path), text);
+        assert_eq!(guess("feed.CSV", ""), FeedFormat::Csv);
+        assert_eq!(
+            guess("feed.json", r#"{"type": "bundle"}"#),
+            FeedFormat::Stix
+        );
+        assert_eq!(guess("feed.json", r#"{"Event": {}}"#), FeedFormat::Misp);
+        assert_eq!(guess("feed.txt", ""), FeedFormat::Text);
+        assert_eq!(guess("feed", ""), FeedFormat::Text);
+    }
+}
@@ -0,0 +1,355 @@
+//! Threat-intelligence indicator matching.
+//!
+//! Indicators are loaded from the feed files in `detection.intel` and
+//! checked against every packet's addresses, DNS query names, TLS and QUIC
+//! server names and fingerprints, and HTTP Host headers. A background
+//! thread polls the feed files and hands over a freshly built store when
+//! one changes; if a reload fails the previous indicators stay in use.
+
+mod feeds;
+mod store;
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use std::mem::size_of;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Weak};
+use std::thread;
+use std::time::SystemTime;
+
+use super::detector::Detector;
+use super::state::LruMap;
+use super::Alert;
+use crate::attack;
+use crate::capture::http;
+use crate::capture::parser::{AppLayer, ParsedPacket};
+use crate::capture::tls::ClientHello;
+use crate::config::{FeedConfig, IntelConfig};
+use crate::error::NetGuardError;
+
+pub use store::{Indicator, IndicatorStore};
+
+const MAX_RECENT: usize = 10_000;
+/// Typical heap size of the strings in a suppression key.
+const KEY_STRING_BYTES: usize = 160;
+
+/// Feed, indicator value, source and destination of a reported match.
+type MatchKey = (String, String, String, Option<String>);
+
+impl IndicatorStore {
+    /// Loads and indexes every feed. Unreadable or malformed feeds are
+    /// errors; individual entries that cannot be used are skipped.
+    pub fn load(feeds: &[FeedConfig]) -> Result<Self> {
+        let mut store = IndicatorStore::new();
+        for feed in feeds {
+            let contents = feeds::load_feed(feed)?;
+            let mut loaded = 0;
+            let mut skipped = contents.skipped;
+            for indicator in contents.indicators {
+                match store.add(indicator) {
+                    Ok(()) => loaded += 1,
+                    Err(e) => {
+                        log::debug!("Feed {}: skipping indicator: {}", feed.name, e);
+                        skipped += 1;
+                    }
+                }
+            }
+            log::info!(
+                "Loaded {} indicators from feed {} ({} skipped)",
+                loaded,
+                feed.name,
+                skipped
+            );
+        }
+        Ok(store)
+    }
+}
+
+pub struct IntelDetector {
+    config: IntelConfig,
+    store: IndicatorStore,
+    /// Stores rebuilt by the reload thread after a feed changed.
+    updates: Option<Receiver<IndicatorStore>>,
+    /// Held only by the detector; the reload thread stops once it is gone.
+    reloading: Option<Arc<()>>,
+    recent: LruMap<MatchKey, DateTime<Utc>>,
+    matches: u64,
+    reloads: u64,
+}
+
+impl IntelDetector {
+    pub fn new(config: IntelConfig, store: IndicatorStore) -> Self {
+        Self {
+            config,
+            store,
+            updates: None,
+            reloading: None,
+            recent: LruMap::new(MAX_RECENT),
+            matches: 0,
+            reloads: 0,
+        }
+    }
+
+    /// Loads the configured feeds and starts watching them for changes.
+    pub fn load(config: &IntelConfig) -> Result<Self> {
+        let stamps = modified_times(&config.feeds);
+        let store = IndicatorStore::load(&config.feeds)?;
+        let mut detector = Self::new(config.clone(), store);
+        let reloading = Arc::new(());
+        detector.updates = Some(watch(config, stamps, Arc::downgrade(&reloading))?);
+        detector.reloading = Some(reloading);
+        Ok(detector)
+    }
+
+    fn apply_updates(&mut self) {
+        let Some(updates) = &self.updates else {
+            return;
+        };
+        while let Ok(store) = updates.try_recv() {
+            log::info!("Reloaded threat intel: {} indicators", store.len());
+            self.store = store;
+            self.reloads += 1;
+        }
+    }
+
+    fn check(
+        &mut self,
+        packet: &ParsedPacket,
+        what: &str,
+        observed: &str,
+        hits: Vec<Indicator>,
+        now: DateTime<Utc>,
+        alerts: &mut Vec<Alert>,
+    ) {
+        let realert = Duration::seconds(self.config.realert_seconds as i64);
+        for indicator in hits {
+            let key = (
+                indicator.feed.clone(),
+                indicator.value.clone(),
+                packet.source_ip.clone(),
+                Some(packet.destination_ip.clone()),
+            );
+            if self
+                .recent
+                .get_mut(&key)
+                .is_some_and(|last| now - *last < realert)
+            {
+                continue;
+            }
+            self.recent.insert(key, now);
+            self.matches += 1;
+            alerts.push(intel_alert(packet, what, observed, &indicator, now));
+        }
+    }
+}
+
+impl Detector for IntelDetector {
+    fn name(&self) -> &'static str {
+        "intel"
+    }
+
+    fn on_packet(&mut self, packet: &ParsedPacket) -> Vec<Alert> {
+        self.apply_updates();
+        let now = Utc::now();
+        let mut observed: Vec<(&str, String, Vec<Indicator>)> = Vec::new();
+
+        for (what, ip) in [
+            ("Source address", &packet.source_ip),
+            ("Destination address", &packet.destination_ip),
+        ] {
+            if let Ok(address) = ip.parse::<IpAddr>() {
+                let hits = self.store.match_ip(address);
+                if !hits.is_empty() {
+                    observed.push((what, ip.clone(), hits.into_iter().cloned().collect()));
+                }
+            }
+        }
+
+        let mut domains: Vec<(&str, String)> = Vec::new();
+        let mut fingerprints: Vec<(&str, String)> = Vec::new();
+        let mut hello = |hello: &ClientHello| {
+            if let Some(name) = &hello.server_name {
+                domains.push(("TLS server name", name.clone()));
+            }
+            if let Some(ja3) = &hello.ja3 {
+                fingerprints.push(("JA3", ja3.clone()));
+            }
+            if let Some(ja4) = &hello.ja4 {
+                fingerprints.push(("JA4", ja4.clone()));
+            }
+        };
+        match &packet.app {
+            Some(AppLayer::Dns(message)) => {
+                for question in &message.questions {
+                    domains.push(("DNS query", question.name.clone()));
+                }
+            }
+            Some(AppLayer::Tls(client_hello)) => hello(client_hello),
+            Some(AppLayer::Quic(initial)) => {
+                if let Some(client_hello) = &initial.client_hello {
+                    hello(client_hello);
+                }
+            }
+            _ => {}
+        }
+        if packet.tcp_flags.is_some() {
+            if let Some(host) = http::parse_request(&packet.payload)
+                .and_then(|request| request.header("host").map(str::to_string))
+            {
+                let host = host
+                    .rsplit_once(':')
+                    .filter(|(_, port)| port.bytes().all(|b| b.is_ascii_digit()))
+                    .map_or(host.as_str(), |(name, _)| name)
+                    .to_string();
+                domains.push(("HTTP host", host));
+            }
+        }
+
+        for (what, name) in domains {
+            let hits = self.store.match_domain(&name);
+            if !hits.is_empty() {
+                observed.push((what, name, hits.into_iter().cloned().collect()));
+            }
+        }
+        for (what, fingerprint) in fingerprints {
+            let hits = self.store.match_fingerprint(&fingerprint);
+            if !hits.is_empty() {
+                observed.push((what, fingerprint, hits.into_iter().cloned().collect()));
+            }
+        }
+
+        let mut alerts = Vec::new();
+        for (what, value, hits) in observed {
+            self.check(packet, what, &value, hits, now, &mut alerts);
+        }
+        alerts
+    }
+
+    fn snapshot(&self) -> serde_json::Value {
+        serde_json::json!({
+            "indicators": self.store.len(),
+            "indicators_by_kind": self.store.counts(),
+            "feeds": self.config.feeds.iter().map(|feed| feed.name.as_str()).collect::<Vec<_>>(),
+            "matches": self.matches,
+            "reloads": self.reloads,
+        })
+    }
+
+    fn memory_usage(&self) -> usize {
+        let recent = LruMap::<MatchKey, DateTime<Utc>>::entry_overhead() + KEY_STRING_BYTES;
+        self.store.memory_usage() + self.recent.len() * recent + size_of::<Self>()
+    }
+}
+
+/// Starts the thread that polls the feeds' modification times and sends a
+/// rebuilt store after a change. It exits at the first poll after `detector`
+/// has no strong references left, i.e. the detector was dropped.
+fn watch(
+    config: &IntelConfig,
+    mut stamps: Vec<Option<SystemTime>>,
+    detector: Weak<()>,
+) -> Result<Receiver<IndicatorStore>> {
+    let (sender, receiver) = mpsc::channel();
+    let feeds = config.feeds.clone();
+    let interval = std::time::Duration::from_secs(config.reload_seconds.max(1));
+    thread::Builder::new()
+        .name("intel-reload".to_string())
+        .spawn(move || loop {
+            thread::sleep(interval);
+            if detector.strong_count() == 0 {
+                return;
+            }
+            let current = modified_times(&feeds);
+            if current == stamps {
+                continue;
+            }
+            stamps = current;
+            match IndicatorStore::load(&feeds) {
+                Ok(store) => {
+                    if sender.send(store).is_err() {
+                        return;
+                    }
+                }
+                Err(e) => log::warn!("Keeping previous threat intel: {}", e),
+            }
+        })
+        .map_err(|e| {
+            NetGuardError::ConfigError(format!("Cannot start threat intel reloader: {}", e))
+        })?;
+    Ok(receiver)
+}
+
+fn modified_times(feeds: &[FeedConfig]) -> Vec<Option<SystemTime>> {
+    feeds
+        .iter()
+        .map(|feed| {
+            Path::new(&feed.path)
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .ok()
+        })
+        .collect()
+}
+
+fn intel_alert(
+    packet: &ParsedPacket,
+    what: &str,
+    observed: &str,
+    indicator: &Indicator,
+    now: DateTime<Utc>,
+) -> Alert {
+    let mut details = format!(
+        "{} {} matched {} indicator {} from feed {}",
+        what,
+        observed,
+        indicator.kind.name(),
+        indicator.value,
+        indicator.feed
+    );
+    if let Some(description) = &indicator.description {
+        details.push_str(&format!(": {}", description));
+    }
+    if !indicator.tags.is_empty() {
+        details.push_str(&format!(" [Tags: {}]", indicator.tags.join(", ")));
+    }
+    if let Some(confidence) = indicator.confidence {
+        details.push_str(&format!(" [Confidence: {}]", confidence));
+    }
+
+    Alert::new(
+        "Threat Intel Match",
+        indicator.severity.clone(),
+        packet.source_ip.clone(),
+        Some(packet.destination_ip.clone()),
+        details,
+    )
+    .at(now)
+    .with_attack(attack::techniques(&[("TA0011", "T1071")]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::RecvTimeoutError;
+
+    #[test]
+    fn reload_thread_stops_with_the_detector() {
+        let config = IntelConfig {
+            reload_seconds: 1,
+            ..IntelConfig::default()
+        };
+        let detector = Arc::new(());
+        let updates = watch(&config, Vec::new(), Arc::downgrade(&detector)).unwrap();
+        assert!(matches!(
+            updates.recv_timeout(std::time::Duration::from_millis(1500)),
+            Err(RecvTimeoutError::Timeout)
+        ));
+        drop(detector);
+        assert!(matches!(
+            updates.recv_timeout(std::time::Duration::from_secs(5)),
+            Err(RecvTimeoutError::Disconnected)
+        ));
+    }
+}
@@ -0,0 +1,389 @@
+//! Indexed indicators: a binary prefix trie per address family for IPs and
+//! CIDRs, suffix lookup for domains and exact lookup for fingerprints and
+//! file hashes.
+
+use pnet::ipnetwork::IpNetwork;
+use std::collections::HashMap;
+use std::mem::size_of;
+use std::net::IpAddr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndicatorKind {
+    /// An address or network.
+    Ip,
+    /// A domain; also matches its subdomains.
+    Domain,
+    Ja3,
+    Ja4,
+    /// An MD5, SHA-1 or SHA-256 hash.
+    Hash,
+}
+
+impl IndicatorKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            IndicatorKind::Ip => "IP",
+            IndicatorKind::Domain => "domain",
+            IndicatorKind::Ja3 => "JA3",
+            IndicatorKind::Ja4 => "JA4",
+            IndicatorKind::Hash => "hash",
+        }
+    }
+
+    /// Guesses the kind of an untyped value, as found in plain-text feeds.
+    pub fn infer(value: &str) -> Option<Self> {
+        if value.parse::<IpNetwork>().is_ok() {
+            return Some(IndicatorKind::Ip);
+        }
+        let is_hex = !value.is_empty() && value.bytes().all(|b| b.is_ascii_hexdigit());
+        if is_hex && matches!(value.len(), 32 | 40 | 64) {
+            return Some(IndicatorKind::Hash);
+        }
+        if is_ja4(value) {
+            return Some(IndicatorKind::Ja4);
+        }
+        let domain = normalize_domain(value);
+        let valid = domain.contains('.')
+            && domain
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'.' || b == b'_')
+            && !domain.bytes().all(|b| b.is_ascii_digit() || b == b'.');
+        valid.then_some(IndicatorKind::Domain)
+    }
+}
+
+/// `t13d1516h2_8daaf6152771_02713d6af862`: three underscore-separated
+/// parts, the first ten characters long and the others twelve hex digits.
+fn is_ja4(value: &str) -> bool {
+    let parts: Vec<&str> = value.split('_').collect();
+    matches!(parts.as_slice(), [a, b, c]
+        if a.len() == 10
+            && (a.starts_with('t') || a.starts_with('q'))
+            && [b, c].iter().all(|part| part.len() == 12 && part.bytes().all(|x| x.is_ascii_hexdigit())))
+}
+
+/// Lowercase, without a trailing dot or a leading `*.` wildcard.
+pub fn normalize_domain(domain: &str) -> String {
+    domain
+        .trim()
+        .trim_end_matches('.')
+        .trim_start_matches("*.")
+        .to_ascii_lowercase()
+}
+
+#[derive(Debug, Clone)]
+pub struct Indicator {
+    pub kind: IndicatorKind,
+    pub value: String,
+    pub feed: String,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
+    /// 0-100, when the feed gives one.
+    pub confidence: Option<u8>,
+    pub severity: String,
+}
+
+#[derive(Default)]
+pub struct IndicatorStore {
+    indicators: Vec<Indicator>,
+    v4: CidrTrie,
+    v6: CidrTrie,
+    domains: HashMap<String, Vec<usize>>,
+    /// JA3 and JA4 fingerprints.
+    fingerprints: HashMap<String, Vec<usize>>,
+    /// File hashes, kept apart from fingerprints: a JA3 is an MD5 too, but
+    /// only feeds typing a value as JA3 mean it as one. Nothing seen in
+    /// traffic is looked up here.
+    hashes: HashMap<String, Vec<usize>>,
+}
+
+impl IndicatorStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.indicators.len()
+    }
+
+    /// Indexes an indicator. Fails when the value does not parse as its kind.
+    pub fn add(&mut self, mut indicator: Indicator) -> Result<(), String> {
+        let id = self.indicators.len();
+        match indicator.kind {
+            IndicatorKind::Ip => {
+                let network = indicator
+                    .value
+                    .parse::<IpNetwork>()
+                    .map_err(|_| format!("invalid address '{}'", indicator.value))?;
+                let (trie, width) = match network {
+                    IpNetwork::V4(_) => (&mut self.v4, 32),
+                    IpNetwork::V6(_) => (&mut self.v6, 128),
+                };
+                trie.insert(address_bits(network.network()), network.prefix(), width, id);
+            }
+            IndicatorKind::Domain => {
+                indicator.value = normalize_domain(&indicator.value);
+                if indicator.value.is_empty() {
+                    return Err("empty domain".to_string());
+                }
+                self.domains
+                    .entry(indicator.value.clone())
+                    .or_default()
+                    .push(id);
+            }
+            IndicatorKind::Ja3 | IndicatorKind::Ja4 | IndicatorKind::Hash => {
+                indicator.value = indicator.value.trim().to_ascii_lowercase();
+                let table = if indicator.kind == IndicatorKind::Hash {
+                    &mut self.hashes
+                } else {
+                    &mut self.fingerprints
+                };
+                table.entry(indicator.value.clone()).or_default().push(id);
+            }
+        }
+        self.indicators.push(indicator);
+        Ok(())
+    }
+
+    /// Indicators of the most specific network containing `ip`.
+    pub fn match_ip(&self, ip: IpAddr) -> Vec<&Indicator> {
+        let ids = match ip {
+            IpAddr::V4(_) => self.v4.longest_match(address_bits(ip), 32),
+            IpAddr::V6(_) => self.v6.longest_match(address_bits(ip), 128),
+        };
+        ids.iter().map(|id| &self.indicators[*id]).collect()
+    }
+
+    /// Indicators for the name itself or, failing that, its closest parent
+    /// domain.
+    pub fn match_domain(&self, name: &str) -> Vec<&Indicator> {
+        let name = normalize_domain(name);
+        let mut suffix = name.as_str();
+        loop {
+            if let Some(ids) = self.domains.get(suffix) {
+                return ids.iter().map(|id| &self.indicators[*id]).collect();
+            }
+            match suffix.split_once('.') {
+                Some((_, parent)) => suffix = parent,
+                None => return Vec::new(),
+            }
+        }
+    }
+
+    /// JA3 and JA4 indicators for a TLS fingerprint; file hashes never
+    /// match.
+    pub fn match_fingerprint(&self, fingerprint: &str) -> Vec<&Indicator> {
+        self.fingerprints
+            .get(&fingerprint.to_ascii_lowercase())
+            .map(|ids| ids.iter().map(|id| &self.indicators[*id]).collect())
+            .unwrap_or_default()
+    }
+
+    /// Indicator counts by kind name.
+    pub fn counts(&self) -> HashMap<&'static str, usize> {
+        let mut counts = HashMap::new();
+        for indicator in &self.indicators {
+            *counts.entry(indicator.kind.name()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        let indicators: usize = self
+            .indicators
+            .iter()
+            .map(|indicator| {
+                size_of::<Indicator>()
+                    + indicator.value.capacity()
+                    + indicator.feed.capacity()
+                    + indicator.description.as_ref().map_or(0, String::capacity)
+                    + indicator.tags.iter().map(String::capacity).sum::<usize>()
+            })
+            .sum();
+        let keyed = |map: &HashMap<String, Vec<usize>>| -> usize {
+            map.iter()
+                .map(|(key, ids)| {
+                    key.capacity()
+                        + ids.capacity() * size_of::<usize>()
+                        + size_of::<(String, Vec<usize>)>()
+                })
+                .sum()
+        };
+        indicators
+            + self.v4.memory_usage()
+            + self.v6.memory_usage()
+            + keyed(&self.domains)
+            + keyed(&self.fingerprints)
+            + keyed(&self.hashes)
+    }
+}
+
+/// The address left-aligned in 128 bits, so both families walk the trie
+/// from the most significant bit.
+fn address_bits(ip: IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(v4) => (u32::from(v4) as u128) << 96,
+        IpAddr::V6(v6) => u128::from(v6),
+    }
+}
+
+/// Binary trie over address bits; a node at depth `n` stands for an `/n`
+/// network.
+struct CidrTrie {
+    nodes: Vec<TrieNode>,
+}
+
+#[derive(Default)]
+struct TrieNode {
+    /// Child node indices; 0 means none, as the root is never a child.
+    children: [u32; 2],
+    indicators: Vec<usize>,
+}
+
+impl Default for CidrTrie {
+    fn default() -> Self {
+        Self {
+            nodes: vec![TrieNode::default()],
+        }
+    }
+}
+
+impl CidrTrie {
+    fn insert(&mut self, bits: u128, prefix: u8, width: u8, id: usize) {
+        let mut node = 0;
+        for depth in 0..prefix.min(width) {
+            let bit = ((bits >> (127 - depth as u32)) & 1) as usize;
+            if self.nodes[node].children[bit] == 0 {
+                self.nodes.push(TrieNode::default());
+                self.nodes[node].children[bit] = (self.nodes.len() - 1) as u32;
+            }
+            node = self.nodes[node].children[bit] as usize;
+        }
+        self.nodes[node].indicators.push(id);
+    }
+
+    fn longest_match(&self, bits: u128, width: u8) -> &[usize] {
+        let mut node = 0;
+        let mut best: &[usize] = &self.nodes[0].indicators;
+        for depth in 0..width {
+            let bit = ((bits >> (127 - depth as u32)) & 1) as usize;
+            let child = self.nodes[node].children[bit];
+            if child == 0 {
+                break;
+            }
+            node = child as usize;
+            if !self.nodes[node].indicators.is_empty() {
+                best = &self.nodes[node].indicators;
+            }
+        }
+        best
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.nodes.capacity() * size_of::<TrieNode>()
+            + self
+                .nodes
+                .iter()
+                .map(|node| node.indicators.capacity() * size_of::<usize>())
+                .sum::<usize>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn indicator(kind: IndicatorKind, value: &str) -> Indicator {
+        Indicator {
+            kind,
+            value: value.to_string(),
+            feed: "test".to_string(),
+            description: None,
+            tags: Vec::new(),
+            confidence: None,
+            severity: "high".to_string(),
+        }
+    }
+
+    fn store(indicators: &[(IndicatorKind, &str)]) -> IndicatorStore {
+        let mut store = IndicatorStore::new();
+        for (kind, value) in indicators {
+            store.add(indicator(*kind, value)).unwrap();
+        }
+        store
+    }
+
+    fn values(hits: Vec<&Indicator>) -> Vec<&str> {
+        hits.into_iter().map(|hit| hit.value.as_str()).collect()
+    }
+
+    #[test]
+    fn file_hashes_do_not_match_ja3() {
+        const MD5: &str = "e7d705a3286e19ea42f587b344ee6865";
+        let store = store(&[(IndicatorKind::Hash, MD5)]);
+        assert!(store.match_fingerprint(MD5).is_empty());
+
+        let mut store = store;
+        store
+            .add(indicator(IndicatorKind::Ja3, &MD5.to_uppercase()))
+            .unwrap();
+        let hits = store.match_fingerprint(MD5);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].kind, IndicatorKind::Ja3);
+        assert_eq!(hits[0].value, MD5);
+    }
+
+    #[test]
+    fn most_specific_network_wins() {
+        let store = store(&[
+            (IndicatorKind::Ip, "10.0.0.0/8"),
+            (IndicatorKind::Ip, "10.1.0.0/16"),
+            (IndicatorKind::Ip, "2001:db8::/32"),
+        ]);
+        let ip = |text: &str| text.parse::<IpAddr>().unwrap();
+        assert_eq!(values(store.match_ip(ip("10.1.2.3"))), ["10.1.0.0/16"]);
+        assert_eq!(values(store.match_ip(ip("10.2.0.1"))), ["10.0.0.0/8"]);
+        assert_eq!(values(store.match_ip(ip("2001:db8::1"))), ["2001:db8::/32"]);
+        assert!(store.match_ip(ip("11.0.0.1")).is_empty());
+    }
+
+    #[test]
+    fn domains_match_subdomains() {
+        let store = store(&[
+            (IndicatorKind::Domain, "*.Evil.Example."),
+            (IndicatorKind::Domain, "cdn.evil.example"),
+        ]);
+        assert_eq!(
+            values(store.match_domain("a.b.evil.example")),
+            ["evil.example"]
+        );
+        assert_eq!(
+            values(store.match_domain("x.cdn.evil.example")),
+            ["cdn.evil.example"]
+        );
+        assert!(store.match_domain("notevil.example").is_empty());
+    }
+
+    #[test]
+    fn kinds_are_inferred() {
+        assert_eq!(IndicatorKind::infer("192.0.2.1"), Some(IndicatorKind::Ip));
+        assert_eq!(
+            IndicatorKind::infer("2001:db8::/48"),
+            Some(IndicatorKind::Ip)
+        );
+        assert_eq!(
+            IndicatorKind::infer("evil.example"),
+            Some(IndicatorKind::Domain)
+        );
+        assert_eq!(
+            IndicatorKind::infer("da39a3ee5e6b4b0d3255bfef95601890afd80709"),
+            Some(IndicatorKind::Hash)
+        );
+        assert_eq!(
+            IndicatorKind::infer("q13d0312h3_55b375c5d22e_06cda9e17597"),
+            Some(IndicatorKind::Ja4)
+        );
+        assert_eq!(IndicatorKind::infer("1.2.3"), None);
+        assert_eq!(IndicatorKind::infer("localhost"), None);
+    }
+}
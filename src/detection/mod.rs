@@ -1,10 +1,46 @@
-use chrono::{DateTime, Utc};
+mod aggregate;
+mod allowlist;
+mod beacon;
+mod correlate;
+mod credentials;
+mod ddos;
+mod detector;
+mod dns;
+mod exfiltration;
+mod ics;
+mod intel;
+pub mod payload;
+mod port_scan;
+mod risk;
+mod rules;
+mod sigma;
+mod smb;
+mod spoofing;
+pub mod state;
+mod suspicious_port;
+mod syn_flood;
+mod volumetric;
+
+pub use detector::{Detector, DetectorFactory, DetectorRegistry};
+pub use risk::HostRisk;
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 
+use crate::attack::Technique;
+use crate::capture::flow::FlowTable;
 use crate::capture::parser::ParsedPacket;
 use crate::config::DetectionConfig;
+use crate::geoip::{GeoInfo, GeoIp};
+use crate::networks::{Direction, Networks};
+
+use aggregate::AlertAggregator;
+use allowlist::{Allowlist, Traffic};
+use correlate::Correlator;
+use risk::RiskScorer;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Alert {
@@ -14,149 +50,503 @@ pub struct Alert {
     pub destination_ip: Option<String>,
     pub details: String,
     pub timestamp: DateTime<Utc>,
+    /// Filled in by the engine from the GeoIP databases, if configured.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_geo: Option<GeoInfo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination_geo: Option<GeoInfo>,
+    /// Assigned by the engine when the alert is emitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// For incidents, the IDs of the alerts they were correlated from.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub related: Vec<String>,
+    /// MITRE ATT&CK techniques the alert indicates.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attack: Vec<Technique>,
+    /// Set on the aggregator's "still ongoing" summaries of an alert that
+    /// was already emitted.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub ongoing: bool,
+}
+
+impl Alert {
+    /// An alert raised now. Enrichment, the ID and related alerts are left
+    /// for the engine to fill in; techniques and an event time other than
+    /// now are set with [`Alert::with_attack`] and [`Alert::at`].
+    pub fn new(
+        alert_type: impl Into<String>,
+        severity: impl Into<String>,
+        source_ip: impl Into<String>,
+        destination_ip: Option<String>,
+        details: impl Into<String>,
+    ) -> Self {
+        Self {
+            alert_type: alert_type.into(),
+            severity: severity.into(),
+            source_ip: source_ip.into(),
+            destination_ip,
+            details: details.into(),
+            timestamp: Utc::now(),
+            source_geo: None,
+            destination_geo: None,
+            id: None,
+            related: Vec::new(),
+            attack: Vec::new(),
+            ongoing: false,
+        }
+    }
+
+    pub fn at(mut self, timestamp: DateTime<Utc>) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+
+    pub fn with_attack(mut self, attack: Vec<Technique>) -> Self {
+        self.attack = attack;
+        self
+    }
+
+    /// Rank of the alert's severity, see [`severity_rank`].
+    pub fn priority(&self) -> u8 {
+        severity_rank(&self.severity)
+    }
+}
+
+/// Orders severities: critical (4) > high (3) > medium (2) > low (1).
+/// Anything else ranks 0.
+pub fn severity_rank(severity: &str) -> u8 {
+    match severity {
+        "critical" => 4,
+        "high" => 3,
+        "medium" => 2,
+        "low" => 1,
+        _ => 0,
+    }
+}
+
+/// Moves a severity `steps` levels up (positive) or down, staying within
+/// low..critical. Unknown severities are left alone.
+fn shift_severity(severity: &str, steps: i8) -> String {
+    const LEVELS: [&str; 4] = ["low", "medium", "high", "critical"];
+    match severity_rank(severity) {
+        0 => severity.to_string(),
+        rank => {
+            let level = (rank as i16 - 1 + steps as i16).clamp(0, 3);
+            LEVELS[level as usize].to_string()
+        }
+    }
+}
+
+/// Moves the severities of a detector's alerts by the steps configured for
+/// their traffic direction, unless the detector rates direction itself.
+fn shift_by_direction(
+    mut alerts: Vec<Alert>,
+    detector: &dyn Detector,
+    networks: &Networks,
+    steps: &HashMap<Direction, i8>,
+) -> Vec<Alert> {
+    if steps.is_empty() || detector.rates_direction() {
+        return alerts;
+    }
+    for alert in alerts.iter_mut() {
+        let Some(destination) = &alert.destination_ip else {
+            continue;
+        };
+        let direction = networks.direction(&alert.source_ip, destination);
+        if let Some(steps) = steps.get(&direction) {
+            alert.severity = shift_severity(&alert.severity, *steps);
+        }
+    }
+    alerts
+}
+
+struct EngineState {
+    detectors: Vec<Box<dyn Detector>>,
+    flows: FlowTable,
+    aggregator: Option<AlertAggregator>,
+    risk: Option<RiskScorer>,
+    correlator: Option<Correlator>,
+    last_sweep: DateTime<Utc>,
+    geoip: Option<Arc<GeoIp>>,
+    networks: Networks,
+    direction_severity: HashMap<Direction, i8>,
+    allowlist: Allowlist,
+    /// Capture interface, for allowlist entries limited to interfaces.
+    interface: Option<String>,
+}
+
+impl EngineState {
+    /// Adds location and ASN details for addresses the databases know.
+    fn enrich(
+        &self,
+        source_ip: &str,
+        destination_ip: Option<&str>,
+    ) -> (Option<GeoInfo>, Option<GeoInfo>) {
+        let Some(geoip) = &self.geoip else {
+            return (None, None);
+        };
+        (
+            geoip.lookup_str(source_ip),
+            destination_ip.and_then(|ip| geoip.lookup_str(ip)),
+        )
+    }
 }
 
 pub struct DetectionEngine {
-    config: DetectionConfig,
-    port_scan_tracker: Arc<Mutex<HashMap<String, Vec<(u16, DateTime<Utc>)>>>>,
-    packet_rate_tracker: Arc<Mutex<HashMap<String, Vec<DateTime<Utc>>>>>,
+    state: Arc<Mutex<EngineState>>,
 }
 
 impl DetectionEngine {
-    pub fn new(config: DetectionConfig) -> Self {
-        Self {
-            config,
-            port_scan_tracker: Arc::new(Mutex::new(HashMap::new())),
-            packet_rate_tracker: Arc::new(Mutex::new(HashMap::new())),
-        }
+    pub fn new(config: DetectionConfig) -> Result<Self> {
+        Self::with_registry(config, &DetectorRegistry::with_builtin())
+    }
+    
+    pub fn with_registry(config: DetectionConfig, registry: &DetectorRegistry) -> Result<Self> {
+        let detectors = registry.build(&config)?;
+        Ok(Self {
+            state: Arc::new(Mutex::new(EngineState {
+                detectors,
+                flows: FlowTable::new(config.flow_timeout_seconds),
+                aggregator: config
+                    .aggregation
+                    .enabled
+                    .then(|| AlertAggregator::new(config.aggregation.clone())),
+                risk: config
+                    .risk
+                    .enabled
+                    .then(|| RiskScorer::new(config.risk.clone())),
+                correlator: config
+                    .correlation
+                    .enabled
+                    .then(|| Correlator::new(&config.correlation))
+                    .transpose()?,
+                last_sweep: Utc::now(),
+                geoip: GeoIp::shared(&config.geoip)?,
+                networks: Networks::from_config(&config.networks)?,
+                direction_severity: config.direction_severity.clone(),
+                allowlist: Allowlist::new(&config.allowlist)?,
+                interface: None,
+            })),
+        })
     }
     
-    pub fn check_packet(&self, packet: &ParsedPacket) -> Option<Alert> {
-        // Check for port scanning
-        if self.config.port_scan.enabled {
-            if let Some(alert) = self.check_port_scan(packet) {
-                return Some(alert);
+    /// Names the interface the packets are captured on.
+    pub fn with_interface(self, interface: &str) -> Self {
+        self.state.lock().unwrap().interface = Some(interface.to_string());
+        self
+    }
+    
+    /// Runs every detector over the packet and returns all findings,
+    /// merged, deduplicated and ordered by priority, most severe first.
+    /// Flows that expired since the last sweep are handed to the detectors'
+    /// flow hooks too, the findings to their alert hooks, and pending "still
+    /// ongoing" updates are flushed. Escalations for hosts whose risk score
+    /// crossed the threshold come next, and incidents correlated from the
+    /// alerts last. Every returned alert carries an ID.
+    pub fn check_packet(&self, packet: &ParsedPacket) -> Vec<Alert> {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        let now = Utc::now();
+        let mut alerts = Vec::new();
+        
+        state.flows.update(packet, now);
+        let sweep = now - state.last_sweep >= Duration::seconds(1);
+        if sweep {
+            state.last_sweep = now;
+            for mut flow in state.flows.expire(now) {
+                (flow.source_geo, flow.destination_geo) =
+                    state.enrich(&flow.source_ip, Some(&flow.destination_ip));
+                flow.direction = Some(
+                    state
+                        .networks
+                        .direction(&flow.source_ip, &flow.destination_ip),
+                );
+                let traffic = Traffic::flow(&flow);
+                for detector in state.detectors.iter_mut() {
+                    if !state.allowlist.is_empty()
+                        && state.allowlist.suppresses(
+                            detector.name(),
+                            &traffic,
+                            state.interface.as_deref(),
+                            now,
+                        )
+                    {
+                        continue;
+                    }
+                    alerts.extend(shift_by_direction(
+                        detector.on_flow(&flow),
+                        detector.as_ref(),
+                        &state.networks,
+                        &state.direction_severity,
+                    ));
+                }
             }
         }
         
-        // Check for DDoS
-        if self.config.ddos.enabled {
-            if let Some(alert) = self.check_ddos(packet) {
-                return Some(alert);
+        // Every detector sees every packet so stateful ones stay in sync,
+        // unless an allowlist entry hides it.
+        let traffic = Traffic::packet(packet);
+        for detector in state.detectors.iter_mut() {
+            if !state.allowlist.is_empty()
+                && state.allowlist.suppresses(
+                    detector.name(),
+                    &traffic,
+                    state.interface.as_deref(),
+                    now,
+                )
+            {
+                continue;
             }
+            alerts.extend(shift_by_direction(
+                detector.on_packet(packet),
+                detector.as_ref(),
+                &state.networks,
+                &state.direction_severity,
+            ));
         }
         
-        // Check for suspicious ports
-        if let Some(alert) = self.check_suspicious_port(packet) {
-            return Some(alert);
+        // Detectors that work on other detectors' findings see them once.
+        let mut derived = Vec::new();
+        for alert in &alerts {
+            for detector in state.detectors.iter_mut() {
+                derived.extend(shift_by_direction(
+                    detector.on_alert(alert),
+                    detector.as_ref(),
+                    &state.networks,
+                    &state.direction_severity,
+                ));
+            }
+        }
+        alerts.extend(derived);
+        for alert in alerts.iter_mut() {
+            (alert.source_geo, alert.destination_geo) =
+                state.enrich(&alert.source_ip, alert.destination_ip.as_deref());
         }
         
-        None
+        let mut alerts = merge_alerts(alerts);
+        if let Some(aggregator) = state.aggregator.as_mut() {
+            alerts = aggregator.process(alerts, now);
+            if sweep {
+                alerts.extend(aggregator.flush(now));
+            }
+        }
+        if let Some(risk) = state.risk.as_mut() {
+            for mut escalation in risk.process(&alerts, &state.networks, now) {
+                escalation.source_geo = state
+                    .geoip
+                    .as_ref()
+                    .and_then(|geoip| geoip.lookup_str(&escalation.source_ip));
+                alerts.push(escalation);
+            }
+        }
+        for alert in alerts.iter_mut() {
+            alert.id = Some(new_alert_id());
+        }
+        if let Some(correlator) = state.correlator.as_mut() {
+            for mut incident in correlator.process(&alerts, now) {
+                (incident.source_geo, incident.destination_geo) =
+                    state.enrich(&incident.source_ip, incident.destination_ip.as_deref());
+                incident.id = Some(new_alert_id());
+                alerts.push(incident);
+            }
+        }
+        alerts
     }
     
-    fn check_port_scan(&self, packet: &ParsedPacket) -> Option<Alert> {
-        if let Some(dest_port) = packet.destination_port {
-            let mut tracker = self.port_scan_tracker.lock().unwrap();
-            let now = Utc::now();
-            
-            let ports = tracker.entry(packet.source_ip.clone()).or_insert_with(Vec::new);
-            
-            // Remove old entries
-            ports.retain(|(_, time)| {
-                (now - *time).num_seconds() < self.config.port_scan.window_seconds as i64
-            });
-            
-            // Add current port
-            ports.push((dest_port, now));
-            
-            // Check if threshold exceeded
-            let unique_ports: std::collections::HashSet<_> = ports.iter().map(|(p, _)| p).collect();
-            
-            if unique_ports.len() >= self.config.port_scan.threshold {
-                let port_list: Vec<String> = unique_ports.iter().map(|p| p.to_string()).collect();
-                
-                return Some(Alert {
-                    alert_type: "Port Scan".to_string(),
-                    severity: "high".to_string(),
-                    source_ip: packet.source_ip.clone(),
-                    destination_ip: Some(packet.destination_ip.clone()),
-                    details: format!(
-                        "Scanned {} unique ports in {} seconds: {}",
-                        unique_ports.len(),
-                        self.config.port_scan.window_seconds,
-                        port_list.join(", ")
-                    ),
-                    timestamp: now,
-                });
-            }
+    /// Seeds host risk scores persisted by an earlier run.
+    pub fn restore_risk(&self, hosts: Vec<HostRisk>) {
+        if let Some(risk) = self.state.lock().unwrap().risk.as_mut() {
+            risk.restore(hosts);
         }
-        
-        None
     }
     
-    fn check_ddos(&self, packet: &ParsedPacket) -> Option<Alert> {
-        let mut tracker = self.packet_rate_tracker.lock().unwrap();
-        let now = Utc::now();
-        
-        let packets = tracker.entry(packet.source_ip.clone()).or_insert_with(Vec::new);
-        
-        // Remove old entries
-        packets.retain(|time| {
-            (now - *time).num_seconds() < self.config.ddos.window_seconds as i64
-        });
-        
-        // Add current packet
-        packets.push(now);
-        
-        // Check if threshold exceeded
-        if packets.len() >= self.config.ddos.threshold {
-            let rate = packets.len() as f64 / self.config.ddos.window_seconds as f64;
-            
-            return Some(Alert {
-                alert_type: "Possible DDoS".to_string(),
-                severity: "critical".to_string(),
-                source_ip: packet.source_ip.clone(),
-                destination_ip: Some(packet.destination_ip.clone()),
-                details: format!(
-                    "High packet rate detected: {:.0} packets/second (threshold: {})",
-                    rate,
-                    self.config.ddos.threshold
-                ),
-                timestamp: now,
-            });
+    /// Host risk scores changed since the last call, for persisting.
+    pub fn take_risk_updates(&self) -> Vec<HostRisk> {
+        self.state
+            .lock()
+            .unwrap()
+            .risk
+            .as_mut()
+            .map(RiskScorer::take_updates)
+            .unwrap_or_default()
+    }
+    
+    /// Per-detector state summaries keyed by detector name.
+    pub fn snapshot(&self) -> serde_json::Value {
+        let state = self.state.lock().unwrap();
+        let mut snapshot = serde_json::Map::new();
+        snapshot.insert("active_flows".to_string(), state.flows.len().into());
+        if let Some(aggregator) = &state.aggregator {
+            snapshot.insert("aggregated_alerts".to_string(), aggregator.len().into());
         }
-        
-        None
+        if let Some(risk) = &state.risk {
+            let top: Vec<serde_json::Value> = risk
+                .top(10, Utc::now())
+                .into_iter()
+                .map(|(host, score)| serde_json::json!({ "host": host, "score": score }))
+                .collect();
+            snapshot.insert(
+                "risk".to_string(),
+                serde_json::json!({ "hosts": risk.len(), "top": top }),
+            );
+        }
+        if let Some(correlator) = &state.correlator {
+            snapshot.insert("correlation".to_string(), correlator.snapshot());
+        }
+        if !state.allowlist.is_empty() {
+            snapshot.insert("allowlist".to_string(), state.allowlist.snapshot(Utc::now()));
+        }
+        let mut memory = serde_json::Map::new();
+        for detector in &state.detectors {
+            snapshot.insert(detector.name().to_string(), detector.snapshot());
+            memory.insert(detector.name().to_string(), detector.memory_usage().into());
+        }
+        snapshot.insert("memory_bytes".to_string(), memory.into());
+        serde_json::Value::Object(snapshot)
     }
     
-    fn check_suspicious_port(&self, packet: &ParsedPacket) -> Option<Alert> {
-        if let Some(dest_port) = packet.destination_port {
-            if self.config.suspicious_ports.contains(&dest_port) {
-                return Some(Alert {
-                    alert_type: "Suspicious Port".to_string(),
-                    severity: "medium".to_string(),
-                    source_ip: packet.source_ip.clone(),
-                    destination_ip: Some(packet.destination_ip.clone()),
-                    details: format!(
-                        "Connection to suspicious port {} ({})",
-                        dest_port,
-                        get_port_description(dest_port)
-                    ),
-                    timestamp: Utc::now(),
-                });
-            }
+    /// Packets and flows hidden from detectors, as (allowlist reason,
+    /// detector, count).
+    pub fn suppressed_hits(&self) -> Vec<(String, &'static str, u64)> {
+        let state = self.state.lock().unwrap();
+        state
+            .allowlist
+            .hits()
+            .into_iter()
+            .map(|(reason, detector, count)| (reason.to_string(), detector, count))
+            .collect()
+    }
+    
+    /// Approximate bytes of detector state, per detector, and of the flow
+    /// table, aggregated alerts, host risk scores and correlation sequences.
+    pub fn memory_usage(&self) -> Vec<(&'static str, usize)> {
+        let state = self.state.lock().unwrap();
+        let mut usage: Vec<(&'static str, usize)> = state
+            .detectors
+            .iter()
+            .map(|detector| (detector.name(), detector.memory_usage()))
+            .collect();
+        usage.push(("flows", state.flows.memory_usage()));
+        if let Some(aggregator) = &state.aggregator {
+            usage.push(("aggregation", aggregator.memory_usage()));
+        }
+        if let Some(risk) = &state.risk {
+            usage.push(("risk", risk.memory_usage()));
         }
+        if let Some(correlator) = &state.correlator {
+            usage.push(("correlation", correlator.memory_usage()));
+        }
+        usage
+    }
+}
+
+/// A random 64-bit ID in hex.
+fn new_alert_id() -> String {
+    format!("{:016x}", rand::random::<u64>())
+}
+
+/// Collapses findings of the same type between the same endpoints into one
+/// alert carrying the highest severity and every distinct detail, then
+/// sorts by priority (stable, so detector order breaks ties).
+fn merge_alerts(alerts: Vec<Alert>) -> Vec<Alert> {
+    let mut merged: Vec<Alert> = Vec::with_capacity(alerts.len());
+    
+    for alert in alerts {
+        let related = merged.iter_mut().find(|existing| {
+            existing.alert_type == alert.alert_type
+                && existing.source_ip == alert.source_ip
+                && existing.destination_ip == alert.destination_ip
+        });
         
-        None
+        match related {
+            Some(existing) => {
+                if alert.priority() > existing.priority() {
+                    existing.severity = alert.severity;
+                }
+                if !existing.details.split("; ").any(|detail| detail == alert.details) {
+                    existing.details.push_str("; ");
+                    existing.details.push_str(&alert.details);
+                }
+                existing.timestamp = existing.timestamp.min(alert.timestamp);
+            }
+            None => merged.push(alert),
+        }
     }
+    
+    merged.sort_by_key(|alert| std::cmp::Reverse(alert.priority()));
+    merged
 }
 
-fn get_port_description(port: u16) -> &'static str {
-    match port {
-        23 => "Telnet - Insecure protocol",
-        135 => "RPC - Often targeted",
-        445 => "SMB - Ransomware vector",
-        3389 => "RDP - Brute force target",
-        _ => "Unknown service",
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networks::default_groups;
+    use suspicious_port::SuspiciousPortDetector;
+
+    struct Plain;
+
+    impl Detector for Plain {
+        fn name(&self) -> &'static str {
+            "plain"
+        }
+    }
+
+    fn inbound(severity: &str) -> Vec<Alert> {
+        vec![Alert::new(
+            "Suspicious Port",
+            severity,
+            "203.0.113.5",
+            Some("10.0.0.9".to_string()),
+            "",
+        )]
+    }
+
+    #[test]
+    fn direction_shift_skips_detectors_that_rate_direction() {
+        let networks = Networks::from_config(&default_groups()).unwrap();
+        let steps = HashMap::from([(Direction::Inbound, 1)]);
+
+        let shifted = shift_by_direction(inbound("high"), &Plain, &networks, &steps);
+        assert_eq!(shifted[0].severity, "critical");
+
+        let ports = SuspiciousPortDetector::new(vec![4444], networks.clone());
+        let kept = shift_by_direction(inbound("high"), &ports, &networks, &steps);
+        assert_eq!(kept[0].severity, "high");
+    }
+
+    fn alert(alert_type: &str, severity: &str, destination: &str, details: &str) -> Alert {
+        Alert::new(
+            alert_type,
+            severity,
+            "203.0.113.5",
+            Some(destination.to_string()),
+            details,
+        )
+    }
+
+    #[test]
+    fn same_finding_between_the_same_endpoints_is_merged() {
+        let merged = merge_alerts(vec![
+            alert("Port Scan", "medium", "10.0.0.9", "ports 1-10"),
+            alert("Suspicious Port", "low", "10.0.0.9", "port 23"),
+            alert("Port Scan", "high", "10.0.0.9", "ports 11-20"),
+            alert("Port Scan", "high", "10.0.0.9", "ports 1-10"),
+            alert("Port Scan", "low", "10.0.0.8", "ports 1-10"),
+        ]);
+
+        let types: Vec<(&str, &str)> = merged
+            .iter()
+            .map(|alert| (alert.alert_type.as_str(), alert.severity.as_str()))
+            .collect();
+        assert_eq!(
+            types,
+            [
+                ("Port Scan", "high"),
+                ("Suspicious Port", "low"),
+                ("Port Scan", "low")
+            ]
+        );
+        assert_eq!(merged[0].details, "ports 1-10; ports 11-20");
     }
 }
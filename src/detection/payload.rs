@@ -0,0 +1,182 @@
+//! Multi-pattern payload matching.
+//!
+//! Every pattern is compiled into one case-insensitive Aho-Corasick
+//! automaton, so a payload is scanned once no matter how many patterns there
+//! are; case-sensitive patterns are confirmed against the payload at the
+//! match position. The signature engine uses this as a prefilter: only rules
+//! whose pattern occurs get fully evaluated.
+//!
+//! Stream scanning carries the last bytes of each direction over to the next
+//! chunk, so a pattern split across two TCP segments is still found. Chunks
+//! are taken in arrival order; there is no reordering by sequence number.
+
+use aho_corasick::{AhoCorasick, MatchKind};
+use anyhow::Result;
+use std::collections::HashMap;
+
+use crate::error::NetGuardError;
+
+/// Collects patterns; identical patterns share one id.
+#[derive(Default)]
+pub struct PatternSetBuilder {
+    patterns: Vec<(Vec<u8>, bool)>,
+    ids: HashMap<(Vec<u8>, bool), usize>,
+}
+
+impl PatternSetBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a pattern and returns its id. `nocase` matches ASCII letters
+    /// case-insensitively.
+    pub fn add(&mut self, pattern: &[u8], nocase: bool) -> usize {
+        let key = if nocase {
+            (pattern.to_ascii_lowercase(), true)
+        } else {
+            (pattern.to_vec(), false)
+        };
+        if let Some(id) = self.ids.get(&key) {
+            return *id;
+        }
+        let id = self.patterns.len();
+        self.patterns.push(key.clone());
+        self.ids.insert(key, id);
+        id
+    }
+
+    pub fn build(self) -> Result<PatternSet> {
+        let max_len = self
+            .patterns
+            .iter()
+            .map(|(pattern, _)| pattern.len())
+            .max()
+            .unwrap_or(0);
+        let automaton = if self.patterns.is_empty() {
+            None
+        } else {
+            let automaton = AhoCorasick::builder()
+                .match_kind(MatchKind::Standard)
+                .ascii_case_insensitive(true)
+                .build(self.patterns.iter().map(|(pattern, _)| pattern))
+                .map_err(|e| NetGuardError::RuleError(format!("Cannot compile patterns: {}", e)))?;
+            Some(automaton)
+        };
+        Ok(PatternSet {
+            automaton,
+            patterns: self.patterns,
+            max_len,
+        })
+    }
+}
+
+pub struct PatternSet {
+    automaton: Option<AhoCorasick>,
+    /// Patterns by id, with whether they ignore case.
+    patterns: Vec<(Vec<u8>, bool)>,
+    max_len: usize,
+}
+
+impl PatternSet {
+    pub fn len(&self) -> usize {
+        self.patterns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Length of the longest pattern; a stream must keep one byte less of
+    /// history to catch every split match.
+    pub fn max_pattern_len(&self) -> usize {
+        self.max_len
+    }
+
+    /// Calls `on_match` with the id of every pattern occurrence in `data`.
+    /// A pattern occurring several times is reported several times.
+    pub fn scan(&self, data: &[u8], mut on_match: impl FnMut(usize)) {
+        self.scan_from(data, 0, &mut on_match);
+    }
+
+    /// Scans the next chunk of a stream, including occurrences that start
+    /// in earlier chunks. Only occurrences ending inside `chunk` are
+    /// reported, so none is reported twice. Call [`StreamState::push`]
+    /// afterwards.
+    pub fn scan_stream(&self, stream: &StreamState, chunk: &[u8], mut on_match: impl FnMut(usize)) {
+        let carry = self.max_len.saturating_sub(1).min(stream.history.len());
+        if carry == 0 {
+            self.scan_from(chunk, 0, &mut on_match);
+            return;
+        }
+        let mut data = Vec::with_capacity(carry + chunk.len());
+        data.extend_from_slice(&stream.history[stream.history.len() - carry..]);
+        data.extend_from_slice(chunk);
+        self.scan_from(&data, carry, &mut on_match);
+    }
+
+    /// Reports every match (overlapping ones included) that ends past the
+    /// first `min_end` bytes.
+    fn scan_from(&self, data: &[u8], min_end: usize, on_match: &mut impl FnMut(usize)) {
+        let Some(automaton) = &self.automaton else {
+            return;
+        };
+        for found in automaton.find_overlapping_iter(data) {
+            if found.end() <= min_end {
+                continue;
+            }
+            let id = found.pattern().as_usize();
+            let (pattern, nocase) = &self.patterns[id];
+            if *nocase || data[found.range()] == pattern[..] {
+                on_match(id);
+            }
+        }
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        self.automaton
+            .as_ref()
+            .map_or(0, |automaton| automaton.memory_usage())
+            + self
+                .patterns
+                .iter()
+                .map(|(pattern, _)| pattern.capacity() + std::mem::size_of::<(Vec<u8>, bool)>())
+                .sum::<usize>()
+    }
+}
+
+/// The most recent bytes of one direction of a stream.
+pub struct StreamState {
+    history: Vec<u8>,
+    limit: usize,
+}
+
+impl StreamState {
+    /// Keeps at most `limit` bytes of history.
+    pub fn new(limit: usize) -> Self {
+        Self {
+            history: Vec::new(),
+            limit,
+        }
+    }
+
+    pub fn history(&self) -> &[u8] {
+        &self.history
+    }
+
+    /// Appends a chunk, dropping the oldest bytes beyond the limit.
+    pub fn push(&mut self, chunk: &[u8]) {
+        if chunk.len() >= self.limit {
+            self.history.clear();
+            self.history
+                .extend_from_slice(&chunk[chunk.len() - self.limit..]);
+            return;
+        }
+        let overflow = (self.history.len() + chunk.len()).saturating_sub(self.limit);
+        self.history.drain(..overflow);
+        self.history.extend_from_slice(chunk);
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        self.history.capacity()
+    }
+}
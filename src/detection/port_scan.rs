@@ -0,0 +1,582 @@
+//! Scan detection and classification.
+//!
+//! Only packets that look like probes are counted: TCP packets with a scan
+//! flag pattern (SYN, FIN, NULL, Xmas), ACK and Maimon (FIN/ACK) probes once
+//! the target answers them with a RST, UDP probes answered with ICMP port
+//! unreachable, and ICMP echo requests. Per source, probes within the window
+//! are checked for vertical scans (many ports), horizontal sweeps (one port
+//! across many hosts) and ping sweeps.
+//!
+//! State is bounded: sources live in an LRU map, distinct ports are
+//! estimated with a windowed HyperLogLog, and only the most recent probes
+//! are kept to classify the scan and name its targets.
+
+use chrono::{DateTime, Duration, Utc};
+use pnet::packet::tcp::TcpFlags;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::mem::size_of;
+
+use super::detector::Detector;
+use super::state::{HyperLogLog, LruMap, Ring};
+use super::Alert;
+use crate::attack;
+use crate::capture::icmp::IcmpMessage;
+use crate::capture::parser::{AppLayer, ParsedPacket};
+use crate::config::PortScanConfig;
+
+const MAX_SOURCES: usize = 10_000;
+const MAX_RECENT_PROBES: usize = 512;
+const WINDOW_BUCKETS: usize = 5;
+const MAX_UNCONFIRMED: usize = 65_536;
+/// Typical heap size of an address string, for memory estimates.
+const IP_STRING_BYTES: usize = 40;
+/// Targets listed in an alert before the rest are summarized as a count.
+const MAX_LISTED: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum ScanType {
+    Syn,
+    Fin,
+    Null,
+    Xmas,
+    Ack,
+    Maimon,
+    Udp,
+    Ping,
+}
+
+impl ScanType {
+    fn name(&self) -> &'static str {
+        match self {
+            ScanType::Syn => "SYN",
+            ScanType::Fin => "FIN",
+            ScanType::Null => "NULL",
+            ScanType::Xmas => "Xmas",
+            ScanType::Ack => "ACK",
+            ScanType::Maimon => "Maimon",
+            ScanType::Udp => "UDP",
+            ScanType::Ping => "ICMP echo",
+        }
+    }
+
+    /// ACK and Maimon probes look like ordinary traffic until the target
+    /// answers them with a RST.
+    fn needs_confirmation(&self) -> bool {
+        matches!(self, ScanType::Ack | ScanType::Maimon)
+    }
+
+    fn is_udp(&self) -> bool {
+        matches!(self, ScanType::Udp)
+    }
+}
+
+fn classify(flags: u8) -> Option<ScanType> {
+    let flags = flags
+        & (TcpFlags::FIN
+            | TcpFlags::SYN
+            | TcpFlags::RST
+            | TcpFlags::PSH
+            | TcpFlags::ACK
+            | TcpFlags::URG);
+    match flags {
+        TcpFlags::SYN => Some(ScanType::Syn),
+        TcpFlags::FIN => Some(ScanType::Fin),
+        0 => Some(ScanType::Null),
+        f if f == TcpFlags::FIN | TcpFlags::PSH | TcpFlags::URG => Some(ScanType::Xmas),
+        TcpFlags::ACK => Some(ScanType::Ack),
+        f if f == TcpFlags::FIN | TcpFlags::ACK => Some(ScanType::Maimon),
+        _ => None,
+    }
+}
+
+struct Probe {
+    host: String,
+    port: Option<u16>,
+    scan_type: ScanType,
+    time: DateTime<Utc>,
+}
+
+/// (scanner, scanner port, target, target port)
+type ConnectionKey = (String, u16, String, u16);
+
+struct SourceScan {
+    /// Distinct destination ports probed, per window bucket.
+    ports: Ring<HyperLogLog>,
+    /// Most recent probes within the window.
+    recent: VecDeque<Probe>,
+}
+
+pub struct PortScanDetector {
+    config: PortScanConfig,
+    sources: LruMap<String, SourceScan>,
+    unconfirmed: LruMap<ConnectionKey, ScanType>,
+}
+
+impl PortScanDetector {
+    pub fn new(config: PortScanConfig) -> Self {
+        Self {
+            config,
+            sources: LruMap::new(MAX_SOURCES),
+            unconfirmed: LruMap::new(MAX_UNCONFIRMED),
+        }
+    }
+
+    fn record(&mut self, scanner: &str, probe: Probe) -> Vec<Alert> {
+        let now = probe.time;
+        let window = Duration::seconds(self.config.window_seconds as i64);
+        let window_ms = window.num_milliseconds();
+
+        let source = self
+            .sources
+            .get_or_insert_with(scanner.to_string(), || SourceScan {
+                ports: Ring::new(window_ms, WINDOW_BUCKETS),
+                recent: VecDeque::new(),
+            });
+        if let Some(port) = probe.port {
+            source.ports.current(now).insert(&port);
+        }
+        while source
+            .recent
+            .front()
+            .is_some_and(|oldest| now - oldest.time >= window)
+        {
+            source.recent.pop_front();
+        }
+        if source.recent.len() >= MAX_RECENT_PROBES {
+            source.recent.pop_front();
+        }
+        let scan_type = probe.scan_type;
+        let port = probe.port;
+        source.recent.push_back(probe);
+
+        let distinct_ports = source.ports.distinct(now) as usize;
+        let probes = source.recent.make_contiguous();
+        let mut alerts = Vec::new();
+        if scan_type == ScanType::Ping {
+            alerts.extend(check_ping_sweep(&self.config, scanner, probes, now));
+        } else {
+            alerts.extend(check_vertical(
+                &self.config,
+                scanner,
+                probes,
+                distinct_ports,
+                now,
+            ));
+            if let Some(port) = port {
+                alerts.extend(check_horizontal(
+                    &self.config,
+                    scanner,
+                    probes,
+                    port,
+                    scan_type,
+                    now,
+                ));
+            }
+        }
+        alerts
+    }
+
+    fn on_tcp(&mut self, packet: &ParsedPacket, flags: u8, now: DateTime<Utc>) -> Vec<Alert> {
+        let (Some(source_port), Some(destination_port)) =
+            (packet.source_port, packet.destination_port)
+        else {
+            return Vec::new();
+        };
+
+        // A RST from the target confirms an earlier ACK or Maimon probe.
+        if flags & TcpFlags::RST != 0 {
+            let key = (
+                packet.destination_ip.clone(),
+                destination_port,
+                packet.source_ip.clone(),
+                source_port,
+            );
+            let Some(scan_type) = self.unconfirmed.remove(&key) else {
+                return Vec::new();
+            };
+            let probe = Probe {
+                host: packet.source_ip.clone(),
+                port: Some(source_port),
+                scan_type,
+                time: now,
+            };
+            return self.record(&packet.destination_ip, probe);
+        }
+
+        let Some(scan_type) = classify(flags) else {
+            return Vec::new();
+        };
+        if scan_type.needs_confirmation() {
+            self.unconfirmed.insert(
+                (
+                    packet.source_ip.clone(),
+                    source_port,
+                    packet.destination_ip.clone(),
+                    destination_port,
+                ),
+                scan_type,
+            );
+            return Vec::new();
+        }
+
+        let probe = Probe {
+            host: packet.destination_ip.clone(),
+            port: Some(destination_port),
+            scan_type,
+            time: now,
+        };
+        self.record(&packet.source_ip, probe)
+    }
+
+    fn on_icmp(
+        &mut self,
+        packet: &ParsedPacket,
+        message: &IcmpMessage,
+        now: DateTime<Utc>,
+    ) -> Vec<Alert> {
+        match message {
+            IcmpMessage::EchoRequest => {
+                let probe = Probe {
+                    host: packet.destination_ip.clone(),
+                    port: None,
+                    scan_type: ScanType::Ping,
+                    time: now,
+                };
+                self.record(&packet.source_ip, probe)
+            }
+            // The unreachable goes back to the scanner and quotes its probe.
+            IcmpMessage::Unreachable {
+                port_unreachable: true,
+                original: Some(original),
+            } if original.protocol == "UDP" => {
+                let probe = Probe {
+                    host: original.destination_ip.clone(),
+                    port: original.destination_port,
+                    scan_type: ScanType::Udp,
+                    time: now,
+                };
+                self.record(&packet.destination_ip, probe)
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl Detector for PortScanDetector {
+    fn name(&self) -> &'static str {
+        "port_scan"
+    }
+
+    fn on_packet(&mut self, packet: &ParsedPacket) -> Vec<Alert> {
+        let now = Utc::now();
+        if let Some(flags) = packet.tcp_flags {
+            return self.on_tcp(packet, flags, now);
+        }
+        match &packet.app {
+            Some(AppLayer::Icmp(message)) => self.on_icmp(packet, message, now),
+            _ => Vec::new(),
+        }
+    }
+
+    fn snapshot(&self) -> serde_json::Value {
+        serde_json::json!({
+            "tracked_sources": self.sources.len(),
+            "unconfirmed_probes": self.unconfirmed.len(),
+        })
+    }
+
+    fn memory_usage(&self) -> usize {
+        let sources: usize = self
+            .sources
+            .values()
+            .map(|source| {
+                source.ports.len() * size_of::<(i64, HyperLogLog)>()
+                    + source.recent.capacity() * size_of::<Probe>()
+                    + source
+                        .recent
+                        .iter()
+                        .map(|probe| probe.host.len())
+                        .sum::<usize>()
+            })
+            .sum();
+        let connections =
+            self.unconfirmed.len() * (LruMap::<ConnectionKey, ScanType>::entry_overhead() + 2 * IP_STRING_BYTES);
+        self.sources.len() * LruMap::<String, SourceScan>::entry_overhead() + sources + connections
+    }
+}
+
+/// Many ports probed on one or more hosts.
+fn check_vertical(
+    config: &PortScanConfig,
+    scanner: &str,
+    probes: &[Probe],
+    distinct_ports: usize,
+    now: DateTime<Utc>,
+) -> Option<Alert> {
+    if distinct_ports < config.threshold {
+        return None;
+    }
+
+    let port_probes: Vec<&Probe> = probes
+        .iter()
+        .filter(|probe| probe.scan_type != ScanType::Ping)
+        .collect();
+    let ports: BTreeSet<u16> = port_probes.iter().filter_map(|probe| probe.port).collect();
+    let hosts: BTreeSet<&str> = port_probes
+        .iter()
+        .map(|probe| probe.host.as_str())
+        .collect();
+    let mut types: HashMap<ScanType, usize> = HashMap::new();
+    for probe in &port_probes {
+        *types.entry(probe.scan_type).or_default() += 1;
+    }
+    let mut types: Vec<(ScanType, usize)> = types.into_iter().collect();
+    types.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    let scan_types: Vec<&str> = types
+        .iter()
+        .map(|(scan_type, _)| scan_type.name())
+        .collect();
+
+    Some(
+        Alert::new(
+            "Port Scan",
+            "high",
+            scanner,
+            single(&hosts),
+            format!(
+                "{} scan of about {} unique ports on {} in {} seconds: recent ports {}; hosts {}",
+                scan_types.join("/"),
+                distinct_ports.max(ports.len()),
+                plural(hosts.len(), "host"),
+                config.window_seconds,
+                list(ports.iter().map(|port| port.to_string())),
+                list(hosts.iter().map(|host| host.to_string()))
+            ),
+        )
+        .at(now)
+        .with_attack(attack::techniques(&[("TA0007", "T1046")])),
+    )
+}
+
+/// One port probed across many hosts.
+fn check_horizontal(
+    config: &PortScanConfig,
+    scanner: &str,
+    probes: &[Probe],
+    port: u16,
+    scan_type: ScanType,
+    now: DateTime<Utc>,
+) -> Option<Alert> {
+    let hosts: BTreeSet<&str> = probes
+        .iter()
+        .filter(|probe| {
+            probe.port == Some(port)
+                && probe.scan_type != ScanType::Ping
+                && probe.scan_type.is_udp() == scan_type.is_udp()
+        })
+        .map(|probe| probe.host.as_str())
+        .collect();
+    if hosts.len() < config.sweep_threshold {
+        return None;
+    }
+
+    Some(
+        Alert::new(
+            "Horizontal Sweep",
+            "high",
+            scanner,
+            None,
+            format!(
+                "{} sweep of {}/{} across {} hosts in {} seconds: hosts {}",
+                scan_type.name(),
+                if scan_type.is_udp() { "udp" } else { "tcp" },
+                port,
+                hosts.len(),
+                config.window_seconds,
+                list(hosts.iter().map(|host| host.to_string()))
+            ),
+        )
+        .at(now)
+        .with_attack(attack::techniques(&[("TA0007", "T1046")])),
+    )
+}
+
+fn check_ping_sweep(
+    config: &PortScanConfig,
+    scanner: &str,
+    probes: &[Probe],
+    now: DateTime<Utc>,
+) -> Option<Alert> {
+    let hosts: BTreeSet<&str> = probes
+        .iter()
+        .filter(|probe| probe.scan_type == ScanType::Ping)
+        .map(|probe| probe.host.as_str())
+        .collect();
+    if hosts.len() < config.sweep_threshold {
+        return None;
+    }
+
+    Some(
+        Alert::new(
+            "Ping Sweep",
+            "medium",
+            scanner,
+            None,
+            format!(
+                "ICMP echo sweep across {} hosts in {} seconds: hosts {}",
+                hosts.len(),
+                config.window_seconds,
+                list(hosts.iter().map(|host| host.to_string()))
+            ),
+        )
+        .at(now)
+        .with_attack(attack::techniques(&[("TA0007", "T1018")])),
+    )
+}
+
+fn single(hosts: &BTreeSet<&str>) -> Option<String> {
+    if hosts.len() == 1 {
+        hosts.first().map(|host| host.to_string())
+    } else {
+        None
+    }
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("1 {}", noun)
+    } else {
+        format!("{} {}s", count, noun)
+    }
+}
+
+/// Comma-separated list, truncated after `MAX_LISTED` entries.
+fn list(items: impl ExactSizeIterator<Item = String>) -> String {
+    let total = items.len();
+    let mut shown: Vec<String> = items.take(MAX_LISTED).collect();
+    if total > MAX_LISTED {
+        shown.push(format!("... ({} more)", total - MAX_LISTED));
+    }
+    shown.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn detector() -> PortScanDetector {
+        PortScanDetector::new(PortScanConfig {
+            enabled: true,
+            threshold: 5,
+            window_seconds: 10,
+            sweep_threshold: 3,
+        })
+    }
+
+    fn at(millis: i64) -> DateTime<Utc> {
+        Utc.timestamp_millis_opt(1_700_000_000_000 + millis)
+            .unwrap()
+    }
+
+    fn tcp(source: (&str, u16), destination: (&str, u16), flags: u8) -> ParsedPacket {
+        ParsedPacket {
+            source_ip: source.0.to_string(),
+            destination_ip: destination.0.to_string(),
+            source_port: Some(source.1),
+            destination_port: Some(destination.1),
+            protocol: "TCP".to_string(),
+            size: 60,
+            tcp_flags: Some(flags),
+            app: None,
+            payload: Vec::new(),
+        }
+    }
+
+    fn probe(
+        detector: &mut PortScanDetector,
+        host: &str,
+        port: u16,
+        flags: u8,
+        now: DateTime<Utc>,
+    ) -> Vec<Alert> {
+        let packet = tcp(("203.0.113.5", 40000), (host, port), flags);
+        detector.on_tcp(&packet, flags, now)
+    }
+
+    #[test]
+    fn flag_patterns_are_classified() {
+        assert_eq!(classify(TcpFlags::SYN), Some(ScanType::Syn));
+        assert_eq!(classify(0), Some(ScanType::Null));
+        assert_eq!(
+            classify(TcpFlags::FIN | TcpFlags::PSH | TcpFlags::URG),
+            Some(ScanType::Xmas)
+        );
+        assert_eq!(
+            classify(TcpFlags::FIN | TcpFlags::ACK),
+            Some(ScanType::Maimon)
+        );
+        assert_eq!(classify(TcpFlags::SYN | TcpFlags::ACK), None);
+        assert_eq!(classify(TcpFlags::PSH | TcpFlags::ACK), None);
+    }
+
+    #[test]
+    fn vertical_scan_needs_threshold_ports_within_the_window() {
+        let mut scan = detector();
+        for port in 1..5 {
+            assert!(probe(&mut scan, "10.0.0.9", port, TcpFlags::SYN, at(0)).is_empty());
+        }
+        let alerts = probe(&mut scan, "10.0.0.9", 5, TcpFlags::SYN, at(9_999));
+        assert_eq!(alerts[0].alert_type, "Port Scan");
+        assert!(alerts[0].details.starts_with("SYN scan"));
+
+        let mut scan = detector();
+        for port in 1..5 {
+            probe(&mut scan, "10.0.0.9", port, TcpFlags::SYN, at(0));
+        }
+        assert!(probe(&mut scan, "10.0.0.9", 5, TcpFlags::SYN, at(10_000)).is_empty());
+    }
+
+    #[test]
+    fn ack_probes_count_only_once_the_target_resets() {
+        let mut scan = detector();
+        let mut alerts = Vec::new();
+        for port in 1..=5 {
+            assert!(probe(&mut scan, "10.0.0.9", port, TcpFlags::ACK, at(0)).is_empty());
+            let reset = tcp(("10.0.0.9", port), ("203.0.113.5", 40000), TcpFlags::RST);
+            alerts = scan.on_tcp(&reset, TcpFlags::RST, at(0));
+        }
+        assert_eq!(alerts[0].source_ip, "203.0.113.5");
+        assert!(alerts[0].details.starts_with("ACK scan"));
+
+        let unprompted = tcp(("10.0.0.9", 6), ("203.0.113.5", 40000), TcpFlags::RST);
+        assert!(scan.on_tcp(&unprompted, TcpFlags::RST, at(0)).is_empty());
+    }
+
+    #[test]
+    fn one_port_across_hosts_is_a_horizontal_sweep() {
+        let mut scan = detector();
+        assert!(probe(&mut scan, "10.0.0.1", 22, TcpFlags::SYN, at(0)).is_empty());
+        assert!(probe(&mut scan, "10.0.0.2", 22, TcpFlags::SYN, at(0)).is_empty());
+        let alerts = probe(&mut scan, "10.0.0.3", 22, TcpFlags::SYN, at(0));
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].alert_type, "Horizontal Sweep");
+        assert!(alerts[0].details.contains("tcp/22 across 3 hosts"));
+    }
+
+    #[test]
+    fn echo_requests_to_many_hosts_are_a_ping_sweep() {
+        let mut scan = detector();
+        let alerts: Vec<Alert> = ["10.0.0.1", "10.0.0.2", "10.0.0.3"]
+            .iter()
+            .flat_map(|host| {
+                let mut packet = tcp(("203.0.113.5", 0), (host, 0), 0);
+                packet.protocol = "ICMP".to_string();
+                packet.tcp_flags = None;
+                scan.on_icmp(&packet, &IcmpMessage::EchoRequest, at(0))
+            })
+            .collect();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].alert_type, "Ping Sweep");
+    }
+}
@@ -0,0 +1,347 @@
+//! Per-host risk scores accumulated from alerts.
+//!
+//! Every alert the engine emits adds points to its source host: a base by
+//! severity, multiplied by weights for the alert type, the traffic
+//! direction and threat-intel matches. Scores decay exponentially with a
+//! configured half-life, so a host that stops misbehaving drops down the
+//! ranking. When a score crosses the threshold the scorer raises an
+//! escalation alert; it re-arms once the score has decayed below half the
+//! threshold.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::mem::size_of;
+use std::net::IpAddr;
+
+use super::state::LruMap;
+use super::Alert;
+use crate::config::RiskConfig;
+use crate::networks::Networks;
+
+const MAX_HOSTS: usize = 100_000;
+/// Alert types listed in escalation details.
+const TOP_TYPES: usize = 3;
+pub const ESCALATION_TYPE: &str = "High Risk Host";
+const INTEL_TYPE: &str = "Threat Intel Match";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HostRisk {
+    pub host: String,
+    /// Score as of `updated`.
+    pub score: f64,
+    pub updated: DateTime<Utc>,
+    /// Alerts counted towards the score.
+    pub alerts: u64,
+    /// Decayed points by alert type.
+    pub by_type: HashMap<String, f64>,
+    /// Above the threshold and already reported.
+    pub escalated: bool,
+}
+
+impl HostRisk {
+    fn new(host: &str, now: DateTime<Utc>) -> Self {
+        Self {
+            host: host.to_string(),
+            score: 0.0,
+            updated: now,
+            alerts: 0,
+            by_type: HashMap::new(),
+            escalated: false,
+        }
+    }
+
+    /// The score decayed to `now`.
+    pub fn score_at(&self, now: DateTime<Utc>, half_life_hours: f64) -> f64 {
+        self.score * decay(self.updated, now, half_life_hours)
+    }
+
+    /// Alert types by contribution, largest first.
+    pub fn top_types(&self, count: usize) -> Vec<(&str, f64)> {
+        let mut types: Vec<(&str, f64)> = self
+            .by_type
+            .iter()
+            .map(|(alert_type, points)| (alert_type.as_str(), *points))
+            .collect();
+        types.sort_by(|a, b| b.1.total_cmp(&a.1));
+        types.truncate(count);
+        types
+    }
+
+    /// Decays the score and per-type points to `now`.
+    pub fn decay_to(&mut self, now: DateTime<Utc>, half_life_hours: f64) {
+        let factor = decay(self.updated, now, half_life_hours);
+        self.score *= factor;
+        for points in self.by_type.values_mut() {
+            *points *= factor;
+        }
+        self.updated = now.max(self.updated);
+    }
+}
+
+/// Multiplier for `half_life_hours` decay between two times.
+fn decay(from: DateTime<Utc>, to: DateTime<Utc>, half_life_hours: f64) -> f64 {
+    let hours = (to - from).num_milliseconds().max(0) as f64 / 3_600_000.0;
+    if half_life_hours <= 0.0 {
+        return 1.0;
+    }
+    0.5f64.powf(hours / half_life_hours)
+}
+
+pub struct RiskScorer {
+    config: RiskConfig,
+    hosts: LruMap<String, HostRisk>,
+    /// Hosts updated since the last [`RiskScorer::take_updates`].
+    changed: HashSet<String>,
+}
+
+impl RiskScorer {
+    pub fn new(config: RiskConfig) -> Self {
+        Self {
+            config,
+            hosts: LruMap::new(MAX_HOSTS),
+            changed: HashSet::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.hosts.len()
+    }
+
+    /// Seeds scores persisted by an earlier run.
+    pub fn restore(&mut self, hosts: Vec<HostRisk>) {
+        for host in hosts {
+            self.hosts.insert(host.host.clone(), host);
+        }
+    }
+
+    /// Points for one alert, before decay.
+    fn points(&self, alert: &Alert, networks: &Networks) -> f64 {
+        let base = self
+            .config
+            .severity_points
+            .get(&alert.severity)
+            .copied()
+            .unwrap_or(0.0);
+        let type_weight = self
+            .config
+            .type_weights
+            .get(&alert.alert_type)
+            .copied()
+            .unwrap_or(1.0);
+        let direction_weight = alert
+            .destination_ip
+            .as_ref()
+            .and_then(|destination| {
+                let direction = networks.direction(&alert.source_ip, destination);
+                self.config.direction_weights.get(&direction).copied()
+            })
+            .unwrap_or(1.0);
+        let intel_weight = if alert.alert_type == INTEL_TYPE {
+            self.config.intel_weight
+        } else {
+            1.0
+        };
+        base * type_weight * direction_weight * intel_weight
+    }
+
+    /// Scores the alerts' source hosts and returns escalation alerts for
+    /// those that crossed the threshold. "Still ongoing" updates are not
+    /// scored: the alert they summarise already was.
+    pub fn process(
+        &mut self,
+        alerts: &[Alert],
+        networks: &Networks,
+        now: DateTime<Utc>,
+    ) -> Vec<Alert> {
+        let mut escalations = Vec::new();
+        for alert in alerts {
+            if alert.ongoing
+                || alert.alert_type == ESCALATION_TYPE
+                || alert.source_ip.parse::<IpAddr>().is_err()
+            {
+                continue;
+            }
+            let points = self.points(alert, networks);
+            if points <= 0.0 {
+                continue;
+            }
+
+            let half_life = self.config.half_life_hours;
+            let threshold = self.config.threshold;
+            let host = self.hosts.get_or_insert_with(alert.source_ip.clone(), || {
+                HostRisk::new(&alert.source_ip, now)
+            });
+            host.decay_to(now, half_life);
+            host.score += points;
+            host.alerts += 1;
+            *host.by_type.entry(alert.alert_type.clone()).or_insert(0.0) += points;
+            if host.escalated && host.score < threshold / 2.0 {
+                host.escalated = false;
+            }
+            if !host.escalated && host.score >= threshold {
+                host.escalated = true;
+                escalations.push(escalation_alert(host, threshold, now));
+            }
+            self.changed.insert(alert.source_ip.clone());
+        }
+        escalations
+    }
+
+    /// Hosts whose scores changed since the last call, for persisting.
+    pub fn take_updates(&mut self) -> Vec<HostRisk> {
+        let changed = std::mem::take(&mut self.changed);
+        changed
+            .into_iter()
+            .filter_map(|host| self.hosts.get_mut(&host).map(|risk| risk.clone()))
+            .collect()
+    }
+
+    /// The `count` hosts with the highest decayed scores.
+    pub fn top(&self, count: usize, now: DateTime<Utc>) -> Vec<(String, f64)> {
+        let mut hosts: Vec<(String, f64)> = self
+            .hosts
+            .values()
+            .map(|risk| {
+                (
+                    risk.host.clone(),
+                    risk.score_at(now, self.config.half_life_hours),
+                )
+            })
+            .collect();
+        hosts.sort_by(|a, b| b.1.total_cmp(&a.1));
+        hosts.truncate(count);
+        hosts
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        let types: usize = self
+            .hosts
+            .values()
+            .map(|risk| {
+                risk.host.capacity()
+                    + risk
+                        .by_type
+                        .keys()
+                        .map(|alert_type| alert_type.capacity() + size_of::<(String, f64)>())
+                        .sum::<usize>()
+            })
+            .sum();
+        self.hosts.len() * LruMap::<String, HostRisk>::entry_overhead() + types
+    }
+}
+
+fn escalation_alert(host: &HostRisk, threshold: f64, now: DateTime<Utc>) -> Alert {
+    let contributions: Vec<String> = host
+        .top_types(TOP_TYPES)
+        .into_iter()
+        .map(|(alert_type, points)| format!("{} {:.1}", alert_type, points))
+        .collect();
+    Alert::new(
+        ESCALATION_TYPE,
+        if host.score >= threshold * 2.0 {
+            "critical"
+        } else {
+            "high"
+        },
+        host.host.clone(),
+        None,
+        format!(
+            "Risk score {:.1} crossed threshold {:.1} after {} alerts (top: {})",
+            host.score,
+            threshold,
+            host.alerts,
+            contributions.join(", ")
+        ),
+    )
+    .at(now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networks::default_groups;
+    use chrono::Duration;
+
+    fn networks() -> Networks {
+        Networks::from_config(&default_groups()).unwrap()
+    }
+
+    fn alert(severity: &str, source_ip: &str) -> Alert {
+        Alert::new(
+            "Port Scan",
+            severity,
+            source_ip,
+            Some("10.0.0.9".to_string()),
+            "",
+        )
+    }
+
+    #[test]
+    fn ongoing_updates_are_not_scored() {
+        let mut scorer = RiskScorer::new(RiskConfig::default());
+        let networks = networks();
+        let now = Utc::now();
+        scorer.process(&[alert("critical", "10.0.0.5")], &networks, now);
+
+        let mut update = alert("critical", "10.0.0.5");
+        update.ongoing = true;
+        for minute in 1..=10 {
+            let escalations = scorer.process(
+                &[update.clone()],
+                &networks,
+                now + Duration::minutes(minute),
+            );
+            assert!(escalations.is_empty());
+        }
+        let host = scorer.hosts.get_mut(&"10.0.0.5".to_string()).unwrap();
+        assert_eq!((host.alerts, host.score), (1, 50.0));
+    }
+
+    #[test]
+    fn scores_halve_every_half_life() {
+        let mut scorer = RiskScorer::new(RiskConfig::default());
+        let networks = networks();
+        let now = Utc::now();
+        scorer.process(
+            &[alert("critical", "10.0.0.5"), alert("high", "10.0.0.6")],
+            &networks,
+            now,
+        );
+
+        let later = now + Duration::hours(6);
+        assert_eq!(scorer.top(1, later), [("10.0.0.5".to_string(), 25.0)]);
+        assert_eq!(scorer.top(5, later).len(), 2);
+        assert_eq!(decay(now, now + Duration::hours(12), 6.0), 0.25);
+        assert_eq!(decay(now, later, 0.0), 1.0);
+        assert_eq!(decay(later, now, 6.0), 1.0);
+    }
+
+    #[test]
+    fn escalation_fires_once_and_rearms_below_half_the_threshold() {
+        let mut scorer = RiskScorer::new(RiskConfig::default());
+        let networks = networks();
+        let now = Utc::now();
+        let critical = || alert("critical", "10.0.0.5");
+
+        assert!(scorer.process(&[critical()], &networks, now).is_empty());
+        let escalations = scorer.process(&[critical()], &networks, now);
+        assert_eq!(escalations.len(), 1);
+        assert_eq!(escalations[0].alert_type, ESCALATION_TYPE);
+        assert_eq!(escalations[0].severity, "high");
+        assert!(escalations[0]
+            .details
+            .starts_with("Risk score 100.0 crossed threshold 100.0 after 2 alerts"));
+        assert!(scorer.process(&[critical()], &networks, now).is_empty());
+        assert!(scorer.process(&escalations, &networks, now).is_empty());
+
+        // 150 points decay to 18.75 over three half-lives, re-arming the
+        // escalation once the next alert is scored.
+        let later = now + Duration::hours(18);
+        assert!(scorer
+            .process(&[alert("low", "10.0.0.5")], &networks, later)
+            .is_empty());
+        assert!(scorer.process(&[critical()], &networks, later).is_empty());
+        assert_eq!(scorer.process(&[critical()], &networks, later).len(), 1);
+    }
+}
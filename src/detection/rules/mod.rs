@@ -0,0 +1,857 @@
+//! Signature rules in a subset of Suricata/Snort syntax.
+//!
+//! Rules are loaded from the files listed in `detection.rules` and checked
+//! against every packet's transport payload. One content per rule (its
+//! `fast_pattern`, else its longest) goes into a shared Aho-Corasick
+//! prefilter, so only rules whose pattern occurs are evaluated in full. For
+//! TCP, the last bytes of each direction are kept so that a rule split
+//! across segments still matches. Flow direction and establishment,
+//! flowbits and thresholds need per-connection and per-host state, which is
+//! kept in bounded LRU maps. `geoip` and `asn` options are matched against
+//! the databases in `detection.geoip`.
+
+mod parse;
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use pnet::packet::tcp::TcpFlags;
+use std::collections::HashSet;
+use std::fs;
+use std::mem::size_of;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use super::detector::Detector;
+use super::payload::{PatternSet, PatternSetBuilder, StreamState};
+use super::state::LruMap;
+use super::Alert;
+use crate::capture::parser::{AppLayer, ParsedPacket};
+use crate::config::RulesConfig;
+use crate::error::NetGuardError;
+use crate::geoip::GeoIp;
+use crate::networks::Networks;
+
+pub use parse::{parse_rule, Rule};
+use parse::{Action, Content, Flowbit, Match, Pcre, Protocol, ThresholdKind, Track};
+
+const MAX_CONNECTIONS: usize = 65_536;
+const MAX_THRESHOLDS: usize = 65_536;
+/// Candidate positions tried per packet and rule when relative content
+/// matches force backtracking.
+const MATCH_BUDGET: usize = 1_024;
+/// Typical heap size of an address string, for memory estimates.
+const IP_STRING_BYTES: usize = 40;
+
+/// Both endpoints in a fixed order, so each direction maps to one entry.
+type ConnectionKey = (String, u16, String, u16, String);
+
+struct Connection {
+    client: (String, u16),
+    /// TCP handshake completed, or (UDP) traffic seen both ways.
+    established: bool,
+    syn_ack: bool,
+    flowbits: HashSet<String>,
+    /// Recent payload towards the client and towards the server.
+    streams: [StreamState; 2],
+}
+
+struct ThresholdState {
+    start: DateTime<Utc>,
+    count: u64,
+}
+
+pub struct RuleDetector {
+    rules: Vec<Rule>,
+    prefilter: PatternSet,
+    /// Rules to evaluate when a prefilter pattern occurs, by pattern id.
+    pattern_rules: Vec<Vec<usize>>,
+    /// Rules without a positive content, evaluated on every packet.
+    unfiltered: Vec<usize>,
+    stream_window: usize,
+    connections: LruMap<ConnectionKey, Connection>,
+    thresholds: LruMap<(u32, String), ThresholdState>,
+    geoip: Option<Arc<GeoIp>>,
+}
+
+impl RuleDetector {
+    /// Builds a detector over `rules`, keeping up to `stream_window` bytes
+    /// of each TCP direction for matches that span segments. Without
+    /// `geoip`, every address counts as being in no country or AS.
+    pub fn new(
+        mut rules: Vec<Rule>,
+        stream_window: usize,
+        geoip: Option<Arc<GeoIp>>,
+    ) -> Result<Self> {
+        // Pass rules suppress others, and rules that set flowbits must run
+        // before rules testing them on the same packet.
+        rules.sort_by_key(|rule| {
+            let sets_bits = rule.flowbits.iter().any(|bit| {
+                matches!(
+                    bit,
+                    Flowbit::Set(_) | Flowbit::Unset(_) | Flowbit::Toggle(_)
+                )
+            });
+            (rule.action != Action::Pass, !sets_bits)
+        });
+
+        let mut builder = PatternSetBuilder::new();
+        let mut pattern_rules: Vec<Vec<usize>> = Vec::new();
+        let mut unfiltered = Vec::new();
+        for (index, rule) in rules.iter().enumerate() {
+            match prefilter_content(rule) {
+                Some(content) => {
+                    let id = builder.add(&content.pattern, content.nocase);
+                    if id == pattern_rules.len() {
+                        pattern_rules.push(Vec::new());
+                    }
+                    pattern_rules[id].push(index);
+                }
+                None => unfiltered.push(index),
+            }
+        }
+        let prefilter = builder.build()?;
+
+        Ok(Self {
+            rules,
+            stream_window: stream_window.max(prefilter.max_pattern_len().saturating_sub(1)),
+            prefilter,
+            pattern_rules,
+            unfiltered,
+            connections: LruMap::new(MAX_CONNECTIONS),
+            thresholds: LruMap::new(MAX_THRESHOLDS),
+            geoip,
+        })
+    }
+
+    /// Loads every rule file in the config. Rules that fail to parse are
+    /// logged and skipped, as Suricata does; unreadable files are errors.
+    /// So are rules matching on country or ASN when no GeoIP database is
+    /// configured. The network groups serve as address variables unless the
+    /// config redefines them.
+    pub fn load(
+        config: &RulesConfig,
+        networks: &Networks,
+        geoip: Option<Arc<GeoIp>>,
+    ) -> Result<Self> {
+        let mut vars = networks.rule_vars();
+        vars.extend(config.vars.clone());
+        let mut rules = Vec::new();
+        let mut failed = 0;
+        for path in &config.files {
+            let text = fs::read_to_string(path).map_err(|e| {
+                NetGuardError::RuleError(format!("Cannot read rule file {}: {}", path, e))
+            })?;
+            for (line_number, line) in logical_lines(&text) {
+                match parse_rule(&line, &vars) {
+                    Ok(rule) if !rule.geo.is_empty() && geoip.is_none() => {
+                        failed += 1;
+                        log::warn!(
+                            "{}:{}: skipping rule: geoip/asn need a GeoIP database",
+                            path,
+                            line_number
+                        );
+                    }
+                    Ok(rule) => rules.push(rule),
+                    Err(e) => {
+                        failed += 1;
+                        log::warn!("{}:{}: skipping rule: {}", path, line_number, e);
+                    }
+                }
+            }
+        }
+        let detector = Self::new(rules, config.stream_window_bytes, geoip)?;
+        log::info!(
+            "Loaded {} signature rules ({} skipped), {} prefilter patterns",
+            detector.rules.len(),
+            failed,
+            detector.prefilter.len()
+        );
+        Ok(detector)
+    }
+
+    /// Updates connection state and returns its key, whether the packet
+    /// goes to the server, and whether the connection is established.
+    fn track(&mut self, packet: &ParsedPacket) -> (ConnectionKey, bool, bool) {
+        let source = (packet.source_ip.clone(), packet.source_port.unwrap_or(0));
+        let destination = (
+            packet.destination_ip.clone(),
+            packet.destination_port.unwrap_or(0),
+        );
+        let key = if source <= destination {
+            (
+                source.0.clone(),
+                source.1,
+                destination.0.clone(),
+                destination.1,
+                packet.protocol.clone(),
+            )
+        } else {
+            (
+                destination.0.clone(),
+                destination.1,
+                source.0.clone(),
+                source.1,
+                packet.protocol.clone(),
+            )
+        };
+
+        let stream_window = if packet.tcp_flags.is_some() {
+            self.stream_window
+        } else {
+            0
+        };
+        let flags = packet.tcp_flags.unwrap_or(0);
+        let syn = flags & TcpFlags::SYN != 0;
+        let ack = flags & TcpFlags::ACK != 0;
+        let connection = self.connections.get_or_insert_with(key.clone(), || {
+            let client = if packet.tcp_flags.is_none() || (syn && !ack) {
+                source.clone()
+            } else if syn && ack {
+                destination.clone()
+            } else if source.1 >= destination.1 {
+                // Picked up mid-stream: the client usually has the higher
+                // (ephemeral) port.
+                source.clone()
+            } else {
+                destination.clone()
+            };
+            Connection {
+                established: packet.tcp_flags.is_some() && !syn,
+                client,
+                syn_ack: false,
+                flowbits: HashSet::new(),
+                streams: [
+                    StreamState::new(stream_window),
+                    StreamState::new(stream_window),
+                ],
+            }
+        });
+
+        let to_server = connection.client == source;
+        if packet.tcp_flags.is_some() {
+            if syn && ack && !to_server {
+                connection.syn_ack = true;
+            } else if connection.syn_ack && ack && !syn {
+                connection.established = true;
+            }
+        } else if !to_server {
+            connection.established = true;
+        }
+        (key, to_server, connection.established)
+    }
+
+    /// Runs the prefilter over the packet (and, for TCP, the end of the
+    /// previous segment in the same direction), records the payload in the
+    /// stream, and returns the candidate rules in evaluation order along
+    /// with the stream bytes preceding this payload.
+    fn candidates(
+        &mut self,
+        key: &ConnectionKey,
+        to_server: bool,
+        payload: &[u8],
+    ) -> (Vec<usize>, Vec<u8>) {
+        let mut selected = self.unfiltered.clone();
+        let mut history = Vec::new();
+        if payload.is_empty() {
+            return (selected, history);
+        }
+        let Some(connection) = self.connections.get_mut(key) else {
+            return (selected, history);
+        };
+        let stream = &mut connection.streams[to_server as usize];
+        let pattern_rules = &self.pattern_rules;
+        self.prefilter.scan_stream(stream, payload, |id| {
+            selected.extend_from_slice(&pattern_rules[id]);
+        });
+        history.extend_from_slice(stream.history());
+        stream.push(payload);
+
+        selected.sort_unstable();
+        selected.dedup();
+        (selected, history)
+    }
+}
+
+impl Detector for RuleDetector {
+    fn name(&self) -> &'static str {
+        "rules"
+    }
+
+    fn on_packet(&mut self, packet: &ParsedPacket) -> Vec<Alert> {
+        let (key, to_server, established) = self.track(packet);
+        let (candidates, history) = self.candidates(&key, to_server, &packet.payload);
+        let source = packet.source_ip.parse::<IpAddr>().ok();
+        let destination = packet.destination_ip.parse::<IpAddr>().ok();
+        let now = Utc::now();
+        // Looked up once per packet, and only if a candidate rule needs it.
+        let mut geo = None;
+
+        let mut matched = Vec::new();
+        let mut passed = false;
+        for index in candidates {
+            let rule = &self.rules[index];
+            if !protocol_matches(rule.protocol, packet)
+                || !header_matches(rule, packet, source, destination)
+                || rule
+                    .dsize
+                    .is_some_and(|dsize| !dsize.matches(packet.payload.len()))
+                || rule.flow.is_some_and(|flow| {
+                    flow.to_server.is_some_and(|want| want != to_server)
+                        || flow.established.is_some_and(|want| want != established)
+                })
+                || !(payload_matches(&rule.matches, &packet.payload)
+                    || stream_matches(rule, &history, &packet.payload))
+            {
+                continue;
+            }
+            if !rule.geo.is_empty() {
+                let (source_geo, destination_geo) = geo.get_or_insert_with(|| {
+                    let lookup = |ip: Option<IpAddr>| {
+                        self.geoip.as_ref().zip(ip).and_then(|(geoip, ip)| geoip.lookup(ip))
+                    };
+                    (lookup(source), lookup(destination))
+                });
+                if !rule
+                    .geo
+                    .iter()
+                    .all(|condition| condition.matches(source_geo.as_ref(), destination_geo.as_ref()))
+                {
+                    continue;
+                }
+            }
+
+            let Some(connection) = self.connections.get_mut(&key) else {
+                continue;
+            };
+            let bits_hold = rule.flowbits.iter().all(|bit| match bit {
+                Flowbit::IsSet(name) => connection.flowbits.contains(name),
+                Flowbit::IsNotSet(name) => !connection.flowbits.contains(name),
+                _ => true,
+            });
+            if !bits_hold {
+                continue;
+            }
+            let mut alert = true;
+            for bit in &rule.flowbits {
+                match bit {
+                    Flowbit::Set(name) => {
+                        connection.flowbits.insert(name.clone());
+                    }
+                    Flowbit::Unset(name) => {
+                        connection.flowbits.remove(name);
+                    }
+                    Flowbit::Toggle(name) => {
+                        if !connection.flowbits.remove(name) {
+                            connection.flowbits.insert(name.clone());
+                        }
+                    }
+                    Flowbit::NoAlert => alert = false,
+                    Flowbit::IsSet(_) | Flowbit::IsNotSet(_) => {}
+                }
+            }
+
+            if rule.action == Action::Pass {
+                passed = true;
+                continue;
+            }
+            if alert {
+                matched.push(index);
+            }
+        }
+        if passed {
+            return Vec::new();
+        }
+
+        let mut alerts = Vec::new();
+        for index in matched {
+            let rule = &self.rules[index];
+            if threshold_allows(&mut self.thresholds, rule, packet, now) {
+                alerts.push(rule_alert(rule, packet, now));
+            }
+        }
+        alerts
+    }
+
+    fn snapshot(&self) -> serde_json::Value {
+        serde_json::json!({
+            "rules": self.rules.len(),
+            "prefilter_patterns": self.prefilter.len(),
+            "unfiltered_rules": self.unfiltered.len(),
+            "tracked_connections": self.connections.len(),
+            "tracked_thresholds": self.thresholds.len(),
+        })
+    }
+
+    fn memory_usage(&self) -> usize {
+        let connections = self.connections.len()
+            * (LruMap::<ConnectionKey, Connection>::entry_overhead() + 3 * IP_STRING_BYTES);
+        let thresholds = self.thresholds.len()
+            * (LruMap::<(u32, String), ThresholdState>::entry_overhead() + IP_STRING_BYTES);
+        let per_connection: usize = self
+            .connections
+            .values()
+            .map(|connection| {
+                connection.flowbits.capacity() * size_of::<String>()
+                    + connection
+                        .streams
+                        .iter()
+                        .map(StreamState::memory_usage)
+                        .sum::<usize>()
+            })
+            .sum();
+        connections + thresholds + per_connection + self.prefilter.memory_usage()
+    }
+}
+
+/// Joins lines continued with a trailing backslash and drops comments and
+/// blank lines. Yields the first physical line number of each rule.
+fn logical_lines(text: &str) -> Vec<(usize, String)> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    let mut start = 0;
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if current.is_empty() {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            start = index + 1;
+        }
+        match line.strip_suffix('\\') {
+            Some(continued) => current.push_str(continued),
+            None => {
+                current.push_str(line);
+                lines.push((start, std::mem::take(&mut current)));
+            }
+        }
+    }
+    if !current.is_empty() {
+        lines.push((start, current));
+    }
+    lines
+}
+
+/// Applies the rule's `threshold` or `detection_filter`, counting this
+/// match.
+fn threshold_allows(
+    thresholds: &mut LruMap<(u32, String), ThresholdState>,
+    rule: &Rule,
+    packet: &ParsedPacket,
+    now: DateTime<Utc>,
+) -> bool {
+    let Some(threshold) = rule.threshold else {
+        return true;
+    };
+    let tracked = match threshold.track {
+        Track::BySource => packet.source_ip.clone(),
+        Track::ByDestination => packet.destination_ip.clone(),
+    };
+    let state = thresholds.get_or_insert_with((rule.sid, tracked), || ThresholdState {
+        start: now,
+        count: 0,
+    });
+    if now - state.start >= Duration::seconds(threshold.seconds as i64) {
+        state.start = now;
+        state.count = 0;
+    }
+    state.count += 1;
+
+    match threshold.kind {
+        ThresholdKind::Limit => state.count <= threshold.count,
+        ThresholdKind::Threshold => {
+            if state.count >= threshold.count {
+                state.count = 0;
+                true
+            } else {
+                false
+            }
+        }
+        ThresholdKind::Both => state.count == threshold.count,
+        ThresholdKind::Filter => state.count > threshold.count,
+    }
+}
+
+fn protocol_matches(protocol: Protocol, packet: &ParsedPacket) -> bool {
+    match protocol {
+        Protocol::Ip => packet.protocol != "ARP",
+        Protocol::Tcp => packet.protocol == "TCP",
+        Protocol::Udp => packet.protocol == "UDP",
+        Protocol::Icmp => packet.protocol == "ICMP" || packet.protocol == "ICMPv6",
+        Protocol::Tls => matches!(packet.app, Some(AppLayer::Tls(_) | AppLayer::Quic(_))),
+        Protocol::Dns => matches!(packet.app, Some(AppLayer::Dns(_))),
+        Protocol::Smb => matches!(packet.app, Some(AppLayer::Smb(_))),
+        Protocol::DceRpc => matches!(packet.app, Some(AppLayer::DceRpc(_))),
+        Protocol::Ics => matches!(packet.app, Some(AppLayer::Ics(_))),
+    }
+}
+
+fn header_matches(
+    rule: &Rule,
+    packet: &ParsedPacket,
+    source: Option<IpAddr>,
+    destination: Option<IpAddr>,
+) -> bool {
+    let forward = rule.source.matches(source)
+        && rule.source_ports.matches(packet.source_port)
+        && rule.destination.matches(destination)
+        && rule.destination_ports.matches(packet.destination_port);
+    forward
+        || (rule.bidirectional
+            && rule.source.matches(destination)
+            && rule.source_ports.matches(packet.destination_port)
+            && rule.destination.matches(source)
+            && rule.destination_ports.matches(packet.source_port))
+}
+
+/// The content a rule contributes to the prefilter: its `fast_pattern`, or
+/// else its longest positive content. Rules without one are unfiltered.
+fn prefilter_content(rule: &Rule) -> Option<&Content> {
+    let contents = rule.matches.iter().filter_map(|m| match m {
+        Match::Content(content) if !content.negated => Some(content),
+        _ => None,
+    });
+    let mut longest: Option<&Content> = None;
+    for content in contents {
+        if content.fast_pattern {
+            return Some(content);
+        }
+        if longest.is_none_or(|best| content.pattern.len() > best.pattern.len()) {
+            longest = Some(content);
+        }
+    }
+    longest
+}
+
+/// Matches the rule against the end of the stream followed by this payload,
+/// for rules split across segments. Rules anchored with `offset` or `depth`
+/// refer to the start of a packet and are not retried. As with
+/// `PatternSet::scan_stream`, only matches reaching into this payload
+/// count, so a match lying wholly in earlier segments does not fire again.
+fn stream_matches(rule: &Rule, history: &[u8], payload: &[u8]) -> bool {
+    let anchored = rule.matches.iter().any(|m| {
+        matches!(m, Match::Content(content) if content.offset.is_some() || content.depth.is_some())
+    });
+    if history.is_empty() || anchored {
+        return false;
+    }
+    let mut data = Vec::with_capacity(history.len() + payload.len());
+    data.extend_from_slice(history);
+    data.extend_from_slice(payload);
+    let mut search = Search {
+        data: &data,
+        seen: history.len(),
+        budget: MATCH_BUDGET,
+    };
+    match_from(&rule.matches, &mut search, 0, 0)
+}
+
+fn payload_matches(matches: &[Match], payload: &[u8]) -> bool {
+    if matches.is_empty() {
+        return true;
+    }
+    if payload.is_empty() {
+        return false;
+    }
+    let mut search = Search {
+        data: payload,
+        seen: 0,
+        budget: MATCH_BUDGET,
+    };
+    match_from(matches, &mut search, 0, 0)
+}
+
+/// Data a rule's content and pcre options are matched against.
+struct Search<'a> {
+    data: &'a [u8],
+    /// Leading bytes from earlier segments; when non-zero, some positive
+    /// match must end past them.
+    seen: usize,
+    /// Candidate positions left to try.
+    budget: usize,
+}
+
+impl Search<'_> {
+    /// Whether matches that reached `reach` count.
+    fn complete(&self, reach: usize) -> bool {
+        self.seen == 0 || reach > self.seen
+    }
+
+    /// Whether a failed final match should be retried further on, in case
+    /// a later occurrence reaches new data.
+    fn retries_last(&self, rest: &[Match]) -> bool {
+        rest.is_empty() && self.seen > 0
+    }
+}
+
+/// Matches `matches` in order, `previous_end` being where the last positive
+/// match ended and `reach` the furthest any ended. Backtracks over earlier
+/// occurrences only when the next match is relative to this one, or when
+/// the last match of a stream search ended inside the earlier segments.
+fn match_from(matches: &[Match], search: &mut Search, previous_end: usize, reach: usize) -> bool {
+    let Some((first, rest)) = matches.split_first() else {
+        return search.complete(reach);
+    };
+    match first {
+        Match::Content(content) => {
+            let (start, end) = content_window(content, previous_end, search.data.len());
+            if content.negated {
+                return find(search.data, content, start, end).is_none()
+                    && match_from(rest, search, previous_end, reach);
+            }
+            let backtrack = matches!(rest.first(), Some(Match::Content(next)) if next.is_relative())
+                || matches!(rest.first(), Some(Match::Pcre(next)) if next.relative)
+                || search.retries_last(rest);
+            let mut from = start;
+            while let Some(position) = find(search.data, content, from, end) {
+                if search.budget == 0 {
+                    return false;
+                }
+                search.budget -= 1;
+                let match_end = position + content.pattern.len();
+                if match_from(rest, search, match_end, reach.max(match_end)) {
+                    return true;
+                }
+                if !backtrack {
+                    return false;
+                }
+                from = position + 1;
+            }
+            false
+        }
+        Match::Pcre(pcre) => match_pcre(pcre, rest, search, previous_end, reach),
+    }
+}
+
+fn match_pcre(
+    pcre: &Pcre,
+    rest: &[Match],
+    search: &mut Search,
+    previous_end: usize,
+    reach: usize,
+) -> bool {
+    let start = if pcre.relative { previous_end } else { 0 };
+    if start > search.data.len() {
+        return pcre.negated && match_from(rest, search, previous_end, reach);
+    }
+    if pcre.negated {
+        return pcre.regex.find_at(search.data, start).is_none()
+            && match_from(rest, search, previous_end, reach);
+    }
+    let mut from = start;
+    while from <= search.data.len() {
+        let Some(found) = pcre.regex.find_at(search.data, from) else {
+            return false;
+        };
+        if search.budget == 0 {
+            return false;
+        }
+        search.budget -= 1;
+        if match_from(rest, search, found.end(), reach.max(found.end())) {
+            return true;
+        }
+        if !search.retries_last(rest) {
+            return false;
+        }
+        from = found.start() + 1;
+    }
+    false
+}
+
+/// The byte range a content may occupy: `offset`/`depth` from the start of
+/// the payload, or `distance`/`within` from the end of the previous match.
+fn content_window(content: &Content, previous_end: usize, len: usize) -> (usize, usize) {
+    if content.is_relative() {
+        let distance = content.distance.unwrap_or(0);
+        let start = (previous_end as i64).saturating_add(distance).max(0) as usize;
+        let end = match content.within {
+            Some(within) => (previous_end as i64)
+                .saturating_add(distance)
+                .saturating_add(i64::try_from(within).unwrap_or(i64::MAX))
+                .max(0) as usize,
+            None => len,
+        };
+        (start, end.min(len))
+    } else {
+        let start = content.offset.unwrap_or(0);
+        let end = content
+            .depth
+            .map_or(len, |depth| start.saturating_add(depth));
+        (start, end.min(len))
+    }
+}
+
+/// First occurrence of the content's pattern lying entirely inside
+/// `payload[from..end]`.
+fn find(payload: &[u8], content: &Content, from: usize, end: usize) -> Option<usize> {
+    let pattern = &content.pattern;
+    if from >= end || end - from < pattern.len() {
+        return None;
+    }
+    payload[from..end]
+        .windows(pattern.len())
+        .position(|window| {
+            if content.nocase {
+                window.eq_ignore_ascii_case(pattern)
+            } else {
+                window == pattern.as_slice()
+            }
+        })
+        .map(|position| from + position)
+}
+
+fn rule_alert(rule: &Rule, packet: &ParsedPacket, now: DateTime<Utc>) -> Alert {
+    let endpoint = |ip: &str, port: Option<u16>| match port {
+        Some(port) => format!("{}:{}", ip, port),
+        None => ip.to_string(),
+    };
+    let classification = rule
+        .classtype
+        .as_deref()
+        .map(|classtype| format!(" [Classification: {}]", classtype))
+        .unwrap_or_default();
+
+    Alert::new(
+        if rule.msg.is_empty() {
+            format!("Signature {}", rule.sid)
+        } else {
+            rule.msg.clone()
+        },
+        match rule.priority {
+            1 => "high",
+            2 => "medium",
+            _ => "low",
+        }
+        .to_string(),
+        packet.source_ip.clone(),
+        Some(packet.destination_ip.clone()),
+        format!(
+            "[{}:{}:{}] {}{} [Priority: {}] {{{}}} {} -> {}",
+            rule.gid,
+            rule.sid,
+            rule.rev,
+            rule.msg,
+            classification,
+            rule.priority,
+            packet.protocol,
+            endpoint(&packet.source_ip, packet.source_port),
+            endpoint(&packet.destination_ip, packet.destination_port)
+        ),
+    )
+    .at(now)
+    .with_attack(rule.attack.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn detector(rules: &[&str]) -> RuleDetector {
+        let rules = rules
+            .iter()
+            .map(|text| parse_rule(text, &HashMap::new()).unwrap())
+            .collect();
+        RuleDetector::new(rules, 64, None).unwrap()
+    }
+
+    /// Client-to-server TCP segments of one connection, returning the
+    /// number of alerts each raised.
+    fn alerts(detector: &mut RuleDetector, segments: &[&[u8]]) -> Vec<usize> {
+        segments
+            .iter()
+            .map(|payload| {
+                detector
+                    .on_packet(&ParsedPacket {
+                        source_ip: "10.0.0.1".to_string(),
+                        destination_ip: "10.0.0.2".to_string(),
+                        source_port: Some(50000),
+                        destination_port: Some(80),
+                        protocol: "TCP".to_string(),
+                        size: payload.len(),
+                        tcp_flags: Some(0x18),
+                        app: None,
+                        payload: payload.to_vec(),
+                    })
+                    .len()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn stream_match_does_not_refire_on_earlier_segments() {
+        let mut rules = detector(&["alert tcp any any -> any any (pcre:\"/foo/\"; sid:1;)"]);
+        assert_eq!(alerts(&mut rules, &[b"foo", b"bar", b"baz"]), [1, 0, 0]);
+
+        let mut rules = detector(&[
+            "alert tcp any any -> any any (content:\"AAAA\"; fast_pattern; content:\"B\"; sid:2;)",
+        ]);
+        assert_eq!(alerts(&mut rules, &[b"AAAAB", b"AAAA"]), [1, 0]);
+    }
+
+    #[test]
+    fn stream_match_spanning_segments_fires_once() {
+        let mut rules = detector(&[
+            "alert tcp any any -> any any (content:\"GET \"; content:\"/admin\"; distance:0; sid:1;)",
+            "alert tcp any any -> any any (pcre:\"/foo/\"; sid:2;)",
+        ]);
+        assert_eq!(
+            alerts(&mut rules, &[b"GET /ad", b"min HTTP/1.1", b"x"]),
+            [0, 1, 0]
+        );
+        // An earlier complete match must not hide a later one that spans
+        // the boundary.
+        assert_eq!(alerts(&mut rules, &[b"foo bar fo", b"o", b"o"]), [1, 1, 0]);
+    }
+
+    fn content(pattern: &[u8]) -> Content {
+        Content {
+            pattern: pattern.to_vec(),
+            negated: false,
+            nocase: false,
+            offset: None,
+            depth: None,
+            distance: None,
+            within: None,
+            fast_pattern: false,
+        }
+    }
+
+    #[test]
+    fn content_window_saturates_extreme_modifiers() {
+        let payload = [b'a'; 100];
+        let mut relative = content(b"a");
+        relative.distance = Some(i64::MAX);
+        relative.within = Some(usize::MAX);
+        let (start, end) = content_window(&relative, 10, payload.len());
+        assert_eq!(end, 100);
+        assert!(find(&payload, &relative, start, end).is_none());
+        relative.distance = Some(i64::MIN);
+        relative.within = Some(5);
+        assert_eq!(content_window(&relative, 10, payload.len()), (0, 0));
+
+        let mut anchored = content(b"a");
+        anchored.offset = Some(usize::MAX);
+        anchored.depth = Some(usize::MAX);
+        let (start, end) = content_window(&anchored, 0, 100);
+        assert_eq!(end, 100);
+        assert!(find(&payload, &anchored, start, end).is_none());
+    }
+
+    #[test]
+    fn detection_filter_alerts_only_after_count_matches() {
+        let mut rules = detector(&[
+            "alert tcp any any -> any any (content:\"x\"; \
+             detection_filter:track by_src, count 3, seconds 60; sid:1;)",
+        ]);
+        assert_eq!(
+            alerts(&mut rules, &[b"x", b"x", b"x", b"x", b"x"]),
+            [0, 0, 0, 1, 1]
+        );
+
+        let mut rules = detector(&[
+            "alert tcp any any -> any any (content:\"x\"; \
+             detection_filter:track by_src, count 0, seconds 60; sid:1;)",
+        ]);
+        assert_eq!(alerts(&mut rules, &[b"x", b"x"]), [1, 1]);
+    }
+}
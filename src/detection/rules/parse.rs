@@ -0,0 +1,1147 @@
+//! Parser for the supported subset of Suricata/Snort rule syntax.
+//!
+//! ```text
+//! alert tcp $HOME_NET any -> $EXTERNAL_NET [80,8080] (msg:"..."; flow:established,to_server;
+//!     content:"GET "; depth:4; content:"|0d 0a|"; distance:0; pcre:"/x=\d+/i";
+//!     flowbits:set,seen; threshold:type limit,track by_src,count 1,seconds 60;
+//!     classtype:trojan-activity; sid:1000001; rev:2;)
+//! ```
+//!
+//! Keywords outside the subset (HTTP sticky buffers, byte_test, ...) make
+//! the rule fail to load rather than silently match more than intended.
+
+use pnet::ipnetwork::IpNetwork;
+use regex::bytes::{Regex, RegexBuilder};
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use crate::attack::{self, Technique};
+use crate::geoip::GeoInfo;
+
+/// Variable references followed before giving up on a cycle.
+const MAX_VAR_DEPTH: usize = 16;
+/// Largest `offset`, `depth`, `distance` or `within` magnitude, as in Snort;
+/// no packet payload is longer.
+const MAX_CONTENT_MODIFIER: i64 = 65_535;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// `alert`, and the inline actions `drop`, `reject` and `log`, which a
+    /// passive monitor can only report.
+    Alert,
+    /// Suppresses alerts from other rules for a matching packet.
+    Pass,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Ip,
+    Tcp,
+    Udp,
+    Icmp,
+    /// Matches packets the TLS or QUIC dissector decoded.
+    Tls,
+    Dns,
+    Smb,
+    DceRpc,
+    /// Modbus and DNP3.
+    Ics,
+}
+
+#[derive(Debug, Clone)]
+pub enum Address {
+    Any,
+    Network(IpNetwork),
+    List(Vec<Address>),
+    Not(Box<Address>),
+}
+
+impl Address {
+    pub fn matches(&self, ip: Option<IpAddr>) -> bool {
+        match self {
+            Address::Any => true,
+            Address::Network(network) => ip.is_some_and(|ip| network.contains(ip)),
+            Address::Not(inner) => !inner.matches(ip),
+            Address::List(items) => list_matches(
+                items,
+                |item| item.matches(ip),
+                |item| matches!(item, Address::Not(_)),
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Ports {
+    Any,
+    Range(u16, u16),
+    List(Vec<Ports>),
+    Not(Box<Ports>),
+}
+
+impl Ports {
+    pub fn matches(&self, port: Option<u16>) -> bool {
+        match self {
+            Ports::Any => true,
+            Ports::Range(low, high) => port.is_some_and(|port| (*low..=*high).contains(&port)),
+            Ports::Not(inner) => !inner.matches(port),
+            Ports::List(items) => list_matches(
+                items,
+                |item| item.matches(port),
+                |item| matches!(item, Ports::Not(_)),
+            ),
+        }
+    }
+}
+
+/// `[a,b,!c]` matches a or b, but never c.
+fn list_matches<T>(
+    items: &[T],
+    matches: impl Fn(&T) -> bool,
+    negated: impl Fn(&T) -> bool,
+) -> bool {
+    let mut positives = items.iter().filter(|item| !negated(item)).peekable();
+    let included = positives.peek().is_none() || positives.any(&matches);
+    included && items.iter().filter(|item| negated(item)).all(&matches)
+}
+
+#[derive(Debug, Clone)]
+pub struct Content {
+    pub pattern: Vec<u8>,
+    pub negated: bool,
+    pub nocase: bool,
+    pub offset: Option<usize>,
+    pub depth: Option<usize>,
+    pub distance: Option<i64>,
+    pub within: Option<usize>,
+    pub fast_pattern: bool,
+}
+
+impl Content {
+    /// Whether the match is anchored to the end of the previous one.
+    pub fn is_relative(&self) -> bool {
+        self.distance.is_some() || self.within.is_some()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Pcre {
+    pub regex: Regex,
+    pub negated: bool,
+    /// `R` flag: search from the end of the previous match.
+    pub relative: bool,
+}
+
+#[derive(Debug, Clone)]
+pub enum Match {
+    Content(Content),
+    Pcre(Pcre),
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FlowOption {
+    /// `to_server`/`from_client` (true) or `to_client`/`from_server` (false).
+    pub to_server: Option<bool>,
+    /// `established` (true) or `not_established` (false).
+    pub established: Option<bool>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Flowbit {
+    Set(String),
+    Unset(String),
+    Toggle(String),
+    IsSet(String),
+    IsNotSet(String),
+    NoAlert,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThresholdKind {
+    /// Alert on the first `count` matches in each period.
+    Limit,
+    /// Alert on every `count`th match.
+    Threshold,
+    /// Alert once per period, after `count` matches.
+    Both,
+    /// `detection_filter`: alert on every match after the first `count`
+    /// in the period.
+    Filter,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Track {
+    BySource,
+    ByDestination,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Threshold {
+    pub kind: ThresholdKind,
+    pub track: Track,
+    pub count: u64,
+    pub seconds: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Dsize {
+    Equal(usize),
+    Less(usize),
+    Greater(usize),
+    Range(usize, usize),
+}
+
+impl Dsize {
+    pub fn matches(&self, size: usize) -> bool {
+        match *self {
+            Dsize::Equal(n) => size == n,
+            Dsize::Less(n) => size < n,
+            Dsize::Greater(n) => size > n,
+            Dsize::Range(low, high) => size > low && size < high,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeoSide {
+    Source,
+    Destination,
+    /// Both addresses must match.
+    Both,
+    /// Either address may match.
+    Any,
+}
+
+#[derive(Debug, Clone)]
+pub enum GeoValues {
+    /// Upper-case ISO country codes.
+    Countries(Vec<String>),
+    Asns(Vec<u32>),
+}
+
+/// `geoip:src,RU,CN` (Suricata) or `asn:dst,!13335` (a NetGuard extension),
+/// matched against the GeoIP databases.
+#[derive(Debug, Clone)]
+pub struct GeoMatch {
+    pub side: GeoSide,
+    /// `!` before the list: the address must be in none of them.
+    pub negated: bool,
+    pub values: GeoValues,
+}
+
+impl GeoMatch {
+    pub fn matches(&self, source: Option<&GeoInfo>, destination: Option<&GeoInfo>) -> bool {
+        let test = |geo: Option<&GeoInfo>| {
+            let listed = geo.is_some_and(|geo| match &self.values {
+                GeoValues::Countries(countries) => geo
+                    .country
+                    .as_ref()
+                    .is_some_and(|country| countries.contains(country)),
+                GeoValues::Asns(asns) => geo.asn.is_some_and(|asn| asns.contains(&asn)),
+            });
+            listed != self.negated
+        };
+        match self.side {
+            GeoSide::Source => test(source),
+            GeoSide::Destination => test(destination),
+            GeoSide::Both => test(source) && test(destination),
+            GeoSide::Any => test(source) || test(destination),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub action: Action,
+    pub protocol: Protocol,
+    pub source: Address,
+    pub source_ports: Ports,
+    /// `<>`: the header matches in either direction.
+    pub bidirectional: bool,
+    pub destination: Address,
+    pub destination_ports: Ports,
+    pub msg: String,
+    pub gid: u32,
+    pub sid: u32,
+    pub rev: u32,
+    pub classtype: Option<String>,
+    /// 1 (most severe) to 4; from `priority`, else the classtype's default.
+    pub priority: u8,
+    /// From `mitre_tactic_id`/`mitre_technique_id` metadata, else the
+    /// classtype's usual technique.
+    pub attack: Vec<Technique>,
+    pub matches: Vec<Match>,
+    pub flow: Option<FlowOption>,
+    pub flowbits: Vec<Flowbit>,
+    pub threshold: Option<Threshold>,
+    pub dsize: Option<Dsize>,
+    pub geo: Vec<GeoMatch>,
+}
+
+/// Parses one rule. `vars` holds address and port variables by name,
+/// without the `$`.
+pub fn parse_rule(text: &str, vars: &HashMap<String, String>) -> Result<Rule, String> {
+    let text = text.trim();
+    let open = text.find('(').ok_or("missing rule options")?;
+    if !text.ends_with(')') {
+        return Err("rule options are not closed".to_string());
+    }
+    let header: Vec<&str> = text[..open].split_whitespace().collect();
+    let [action, protocol, source, source_ports, direction, destination, destination_ports] =
+        header[..]
+    else {
+        return Err(format!("expected 7 header fields, found {}", header.len()));
+    };
+
+    let mut rule = Rule {
+        action: match action {
+            "alert" | "drop" | "reject" | "rejectsrc" | "rejectdst" | "rejectboth" | "log" => {
+                Action::Alert
+            }
+            "pass" => Action::Pass,
+            other => return Err(format!("unknown action '{}'", other)),
+        },
+        protocol: parse_protocol(protocol)?,
+        source: parse_address(source, vars, 0)?,
+        source_ports: parse_ports(source_ports, vars, 0)?,
+        bidirectional: match direction {
+            "->" => false,
+            "<>" => true,
+            other => return Err(format!("unknown direction '{}'", other)),
+        },
+        destination: parse_address(destination, vars, 0)?,
+        destination_ports: parse_ports(destination_ports, vars, 0)?,
+        msg: String::new(),
+        gid: 1,
+        sid: 0,
+        rev: 0,
+        classtype: None,
+        priority: 0,
+        attack: Vec::new(),
+        matches: Vec::new(),
+        flow: None,
+        flowbits: Vec::new(),
+        threshold: None,
+        dsize: None,
+        geo: Vec::new(),
+    };
+
+    for (keyword, value) in split_options(&text[open + 1..text.len() - 1])? {
+        apply_option(&mut rule, &keyword, value.as_deref())
+            .map_err(|e| format!("{}: {}", keyword, e))?;
+    }
+
+    if rule.sid == 0 {
+        return Err("missing sid".to_string());
+    }
+    if rule.priority == 0 {
+        rule.priority = rule
+            .classtype
+            .as_deref()
+            .map(classtype_priority)
+            .unwrap_or(3);
+    }
+    if rule.attack.is_empty() {
+        rule.attack = rule
+            .classtype
+            .as_deref()
+            .and_then(classtype_attack)
+            .map(|(tactic, technique)| attack::techniques(&[(tactic, technique)]))
+            .unwrap_or_default();
+    }
+    Ok(rule)
+}
+
+fn parse_protocol(protocol: &str) -> Result<Protocol, String> {
+    Ok(match protocol.to_ascii_lowercase().as_str() {
+        "ip" | "pkthdr" => Protocol::Ip,
+        // No dissector for these; their rules match the raw TCP stream.
+        "tcp" | "http" | "http2" | "ftp" | "smtp" | "ssh" | "imap" | "pop3" | "telnet" => {
+            Protocol::Tcp
+        }
+        "udp" => Protocol::Udp,
+        "icmp" => Protocol::Icmp,
+        "tls" | "ssl" | "quic" => Protocol::Tls,
+        "dns" => Protocol::Dns,
+        "smb" => Protocol::Smb,
+        "dcerpc" => Protocol::DceRpc,
+        "modbus" | "dnp3" => Protocol::Ics,
+        other => return Err(format!("unsupported protocol '{}'", other)),
+    })
+}
+
+fn resolve_var<'a>(
+    name: &str,
+    vars: &'a HashMap<String, String>,
+    depth: usize,
+) -> Result<&'a str, String> {
+    if depth >= MAX_VAR_DEPTH {
+        return Err(format!("variable ${} is nested too deeply", name));
+    }
+    vars.get(name)
+        .map(String::as_str)
+        .ok_or_else(|| format!("undefined variable ${}", name))
+}
+
+/// Splits a `[a,b,[c,d]]` list body on top-level commas.
+fn split_list(body: &str) -> Result<Vec<&str>, String> {
+    let mut items = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in body.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth = depth.checked_sub(1).ok_or("unbalanced ']'")?,
+            ',' if depth == 0 => {
+                items.push(body[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if depth != 0 {
+        return Err("unbalanced '['".to_string());
+    }
+    items.push(body[start..].trim());
+    Ok(items.into_iter().filter(|item| !item.is_empty()).collect())
+}
+
+fn parse_address(
+    text: &str,
+    vars: &HashMap<String, String>,
+    depth: usize,
+) -> Result<Address, String> {
+    let text = text.trim();
+    if let Some(inner) = text.strip_prefix('!') {
+        return Ok(Address::Not(Box::new(parse_address(inner, vars, depth)?)));
+    }
+    if let Some(name) = text.strip_prefix('$') {
+        return parse_address(resolve_var(name, vars, depth)?, vars, depth + 1);
+    }
+    if let Some(body) = text.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+        return split_list(body)?
+            .into_iter()
+            .map(|item| parse_address(item, vars, depth))
+            .collect::<Result<_, _>>()
+            .map(Address::List);
+    }
+    if text.eq_ignore_ascii_case("any") {
+        return Ok(Address::Any);
+    }
+    text.parse::<IpNetwork>()
+        .map(Address::Network)
+        .map_err(|_| format!("invalid address '{}'", text))
+}
+
+fn parse_ports(text: &str, vars: &HashMap<String, String>, depth: usize) -> Result<Ports, String> {
+    let text = text.trim();
+    if let Some(inner) = text.strip_prefix('!') {
+        return Ok(Ports::Not(Box::new(parse_ports(inner, vars, depth)?)));
+    }
+    if let Some(name) = text.strip_prefix('$') {
+        return parse_ports(resolve_var(name, vars, depth)?, vars, depth + 1);
+    }
+    if let Some(body) = text.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+        return split_list(body)?
+            .into_iter()
+            .map(|item| parse_ports(item, vars, depth))
+            .collect::<Result<_, _>>()
+            .map(Ports::List);
+    }
+    if text.eq_ignore_ascii_case("any") {
+        return Ok(Ports::Any);
+    }
+    let port = |s: &str, default: u16| -> Result<u16, String> {
+        if s.is_empty() {
+            Ok(default)
+        } else {
+            s.parse().map_err(|_| format!("invalid port '{}'", text))
+        }
+    };
+    match text.split_once(':') {
+        Some((low, high)) => Ok(Ports::Range(port(low, 0)?, port(high, u16::MAX)?)),
+        None => {
+            let single = port(text, 0)?;
+            Ok(Ports::Range(single, single))
+        }
+    }
+}
+
+/// Splits the option block into `(keyword, value)` pairs, honouring quotes
+/// and backslash escapes.
+fn split_options(body: &str) -> Result<Vec<(String, Option<String>)>, String> {
+    let mut options = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut chars = body.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                current.push(c);
+                current.extend(chars.next());
+            }
+            '"' => {
+                quoted = !quoted;
+                current.push(c);
+            }
+            ';' if !quoted => {
+                push_option(&mut options, &current);
+                current.clear();
+            }
+            _ => current.push(c),
+        }
+    }
+    if quoted {
+        return Err("unterminated quoted string".to_string());
+    }
+    push_option(&mut options, &current);
+    Ok(options)
+}
+
+fn push_option(options: &mut Vec<(String, Option<String>)>, text: &str) {
+    let text = text.trim();
+    if text.is_empty() {
+        return;
+    }
+    match text.split_once(':') {
+        Some((keyword, value)) => options.push((
+            keyword.trim().to_ascii_lowercase(),
+            Some(value.trim().to_string()),
+        )),
+        None => options.push((text.to_ascii_lowercase(), None)),
+    }
+}
+
+fn apply_option(rule: &mut Rule, keyword: &str, value: Option<&str>) -> Result<(), String> {
+    let required = || value.ok_or_else(|| "missing value".to_string());
+    let number = |text: &str| -> Result<i64, String> {
+        text.trim()
+            .parse()
+            .map_err(|_| format!("invalid number '{}'", text))
+    };
+    let id = |text: &str| -> Result<u32, String> {
+        u32::try_from(number(text)?).map_err(|_| format!("{} out of range: {}", keyword, text))
+    };
+    let modifier = |text: &str| -> Result<i64, String> {
+        let value = number(text)?;
+        if value.abs() > MAX_CONTENT_MODIFIER {
+            return Err(format!("{} out of range: {}", keyword, text));
+        }
+        Ok(value)
+    };
+
+    match keyword {
+        "msg" => rule.msg = unescape(unquote(required()?)),
+        "sid" => rule.sid = id(required()?)?,
+        "rev" => rule.rev = id(required()?)?,
+        "gid" => rule.gid = id(required()?)?,
+        "classtype" => rule.classtype = Some(required()?.to_string()),
+        "priority" => rule.priority = number(required()?)?.clamp(1, 4) as u8,
+        "metadata" => rule.attack.extend(parse_attack_metadata(required()?)),
+        "reference" | "target" => {}
+        "content" => {
+            let (negated, text) = negation(required()?);
+            let pattern = parse_content(unquote(text))?;
+            if pattern.is_empty() {
+                return Err("empty content".to_string());
+            }
+            rule.matches.push(Match::Content(Content {
+                pattern,
+                negated,
+                nocase: false,
+                offset: None,
+                depth: None,
+                distance: None,
+                within: None,
+                fast_pattern: false,
+            }));
+        }
+        "nocase" => last_content(rule)?.nocase = true,
+        "fast_pattern" => last_content(rule)?.fast_pattern = true,
+        "rawbytes" => {
+            last_content(rule)?;
+        }
+        "offset" => last_content(rule)?.offset = Some(modifier(required()?)?.max(0) as usize),
+        "depth" => last_content(rule)?.depth = Some(modifier(required()?)?.max(0) as usize),
+        "distance" => last_content(rule)?.distance = Some(modifier(required()?)?),
+        "within" => last_content(rule)?.within = Some(modifier(required()?)?.max(0) as usize),
+        "pcre" => {
+            let (negated, text) = negation(required()?);
+            rule.matches
+                .push(Match::Pcre(parse_pcre(unquote(text), negated)?));
+        }
+        "flow" => {
+            let mut flow = FlowOption::default();
+            for part in required()?.split(',').map(str::trim) {
+                match part {
+                    "to_server" | "from_client" => flow.to_server = Some(true),
+                    "to_client" | "from_server" => flow.to_server = Some(false),
+                    "established" => flow.established = Some(true),
+                    "not_established" => flow.established = Some(false),
+                    "stateless" | "only_stream" | "no_stream" | "no_frag" | "only_frag" => {}
+                    other => return Err(format!("unsupported flow option '{}'", other)),
+                }
+            }
+            rule.flow = Some(flow);
+        }
+        "flowbits" => {
+            let value = required()?;
+            let (command, name) = match value.split_once(',') {
+                Some((command, name)) => (command.trim(), Some(name.trim().to_string())),
+                None => (value.trim(), None),
+            };
+            let name = || {
+                name.clone()
+                    .ok_or_else(|| format!("{} needs a name", command))
+            };
+            rule.flowbits.push(match command {
+                "set" => Flowbit::Set(name()?),
+                "unset" => Flowbit::Unset(name()?),
+                "toggle" => Flowbit::Toggle(name()?),
+                "isset" => Flowbit::IsSet(name()?),
+                "isnotset" => Flowbit::IsNotSet(name()?),
+                "noalert" => Flowbit::NoAlert,
+                other => return Err(format!("unsupported command '{}'", other)),
+            });
+        }
+        "threshold" | "detection_filter" => {
+            let mut kind = if keyword == "detection_filter" {
+                Some(ThresholdKind::Filter)
+            } else {
+                None
+            };
+            let (mut track, mut count, mut seconds) = (None, None, None);
+            for part in required()?.split(',') {
+                let (key, value) = part
+                    .trim()
+                    .split_once(char::is_whitespace)
+                    .ok_or_else(|| format!("invalid setting '{}'", part.trim()))?;
+                match (key, value.trim()) {
+                    ("type", "limit") => kind = Some(ThresholdKind::Limit),
+                    ("type", "threshold") => kind = Some(ThresholdKind::Threshold),
+                    ("type", "both") => kind = Some(ThresholdKind::Both),
+                    ("track", "by_src") => track = Some(Track::BySource),
+                    ("track", "by_dst") => track = Some(Track::ByDestination),
+                    ("count", n) => count = Some(number(n)? as u64),
+                    ("seconds", n) => seconds = Some(number(n)?.max(1) as u64),
+                    (key, value) => return Err(format!("unsupported setting '{} {}'", key, value)),
+                }
+            }
+            let kind = kind.ok_or("missing type")?;
+            let count = count.ok_or("missing count")?;
+            rule.threshold = Some(Threshold {
+                kind,
+                track: track.ok_or("missing track")?,
+                // A detection_filter with count 0 alerts on every match; the
+                // other types need at least one.
+                count: if kind == ThresholdKind::Filter {
+                    count
+                } else {
+                    count.max(1)
+                },
+                seconds: seconds.ok_or("missing seconds")?,
+            });
+        }
+        "dsize" => rule.dsize = Some(parse_dsize(required()?)?),
+        "geoip" | "asn" => rule.geo.push(parse_geo(keyword, required()?)?),
+        other => return Err(format!("unsupported keyword '{}'", other)),
+    }
+    Ok(())
+}
+
+fn last_content(rule: &mut Rule) -> Result<&mut Content, String> {
+    match rule.matches.last_mut() {
+        Some(Match::Content(content)) => Ok(content),
+        _ => Err("modifier without a preceding content".to_string()),
+    }
+}
+
+fn negation(value: &str) -> (bool, &str) {
+    match value.trim().strip_prefix('!') {
+        Some(rest) => (true, rest.trim()),
+        None => (false, value.trim()),
+    }
+}
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            out.extend(chars.next());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Decodes content text: literal characters with `\` escapes, and hex bytes
+/// between pipes (`|0d 0a|`).
+fn parse_content(text: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    let mut hex = false;
+    let mut digits = String::new();
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        if hex {
+            match c {
+                '|' => {
+                    if !digits.is_empty() {
+                        return Err("odd number of hex digits".to_string());
+                    }
+                    hex = false;
+                }
+                c if c.is_whitespace() => {}
+                c if c.is_ascii_hexdigit() => {
+                    digits.push(c);
+                    if digits.len() == 2 {
+                        bytes.push(u8::from_str_radix(&digits, 16).expect("two hex digits"));
+                        digits.clear();
+                    }
+                }
+                c => return Err(format!("invalid hex character '{}'", c)),
+            }
+        } else {
+            match c {
+                '|' => hex = true,
+                '\\' => {
+                    let escaped = chars.next().ok_or("trailing backslash")?;
+                    let mut buf = [0; 4];
+                    bytes.extend_from_slice(escaped.encode_utf8(&mut buf).as_bytes());
+                }
+                c => {
+                    let mut buf = [0; 4];
+                    bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+            }
+        }
+    }
+    if hex {
+        return Err("unterminated hex block".to_string());
+    }
+    Ok(bytes)
+}
+
+/// Parses `/pattern/flags`. Perl flags `i`, `s`, `m` and `x` map to regex
+/// flags; `R` makes the match relative. Buffer flags (`U`, `H`, ...) have
+/// no buffer to select and are rejected.
+fn parse_pcre(text: &str, negated: bool) -> Result<Pcre, String> {
+    let body = text
+        .strip_prefix('/')
+        .ok_or("pattern must start with '/'")?;
+    let end = body.rfind('/').ok_or("pattern must end with '/'")?;
+    let (pattern, flags) = (&body[..end], &body[end + 1..]);
+
+    let mut builder = RegexBuilder::new(&unescape_pcre(pattern));
+    builder.unicode(false);
+    let mut relative = false;
+    for flag in flags.chars() {
+        match flag {
+            'i' => {
+                builder.case_insensitive(true);
+            }
+            's' => {
+                builder.dot_matches_new_line(true);
+            }
+            'm' => {
+                builder.multi_line(true);
+            }
+            'x' => {
+                builder.ignore_whitespace(true);
+            }
+            'R' => relative = true,
+            'O' | 'B' => {}
+            other => return Err(format!("unsupported flag '{}'", other)),
+        }
+    }
+    let regex = builder.build().map_err(|e| e.to_string())?;
+    Ok(Pcre {
+        regex,
+        negated,
+        relative,
+    })
+}
+
+/// Rule text escapes `"` and `;` inside the pattern; the regex must not see
+/// those backslashes, but must keep every other escape.
+fn unescape_pcre(pattern: &str) -> String {
+    pattern.replace("\\\"", "\"").replace("\\;", ";")
+}
+
+/// Parses `<side>,[!]value,...`, where side is `src`, `dst`, `both` or
+/// `any`.
+fn parse_geo(keyword: &str, text: &str) -> Result<GeoMatch, String> {
+    let (side, list) = text.split_once(',').ok_or("expected a direction and a list")?;
+    let side = match side.trim() {
+        "src" => GeoSide::Source,
+        "dst" => GeoSide::Destination,
+        "both" => GeoSide::Both,
+        "any" => GeoSide::Any,
+        other => return Err(format!("unknown direction '{}'", other)),
+    };
+    let (negated, list) = negation(list);
+    let items: Vec<&str> = list
+        .split(',')
+        .map(|item| item.trim())
+        .filter(|item| !item.is_empty())
+        .collect();
+    if items.is_empty() {
+        return Err("empty list".to_string());
+    }
+    let values = if keyword == "asn" {
+        GeoValues::Asns(
+            items
+                .iter()
+                .map(|item| {
+                    let number = item.strip_prefix("AS").unwrap_or(item);
+                    number
+                        .parse()
+                        .map_err(|_| format!("invalid ASN '{}'", item))
+                })
+                .collect::<Result<_, _>>()?,
+        )
+    } else {
+        for item in &items {
+            if item.len() != 2 || !item.bytes().all(|b| b.is_ascii_alphabetic()) {
+                return Err(format!("invalid country code '{}'", item));
+            }
+        }
+        GeoValues::Countries(items.iter().map(|item| item.to_ascii_uppercase()).collect())
+    };
+    Ok(GeoMatch {
+        side,
+        negated,
+        values,
+    })
+}
+
+fn parse_dsize(text: &str) -> Result<Dsize, String> {
+    let text = text.trim();
+    let number = |s: &str| {
+        s.trim()
+            .parse::<usize>()
+            .map_err(|_| format!("invalid size '{}'", text))
+    };
+    if let Some((low, high)) = text.split_once("<>") {
+        Ok(Dsize::Range(number(low)?, number(high)?))
+    } else if let Some(n) = text.strip_prefix('<') {
+        Ok(Dsize::Less(number(n)?))
+    } else if let Some(n) = text.strip_prefix('>') {
+        Ok(Dsize::Greater(number(n)?))
+    } else {
+        Ok(Dsize::Equal(number(text)?))
+    }
+}
+
+/// Techniques from metadata in the Emerging Threats style:
+/// `mitre_tactic_id TA0011, mitre_technique_id T1071, ...`.
+fn parse_attack_metadata(text: &str) -> Vec<Technique> {
+    let mut tactics = Vec::new();
+    let mut techniques = Vec::new();
+    for entry in text.split(',') {
+        let mut parts = entry.split_whitespace();
+        match (parts.next(), parts.next()) {
+            (Some("mitre_tactic_id"), Some(id)) => tactics.push(id.to_string()),
+            (Some("mitre_technique_id"), Some(id)) => techniques.push(id.to_string()),
+            _ => {}
+        }
+    }
+    attack::pair(&tactics, &techniques)
+}
+
+/// The technique rules of the standard Suricata classtypes usually
+/// detect, for rules without ATT&CK metadata.
+fn classtype_attack(classtype: &str) -> Option<(&'static str, &'static str)> {
+    Some(match classtype {
+        "attempted-recon" | "successful-recon-limited" | "successful-recon-largescale"
+        | "network-scan" => ("TA0043", "T1595"),
+        "web-application-attack" => ("TA0001", "T1190"),
+        "exploit-kit" => ("TA0001", "T1189"),
+        "shellcode-detect" => ("TA0002", "T1203"),
+        "trojan-activity" | "command-and-control" | "domain-c2" => ("TA0011", "T1071"),
+        "default-login-attempt" | "suspicious-login" => ("TA0006", "T1110"),
+        "attempted-dos" | "denial-of-service" | "successful-dos" => ("TA0040", "T1499"),
+        "coin-mining" => ("TA0040", "T1496"),
+        "external-ip-check" => ("TA0007", "T1016"),
+        _ => return None,
+    })
+}
+
+/// Default priority of the standard Suricata classtypes
+/// (classification.config).
+fn classtype_priority(classtype: &str) -> u8 {
+    match classtype {
+        "attempted-admin"
+        | "attempted-user"
+        | "inappropriate-content"
+        | "policy-violation"
+        | "shellcode-detect"
+        | "successful-admin"
+        | "successful-user"
+        | "trojan-activity"
+        | "unsuccessful-user"
+        | "web-application-attack"
+        | "targeted-activity"
+        | "exploit-kit"
+        | "domain-c2"
+        | "credential-theft"
+        | "command-and-control" => 1,
+        "attempted-dos"
+        | "attempted-recon"
+        | "bad-unknown"
+        | "default-login-attempt"
+        | "denial-of-service"
+        | "misc-attack"
+        | "non-standard-protocol"
+        | "rpc-portmap-decode"
+        | "successful-dos"
+        | "successful-recon-largescale"
+        | "successful-recon-limited"
+        | "suspicious-filename-detect"
+        | "suspicious-login"
+        | "system-call-detect"
+        | "unusual-client-port-connection"
+        | "web-application-activity"
+        | "social-engineering"
+        | "coin-mining"
+        | "external-ip-check"
+        | "pup-activity"
+        | "credential-theft-attempt" => 2,
+        // misc-activity, network-scan, not-suspicious, unknown, ...
+        _ => 3,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> HashMap<String, String> {
+        HashMap::from([
+            (
+                "HOME_NET".to_string(),
+                "[10.0.0.0/8,192.168.0.0/16]".to_string(),
+            ),
+            ("EXTERNAL_NET".to_string(), "!$HOME_NET".to_string()),
+            ("HTTP_PORTS".to_string(), "[80,8000:8080]".to_string()),
+        ])
+    }
+
+    fn parse(text: &str) -> Result<Rule, String> {
+        parse_rule(text, &vars())
+    }
+
+    fn contents(rule: &Rule) -> Vec<&Content> {
+        rule.matches
+            .iter()
+            .filter_map(|m| match m {
+                Match::Content(content) => Some(content),
+                Match::Pcre(_) => None,
+            })
+            .collect()
+    }
+
+    fn ip(text: &str) -> Option<IpAddr> {
+        Some(text.parse().unwrap())
+    }
+
+    #[test]
+    fn header_resolves_variables_and_negations() {
+        let rule = parse(
+            "alert tcp $HOME_NET any -> $EXTERNAL_NET [$HTTP_PORTS,!8008] (msg:\"x\"; sid:1;)",
+        )
+        .unwrap();
+        assert_eq!(rule.action, Action::Alert);
+        assert_eq!(rule.protocol, Protocol::Tcp);
+        assert!(!rule.bidirectional);
+        assert!(rule.source.matches(ip("10.1.2.3")));
+        assert!(!rule.source.matches(ip("8.8.8.8")));
+        assert!(rule.destination.matches(ip("8.8.8.8")));
+        assert!(!rule.destination.matches(ip("192.168.1.1")));
+        assert!(rule.destination_ports.matches(Some(80)));
+        assert!(rule.destination_ports.matches(Some(8080)));
+        assert!(!rule.destination_ports.matches(Some(8008)));
+        assert!(!rule.destination_ports.matches(Some(443)));
+        assert!(rule.source_ports.matches(None));
+    }
+
+    #[test]
+    fn header_errors() {
+        let err = |text: &str| parse(text).unwrap_err();
+        assert!(err("alert tcp any any -> any any").contains("missing rule options"));
+        assert!(err("alert tcp any any any (sid:1;)").contains("7 header fields"));
+        assert!(err("block tcp any any -> any any (sid:1;)").contains("unknown action"));
+        assert!(err("alert sctp any any -> any any (sid:1;)").contains("unsupported protocol"));
+        assert!(err("alert tcp any any <- any any (sid:1;)").contains("unknown direction"));
+        assert!(err("alert tcp $NOPE any -> any any (sid:1;)").contains("NOPE"));
+        assert!(err("alert tcp any any -> any any (msg:\"x\";)").contains("missing sid"));
+    }
+
+    #[test]
+    fn inline_actions_alert_and_pass_suppresses() {
+        let rule = parse("drop udp any any <> any 53 (sid:2;)").unwrap();
+        assert_eq!(rule.action, Action::Alert);
+        assert!(rule.bidirectional);
+        let rule = parse("pass ip any any -> any any (sid:3;)").unwrap();
+        assert_eq!(rule.action, Action::Pass);
+    }
+
+    #[test]
+    fn content_modifiers_apply_to_the_preceding_content() {
+        let rule = parse(
+            "alert tcp any any -> any any (content:\"GET \"; depth:4; offset:0; \
+             content:\"|0d 0a|Host\\;\"; nocase; distance:-2; within:64; fast_pattern; \
+             content:!\"evil\"; sid:10; rev:3; gid:2;)",
+        )
+        .unwrap();
+        assert_eq!((rule.gid, rule.sid, rule.rev), (2, 10, 3));
+        let contents = contents(&rule);
+        assert_eq!(contents.len(), 3);
+        assert_eq!(contents[0].pattern, b"GET ");
+        assert_eq!((contents[0].offset, contents[0].depth), (Some(0), Some(4)));
+        assert!(!contents[0].is_relative());
+        assert_eq!(contents[1].pattern, b"\r\nHost;");
+        assert!(contents[1].nocase && contents[1].fast_pattern);
+        assert_eq!(
+            (contents[1].distance, contents[1].within),
+            (Some(-2), Some(64))
+        );
+        assert!(contents[1].is_relative());
+        assert!(contents[2].negated);
+    }
+
+    #[test]
+    fn content_modifier_errors() {
+        let err = |options: &str| {
+            parse(&format!(
+                "alert tcp any any -> any any ({} sid:1;)",
+                options
+            ))
+            .unwrap_err()
+        };
+        assert!(err("nocase;").contains("preceding content"));
+        assert!(err("content:\"\";").contains("empty content"));
+        assert!(err("content:\"a\"; depth:x;").contains("invalid number"));
+        assert!(err("content:\"a\"; byte_test:1,>,2,0;").contains("unsupported keyword"));
+    }
+
+    #[test]
+    fn out_of_range_values_are_rejected() {
+        let err = |options: &str| {
+            parse(&format!(
+                "alert tcp any any -> any any (content:\"a\"; {})",
+                options
+            ))
+            .unwrap_err()
+        };
+        assert!(err("distance:9223372036854775807; sid:1;").contains("distance out of range"));
+        assert!(err("distance:-65536; sid:1;").contains("distance out of range"));
+        assert!(err("within:65536; sid:1;").contains("within out of range"));
+        assert!(err("offset:100000; sid:1;").contains("offset out of range"));
+        assert!(err("depth:4294967296; sid:1;").contains("depth out of range"));
+        assert!(err("sid:4294967296;").contains("sid out of range"));
+        assert!(err("sid:-1;").contains("sid out of range"));
+        assert!(err("sid:1; rev:4294967297;").contains("rev out of range"));
+        assert!(err("sid:1; gid:-2;").contains("gid out of range"));
+
+        let rule = parse(
+            "alert tcp any any -> any any (content:\"a\"; content:\"b\"; distance:-65535; \
+             within:65535; sid:4294967295;)",
+        )
+        .unwrap();
+        assert_eq!(rule.sid, u32::MAX);
+    }
+
+    #[test]
+    fn pcre_flags() {
+        let rule = parse(
+            "alert tcp any any -> any any (content:\"x\"; pcre:\"/^a.b\\;c\\\"$/smiR\"; \
+             pcre:!\"/ z /x\"; sid:1;)",
+        )
+        .unwrap();
+        let pcres: Vec<&Pcre> = rule
+            .matches
+            .iter()
+            .filter_map(|m| match m {
+                Match::Pcre(pcre) => Some(pcre),
+                Match::Content(_) => None,
+            })
+            .collect();
+        assert_eq!(pcres.len(), 2);
+        assert!(pcres[0].relative && !pcres[0].negated);
+        assert!(pcres[0].regex.is_match(b"first\nA\nB;C\""));
+        assert!(pcres[1].negated && !pcres[1].relative);
+        assert!(pcres[1].regex.is_match(b"z"));
+
+        let err = |pcre: &str| {
+            parse(&format!(
+                "alert tcp any any -> any any (pcre:\"{}\"; sid:1;)",
+                pcre
+            ))
+            .unwrap_err()
+        };
+        assert!(err("abc/").contains("start with '/'"));
+        assert!(err("/abc/U").contains("unsupported flag 'U'"));
+        assert!(err("/(abc/").starts_with("pcre:"));
+    }
+
+    #[test]
+    fn thresholds() {
+        let rule = parse(
+            "alert tcp any any -> any any (threshold: type both, track by_dst, count 5, \
+             seconds 60; sid:1;)",
+        )
+        .unwrap();
+        let threshold = rule.threshold.unwrap();
+        assert_eq!(threshold.kind, ThresholdKind::Both);
+        assert_eq!(threshold.track, Track::ByDestination);
+        assert_eq!((threshold.count, threshold.seconds), (5, 60));
+
+        let rule = parse(
+            "alert tcp any any -> any any (detection_filter:track by_src, count 0, seconds 10; \
+             sid:1;)",
+        )
+        .unwrap();
+        let threshold = rule.threshold.unwrap();
+        assert_eq!(threshold.kind, ThresholdKind::Filter);
+        assert_eq!(threshold.track, Track::BySource);
+        assert_eq!(threshold.count, 0);
+
+        let err = |options: &str| {
+            parse(&format!(
+                "alert tcp any any -> any any (threshold:{}; sid:1;)",
+                options
+            ))
+            .unwrap_err()
+        };
+        assert!(err("track by_src, count 1, seconds 1").contains("missing type"));
+        assert!(err("type limit, count 1, seconds 1").contains("missing track"));
+        assert!(err("type limit, track by_rule, count 1, seconds 1").contains("unsupported"));
+    }
+
+    #[test]
+    fn priority_and_attack_default_from_classtype() {
+        let rule = parse(
+            "alert tcp any any -> any any (classtype:trojan-activity; \
+             metadata:mitre_tactic_id TA0011, mitre_technique_id T1071; sid:1;)",
+        )
+        .unwrap();
+        assert_eq!(rule.priority, classtype_priority("trojan-activity"));
+        assert_eq!(rule.attack.len(), 1);
+        assert_eq!(rule.attack[0].to_string(), "TA0011/T1071");
+
+        let rule = parse("alert tcp any any -> any any (priority:9; sid:1;)").unwrap();
+        assert_eq!(rule.priority, 4);
+        assert!(rule.attack.is_empty());
+    }
+}
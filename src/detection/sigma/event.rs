@@ -0,0 +1,269 @@
+//! Structured events that Sigma rules are evaluated against.
+//!
+//! Field names follow the Sigma taxonomy where one exists: the `firewall`
+//! names for flows, `dns` for DNS and the W3C names of `proxy` for HTTP.
+//! A field may hold several values (one per DNS question, per ALPN
+//! protocol); a rule matches if any of them does. Every event carries its
+//! `direction` relative to the home networks and the network groups of
+//! each end (`src_network`, `dst_network`); with a GeoIP database, also
+//! `src_`/`dst_` `country`, `city`, `asn` and `as_org`.
+
+use crate::capture::dns;
+use crate::capture::flow::FlowRecord;
+use crate::capture::http::{self, HttpRequest};
+use crate::capture::parser::{AppLayer, ParsedPacket};
+use crate::capture::tls::ClientHello;
+use crate::detection::Alert;
+use crate::geoip::GeoIp;
+use crate::networks::Networks;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    Flow,
+    Dns,
+    Http,
+    Tls,
+    Alert,
+}
+
+impl EventKind {
+    pub const ALL: [EventKind; 5] = [
+        EventKind::Flow,
+        EventKind::Dns,
+        EventKind::Http,
+        EventKind::Tls,
+        EventKind::Alert,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventKind::Flow => "FLOW",
+            EventKind::Dns => "DNS",
+            EventKind::Http => "HTTP",
+            EventKind::Tls => "TLS",
+            EventKind::Alert => "ALERT",
+        }
+    }
+
+    /// Sigma logsource categories and services describing this stream.
+    pub fn log_sources(&self) -> &'static [&'static str] {
+        match self {
+            EventKind::Flow => &["firewall", "network_connection", "flow", "conn"],
+            EventKind::Dns => &["dns"],
+            EventKind::Http => &["proxy", "webserver", "http"],
+            EventKind::Tls => &["tls", "ssl"],
+            EventKind::Alert => &["alert", "netguard"],
+        }
+    }
+}
+
+pub struct Event {
+    pub kind: EventKind,
+    pub source_ip: String,
+    pub destination_ip: Option<String>,
+    fields: Vec<(String, String)>,
+}
+
+impl Event {
+    fn new(kind: EventKind, source_ip: &str, destination_ip: Option<&str>) -> Self {
+        let mut event = Self {
+            kind,
+            source_ip: source_ip.to_string(),
+            destination_ip: destination_ip.map(str::to_string),
+            fields: Vec::new(),
+        };
+        event.add("src_ip", source_ip);
+        if let Some(destination_ip) = destination_ip {
+            event.add("dst_ip", destination_ip);
+        }
+        event
+    }
+
+    /// An event between the packet's endpoints, or reversed when `reverse`
+    /// is set so that the source is always the client.
+    fn from_packet(kind: EventKind, packet: &ParsedPacket, reverse: bool) -> Self {
+        let (source, destination) = if reverse {
+            (
+                (&packet.destination_ip, packet.destination_port),
+                (&packet.source_ip, packet.source_port),
+            )
+        } else {
+            (
+                (&packet.source_ip, packet.source_port),
+                (&packet.destination_ip, packet.destination_port),
+            )
+        };
+        let mut event = Self::new(kind, source.0, Some(destination.0));
+        event.add_opt("src_port", source.1);
+        event.add_opt("dst_port", destination.1);
+        event.add("proto", &packet.protocol);
+        event
+    }
+
+    fn add(&mut self, name: &str, value: impl ToString) {
+        self.fields.push((name.to_string(), value.to_string()));
+    }
+
+    fn add_opt(&mut self, name: &str, value: Option<impl ToString>) {
+        if let Some(value) = value {
+            self.add(name, value);
+        }
+    }
+
+    /// Adds the direction and the network groups of both endpoints.
+    pub fn add_networks(&mut self, networks: &Networks) {
+        if let Some(destination) = &self.destination_ip {
+            let direction = networks.direction(&self.source_ip, destination);
+            self.add("direction", direction);
+        }
+        let endpoints = [
+            ("src_network", Some(self.source_ip.clone())),
+            ("dst_network", self.destination_ip.clone()),
+        ];
+        for (field, ip) in endpoints {
+            if let Some(ip) = ip.and_then(|ip| ip.parse().ok()) {
+                for group in networks.groups_of(ip) {
+                    self.add(field, group);
+                }
+            }
+        }
+    }
+
+    /// Adds what the GeoIP databases know about both endpoints.
+    pub fn add_geo(&mut self, geoip: &GeoIp) {
+        let endpoints = [
+            ("src", Some(self.source_ip.clone())),
+            ("dst", self.destination_ip.clone()),
+        ];
+        for (prefix, ip) in endpoints {
+            let Some(geo) = ip.and_then(|ip| geoip.lookup_str(&ip)) else {
+                continue;
+            };
+            self.add_opt(&format!("{}_country", prefix), geo.country);
+            self.add_opt(&format!("{}_city", prefix), geo.city);
+            self.add_opt(&format!("{}_asn", prefix), geo.asn);
+            self.add_opt(&format!("{}_as_org", prefix), geo.as_org);
+        }
+    }
+
+    /// Every value of a field, matched case-insensitively by name.
+    pub fn values<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.fields
+            .iter()
+            .filter(move |(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Every value of every field, for keyword searches.
+    pub fn all_values(&self) -> impl Iterator<Item = &str> {
+        self.fields.iter().map(|(_, value)| value.as_str())
+    }
+}
+
+/// The DNS, TLS and HTTP events carried by one packet.
+pub fn packet_events(packet: &ParsedPacket) -> Vec<Event> {
+    let mut events = Vec::new();
+    match &packet.app {
+        Some(AppLayer::Dns(message)) => {
+            let mut event = Event::from_packet(EventKind::Dns, packet, message.response);
+            for question in &message.questions {
+                event.add("query", &question.name);
+                event.add("record_type", dns::type_name(question.qtype));
+            }
+            event.add("response", message.response);
+            if message.response {
+                event.add("rcode", message.rcode);
+                event.add("answer_count", message.answer_count);
+            }
+            events.push(event);
+        }
+        Some(AppLayer::Tls(hello)) => events.push(tls_event(packet, hello, "tcp")),
+        Some(AppLayer::Quic(initial)) => {
+            if let Some(hello) = &initial.client_hello {
+                events.push(tls_event(packet, hello, "quic"));
+            }
+        }
+        _ => {}
+    }
+
+    // HTTP Basic requests are claimed by the credentials dissector, so
+    // requests are recognised here rather than through `packet.app`.
+    if packet.tcp_flags.is_some() {
+        if let Some(request) = http::parse_request(&packet.payload) {
+            events.push(http_event(packet, &request));
+        }
+    }
+    events
+}
+
+fn tls_event(packet: &ParsedPacket, hello: &ClientHello, transport: &str) -> Event {
+    let mut event = Event::from_packet(EventKind::Tls, packet, false);
+    event.add_opt("server_name", hello.server_name.as_ref());
+    for protocol in &hello.alpn {
+        event.add("alpn", protocol);
+    }
+    event.add_opt("ja3", hello.ja3.as_ref());
+    event.add_opt("ja4", hello.ja4.as_ref());
+    event.add("transport", transport);
+    event
+}
+
+fn http_event(packet: &ParsedPacket, request: &HttpRequest) -> Event {
+    let mut event = Event::from_packet(EventKind::Http, packet, false);
+    event.add("cs-method", &request.method);
+    event.add("cs-version", &request.version);
+    event.add("c-uri", &request.uri);
+    let (stem, query) = request
+        .uri
+        .split_once('?')
+        .unwrap_or((request.uri.as_str(), ""));
+    event.add("c-uri-stem", stem);
+    event.add("c-uri-query", query);
+    if let Some((_, extension)) = stem
+        .rsplit('/')
+        .next()
+        .and_then(|name| name.rsplit_once('.'))
+    {
+        event.add("c-uri-extension", extension);
+    }
+    for (name, value) in &request.headers {
+        let field = match name.as_str() {
+            "host" => "cs-host".to_string(),
+            "user-agent" => "c-useragent".to_string(),
+            "referer" => "cs-referrer".to_string(),
+            _ => format!("cs-{}", name),
+        };
+        event.add(&field, value);
+    }
+    event
+}
+
+pub fn flow_event(flow: &FlowRecord) -> Event {
+    let mut event = Event::new(EventKind::Flow, &flow.source_ip, Some(&flow.destination_ip));
+    event.add_opt("src_port", flow.source_port);
+    event.add_opt("dst_port", flow.destination_port);
+    event.add("proto", &flow.protocol);
+    event.add("packets_sent", flow.packets_sent);
+    event.add("bytes_sent", flow.bytes_sent);
+    event.add("packets_received", flow.packets_received);
+    event.add("bytes_received", flow.bytes_received);
+    event.add(
+        "duration",
+        (flow.last_seen - flow.first_seen).num_milliseconds() as f64 / 1000.0,
+    );
+    event.add("tcp_flags", flow.tcp_flags);
+    event.add_opt("server_name", flow.server_name.as_ref());
+    event
+}
+
+pub fn alert_event(alert: &Alert) -> Event {
+    let mut event = Event::new(
+        EventKind::Alert,
+        &alert.source_ip,
+        alert.destination_ip.as_deref(),
+    );
+    event.add("alert_type", &alert.alert_type);
+    event.add("severity", &alert.severity);
+    event.add("details", &alert.details);
+    event
+}
@@ -0,0 +1,225 @@
+//! Sigma rules over NetGuard's own event streams.
+//!
+//! Rules are loaded from the files and directories listed in
+//! `detection.sigma` and evaluated in process against structured events:
+//! closed flows, DNS messages, HTTP requests, TLS and QUIC ClientHellos,
+//! and the alerts raised by the other detectors. A rule applies to the
+//! streams its `logsource` describes; rules for other log sources (Windows,
+//! cloud audit logs, ...) are skipped. A match raises an alert named after
+//! the rule's title, with its level as severity and its tags in the
+//! details. Events carry their direction and the endpoints' network groups,
+//! and their country and ASN when `detection.geoip` names a database.
+
+mod event;
+mod parse;
+
+use anyhow::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::detector::Detector;
+use super::Alert;
+use crate::capture::flow::FlowRecord;
+use crate::capture::parser::ParsedPacket;
+use crate::config::SigmaConfig;
+use crate::error::NetGuardError;
+use crate::geoip::GeoIp;
+use crate::networks::Networks;
+
+use event::{Event, EventKind};
+pub use parse::{parse_rule, SigmaRule};
+
+pub struct SigmaDetector {
+    rules: Vec<SigmaRule>,
+    /// Rules to evaluate per event kind, by index into `rules`.
+    by_kind: Vec<(EventKind, Vec<usize>)>,
+    networks: Networks,
+    geoip: Option<Arc<GeoIp>>,
+    events: u64,
+    matches: u64,
+}
+
+impl SigmaDetector {
+    pub fn new(rules: Vec<SigmaRule>, networks: Networks, geoip: Option<Arc<GeoIp>>) -> Self {
+        let by_kind = EventKind::ALL
+            .into_iter()
+            .map(|kind| {
+                let indices = rules
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, rule)| rule.kinds.contains(&kind))
+                    .map(|(index, _)| index)
+                    .collect();
+                (kind, indices)
+            })
+            .collect();
+        Self {
+            rules,
+            by_kind,
+            networks,
+            geoip,
+            events: 0,
+            matches: 0,
+        }
+    }
+
+    /// Loads every rule in the configured files and directories. Rules that
+    /// fail to parse are logged and skipped; unreadable files are errors.
+    pub fn load(
+        config: &SigmaConfig,
+        networks: Networks,
+        geoip: Option<Arc<GeoIp>>,
+    ) -> Result<Self> {
+        let mut paths = Vec::new();
+        for path in &config.files {
+            collect_rule_files(Path::new(path), &mut paths)?;
+        }
+
+        let mut rules = Vec::new();
+        let mut failed = 0;
+        let mut other_sources = 0;
+        for path in &paths {
+            let text = fs::read_to_string(path).map_err(|e| {
+                NetGuardError::RuleError(format!(
+                    "Cannot read Sigma rule file {}: {}",
+                    path.display(),
+                    e
+                ))
+            })?;
+            for document in serde_yaml::Deserializer::from_str(&text) {
+                let parsed = serde::Deserialize::deserialize(document)
+                    .map_err(|e| e.to_string())
+                    .and_then(|value: serde_yaml::Value| parse_rule(&value));
+                match parsed {
+                    Ok(Some(rule)) => rules.push(rule),
+                    Ok(None) => other_sources += 1,
+                    Err(e) => {
+                        failed += 1;
+                        log::warn!("{}: skipping Sigma rule: {}", path.display(), e);
+                    }
+                }
+            }
+        }
+
+        let detector = Self::new(rules, networks, geoip);
+        log::info!(
+            "Loaded {} Sigma rules ({} skipped, {} for other log sources)",
+            detector.rules.len(),
+            failed,
+            other_sources
+        );
+        Ok(detector)
+    }
+
+    fn evaluate(&mut self, mut event: Event) -> Vec<Alert> {
+        self.events += 1;
+        let Some((_, indices)) = self.by_kind.iter().find(|(kind, _)| *kind == event.kind) else {
+            return Vec::new();
+        };
+        if indices.is_empty() {
+            return Vec::new();
+        }
+        event.add_networks(&self.networks);
+        if let Some(geoip) = &self.geoip {
+            event.add_geo(geoip);
+        }
+        let alerts: Vec<Alert> = indices
+            .iter()
+            .map(|index| &self.rules[*index])
+            .filter(|rule| rule.matches(&event))
+            .map(|rule| sigma_alert(rule, &event))
+            .collect();
+        self.matches += alerts.len() as u64;
+        alerts
+    }
+}
+
+impl Detector for SigmaDetector {
+    fn name(&self) -> &'static str {
+        "sigma"
+    }
+
+    fn on_packet(&mut self, packet: &ParsedPacket) -> Vec<Alert> {
+        event::packet_events(packet)
+            .into_iter()
+            .flat_map(|event| self.evaluate(event))
+            .collect()
+    }
+
+    fn on_flow(&mut self, flow: &FlowRecord) -> Vec<Alert> {
+        self.evaluate(event::flow_event(flow))
+    }
+
+    fn on_alert(&mut self, alert: &Alert) -> Vec<Alert> {
+        self.evaluate(event::alert_event(alert))
+    }
+
+    fn snapshot(&self) -> serde_json::Value {
+        let rules_by_stream: serde_json::Map<String, serde_json::Value> = self
+            .by_kind
+            .iter()
+            .map(|(kind, indices)| (kind.name().to_ascii_lowercase(), indices.len().into()))
+            .collect();
+        serde_json::json!({
+            "rules": self.rules.len(),
+            "rules_by_stream": rules_by_stream,
+            "events": self.events,
+            "matches": self.matches,
+        })
+    }
+}
+
+/// Adds `path`, or the `.yml`/`.yaml` files below it if it is a directory,
+/// in a stable order.
+fn collect_rule_files(path: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+    let mut entries = fs::read_dir(path)
+        .map_err(|e| {
+            NetGuardError::RuleError(format!(
+                "Cannot read Sigma rule directory {}: {}",
+                path.display(),
+                e
+            ))
+        })?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+    for entry in entries {
+        let is_rule = entry
+            .extension()
+            .is_some_and(|extension| extension == "yml" || extension == "yaml");
+        if entry.is_dir() || is_rule {
+            collect_rule_files(&entry, files)?;
+        }
+    }
+    Ok(())
+}
+
+fn sigma_alert(rule: &SigmaRule, event: &Event) -> Alert {
+    let mut details = format!(
+        "[sigma:{}] {} [Level: {}]",
+        rule.id.as_deref().unwrap_or("-"),
+        rule.title,
+        rule.level.name()
+    );
+    if !rule.tags.is_empty() {
+        details.push_str(&format!(" [Tags: {}]", rule.tags.join(", ")));
+    }
+    details.push_str(&format!(" {{{}}} {}", event.kind.name(), event.source_ip));
+    if let Some(destination) = &event.destination_ip {
+        details.push_str(&format!(" -> {}", destination));
+    }
+
+    Alert::new(
+        rule.title.clone(),
+        rule.level.severity(),
+        event.source_ip.clone(),
+        event.destination_ip.clone(),
+        details,
+    )
+    .with_attack(rule.attack.clone())
+}
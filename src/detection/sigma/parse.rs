@@ -0,0 +1,742 @@
+//! Parser for Sigma rules: logsource, detection and condition.
+//!
+//! ```yaml
+//! title: Lookup of a .onion domain
+//! id: 8a3c2f1e-...
+//! logsource: { category: dns }
+//! detection:
+//!     selection:
+//!         query|endswith: '.onion'
+//!     filter:
+//!         src_ip|cidr: 10.0.0.0/8
+//!     condition: selection and not filter
+//! level: high
+//! tags: [attack.command_and_control, attack.t1090.003]
+//! ```
+//!
+//! Supported modifiers are `contains`, `startswith`, `endswith`, `all`,
+//! `re` (with `i`, `m` and `s`), `cidr`, `lt`, `lte`, `gt`, `gte` and
+//! `exists`. Other modifiers, aggregations in the condition and correlation
+//! rules make the rule fail to load rather than silently match more than
+//! intended. Plain values match case-insensitively and support the `*` and
+//! `?` wildcards.
+
+use pnet::ipnetwork::IpNetwork;
+use regex::{Regex, RegexBuilder};
+use serde_yaml::{Mapping, Value};
+use std::net::IpAddr;
+
+use super::event::{Event, EventKind};
+use crate::attack::{self, Technique};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Informational,
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl Level {
+    fn parse(text: &str) -> Result<Self, String> {
+        match text.to_ascii_lowercase().as_str() {
+            "informational" => Ok(Level::Informational),
+            "low" => Ok(Level::Low),
+            "medium" => Ok(Level::Medium),
+            "high" => Ok(Level::High),
+            "critical" => Ok(Level::Critical),
+            other => Err(format!("unknown level '{}'", other)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Level::Informational => "informational",
+            Level::Low => "low",
+            Level::Medium => "medium",
+            Level::High => "high",
+            Level::Critical => "critical",
+        }
+    }
+
+    /// The alert severity; informational rules report as low.
+    pub fn severity(&self) -> &'static str {
+        match self {
+            Level::Informational | Level::Low => "low",
+            Level::Medium => "medium",
+            Level::High => "high",
+            Level::Critical => "critical",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum ValueMatcher {
+    /// The field is absent or empty.
+    Null,
+    Exists(bool),
+    Pattern(Regex),
+    Cidr(IpNetwork),
+    Compare(Comparison, f64),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Comparison {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl ValueMatcher {
+    /// Whether the matcher accepts the field, given all of its values.
+    fn matches_field<'a>(&self, mut values: impl Iterator<Item = &'a str>) -> bool {
+        match self {
+            ValueMatcher::Null => values.all(str::is_empty),
+            ValueMatcher::Exists(want) => values.next().is_some() == *want,
+            _ => values.any(|value| self.matches_value(value)),
+        }
+    }
+
+    fn matches_value(&self, value: &str) -> bool {
+        match self {
+            ValueMatcher::Null => value.is_empty(),
+            ValueMatcher::Exists(want) => *want,
+            ValueMatcher::Pattern(regex) => regex.is_match(value),
+            ValueMatcher::Cidr(network) => {
+                value.parse::<IpAddr>().is_ok_and(|ip| network.contains(ip))
+            }
+            ValueMatcher::Compare(comparison, bound) => {
+                value.parse::<f64>().is_ok_and(|value| match comparison {
+                    Comparison::Less => value < *bound,
+                    Comparison::LessOrEqual => value <= *bound,
+                    Comparison::Greater => value > *bound,
+                    Comparison::GreaterOrEqual => value >= *bound,
+                })
+            }
+        }
+    }
+}
+
+/// One `field|modifiers: values` entry.
+#[derive(Debug, Clone)]
+struct FieldMatch {
+    field: String,
+    values: Vec<ValueMatcher>,
+    /// `|all`: every value must match instead of any.
+    all: bool,
+}
+
+impl FieldMatch {
+    fn matches(&self, event: &Event) -> bool {
+        let field = |matcher: &ValueMatcher| matcher.matches_field(event.values(&self.field));
+        if self.all {
+            self.values.iter().all(field)
+        } else {
+            self.values.iter().any(field)
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Selection {
+    /// Maps of field matches; a map matches when all its entries do, the
+    /// selection when any map does.
+    Fields(Vec<Vec<FieldMatch>>),
+    /// Values searched for in every field.
+    Keywords(Vec<ValueMatcher>),
+}
+
+impl Selection {
+    fn matches(&self, event: &Event) -> bool {
+        match self {
+            Selection::Fields(maps) => maps
+                .iter()
+                .any(|map| map.iter().all(|entry| entry.matches(event))),
+            Selection::Keywords(keywords) => keywords
+                .iter()
+                .any(|keyword| event.all_values().any(|value| keyword.matches_value(value))),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Condition {
+    Selection(usize),
+    Not(Box<Condition>),
+    And(Vec<Condition>),
+    Or(Vec<Condition>),
+}
+
+impl Condition {
+    fn matches(&self, selections: &[Selection], event: &Event) -> bool {
+        match self {
+            Condition::Selection(index) => selections[*index].matches(event),
+            Condition::Not(inner) => !inner.matches(selections, event),
+            Condition::And(items) => items.iter().all(|item| item.matches(selections, event)),
+            Condition::Or(items) => items.iter().any(|item| item.matches(selections, event)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SigmaRule {
+    pub id: Option<String>,
+    pub title: String,
+    pub level: Level,
+    pub tags: Vec<String>,
+    /// From the `attack.*` tags.
+    pub attack: Vec<Technique>,
+    /// Event streams the rule's logsource describes.
+    pub kinds: Vec<EventKind>,
+    selections: Vec<Selection>,
+    condition: Condition,
+}
+
+impl SigmaRule {
+    pub fn matches(&self, event: &Event) -> bool {
+        self.condition.matches(&self.selections, event)
+    }
+}
+
+/// Parses one rule document. Returns `Ok(None)` for rules whose logsource
+/// is not one of NetGuard's event streams (Windows, cloud, ...).
+pub fn parse_rule(document: &Value) -> Result<Option<SigmaRule>, String> {
+    let rule = document.as_mapping().ok_or("rule is not a YAML mapping")?;
+    if rule.contains_key("correlation") {
+        return Err("correlation rules are not supported".to_string());
+    }
+    if rule.contains_key("action") {
+        return Err("rule collections (action: global) are not supported".to_string());
+    }
+
+    let title = string_field(rule, "title")?.ok_or("missing title")?;
+    let logsource = rule
+        .get("logsource")
+        .and_then(Value::as_mapping)
+        .ok_or("missing logsource")?;
+    let kinds = log_source_kinds(logsource)?;
+    if kinds.is_empty() {
+        return Ok(None);
+    }
+
+    let level = match string_field(rule, "level")? {
+        Some(level) => Level::parse(&level)?,
+        None => Level::Medium,
+    };
+    let tags = match rule.get("tags") {
+        Some(Value::Sequence(tags)) => tags
+            .iter()
+            .map(|tag| scalar_string(tag).ok_or("tags must be strings"))
+            .collect::<Result<_, _>>()?,
+        Some(_) => return Err("tags must be a list".to_string()),
+        None => Vec::new(),
+    };
+
+    let detection = rule
+        .get("detection")
+        .and_then(Value::as_mapping)
+        .ok_or("missing detection")?;
+    let mut names = Vec::new();
+    let mut selections = Vec::new();
+    for (name, body) in detection {
+        let name = name.as_str().ok_or("detection keys must be strings")?;
+        if name == "condition" || name == "timeframe" {
+            continue;
+        }
+        let selection =
+            parse_selection(body).map_err(|e| format!("selection '{}': {}", name, e))?;
+        names.push(name.to_string());
+        selections.push(selection);
+    }
+    if detection.contains_key("timeframe") {
+        return Err("timeframe is not supported".to_string());
+    }
+
+    let conditions = match detection.get("condition") {
+        Some(Value::String(condition)) => vec![condition.clone()],
+        Some(Value::Sequence(items)) => items
+            .iter()
+            .map(|item| scalar_string(item).ok_or("condition must be a string"))
+            .collect::<Result<_, _>>()?,
+        _ => return Err("missing condition".to_string()),
+    };
+    let mut parsed = conditions
+        .iter()
+        .map(|condition| parse_condition(condition, &names))
+        .collect::<Result<Vec<_>, _>>()?;
+    let condition = if parsed.len() == 1 {
+        parsed.remove(0)
+    } else {
+        Condition::Or(parsed)
+    };
+
+    Ok(Some(SigmaRule {
+        id: string_field(rule, "id")?,
+        title,
+        level,
+        attack: attack::from_sigma_tags(&tags),
+        tags,
+        kinds,
+        selections,
+        condition,
+    }))
+}
+
+/// Maps `category`/`service` to the event streams they describe; a product
+/// other than NetGuard's own or Zeek's (whose field names it borrows)
+/// describes none.
+fn log_source_kinds(logsource: &Mapping) -> Result<Vec<EventKind>, String> {
+    let category = string_field(logsource, "category")?.map(|s| s.to_ascii_lowercase());
+    let service = string_field(logsource, "service")?.map(|s| s.to_ascii_lowercase());
+    let product = string_field(logsource, "product")?.map(|s| s.to_ascii_lowercase());
+
+    if product
+        .as_deref()
+        .is_some_and(|product| product != "netguard" && product != "zeek")
+    {
+        return Ok(Vec::new());
+    }
+    if category.is_none() && service.is_none() {
+        return Ok(Vec::new());
+    }
+    let describes = |name: &Option<String>, kind: EventKind| {
+        name.as_deref()
+            .is_none_or(|name| kind.log_sources().contains(&name))
+    };
+    Ok(EventKind::ALL
+        .into_iter()
+        .filter(|kind| describes(&category, *kind) && describes(&service, *kind))
+        .collect())
+}
+
+fn parse_selection(body: &Value) -> Result<Selection, String> {
+    match body {
+        Value::Mapping(map) => Ok(Selection::Fields(vec![parse_field_map(map)?])),
+        Value::Sequence(items) if items.iter().all(Value::is_mapping) => {
+            let maps = items
+                .iter()
+                .filter_map(Value::as_mapping)
+                .map(parse_field_map)
+                .collect::<Result<_, _>>()?;
+            Ok(Selection::Fields(maps))
+        }
+        Value::Sequence(items) => {
+            let keywords = items
+                .iter()
+                .map(|item| value_matcher(item, &[]))
+                .collect::<Result<_, _>>()?;
+            Ok(Selection::Keywords(keywords))
+        }
+        other => Ok(Selection::Keywords(vec![value_matcher(other, &[])?])),
+    }
+}
+
+fn parse_field_map(map: &Mapping) -> Result<Vec<FieldMatch>, String> {
+    let mut entries = Vec::new();
+    for (key, value) in map {
+        let key = key.as_str().ok_or("field names must be strings")?;
+        let mut parts = key.split('|');
+        let field = parts.next().unwrap_or_default().to_string();
+        let modifiers: Vec<&str> = parts.collect();
+        let all = modifiers.contains(&"all");
+        let modifiers: Vec<&str> = modifiers.into_iter().filter(|m| *m != "all").collect();
+
+        let values = match value {
+            Value::Sequence(items) => items
+                .iter()
+                .map(|item| value_matcher(item, &modifiers))
+                .collect::<Result<Vec<_>, _>>()?,
+            other => vec![value_matcher(other, &modifiers)?],
+        };
+        if field.is_empty() {
+            return Err("empty field name".to_string());
+        }
+        entries.push(FieldMatch { field, values, all });
+    }
+    Ok(entries)
+}
+
+fn value_matcher(value: &Value, modifiers: &[&str]) -> Result<ValueMatcher, String> {
+    if value.is_null() {
+        return if modifiers.is_empty() {
+            Ok(ValueMatcher::Null)
+        } else {
+            Err("modifiers cannot apply to null".to_string())
+        };
+    }
+    let text = scalar_string(value).ok_or("values must be scalars")?;
+
+    match modifiers {
+        [] => wildcard(&text, false, false),
+        ["contains"] => wildcard(&text, true, true),
+        ["startswith"] => wildcard(&text, false, true),
+        ["endswith"] => wildcard(&text, true, false),
+        ["re", flags @ ..] => {
+            let mut builder = RegexBuilder::new(&text);
+            for flag in flags {
+                match *flag {
+                    "i" => builder.case_insensitive(true),
+                    "m" => builder.multi_line(true),
+                    "s" => builder.dot_matches_new_line(true),
+                    other => return Err(format!("unsupported modifier '{}'", other)),
+                };
+            }
+            builder
+                .build()
+                .map(ValueMatcher::Pattern)
+                .map_err(|e| format!("invalid regex '{}': {}", text, e))
+        }
+        ["cidr"] => text
+            .parse::<IpNetwork>()
+            .map(ValueMatcher::Cidr)
+            .map_err(|e| format!("invalid network '{}': {}", text, e)),
+        [comparison @ ("lt" | "lte" | "gt" | "gte")] => {
+            let bound = text
+                .parse::<f64>()
+                .map_err(|_| format!("'{}' is not a number", text))?;
+            let comparison = match *comparison {
+                "lt" => Comparison::Less,
+                "lte" => Comparison::LessOrEqual,
+                "gt" => Comparison::Greater,
+                _ => Comparison::GreaterOrEqual,
+            };
+            Ok(ValueMatcher::Compare(comparison, bound))
+        }
+        ["exists"] => match value {
+            Value::Bool(want) => Ok(ValueMatcher::Exists(*want)),
+            _ => Err("exists takes true or false".to_string()),
+        },
+        other => Err(format!("unsupported modifiers '{}'", other.join("|"))),
+    }
+}
+
+/// Compiles a Sigma string value, with `*` and `?` wildcards and `\`
+/// escaping them, into a case-insensitive anchored regex.
+fn wildcard(text: &str, open_start: bool, open_end: bool) -> Result<ValueMatcher, String> {
+    let mut pattern = String::from("^");
+    if open_start {
+        pattern.push_str(".*");
+    }
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            '\\' if matches!(chars.peek(), Some('*' | '?' | '\\')) => {
+                let escaped = chars.next().unwrap_or('\\');
+                pattern.push_str(&regex::escape(&escaped.to_string()));
+            }
+            c => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    if open_end {
+        pattern.push_str(".*");
+    }
+    pattern.push('$');
+    RegexBuilder::new(&pattern)
+        .case_insensitive(true)
+        .dot_matches_new_line(true)
+        .build()
+        .map(ValueMatcher::Pattern)
+        .map_err(|e| format!("invalid value '{}': {}", text, e))
+}
+
+fn parse_condition(text: &str, names: &[String]) -> Result<Condition, String> {
+    if text.contains('|') {
+        return Err("aggregations in conditions are not supported".to_string());
+    }
+    let spaced = text.replace('(', " ( ").replace(')', " ) ");
+    let tokens: Vec<&str> = spaced.split_whitespace().collect();
+    let mut parser = ConditionParser {
+        tokens,
+        position: 0,
+        names,
+    };
+    let condition = parser.or()?;
+    match parser.tokens.get(parser.position) {
+        Some(token) => Err(format!("unexpected '{}' in condition", token)),
+        None => Ok(condition),
+    }
+}
+
+/// Recursive descent over `or` < `and` < `not` < primaries.
+struct ConditionParser<'a> {
+    tokens: Vec<&'a str>,
+    position: usize,
+    names: &'a [String],
+}
+
+impl ConditionParser<'_> {
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        self.tokens
+            .get(self.position)
+            .is_some_and(|token| token.eq_ignore_ascii_case(keyword))
+    }
+
+    fn next(&mut self) -> Result<&str, String> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .ok_or("condition ends unexpectedly")?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn or(&mut self) -> Result<Condition, String> {
+        let mut items = vec![self.and()?];
+        while self.peek_keyword("or") {
+            self.position += 1;
+            items.push(self.and()?);
+        }
+        Ok(if items.len() == 1 {
+            items.remove(0)
+        } else {
+            Condition::Or(items)
+        })
+    }
+
+    fn and(&mut self) -> Result<Condition, String> {
+        let mut items = vec![self.not()?];
+        while self.peek_keyword("and") {
+            self.position += 1;
+            items.push(self.not()?);
+        }
+        Ok(if items.len() == 1 {
+            items.remove(0)
+        } else {
+            Condition::And(items)
+        })
+    }
+
+    fn not(&mut self) -> Result<Condition, String> {
+        if self.peek_keyword("not") {
+            self.position += 1;
+            return Ok(Condition::Not(Box::new(self.not()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Condition, String> {
+        let token = self.next()?.to_string();
+        if token == "(" {
+            let inner = self.or()?;
+            return match self.next()? {
+                ")" => Ok(inner),
+                other => Err(format!("expected ')' in condition, found '{}'", other)),
+            };
+        }
+
+        let quantifier = token.to_ascii_lowercase();
+        if (quantifier == "1" || quantifier == "all") && self.peek_keyword("of") {
+            self.position += 1;
+            let target = self.next()?.to_string();
+            let selected = self.expand(&target)?;
+            return Ok(if quantifier == "all" {
+                Condition::And(selected)
+            } else {
+                Condition::Or(selected)
+            });
+        }
+
+        self.names
+            .iter()
+            .position(|name| *name == token)
+            .map(Condition::Selection)
+            .ok_or_else(|| format!("unknown selection '{}' in condition", token))
+    }
+
+    /// Selections named by `them` (all but those starting with `_`) or by a
+    /// pattern with `*` wildcards.
+    fn expand(&self, target: &str) -> Result<Vec<Condition>, String> {
+        let matcher = if target == "them" {
+            None
+        } else {
+            let mut pattern = String::from("^");
+            for part in target.split('*') {
+                pattern.push_str(&regex::escape(part));
+                pattern.push_str(".*");
+            }
+            pattern.truncate(pattern.len() - 2);
+            pattern.push('$');
+            Some(Regex::new(&pattern).map_err(|e| e.to_string())?)
+        };
+        let selected: Vec<Condition> = self
+            .names
+            .iter()
+            .enumerate()
+            .filter(|(_, name)| match &matcher {
+                None => !name.starts_with('_'),
+                Some(matcher) => matcher.is_match(name),
+            })
+            .map(|(index, _)| Condition::Selection(index))
+            .collect();
+        if selected.is_empty() {
+            return Err(format!("'{}' matches no selection", target));
+        }
+        Ok(selected)
+    }
+}
+
+fn string_field(map: &Mapping, key: &str) -> Result<Option<String>, String> {
+    match map.get(key) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => scalar_string(value)
+            .map(Some)
+            .ok_or_else(|| format!("{} must be a string", key)),
+    }
+}
+
+fn scalar_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::detection::sigma::event::alert_event;
+    use crate::detection::Alert;
+
+    /// The condition with selection names, fully parenthesised.
+    fn render(condition: &Condition, names: &[&str]) -> String {
+        let join = |items: &[Condition], separator: &str| {
+            let items: Vec<String> = items.iter().map(|item| render(item, names)).collect();
+            format!("({})", items.join(separator))
+        };
+        match condition {
+            Condition::Selection(index) => names[*index].to_string(),
+            Condition::Not(inner) => format!("not {}", render(inner, names)),
+            Condition::And(items) => join(items, " and "),
+            Condition::Or(items) => join(items, " or "),
+        }
+    }
+
+    fn condition(text: &str) -> Result<String, String> {
+        let names = ["selection", "filter_a", "filter_b", "keywords", "_helper"];
+        let owned: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+        parse_condition(text, &owned).map(|condition| render(&condition, &names))
+    }
+
+    #[test]
+    fn condition_precedence() {
+        assert_eq!(condition("selection").unwrap(), "selection");
+        assert_eq!(
+            condition("selection and not filter_a or keywords").unwrap(),
+            "((selection and not filter_a) or keywords)"
+        );
+        assert_eq!(
+            condition("selection AND (filter_a OR filter_b)").unwrap(),
+            "(selection and (filter_a or filter_b))"
+        );
+        assert_eq!(condition("not not selection").unwrap(), "not not selection");
+        assert_eq!(
+            condition("(selection)and(keywords)").unwrap(),
+            "(selection and keywords)"
+        );
+    }
+
+    #[test]
+    fn condition_quantifiers() {
+        assert_eq!(
+            condition("1 of filter_*").unwrap(),
+            "(filter_a or filter_b)"
+        );
+        assert_eq!(
+            condition("selection and not all of filter*").unwrap(),
+            "(selection and not (filter_a and filter_b))"
+        );
+        assert_eq!(
+            condition("all of them").unwrap(),
+            "(selection and filter_a and filter_b and keywords)"
+        );
+        assert_eq!(condition("1 of _helper").unwrap(), "(_helper)");
+    }
+
+    #[test]
+    fn condition_errors() {
+        let err = |text: &str| condition(text).unwrap_err();
+        assert!(err("selection | count() > 5").contains("aggregations"));
+        assert!(err("unknown").contains("unknown selection 'unknown'"));
+        assert!(err("selection and").contains("ends unexpectedly"));
+        assert!(err("(selection or keywords").contains("ends unexpectedly"));
+        assert!(err("selection keywords").contains("unexpected 'keywords'"));
+        assert!(err("1 of nothing*").contains("matches no selection"));
+        assert!(err("").contains("ends unexpectedly"));
+    }
+
+    fn rule(yaml: &str) -> Result<Option<SigmaRule>, String> {
+        parse_rule(&serde_yaml::from_str(yaml).unwrap())
+    }
+
+    fn alert(alert_type: &str, severity: &str, source_ip: &str) -> Alert {
+        Alert::new(
+            alert_type,
+            severity,
+            source_ip,
+            Some("198.51.100.7".to_string()),
+            "details",
+        )
+        .at(chrono::Utc::now())
+    }
+
+    #[test]
+    fn rule_with_filters_and_condition_list() {
+        let rule = rule(
+            "title: Scans from outside the lab
+logsource: { category: alert }
+detection:
+    selection:
+        alert_type|startswith: 'Port Scan'
+        severity: [high, critical]
+    filter_lab:
+        src_ip|cidr: 10.9.0.0/16
+    keywords:
+        - '*beacon*'
+    condition:
+        - selection and not filter_lab
+        - keywords
+level: high
+tags: [attack.discovery, attack.t1046]
+",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(rule.level, Level::High);
+        assert_eq!(rule.kinds, [EventKind::Alert]);
+        assert_eq!(rule.attack.len(), 1);
+        assert_eq!(rule.attack[0].to_string(), "TA0007/T1046");
+
+        let matches = |alert: Alert| rule.matches(&alert_event(&alert));
+        assert!(matches(alert("Port Scan (SYN)", "HIGH", "10.1.0.5")));
+        assert!(!matches(alert("Port Scan (SYN)", "low", "10.1.0.5")));
+        assert!(!matches(alert("Port Scan (SYN)", "high", "10.9.3.3")));
+        assert!(matches(alert("C2 Beaconing", "low", "10.9.3.3")));
+    }
+
+    #[test]
+    fn rule_errors_and_unsupported_sources() {
+        assert!(
+            rule("title: x\nlogsource: { product: windows }\ndetection: {}\n")
+                .unwrap()
+                .is_none()
+        );
+        let err = |yaml: &str| rule(yaml).unwrap_err();
+        assert!(
+            err("title: x\nlogsource: { category: dns }\ndetection: { s: { query: a } }\n")
+                .contains("missing condition")
+        );
+        assert!(err("title: x\nlogsource: { category: dns }\n\
+             detection: { s: { query|base64: a }, condition: s }\n")
+        .contains("unsupported modifiers 'base64'"));
+        assert!(err("title: x\nlogsource: { category: dns }\n\
+             detection: { s: { query: a }, condition: s, timeframe: 5m }\n")
+        .contains("timeframe"));
+        assert!(err("correlation: { type: event_count }\n").contains("correlation"));
+    }
+}
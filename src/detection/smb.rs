@@ -0,0 +1,432 @@
+//! Lateral movement detection over SMB2 and DCE-RPC.
+//!
+//! The dissector only sees one message at a time, so this tracker keeps the
+//! session state needed to interpret it: which tree ID maps to which share,
+//! which file ID is which named pipe, and which DCE-RPC interface each
+//! presentation context was bound to.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::mem::size_of;
+
+use super::detector::Detector;
+use super::state::LruMap;
+use super::Alert;
+use crate::attack;
+use crate::capture::dcerpc::DceRpcPdu;
+use crate::capture::parser::{AppLayer, ParsedPacket};
+use crate::capture::smb::{SmbCommand, SmbMessage};
+use crate::config::SmbConfig;
+
+/// Pending request/response correlations; responses we never saw must not
+/// pin memory forever.
+const MAX_PENDING: usize = 10_000;
+/// Open trees, pipes and bindings. Closes and disconnects we never saw, and
+/// RPC over TCP connections, would otherwise accumulate.
+const MAX_OPEN: usize = 50_000;
+/// Presentation contexts remembered per DCE-RPC association.
+const MAX_CONTEXTS: usize = 256;
+/// Typical heap size of a connection key string, for memory estimates.
+const CONNECTION_BYTES: usize = 40;
+
+/// Identifies one DCE-RPC association: an SMB pipe handle, or a bare TCP
+/// connection (file ID 0) for RPC over TCP.
+type BindingKey = (String, u128);
+
+pub struct SmbTracker {
+    config: SmbConfig,
+    pending_trees: LruMap<(String, u64, u64), String>,
+    trees: LruMap<(String, u64, u32), String>,
+    pending_creates: LruMap<(String, u64, u64), String>,
+    pipes: LruMap<(String, u128), String>,
+    bindings: LruMap<BindingKey, HashMap<u16, String>>,
+}
+
+impl SmbTracker {
+    pub fn new(config: SmbConfig) -> Self {
+        Self {
+            config,
+            pending_trees: LruMap::new(MAX_PENDING),
+            trees: LruMap::new(MAX_OPEN),
+            pending_creates: LruMap::new(MAX_PENDING),
+            pipes: LruMap::new(MAX_OPEN),
+            bindings: LruMap::new(MAX_OPEN),
+        }
+    }
+
+    fn inspect(&mut self, packet: &ParsedPacket) -> Vec<Alert> {
+        match &packet.app {
+            Some(AppLayer::Smb(messages)) => messages
+                .iter()
+                .filter_map(|message| self.inspect_smb(packet, message))
+                .collect(),
+            Some(AppLayer::DceRpc(pdu)) => {
+                let key = (
+                    format!(
+                        "{}:{}->{}:{}",
+                        packet.source_ip,
+                        packet.source_port.unwrap_or(0),
+                        packet.destination_ip,
+                        packet.destination_port.unwrap_or(0)
+                    ),
+                    0,
+                );
+                self.inspect_rpc(packet, key, pdu, None).into_iter().collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn inspect_smb(&mut self, packet: &ParsedPacket, message: &SmbMessage) -> Option<Alert> {
+        // Key state by client -> server so requests and responses agree.
+        let connection = if message.is_response {
+            format!("{}->{}", packet.destination_ip, packet.source_ip)
+        } else {
+            format!("{}->{}", packet.source_ip, packet.destination_ip)
+        };
+
+        match (message.command, message.is_response) {
+            (SmbCommand::TreeConnect, false) => {
+                if let Some(path) = &message.tree_path {
+                    log::debug!("SMB tree connect {} -> {}", packet.source_ip, path);
+                    self.pending_trees.insert(
+                        (connection, message.session_id, message.message_id),
+                        path.clone(),
+                    );
+                }
+                None
+            }
+            (SmbCommand::TreeConnect, true) => {
+                let pending = (connection.clone(), message.session_id, message.message_id);
+                if let Some(path) = self.pending_trees.remove(&pending) {
+                    if message.status == 0 {
+                        self.trees
+                            .insert((connection, message.session_id, message.tree_id), path);
+                    }
+                }
+                None
+            }
+            (SmbCommand::TreeDisconnect, false) => {
+                self.trees
+                    .remove(&(connection, message.session_id, message.tree_id));
+                None
+            }
+            (SmbCommand::Close, false) => {
+                if let Some(file_id) = message.file_id {
+                    let key = (connection, file_id);
+                    self.pipes.remove(&key);
+                    self.bindings.remove(&key);
+                }
+                None
+            }
+            (SmbCommand::Create, false) => {
+                let tree = self
+                    .trees
+                    .get_mut(&(connection.clone(), message.session_id, message.tree_id))
+                    .cloned();
+                let file_name = message.file_name.clone().unwrap_or_default();
+                self.pending_creates.insert(
+                    (connection, message.session_id, message.message_id),
+                    file_name.clone(),
+                );
+
+                let tree = tree?;
+                let share = tree.rsplit('\\').next().unwrap_or(&tree);
+                if message.requests_write_access() && self.is_admin_share(share) {
+                    return Some(
+                        Alert::new(
+                            "SMB Admin Share Write",
+                            "high",
+                            packet.source_ip.clone(),
+                            Some(packet.destination_ip.clone()),
+                            format!(
+                                "Write access requested to {}\\{} (access mask 0x{:08x})",
+                                tree, file_name, message.desired_access
+                            ),
+                        )
+                        .with_attack(attack::techniques(&[("TA0008", "T1021.002")])),
+                    );
+                }
+                None
+            }
+            (SmbCommand::Create, true) => {
+                let pending = (connection.clone(), message.session_id, message.message_id);
+                if let (Some(name), Some(file_id)) =
+                    (self.pending_creates.remove(&pending), message.file_id)
+                {
+                    self.pipes.insert((connection, file_id), name);
+                }
+                None
+            }
+            _ => {
+                let pdu = message.rpc.as_ref()?;
+                let file_id = message.file_id.unwrap_or(0);
+                let pipe = self.pipes.get_mut(&(connection.clone(), file_id)).cloned();
+                self.inspect_rpc(packet, (connection, file_id), pdu, pipe)
+            }
+        }
+    }
+
+    fn inspect_rpc(
+        &mut self,
+        packet: &ParsedPacket,
+        key: BindingKey,
+        pdu: &DceRpcPdu,
+        pipe: Option<String>,
+    ) -> Option<Alert> {
+        match pdu {
+            DceRpcPdu::Bind { contexts, .. } => {
+                let bound = self.bindings.get_or_insert_with(key, HashMap::new);
+                for context in contexts {
+                    if bound.len() >= MAX_CONTEXTS && !bound.contains_key(&context.context_id) {
+                        break;
+                    }
+                    let interface = context
+                        .interface_name()
+                        .map(str::to_string)
+                        .unwrap_or_else(|| context.interface_uuid.clone());
+                    log::debug!(
+                        "DCE-RPC bind {} -> {} interface {} v{}",
+                        packet.source_ip,
+                        packet.destination_ip,
+                        interface,
+                        context.interface_version
+                    );
+                    bound.insert(context.context_id, interface);
+                }
+                None
+            }
+            DceRpcPdu::Request { context_id, opnum } => {
+                let interface = self.bindings.get_mut(&key)?.get(context_id)?;
+                let operation = remote_execution_operation(interface, *opnum)?;
+                let via = pipe
+                    .map(|name| format!(" via pipe \\{}", name.trim_start_matches('\\')))
+                    .unwrap_or_default();
+
+                Some(
+                    Alert::new(
+                        "Remote Service Creation",
+                        "critical",
+                        packet.source_ip.clone(),
+                        Some(packet.destination_ip.clone()),
+                        format!("{} call {} (opnum {}){}", interface, operation, opnum, via),
+                    )
+                    .with_attack(attack::techniques(&[
+                        ("TA0002", "T1569.002"),
+                        ("TA0003", "T1543.003"),
+                    ])),
+                )
+            }
+            DceRpcPdu::Other => None,
+        }
+    }
+
+    fn is_admin_share(&self, share: &str) -> bool {
+        self.config
+            .admin_shares
+            .iter()
+            .any(|admin| admin.eq_ignore_ascii_case(share))
+    }
+}
+
+/// RPC operations that create services or scheduled tasks on the remote host.
+fn remote_execution_operation(interface: &str, opnum: u16) -> Option<&'static str> {
+    match (interface, opnum) {
+        ("svcctl", 12) => Some("RCreateServiceW"),
+        ("svcctl", 24) => Some("RCreateServiceA"),
+        ("svcctl", 44) => Some("RCreateServiceWOW64A"),
+        ("svcctl", 45) => Some("RCreateServiceWOW64W"),
+        ("atsvc", 0) => Some("NetrJobAdd"),
+        ("ITaskSchedulerService", 1) => Some("SchRpcRegisterTask"),
+        _ => None,
+    }
+}
+
+impl Detector for SmbTracker {
+    fn name(&self) -> &'static str {
+        "smb"
+    }
+
+    fn on_packet(&mut self, packet: &ParsedPacket) -> Vec<Alert> {
+        if packet.protocol != "TCP" || packet.app.is_none() {
+            return Vec::new();
+        }
+        self.inspect(packet)
+    }
+
+    fn snapshot(&self) -> serde_json::Value {
+        serde_json::json!({
+            "trees": self.trees.len(),
+            "pipes": self.pipes.len(),
+            "bindings": self.bindings.len(),
+            "pending": self.pending_trees.len() + self.pending_creates.len(),
+        })
+    }
+    fn memory_usage(&self) -> usize {
+        let contexts: usize = self
+            .bindings
+            .values()
+            .map(|bound| {
+                bound.capacity() * size_of::<(u16, String)>()
+                    + bound.values().map(String::capacity).sum::<usize>()
+            })
+            .sum();
+        names_usage(&self.pending_trees)
+            + names_usage(&self.trees)
+            + names_usage(&self.pending_creates)
+            + names_usage(&self.pipes)
+            + self.bindings.len()
+                * (LruMap::<BindingKey, HashMap<u16, String>>::entry_overhead() + CONNECTION_BYTES)
+            + contexts
+    }
+}
+
+/// Memory held by a map from connection-keyed entries to share, file or
+/// pipe names.
+fn names_usage<K: Hash + Eq + Clone>(map: &LruMap<K, String>) -> usize {
+    map.len() * (LruMap::<K, String>::entry_overhead() + CONNECTION_BYTES)
+        + map.values().map(String::capacity).sum::<usize>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::dcerpc::PresentationContext;
+
+    const SVCCTL: &str = "367abb81-9844-35f1-ad32-98f038001003";
+
+    fn message(command: SmbCommand, is_response: bool) -> SmbMessage {
+        SmbMessage {
+            command,
+            is_response,
+            status: 0,
+            message_id: 1,
+            session_id: 7,
+            tree_id: 3,
+            tree_path: None,
+            file_name: None,
+            desired_access: 0,
+            file_id: None,
+            rpc: None,
+        }
+    }
+
+    /// Feeds one message through the tracker, client to server for
+    /// requests and back for responses.
+    fn send(tracker: &mut SmbTracker, message: SmbMessage) -> Vec<Alert> {
+        let (source, destination) = if message.is_response {
+            ("10.0.0.2", "10.0.0.1")
+        } else {
+            ("10.0.0.1", "10.0.0.2")
+        };
+        tracker.on_packet(&ParsedPacket {
+            source_ip: source.to_string(),
+            destination_ip: destination.to_string(),
+            source_port: Some(if message.is_response { 445 } else { 50000 }),
+            destination_port: Some(if message.is_response { 50000 } else { 445 }),
+            protocol: "TCP".to_string(),
+            size: 0,
+            tcp_flags: None,
+            app: Some(AppLayer::Smb(vec![message])),
+            payload: Vec::new(),
+        })
+    }
+
+    fn connect_tree(tracker: &mut SmbTracker, path: &str) {
+        let mut request = message(SmbCommand::TreeConnect, false);
+        request.tree_path = Some(path.to_string());
+        send(tracker, request);
+        send(tracker, message(SmbCommand::TreeConnect, true));
+    }
+
+    fn open_pipe(tracker: &mut SmbTracker, name: &str, file_id: u128) {
+        let mut request = message(SmbCommand::Create, false);
+        request.file_name = Some(name.to_string());
+        send(tracker, request);
+        let mut response = message(SmbCommand::Create, true);
+        response.file_id = Some(file_id);
+        send(tracker, response);
+    }
+
+    fn rpc(file_id: u128, pdu: DceRpcPdu) -> SmbMessage {
+        let mut write = message(SmbCommand::Write, false);
+        write.file_id = Some(file_id);
+        write.rpc = Some(pdu);
+        write
+    }
+
+    fn create_service(file_id: u128) -> SmbMessage {
+        rpc(
+            file_id,
+            DceRpcPdu::Request {
+                context_id: 0,
+                opnum: 12,
+            },
+        )
+    }
+
+    #[test]
+    fn service_creation_over_pipe_alerts_until_close() {
+        let mut tracker = SmbTracker::new(SmbConfig::default());
+        connect_tree(&mut tracker, "\\\\server\\IPC$");
+        open_pipe(&mut tracker, "svcctl", 42);
+        let bind = DceRpcPdu::Bind {
+            contexts: vec![PresentationContext {
+                context_id: 0,
+                interface_uuid: SVCCTL.to_string(),
+                interface_version: 2,
+            }],
+        };
+        send(&mut tracker, rpc(42, bind));
+
+        let alerts = send(&mut tracker, create_service(42));
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].alert_type, "Remote Service Creation");
+        assert!(alerts[0].details.contains("RCreateServiceW"));
+        assert!(alerts[0].details.ends_with("via pipe \\svcctl"));
+
+        let mut close = message(SmbCommand::Close, false);
+        close.file_id = Some(42);
+        send(&mut tracker, close);
+        assert!(tracker.pipes.is_empty());
+        assert!(tracker.bindings.is_empty());
+        assert!(send(&mut tracker, create_service(42)).is_empty());
+    }
+
+    #[test]
+    fn admin_share_write_alerts_until_tree_disconnect() {
+        let mut tracker = SmbTracker::new(SmbConfig::default());
+        connect_tree(&mut tracker, "\\\\server\\ADMIN$");
+        let mut create = message(SmbCommand::Create, false);
+        create.file_name = Some("evil.exe".to_string());
+        create.desired_access = 0x2;
+
+        let alerts = send(&mut tracker, create.clone());
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].alert_type, "SMB Admin Share Write");
+
+        send(&mut tracker, message(SmbCommand::TreeDisconnect, false));
+        assert!(tracker.trees.is_empty());
+        assert!(send(&mut tracker, create).is_empty());
+    }
+
+    #[test]
+    fn unanswered_requests_are_bounded() {
+        let mut tracker = SmbTracker::new(SmbConfig::default());
+        let empty = tracker.memory_usage();
+        for message_id in 0..MAX_PENDING as u64 + 100 {
+            let mut request = message(SmbCommand::TreeConnect, false);
+            request.message_id = message_id;
+            request.tree_path = Some("\\\\server\\C$".to_string());
+            send(&mut tracker, request);
+        }
+        assert_eq!(tracker.pending_trees.len(), MAX_PENDING);
+        assert!(tracker.memory_usage() > empty);
+
+        // The most recent request is still answerable.
+        let mut response = message(SmbCommand::TreeConnect, true);
+        response.message_id = MAX_PENDING as u64 + 99;
+        send(&mut tracker, response);
+        assert_eq!(tracker.trees.len(), 1);
+    }
+}
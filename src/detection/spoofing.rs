@@ -0,0 +1,378 @@
+//! ARP and IPv6 Neighbor Discovery spoofing detection.
+//!
+//! Keeps an IP -> MAC binding table learned from ARP and NDP traffic, with
+//! the history of previous MACs for each address, and alerts when a
+//! protected address (typically a gateway) moves, when a host floods
+//! gratuitous ARPs, or when an unexpected router advertises itself.
+
+use chrono::{DateTime, Duration, Utc};
+use std::collections::{HashMap, HashSet};
+
+use super::detector::Detector;
+use super::Alert;
+use crate::attack;
+use crate::capture::neighbor::{ArpMessage, NdpMessage};
+use crate::capture::parser::{AppLayer, ParsedPacket};
+use crate::config::SpoofingConfig;
+
+const MAX_BINDINGS: usize = 65_536;
+const MAX_HISTORY: usize = 16;
+
+struct Binding {
+    mac: String,
+    last_seen: DateTime<Utc>,
+    /// Previous MACs for this address and when they were replaced.
+    history: Vec<(String, DateTime<Utc>)>,
+}
+
+pub struct SpoofingTracker {
+    config: SpoofingConfig,
+    bindings: HashMap<String, Binding>,
+    gratuitous: HashMap<String, Vec<DateTime<Utc>>>,
+    learned_routers: HashSet<String>,
+}
+
+impl SpoofingTracker {
+    pub fn new(config: SpoofingConfig) -> Self {
+        Self {
+            config,
+            bindings: HashMap::new(),
+            gratuitous: HashMap::new(),
+            learned_routers: HashSet::new(),
+        }
+    }
+
+    fn inspect(&mut self, packet: &ParsedPacket) -> Option<Alert> {
+        match &packet.app {
+            Some(AppLayer::Arp(arp)) => self.inspect_arp(packet, arp),
+            Some(AppLayer::Ndp(ndp)) => self.inspect_ndp(packet, ndp),
+            _ => None,
+        }
+    }
+
+    fn inspect_arp(&mut self, packet: &ParsedPacket, arp: &ArpMessage) -> Option<Alert> {
+        if arp.is_probe() {
+            return None;
+        }
+
+        let binding_alert = self.observe(packet, &arp.sender_ip, &arp.sender_mac, "ARP Spoofing");
+        if binding_alert.is_some() || !arp.is_gratuitous() {
+            return binding_alert;
+        }
+
+        let now = Utc::now();
+        let window = Duration::seconds(self.config.window_seconds as i64);
+        if self.gratuitous.len() >= MAX_BINDINGS {
+            self.gratuitous
+                .retain(|_, times| times.iter().any(|time| now - *time < window));
+        }
+        let times = self.gratuitous.entry(arp.sender_mac.clone()).or_default();
+        times.retain(|time| now - *time < window);
+        times.push(now);
+
+        if times.len() >= self.config.gratuitous_threshold {
+            return Some(
+                Alert::new(
+                    "Gratuitous ARP Storm",
+                    "high",
+                    packet.source_ip.clone(),
+                    None,
+                    format!(
+                        "{} gratuitous ARP {} from {} in {} seconds",
+                        times.len(),
+                        if arp.is_reply { "replies" } else { "requests" },
+                        arp.sender_mac,
+                        self.config.window_seconds
+                    ),
+                )
+                .at(now)
+                .with_attack(attack::techniques(&[
+                    ("TA0006", "T1557.002"),
+                    ("TA0009", "T1557.002"),
+                ])),
+            );
+        }
+
+        None
+    }
+
+    fn inspect_ndp(&mut self, packet: &ParsedPacket, ndp: &NdpMessage) -> Option<Alert> {
+        match ndp {
+            NdpMessage::NeighborSolicitation { source_link_addr } => {
+                // Duplicate address detection solicits from the unspecified address.
+                let mac = source_link_addr.as_ref()?;
+                if packet.source_ip == "::" {
+                    return None;
+                }
+                self.observe(packet, &packet.source_ip, mac, "NDP Spoofing")
+            }
+            NdpMessage::NeighborAdvertisement {
+                target,
+                target_link_addr,
+                is_router,
+                is_override,
+            } => {
+                log::debug!(
+                    "NA {} is-at {} (router={}, override={})",
+                    target,
+                    target_link_addr,
+                    is_router,
+                    is_override
+                );
+                self.observe(packet, target, target_link_addr, "NDP Spoofing")
+            }
+            NdpMessage::RouterAdvertisement {
+                router_lifetime,
+                source_link_addr,
+                prefixes,
+            } => {
+                if self.is_allowed_router(&packet.source_ip, source_link_addr) {
+                    return None;
+                }
+
+                Some(
+                    Alert::new(
+                        "Rogue Router Advertisement",
+                        "high",
+                        packet.source_ip.clone(),
+                        Some(packet.destination_ip.clone()),
+                        format!(
+                            "Unexpected router {} ({}) advertising prefixes [{}] with lifetime {}s",
+                            packet.source_ip,
+                            source_link_addr,
+                            prefixes.join(", "),
+                            router_lifetime
+                        ),
+                    )
+                    .with_attack(attack::techniques(&[
+                        ("TA0006", "T1557"),
+                        ("TA0009", "T1557"),
+                    ])),
+                )
+            }
+        }
+    }
+
+    /// Records an IP -> MAC binding, alerting if a protected IP changes MAC.
+    fn observe(
+        &mut self,
+        packet: &ParsedPacket,
+        ip: &str,
+        mac: &str,
+        alert_type: &str,
+    ) -> Option<Alert> {
+        let now = Utc::now();
+
+        if self.bindings.len() >= MAX_BINDINGS && !self.bindings.contains_key(ip) {
+            let cutoff = now - Duration::hours(1);
+            self.bindings
+                .retain(|_, binding| binding.last_seen > cutoff);
+        }
+
+        let binding = self
+            .bindings
+            .entry(ip.to_string())
+            .or_insert_with(|| Binding {
+                mac: mac.to_string(),
+                last_seen: now,
+                history: Vec::new(),
+            });
+        binding.last_seen = now;
+
+        if binding.mac == mac {
+            return None;
+        }
+
+        let previous = std::mem::replace(&mut binding.mac, mac.to_string());
+        log::debug!("{} moved from {} to {}", ip, previous, mac);
+        binding.history.push((previous.clone(), now));
+        if binding.history.len() > MAX_HISTORY {
+            binding.history.remove(0);
+        }
+
+        if !self
+            .config
+            .protected_ips
+            .iter()
+            .any(|protected| protected == ip)
+        {
+            return None;
+        }
+
+        let technique = if alert_type == "ARP Spoofing" {
+            "T1557.002"
+        } else {
+            "T1557"
+        };
+        Some(
+            Alert::new(
+                alert_type,
+                "critical",
+                packet.source_ip.clone(),
+                Some(packet.destination_ip.clone()),
+                format!(
+                    "Protected address {} changed from {} to {} ({} changes recorded)",
+                    ip,
+                    previous,
+                    mac,
+                    binding.history.len()
+                ),
+            )
+            .at(now)
+            .with_attack(attack::techniques(&[
+                ("TA0006", technique),
+                ("TA0009", technique),
+            ])),
+        )
+    }
+
+    /// With configured routers, only those may advertise. Otherwise the
+    /// first router seen is learned and any later newcomer is flagged.
+    fn is_allowed_router(&mut self, ip: &str, mac: &str) -> bool {
+        if !self.config.allowed_routers.is_empty() {
+            return self
+                .config
+                .allowed_routers
+                .iter()
+                .any(|router| router.eq_ignore_ascii_case(ip) || router.eq_ignore_ascii_case(mac));
+        }
+
+        if self.learned_routers.is_empty() {
+            self.learned_routers.insert(ip.to_string());
+        }
+        self.learned_routers.contains(ip)
+    }
+}
+
+impl Detector for SpoofingTracker {
+    fn name(&self) -> &'static str {
+        "spoofing"
+    }
+
+    fn on_packet(&mut self, packet: &ParsedPacket) -> Vec<Alert> {
+        if !matches!(packet.protocol.as_str(), "ARP" | "ICMPv6") {
+            return Vec::new();
+        }
+        self.inspect(packet).into_iter().collect()
+    }
+
+    fn snapshot(&self) -> serde_json::Value {
+        serde_json::json!({
+            "bindings": self.bindings.len(),
+            "gratuitous_senders": self.gratuitous.len(),
+            "learned_routers": self.learned_routers.iter().collect::<Vec<_>>(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker() -> SpoofingTracker {
+        SpoofingTracker::new(SpoofingConfig {
+            protected_ips: vec!["10.0.0.1".to_string()],
+            gratuitous_threshold: 3,
+            ..SpoofingConfig::default()
+        })
+    }
+
+    fn arp(sender_ip: &str, sender_mac: &str, target_ip: &str) -> ParsedPacket {
+        ParsedPacket {
+            source_ip: sender_ip.to_string(),
+            destination_ip: target_ip.to_string(),
+            source_port: None,
+            destination_port: None,
+            protocol: "ARP".to_string(),
+            size: 42,
+            tcp_flags: None,
+            app: Some(AppLayer::Arp(ArpMessage {
+                is_reply: true,
+                sender_mac: sender_mac.to_string(),
+                sender_ip: sender_ip.to_string(),
+                target_ip: target_ip.to_string(),
+            })),
+            payload: Vec::new(),
+        }
+    }
+
+    fn advertisement(router: &str, mac: &str) -> ParsedPacket {
+        ParsedPacket {
+            source_ip: router.to_string(),
+            destination_ip: "ff02::1".to_string(),
+            source_port: None,
+            destination_port: None,
+            protocol: "ICMPv6".to_string(),
+            size: 64,
+            tcp_flags: None,
+            app: Some(AppLayer::Ndp(NdpMessage::RouterAdvertisement {
+                router_lifetime: 1800,
+                source_link_addr: mac.to_string(),
+                prefixes: vec!["2001:db8::/64".to_string()],
+            })),
+            payload: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn only_protected_addresses_alert_when_their_mac_moves() {
+        let mut spoofing = tracker();
+        assert!(spoofing
+            .on_packet(&arp("10.0.0.1", "00:00:00:00:00:01", "10.0.0.7"))
+            .is_empty());
+        assert!(spoofing
+            .on_packet(&arp("10.0.0.7", "00:00:00:00:00:07", "10.0.0.1"))
+            .is_empty());
+        assert!(spoofing
+            .on_packet(&arp("10.0.0.7", "00:00:00:00:00:66", "10.0.0.1"))
+            .is_empty());
+
+        let alerts = spoofing.on_packet(&arp("10.0.0.1", "00:00:00:00:00:66", "10.0.0.7"));
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].alert_type, "ARP Spoofing");
+        assert_eq!(alerts[0].severity, "critical");
+        assert!(alerts[0]
+            .details
+            .contains("from 00:00:00:00:00:01 to 00:00:00:00:00:66"));
+    }
+
+    #[test]
+    fn repeated_gratuitous_arps_are_a_storm() {
+        let mut spoofing = tracker();
+        let announce = arp("10.0.0.9", "00:00:00:00:00:09", "10.0.0.9");
+        assert!(spoofing.on_packet(&announce).is_empty());
+        assert!(spoofing.on_packet(&announce).is_empty());
+        let alerts = spoofing.on_packet(&announce);
+        assert_eq!(alerts[0].alert_type, "Gratuitous ARP Storm");
+        assert!(alerts[0].details.starts_with("3 gratuitous ARP replies"));
+
+        let probe = arp("0.0.0.0", "00:00:00:00:00:0a", "0.0.0.0");
+        for _ in 0..5 {
+            assert!(spoofing.on_packet(&probe).is_empty());
+        }
+    }
+
+    #[test]
+    fn newcomer_routers_are_flagged_unless_allowed() {
+        let mut spoofing = tracker();
+        assert!(spoofing
+            .on_packet(&advertisement("fe80::1", "00:00:00:00:00:01"))
+            .is_empty());
+        let alerts = spoofing.on_packet(&advertisement("fe80::66", "00:00:00:00:00:66"));
+        assert_eq!(alerts[0].alert_type, "Rogue Router Advertisement");
+
+        let mut spoofing = SpoofingTracker::new(SpoofingConfig {
+            allowed_routers: vec!["00:00:00:00:00:66".to_string()],
+            ..SpoofingConfig::default()
+        });
+        assert!(spoofing
+            .on_packet(&advertisement("fe80::66", "00:00:00:00:00:66"))
+            .is_empty());
+        assert_eq!(
+            spoofing
+                .on_packet(&advertisement("fe80::1", "00:00:00:00:00:01"))
+                .len(),
+            1
+        );
+    }
+}
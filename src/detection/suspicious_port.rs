@@ -0,0 +1,75 @@
+//! Connections to ports on the configured watch list.
+//!
+//! An inbound probe of one of our services matters more than one of our
+//! hosts connecting out to the same port, so the severity depends on the
+//! traffic's direction.
+
+use super::detector::Detector;
+use super::Alert;
+use crate::attack;
+use crate::capture::parser::ParsedPacket;
+use crate::networks::{Direction, Networks};
+
+pub struct SuspiciousPortDetector {
+    ports: Vec<u16>,
+    networks: Networks,
+}
+
+impl SuspiciousPortDetector {
+    pub fn new(ports: Vec<u16>, networks: Networks) -> Self {
+        Self { ports, networks }
+    }
+}
+
+impl Detector for SuspiciousPortDetector {
+    fn name(&self) -> &'static str {
+        "suspicious_port"
+    }
+
+    fn on_packet(&mut self, packet: &ParsedPacket) -> Vec<Alert> {
+        let Some(dest_port) = packet.destination_port else {
+            return Vec::new();
+        };
+        if !self.ports.contains(&dest_port) {
+            return Vec::new();
+        }
+
+        let direction = self
+            .networks
+            .direction(&packet.source_ip, &packet.destination_ip);
+        let (severity, what) = match direction {
+            Direction::Inbound => ("high", "Inbound connection"),
+            Direction::Internal => ("medium", "Internal connection"),
+            Direction::Outbound => ("low", "Outbound connection"),
+            Direction::External => ("low", "Connection"),
+        };
+
+        vec![Alert::new(
+            "Suspicious Port",
+            severity,
+            packet.source_ip.clone(),
+            Some(packet.destination_ip.clone()),
+            format!(
+                "{} to suspicious port {} ({})",
+                what,
+                dest_port,
+                get_port_description(dest_port)
+            ),
+        )
+        .with_attack(attack::techniques(&[("TA0011", "T1571")]))]
+    }
+
+    fn rates_direction(&self) -> bool {
+        true
+    }
+}
+
+fn get_port_description(port: u16) -> &'static str {
+    match port {
+        23 => "Telnet - Insecure protocol",
+        135 => "RPC - Often targeted",
+        445 => "SMB - Ransomware vector",
+        3389 => "RDP - Brute force target",
+        _ => "Unknown service",
+    }
+}
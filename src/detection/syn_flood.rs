@@ -0,0 +1,253 @@
+//! SYN flood and half-open connection detection.
+//!
+//! Counting packets per source misses floods with spoofed sources, where
+//! every SYN appears to come from a different host. This detector instead
+//! keeps handshake state per victim service (destination IP and port):
+//! SYNs, SYN-ACKs, completed handshakes and connections left half-open.
+
+use chrono::{DateTime, Duration, Utc};
+use pnet::packet::tcp::TcpFlags;
+use std::collections::HashMap;
+use std::mem::size_of;
+
+use super::detector::Detector;
+use super::state::LruMap;
+use super::Alert;
+use crate::attack;
+use crate::capture::parser::ParsedPacket;
+use crate::config::SynFloodConfig;
+
+const MAX_VICTIMS: usize = 10_000;
+const MAX_PENDING_PER_VICTIM: usize = 65_536;
+const MAX_SOURCES_PER_VICTIM: usize = 4_096;
+const TOP_SOURCES: usize = 5;
+/// Typical heap size of an address string, for memory estimates.
+const IP_STRING_BYTES: usize = 40;
+
+type Service = (String, u16);
+
+#[derive(Default)]
+struct VictimState {
+    window_start: Option<DateTime<Utc>>,
+    syn: u64,
+    syn_ack: u64,
+    completed: u64,
+    /// Client (IP, port) -> time of its SYN, until the handshake completes.
+    pending: HashMap<Service, DateTime<Utc>>,
+    /// SYNs per source in the current window.
+    sources: HashMap<String, u64>,
+}
+
+pub struct SynFloodDetector {
+    config: SynFloodConfig,
+    victims: LruMap<Service, VictimState>,
+}
+
+impl SynFloodDetector {
+    pub fn new(config: SynFloodConfig) -> Self {
+        Self {
+            config,
+            victims: LruMap::new(MAX_VICTIMS),
+        }
+    }
+}
+
+impl Detector for SynFloodDetector {
+    fn name(&self) -> &'static str {
+        "syn_flood"
+    }
+
+    fn on_packet(&mut self, packet: &ParsedPacket) -> Vec<Alert> {
+        let (Some(flags), Some(source_port), Some(destination_port)) = (
+            packet.tcp_flags,
+            packet.source_port,
+            packet.destination_port,
+        ) else {
+            return Vec::new();
+        };
+        let now = Utc::now();
+        let syn = flags & TcpFlags::SYN != 0;
+        let ack = flags & TcpFlags::ACK != 0;
+        let client = (packet.source_ip.clone(), source_port);
+
+        // SYN-ACKs travel from the victim back to the client.
+        if syn && ack {
+            let service = (packet.source_ip.clone(), source_port);
+            if let Some(victim) = self.victims.get_mut(&service) {
+                victim.syn_ack += 1;
+            }
+            return Vec::new();
+        }
+
+        let service = (packet.destination_ip.clone(), destination_port);
+
+        // Final ACK of the handshake, or an RST abandoning it.
+        if !syn {
+            if ack || flags & TcpFlags::RST != 0 {
+                if let Some(victim) = self.victims.get_mut(&service) {
+                    if victim.pending.remove(&client).is_some() && ack {
+                        victim.completed += 1;
+                    }
+                }
+            }
+            return Vec::new();
+        }
+
+        let config = self.config.clone();
+        let window = Duration::seconds(config.window_seconds as i64);
+        let half_open_timeout = Duration::seconds(config.half_open_timeout_seconds as i64);
+        let victim = self
+            .victims
+            .get_or_insert_with(service.clone(), VictimState::default);
+
+        if victim
+            .window_start
+            .is_none_or(|start| now - start >= window)
+        {
+            victim.window_start = Some(now);
+            victim.syn = 0;
+            victim.syn_ack = 0;
+            victim.completed = 0;
+            victim.sources.clear();
+            victim
+                .pending
+                .retain(|_, sent| now - *sent < half_open_timeout);
+        }
+
+        victim.syn += 1;
+        if victim.sources.len() < MAX_SOURCES_PER_VICTIM
+            || victim.sources.contains_key(&packet.source_ip)
+        {
+            *victim.sources.entry(packet.source_ip.clone()).or_default() += 1;
+        }
+        if victim.pending.len() < MAX_PENDING_PER_VICTIM {
+            victim.pending.insert(client, now);
+        }
+
+        // Half-open entries are aged out at window boundaries only, keeping
+        // the per-SYN cost constant during a flood.
+        let half_open = victim.pending.len();
+        if victim.syn < config.syn_threshold as u64 || half_open < config.half_open_threshold {
+            return Vec::new();
+        }
+
+        // A busy but healthy server completes most handshakes.
+        let completion = victim.completed as f64 / victim.syn as f64;
+        if completion > config.max_completion_ratio {
+            return Vec::new();
+        }
+
+        let mut top: Vec<(&String, &u64)> = victim.sources.iter().collect();
+        top.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        let top_sources: Vec<String> = top
+            .iter()
+            .take(TOP_SOURCES)
+            .map(|(ip, count)| format!("{} ({})", ip, count))
+            .collect();
+        let source_ip = if victim.sources.len() == 1 {
+            top[0].0.clone()
+        } else {
+            "multiple".to_string()
+        };
+
+        vec![Alert::new(
+            "SYN Flood",
+            "critical",
+            source_ip,
+            Some(service.0.clone()),
+            format!(
+                "{} SYNs to port {} in {} seconds: {} SYN-ACKs, {} handshakes completed ({:.0}%), {} half-open; {} distinct sources, top: {}",
+                victim.syn,
+                service.1,
+                config.window_seconds,
+                victim.syn_ack,
+                victim.completed,
+                completion * 100.0,
+                half_open,
+                victim.sources.len(),
+                top_sources.join(", ")
+            ),
+        )
+        .at(now)
+        .with_attack(attack::techniques(&[("TA0040", "T1498.001")]))]
+    }
+
+    fn snapshot(&self) -> serde_json::Value {
+        serde_json::json!({
+            "tracked_services": self.victims.len(),
+            "half_open": self.victims.values().map(|victim| victim.pending.len()).sum::<usize>(),
+        })
+    }
+
+    fn memory_usage(&self) -> usize {
+        let per_victim: usize = self
+            .victims
+            .values()
+            .map(|victim| {
+                victim.pending.capacity()
+                    * (size_of::<(Service, DateTime<Utc>)>() + IP_STRING_BYTES)
+                    + victim.sources.capacity() * (size_of::<(String, u64)>() + IP_STRING_BYTES)
+            })
+            .sum();
+        self.victims.len() * (LruMap::<Service, VictimState>::entry_overhead() + IP_STRING_BYTES)
+            + per_victim
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detector() -> SynFloodDetector {
+        SynFloodDetector::new(SynFloodConfig {
+            enabled: true,
+            window_seconds: 60,
+            syn_threshold: 5,
+            half_open_threshold: 5,
+            half_open_timeout_seconds: 60,
+            max_completion_ratio: 0.2,
+        })
+    }
+
+    fn segment(client: &str, client_port: u16, flags: u8) -> ParsedPacket {
+        ParsedPacket {
+            source_ip: client.to_string(),
+            destination_ip: "10.0.0.9".to_string(),
+            source_port: Some(client_port),
+            destination_port: Some(443),
+            protocol: "TCP".to_string(),
+            size: 60,
+            tcp_flags: Some(flags),
+            app: None,
+            payload: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn spoofed_syns_alert_once_the_thresholds_are_reached() {
+        let mut detector = detector();
+        for client in 1..5 {
+            let packet = segment(&format!("198.51.100.{}", client), 40000, TcpFlags::SYN);
+            assert!(detector.on_packet(&packet).is_empty());
+        }
+        let alerts = detector.on_packet(&segment("198.51.100.5", 40000, TcpFlags::SYN));
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].source_ip, "multiple");
+        assert_eq!(alerts[0].destination_ip.as_deref(), Some("10.0.0.9"));
+        assert!(alerts[0].details.starts_with("5 SYNs to port 443"));
+    }
+
+    #[test]
+    fn a_server_completing_most_handshakes_is_not_flooded() {
+        let mut detector = detector();
+        for port in 40000..40005 {
+            detector.on_packet(&segment("198.51.100.1", port, TcpFlags::SYN));
+            detector.on_packet(&segment("198.51.100.1", port, TcpFlags::ACK));
+        }
+        for port in 40005..40010 {
+            let packet = segment("198.51.100.1", port, TcpFlags::SYN);
+            assert!(detector.on_packet(&packet).is_empty());
+        }
+        assert_eq!(detector.snapshot()["half_open"], 5);
+    }
+}
@@ -0,0 +1,332 @@
+//! Victim-centric volumetric DDoS detection.
+//!
+//! Distributed attacks stay under any per-source threshold, so traffic is
+//! measured per destination IP and per destination prefix instead. Each
+//! one-second sample is checked against absolute limits and against a
+//! baseline learned separately for every hour of the week (EWMA of the mean
+//! and variance), so a Monday-morning peak is not mistaken for an attack.
+
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use std::collections::{HashMap, HashSet};
+use std::mem::size_of;
+use std::net::IpAddr;
+
+use super::detector::Detector;
+use super::state::{Ewma, LruMap};
+use super::Alert;
+use crate::attack;
+use crate::capture::parser::ParsedPacket;
+use crate::config::VolumetricConfig;
+
+const MAX_TARGETS: usize = 10_000;
+const MAX_SOURCES_PER_SECOND: usize = 1_024;
+/// Idle seconds fed to the baseline as zero samples after a gap.
+const MAX_IDLE_SAMPLES: i64 = 60;
+const TOP_PORTS: usize = 3;
+/// Typical heap size of an address string, for memory estimates.
+const IP_STRING_BYTES: usize = 40;
+
+#[derive(Default, Clone, Copy)]
+struct Baseline {
+    packets: Ewma,
+    bytes: Ewma,
+}
+
+/// Traffic to one target during one second.
+#[derive(Default)]
+struct Second {
+    packets: u64,
+    bytes: u64,
+    protocols: HashMap<String, u64>,
+    source_ports: HashMap<u16, u64>,
+    sources: HashSet<String>,
+}
+
+#[derive(Default)]
+struct Target {
+    second: i64,
+    current: Second,
+    /// Baselines keyed by hour of the week (0 = Monday 00:00 UTC).
+    baselines: HashMap<u8, Baseline>,
+}
+
+pub struct VolumetricDetector {
+    config: VolumetricConfig,
+    targets: LruMap<String, Target>,
+}
+
+impl VolumetricDetector {
+    pub fn new(config: VolumetricConfig) -> Self {
+        Self {
+            config,
+            targets: LruMap::new(MAX_TARGETS),
+        }
+    }
+
+    fn account(
+        &mut self,
+        target: String,
+        packet: &ParsedPacket,
+        now: DateTime<Utc>,
+    ) -> Option<Alert> {
+        let entry = self
+            .targets
+            .get_or_insert_with(target.clone(), Target::default);
+        let second = now.timestamp();
+        let mut alert = None;
+
+        if entry.second != second {
+            let finished = std::mem::take(&mut entry.current);
+            if entry.second != 0 {
+                alert = evaluate(&self.config, &target, entry, &finished, now);
+                let idle = (second - entry.second - 1).clamp(0, MAX_IDLE_SAMPLES);
+                let baseline = entry.baselines.entry(hour_of_week(now)).or_default();
+                for _ in 0..idle {
+                    baseline.packets.update(0.0, self.config.ewma_alpha);
+                    baseline.bytes.update(0.0, self.config.ewma_alpha);
+                }
+            }
+            entry.second = second;
+        }
+
+        let current = &mut entry.current;
+        current.packets += 1;
+        current.bytes += packet.size as u64;
+        *current
+            .protocols
+            .entry(packet.protocol.clone())
+            .or_default() += 1;
+        if let Some(port) = packet.source_port {
+            *current.source_ports.entry(port).or_default() += 1;
+        }
+        if current.sources.len() < MAX_SOURCES_PER_SECOND {
+            current.sources.insert(packet.source_ip.clone());
+        }
+
+        alert
+    }
+}
+
+impl Detector for VolumetricDetector {
+    fn name(&self) -> &'static str {
+        "volumetric"
+    }
+
+    fn on_packet(&mut self, packet: &ParsedPacket) -> Vec<Alert> {
+        let Ok(ip) = packet.destination_ip.parse::<IpAddr>() else {
+            return Vec::new();
+        };
+        let now = Utc::now();
+
+        let mut alerts = Vec::new();
+        alerts.extend(self.account(packet.destination_ip.clone(), packet, now));
+        let prefix = prefix_of(ip, self.config.prefix_v4, self.config.prefix_v6);
+        alerts.extend(self.account(prefix, packet, now));
+        alerts
+    }
+
+    fn snapshot(&self) -> serde_json::Value {
+        serde_json::json!({ "tracked_targets": self.targets.len() })
+    }
+
+    fn memory_usage(&self) -> usize {
+        let per_target: usize = self
+            .targets
+            .values()
+            .map(|target| {
+                target.baselines.capacity() * size_of::<(u8, Baseline)>()
+                    + target.current.protocols.capacity() * size_of::<(String, u64)>()
+                    + target.current.source_ports.capacity() * size_of::<(u16, u64)>()
+                    + target.current.sources.capacity() * (size_of::<String>() + IP_STRING_BYTES)
+            })
+            .sum();
+        self.targets.len() * (LruMap::<String, Target>::entry_overhead() + IP_STRING_BYTES)
+            + per_target
+    }
+}
+
+/// Checks a finished second against the limits and the baseline for the
+/// current hour of the week, then folds it into that baseline unless it was
+/// anomalous (so an ongoing attack does not become the new normal).
+fn evaluate(
+    config: &VolumetricConfig,
+    target: &str,
+    entry: &mut Target,
+    second: &Second,
+    now: DateTime<Utc>,
+) -> Option<Alert> {
+    let packets = second.packets as f64;
+    let bytes = second.bytes as f64;
+    let baseline = entry.baselines.entry(hour_of_week(now)).or_default();
+
+    let absolute = second.packets >= config.max_packets_per_second
+        || second.bytes >= config.max_bytes_per_second;
+    let learned = baseline.packets.samples >= config.min_baseline_samples;
+    let spike = |ewma: &Ewma, value: f64| {
+        learned && value > ewma.mean + config.spike_stddev * ewma.stddev().max(1.0)
+    };
+    let relative = second.packets >= config.min_packets_per_second
+        && (spike(&baseline.packets, packets) || spike(&baseline.bytes, bytes));
+
+    if !absolute && !relative {
+        baseline.packets.update(packets, config.ewma_alpha);
+        baseline.bytes.update(bytes, config.ewma_alpha);
+        return None;
+    }
+
+    let (protocol, protocol_packets) = second
+        .protocols
+        .iter()
+        .max_by_key(|(_, count)| **count)
+        .map(|(protocol, count)| (protocol.as_str(), *count))
+        .unwrap_or(("unknown", 0));
+    let mut ports: Vec<(&u16, &u64)> = second.source_ports.iter().collect();
+    ports.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+    let top_ports: Vec<String> = ports
+        .iter()
+        .take(TOP_PORTS)
+        .map(|(port, count)| format!("{} ({:.0}%)", port, **count as f64 * 100.0 / packets))
+        .collect();
+    let sources = if second.sources.len() >= MAX_SOURCES_PER_SECOND {
+        format!("{}+", MAX_SOURCES_PER_SECOND)
+    } else {
+        second.sources.len().to_string()
+    };
+
+    let reason = if absolute {
+        "above absolute limit".to_string()
+    } else {
+        format!(
+            "baseline {:.0} packets/s, {:.0} bytes/s for this hour",
+            baseline.packets.mean, baseline.bytes.mean
+        )
+    };
+
+    Some(Alert::new(
+        "Volumetric DDoS",
+        if absolute { "critical" } else { "high" },
+        "multiple",
+        Some(target.to_string()),
+        format!(
+            "{} packets/s, {} bytes/s from {} sources ({}); dominant protocol {} ({:.0}%), top source ports: {}",
+            second.packets,
+            second.bytes,
+            sources,
+            reason,
+            protocol,
+            protocol_packets as f64 * 100.0 / packets,
+            if top_ports.is_empty() { "none".to_string() } else { top_ports.join(", ") }
+        ),
+    )
+    .at(now)
+    .with_attack(attack::techniques(&[("TA0040", "T1498")])))
+}
+
+fn hour_of_week(time: DateTime<Utc>) -> u8 {
+    (time.weekday().num_days_from_monday() * 24 + time.hour()) as u8
+}
+
+fn prefix_of(ip: IpAddr, prefix_v4: u8, prefix_v6: u8) -> String {
+    match ip {
+        IpAddr::V4(v4) => {
+            let len = prefix_v4.min(32);
+            let mask = u32::MAX.checked_shl(32 - len as u32).unwrap_or(0);
+            format!("{}/{}", std::net::Ipv4Addr::from(u32::from(v4) & mask), len)
+        }
+        IpAddr::V6(v6) => {
+            let len = prefix_v6.min(128);
+            let mask = u128::MAX.checked_shl(128 - len as u32).unwrap_or(0);
+            format!(
+                "{}/{}",
+                std::net::Ipv6Addr::from(u128::from(v6) & mask),
+                len
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn detector() -> VolumetricDetector {
+        VolumetricDetector::new(VolumetricConfig {
+            max_packets_per_second: 10,
+            min_packets_per_second: 5,
+            ewma_alpha: 0.5,
+            min_baseline_samples: 5,
+            ..VolumetricConfig::default()
+        })
+    }
+
+    fn second(offset: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(1_700_000_000 + offset, 0).unwrap()
+    }
+
+    fn packet() -> ParsedPacket {
+        ParsedPacket {
+            source_ip: "198.51.100.1".to_string(),
+            destination_ip: "10.0.0.9".to_string(),
+            source_port: Some(53),
+            destination_port: Some(40000),
+            protocol: "UDP".to_string(),
+            size: 100,
+            tcp_flags: None,
+            app: None,
+            payload: Vec::new(),
+        }
+    }
+
+    /// Sends `packets` packets in the given second, then one in the next
+    /// second to close it, returning any alert raised for the first.
+    fn burst(detector: &mut VolumetricDetector, at: i64, packets: usize) -> Option<Alert> {
+        for _ in 0..packets {
+            detector.account("10.0.0.9".to_string(), &packet(), second(at));
+        }
+        detector.account("10.0.0.9".to_string(), &packet(), second(at + 1))
+    }
+
+    #[test]
+    fn absolute_limit_is_inclusive() {
+        assert!(burst(&mut detector(), 0, 9).is_none());
+        let alert = burst(&mut detector(), 0, 10).unwrap();
+        assert_eq!(alert.severity, "critical");
+        assert_eq!(alert.destination_ip.as_deref(), Some("10.0.0.9"));
+        assert!(alert
+            .details
+            .starts_with("10 packets/s, 1000 bytes/s from 1 sources"));
+    }
+
+    #[test]
+    fn spikes_are_reported_only_once_the_baseline_is_learned() {
+        let mut volumetric = detector();
+        volumetric.config.max_packets_per_second = u64::MAX;
+        let quiet = |volumetric: &mut VolumetricDetector, seconds: i64| {
+            for at in 0..seconds {
+                volumetric.account("10.0.0.9".to_string(), &packet(), second(at));
+            }
+        };
+
+        // Four one-packet seconds are not enough of a baseline.
+        quiet(&mut volumetric, 4);
+        assert!(burst(&mut volumetric, 4, 50).is_none());
+
+        let mut volumetric = detector();
+        volumetric.config.max_packets_per_second = u64::MAX;
+        quiet(&mut volumetric, 5);
+        let alert = burst(&mut volumetric, 5, 50).unwrap();
+        assert_eq!(alert.severity, "high");
+        assert!(alert.details.contains("baseline 1 packets/s"));
+    }
+
+    #[test]
+    fn destinations_are_grouped_into_prefixes() {
+        let v4: IpAddr = "10.1.2.3".parse().unwrap();
+        let v6: IpAddr = "2001:db8:1:2:3:4:5:6".parse().unwrap();
+        assert_eq!(prefix_of(v4, 24, 64), "10.1.2.0/24");
+        assert_eq!(prefix_of(v4, 0, 64), "0.0.0.0/0");
+        assert_eq!(prefix_of(v4, 40, 64), "10.1.2.3/32");
+        assert_eq!(prefix_of(v6, 24, 64), "2001:db8:1:2::/64");
+    }
+}
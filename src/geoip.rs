@@ -0,0 +1,161 @@
+//! Country, city and autonomous-system lookups from local MaxMind databases
+//! (GeoLite2 or GeoIP2 `.mmdb` files).
+//!
+//! The engine uses them to enrich alerts and closed flows, and signature
+//! and Sigma rules to match on country or ASN.
+
+use anyhow::Result;
+use maxminddb::{geoip2, MaxMindDBError, Reader};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, Weak};
+
+use crate::config::GeoIpConfig;
+use crate::error::NetGuardError;
+
+/// Databases opened by [`GeoIp::shared`], so every detector using the same
+/// files shares one copy in memory.
+static SHARED: Mutex<Vec<(GeoIpConfigKey, Weak<GeoIp>)>> = Mutex::new(Vec::new());
+
+type GeoIpConfigKey = (Option<String>, Option<String>);
+
+/// What the databases know about one address.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GeoInfo {
+    /// ISO 3166-1 alpha-2 country code.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub city: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub asn: Option<u32>,
+    /// The autonomous system's organisation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub as_org: Option<String>,
+}
+
+impl fmt::Display for GeoInfo {
+    /// `DE, Berlin, AS3320 Deutsche Telekom AG`, leaving out what is unknown.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts: Vec<String> = Vec::new();
+        parts.extend(self.country.clone());
+        parts.extend(self.city.clone());
+        match (self.asn, &self.as_org) {
+            (Some(asn), Some(org)) => parts.push(format!("AS{} {}", asn, org)),
+            (Some(asn), None) => parts.push(format!("AS{}", asn)),
+            (None, Some(org)) => parts.push(org.clone()),
+            (None, None) => {}
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// `ip (DE, Berlin, AS3320 Deutsche Telekom AG)`, or just the address when
+/// nothing is known about it.
+pub fn describe(ip: &str, geo: Option<&GeoInfo>) -> String {
+    match geo {
+        Some(geo) => format!("{} ({})", ip, geo),
+        None => ip.to_string(),
+    }
+}
+
+pub struct GeoIp {
+    city: Option<Reader<Vec<u8>>>,
+    asn: Option<Reader<Vec<u8>>>,
+}
+
+impl GeoIp {
+    /// Opens the configured databases, or returns `None` if none is
+    /// configured.
+    pub fn open(config: &GeoIpConfig) -> Result<Option<Self>> {
+        if config.city_database.is_none() && config.asn_database.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            city: config
+                .city_database
+                .as_deref()
+                .map(open_database)
+                .transpose()?,
+            asn: config
+                .asn_database
+                .as_deref()
+                .map(open_database)
+                .transpose()?,
+        }))
+    }
+
+    /// Like [`GeoIp::open`], but returns the instance already opened for the
+    /// same files if there is one.
+    pub fn shared(config: &GeoIpConfig) -> Result<Option<Arc<Self>>> {
+        let key = (config.city_database.clone(), config.asn_database.clone());
+        let mut shared = SHARED.lock().unwrap();
+        shared.retain(|(_, geoip)| geoip.strong_count() > 0);
+        if let Some(geoip) = shared
+            .iter()
+            .find(|(opened, _)| *opened == key)
+            .and_then(|(_, geoip)| geoip.upgrade())
+        {
+            return Ok(Some(geoip));
+        }
+        let Some(geoip) = Self::open(config)? else {
+            return Ok(None);
+        };
+        let geoip = Arc::new(geoip);
+        shared.push((key, Arc::downgrade(&geoip)));
+        Ok(Some(geoip))
+    }
+
+    /// Everything known about `ip`, or `None` if it is in no database
+    /// (private and reserved ranges, for instance).
+    pub fn lookup(&self, ip: IpAddr) -> Option<GeoInfo> {
+        let mut info = GeoInfo::default();
+        if let Some(record) = self
+            .city
+            .as_ref()
+            .and_then(|reader| found(reader.lookup::<geoip2::City>(ip)))
+        {
+            let country = record.country.or(record.registered_country);
+            info.country = country
+                .and_then(|country| country.iso_code)
+                .map(str::to_string);
+            info.city = record
+                .city
+                .and_then(|city| city.names)
+                .and_then(|names| names.get("en").map(|name| name.to_string()));
+        }
+        if let Some(record) = self
+            .asn
+            .as_ref()
+            .and_then(|reader| found(reader.lookup::<geoip2::Asn>(ip)))
+        {
+            info.asn = record.autonomous_system_number;
+            info.as_org = record.autonomous_system_organization.map(str::to_string);
+        }
+        (info != GeoInfo::default()).then_some(info)
+    }
+
+    /// [`GeoIp::lookup`] for an address in text form.
+    pub fn lookup_str(&self, ip: &str) -> Option<GeoInfo> {
+        ip.parse().ok().and_then(|ip| self.lookup(ip))
+    }
+}
+
+fn open_database(path: &str) -> Result<Reader<Vec<u8>>> {
+    Reader::open_readfile(path).map_err(|e| {
+        NetGuardError::ConfigError(format!("Cannot open GeoIP database {}: {}", path, e)).into()
+    })
+}
+
+/// The record, treating "not found" as `None` and logging decoding errors.
+fn found<T>(result: Result<T, MaxMindDBError>) -> Option<T> {
+    match result {
+        Ok(record) => Some(record),
+        Err(MaxMindDBError::AddressNotFoundError(_)) => None,
+        Err(e) => {
+            log::debug!("GeoIP lookup failed: {}", e);
+            None
+        }
+    }
+}
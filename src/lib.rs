@@ -0,0 +1,16 @@
+//! NetGuard: network traffic monitoring and threat detection.
+//!
+//! The `netguard` binary is a thin CLI over this library. Other crates can
+//! depend on it to embed the capture pipeline or to register their own
+//! detectors with [`detection::DetectorRegistry`].
+
+pub mod attack;
+pub mod capture;
+pub mod config;
+pub mod detection;
+pub mod error;
+pub mod firewall;
+pub mod geoip;
+pub mod networks;
+pub mod stats;
+pub mod storage;
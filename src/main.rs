@@ -2,15 +2,9 @@ use anyhow::Result;
 use clap::Parser;
 
 mod cli;
-mod capture;
-mod config;
-mod detection;
-mod error;
-mod firewall;
-mod stats;
-mod storage;
 
 use cli::{Cli, Commands};
+use netguard::{capture, config, firewall, stats, storage};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -119,11 +113,38 @@ async fn main() -> Result<()> {
         Commands::Alerts {
             db_path,
             severity,
+            technique,
             export,
             limit,
         } => {
             let storage = storage::Storage::new(&db_path)?;
-            storage::display_alerts(&storage, severity, export, limit)?;
+            storage::display_alerts(&storage, severity, technique, export, limit)?;
+        }
+        
+        Commands::Hosts {
+            db_path,
+            top,
+            config_file,
+        } => {
+            let config = if let Some(config_path) = config_file {
+                config::Config::from_file(&config_path)?
+            } else {
+                config::Config::default()
+            };
+            let storage = storage::Storage::new(&db_path)?;
+            storage::display_hosts(&storage, top, config.detection.risk.half_life_hours)?;
+        }
+        
+        Commands::AttackLayer {
+            db_path,
+            from,
+            to,
+            output,
+        } => {
+            let from = from.map(|from| cli::parse_time(&from, false)).transpose()?;
+            let to = to.map(|to| cli::parse_time(&to, true)).transpose()?;
+            let storage = storage::Storage::new(&db_path)?;
+            storage::export_attack_layer(&storage, from, to, &output)?;
         }
     }
     
@@ -0,0 +1,304 @@
+//! Named network groups and traffic direction.
+//!
+//! Groups are defined in `detection.networks` as lists of addresses and
+//! CIDRs, references to other groups (`$HOME_NET`) and negations
+//! (`!$HOME_NET`), as in Suricata's address variables. `HOME_NET` and, if
+//! defined, `DMZ` are "ours": traffic is classified by which of its ends
+//! fall inside them.
+
+use anyhow::Result;
+use pnet::ipnetwork::IpNetwork;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+
+use crate::error::NetGuardError;
+
+pub const HOME_NET: &str = "HOME_NET";
+pub const DMZ: &str = "DMZ";
+/// Group references followed before giving up on a cycle.
+const MAX_DEPTH: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// From outside to one of our addresses.
+    Inbound,
+    /// From one of our addresses to outside.
+    Outbound,
+    /// Between two of our addresses.
+    Internal,
+    /// Between two outside addresses, e.g. seen on a transit link.
+    External,
+}
+
+impl Direction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Direction::Inbound => "inbound",
+            Direction::Outbound => "outbound",
+            Direction::Internal => "internal",
+            Direction::External => "external",
+        }
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Entry {
+    Network(IpNetwork),
+    Group(String),
+    Not(Box<Entry>),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Networks {
+    groups: HashMap<String, Vec<Entry>>,
+    /// The groups as written, for rule variables.
+    definitions: HashMap<String, Vec<String>>,
+}
+
+impl Networks {
+    /// Parses the group definitions. `HOME_NET` and `EXTERNAL_NET` keep
+    /// their defaults unless redefined, so a config adding its own groups
+    /// still classifies traffic and loads rules using them. Unknown
+    /// addresses, references to undefined groups and reference cycles are
+    /// config errors.
+    pub fn from_config(definitions: &HashMap<String, Vec<String>>) -> Result<Self> {
+        let mut definitions = definitions.clone();
+        for (name, entries) in default_groups() {
+            definitions.entry(name).or_insert(entries);
+        }
+        let mut groups = HashMap::new();
+        for (name, entries) in &definitions {
+            let entries = entries
+                .iter()
+                .map(|entry| parse_entry(entry))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| {
+                    NetGuardError::ConfigError(format!("Network group {}: {}", name, e))
+                })?;
+            groups.insert(name.clone(), entries);
+        }
+        let networks = Self {
+            groups,
+            definitions,
+        };
+        for name in networks.groups.keys() {
+            networks.check_references(name, 0)?;
+        }
+        Ok(networks)
+    }
+
+    fn check_references(&self, name: &str, depth: usize) -> Result<()> {
+        if depth >= MAX_DEPTH {
+            return Err(NetGuardError::ConfigError(format!(
+                "Network group {} is nested too deeply",
+                name
+            ))
+            .into());
+        }
+        let entries = self.groups.get(name).ok_or_else(|| {
+            NetGuardError::ConfigError(format!("Undefined network group ${}", name))
+        })?;
+        for entry in entries {
+            let mut entry = entry;
+            while let Entry::Not(inner) = entry {
+                entry = inner;
+            }
+            if let Entry::Group(group) = entry {
+                self.check_references(group, depth + 1)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether `ip` is in the named group; false for undefined groups.
+    pub fn contains(&self, group: &str, ip: IpAddr) -> bool {
+        self.groups
+            .get(group)
+            .is_some_and(|entries| self.list_matches(entries, ip))
+    }
+
+    /// Like Suricata's address lists: any of the positive entries (or
+    /// anything, if there are none), and none of the negated ones.
+    fn list_matches(&self, entries: &[Entry], ip: IpAddr) -> bool {
+        let mut positives = entries
+            .iter()
+            .filter(|entry| !matches!(entry, Entry::Not(_)))
+            .peekable();
+        let included = positives.peek().is_none() || positives.any(|e| self.matches(e, ip));
+        included
+            && entries
+                .iter()
+                .filter(|entry| matches!(entry, Entry::Not(_)))
+                .all(|entry| self.matches(entry, ip))
+    }
+
+    fn matches(&self, entry: &Entry, ip: IpAddr) -> bool {
+        match entry {
+            Entry::Network(network) => network.contains(ip),
+            Entry::Group(group) => self.contains(group, ip),
+            Entry::Not(inner) => !self.matches(inner, ip),
+        }
+    }
+
+    /// Whether the address is ours: in `HOME_NET` or `DMZ`.
+    pub fn is_home(&self, ip: &str) -> bool {
+        ip.parse::<IpAddr>()
+            .is_ok_and(|ip| self.contains(HOME_NET, ip) || self.contains(DMZ, ip))
+    }
+
+    pub fn direction(&self, source_ip: &str, destination_ip: &str) -> Direction {
+        match (self.is_home(source_ip), self.is_home(destination_ip)) {
+            (true, true) => Direction::Internal,
+            (true, false) => Direction::Outbound,
+            (false, true) => Direction::Inbound,
+            (false, false) => Direction::External,
+        }
+    }
+
+    /// Names of the groups containing `ip`, sorted.
+    pub fn groups_of(&self, ip: IpAddr) -> Vec<&str> {
+        let mut names: Vec<&str> = self
+            .groups
+            .keys()
+            .filter(|name| self.contains(name, ip))
+            .map(String::as_str)
+            .collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// The groups as signature rule address variables (`[a,b,!$C]`).
+    pub fn rule_vars(&self) -> HashMap<String, String> {
+        self.definitions
+            .iter()
+            .map(|(name, entries)| {
+                let value = match entries.as_slice() {
+                    [] => "any".to_string(),
+                    [single] => single.clone(),
+                    entries => format!("[{}]", entries.join(",")),
+                };
+                (name.clone(), value)
+            })
+            .collect()
+    }
+}
+
+fn parse_entry(text: &str) -> Result<Entry, String> {
+    let text = text.trim();
+    if let Some(inner) = text.strip_prefix('!') {
+        return Ok(Entry::Not(Box::new(parse_entry(inner)?)));
+    }
+    if let Some(name) = text.strip_prefix('$') {
+        return Ok(Entry::Group(name.to_string()));
+    }
+    text.parse::<IpNetwork>()
+        .map(Entry::Network)
+        .map_err(|_| format!("invalid address '{}'", text))
+}
+
+/// `HOME_NET` as the private, loopback and link-local ranges, and
+/// `EXTERNAL_NET` as everything else.
+pub fn default_groups() -> HashMap<String, Vec<String>> {
+    let home = [
+        "10.0.0.0/8",
+        "172.16.0.0/12",
+        "192.168.0.0/16",
+        "127.0.0.0/8",
+        "169.254.0.0/16",
+        "::1/128",
+        "fc00::/7",
+        "fe80::/10",
+    ];
+    HashMap::from([
+        (
+            HOME_NET.to_string(),
+            home.iter().map(|network| network.to_string()).collect(),
+        ),
+        ("EXTERNAL_NET".to_string(), vec![format!("!${}", HOME_NET)]),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn networks(groups: &[(&str, &[&str])]) -> Result<Networks> {
+        Networks::from_config(
+            &groups
+                .iter()
+                .map(|(name, entries)| {
+                    (
+                        name.to_string(),
+                        entries.iter().map(|entry| entry.to_string()).collect(),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn missing_default_groups_are_filled_in() {
+        let networks = networks(&[("DMZ", &["203.0.113.0/24"])]).unwrap();
+        assert_eq!(
+            networks.direction("192.168.1.5", "8.8.8.8"),
+            Direction::Outbound
+        );
+        assert_eq!(
+            networks.direction("8.8.8.8", "203.0.113.10"),
+            Direction::Inbound
+        );
+        let vars = networks.rule_vars();
+        assert_eq!(vars["EXTERNAL_NET"], "!$HOME_NET");
+        assert!(vars["HOME_NET"].contains("10.0.0.0/8"));
+        assert_eq!(vars["DMZ"], "203.0.113.0/24");
+    }
+
+    #[test]
+    fn external_net_follows_a_custom_home_net() {
+        let networks = networks(&[("HOME_NET", &["198.51.100.0/24"])]).unwrap();
+        let ip = |text: &str| text.parse::<IpAddr>().unwrap();
+        assert!(networks.contains("EXTERNAL_NET", ip("10.1.1.1")));
+        assert!(!networks.contains("EXTERNAL_NET", ip("198.51.100.7")));
+        assert_eq!(
+            networks.direction("10.1.1.1", "198.51.100.7"),
+            Direction::Inbound
+        );
+    }
+
+    #[test]
+    fn redefined_groups_nest_and_negate() {
+        let networks = networks(&[
+            ("HOME_NET", &["10.0.0.0/8", "!$GUESTS"]),
+            ("GUESTS", &["10.9.0.0/16"]),
+            ("EXTERNAL_NET", &["!$HOME_NET", "!$GUESTS"]),
+        ])
+        .unwrap();
+        let ip = |text: &str| text.parse::<IpAddr>().unwrap();
+        assert!(networks.contains(HOME_NET, ip("10.1.0.1")));
+        assert!(!networks.contains(HOME_NET, ip("10.9.0.1")));
+        assert!(!networks.contains("EXTERNAL_NET", ip("10.9.0.1")));
+        assert!(networks.contains("EXTERNAL_NET", ip("8.8.8.8")));
+        assert_eq!(networks.groups_of(ip("10.9.0.1")), ["GUESTS"]);
+        assert_eq!(
+            networks.rule_vars()["EXTERNAL_NET"],
+            "[!$HOME_NET,!$GUESTS]"
+        );
+    }
+
+    #[test]
+    fn invalid_groups_are_config_errors() {
+        let err = |groups: &[(&str, &[&str])]| networks(groups).unwrap_err().to_string();
+        assert!(err(&[("HOME_NET", &["10.0.0.0/33"])]).contains("invalid address"));
+        assert!(err(&[("HOME_NET", &["$NOPE"])]).contains("Undefined network group $NOPE"));
+        assert!(err(&[("A", &["$B"]), ("B", &["$A"])]).contains("nested too deeply"));
+    }
+}
@@ -4,8 +4,41 @@ use std::collections::HashMap;
 use std::time::Duration;
 use tokio::time;
 
+use crate::capture::parser::{AppLayer, ParsedPacket};
+use crate::capture::quic;
 use crate::storage::Storage;
 
+/// Counters gathered from live capture by the monitor.
+#[derive(Default)]
+pub struct CaptureStats {
+    quic_versions: HashMap<String, u64>,
+}
+
+impl CaptureStats {
+    pub fn record(&mut self, packet: &ParsedPacket) {
+        if let Some(AppLayer::Quic(initial)) = &packet.app {
+            *self
+                .quic_versions
+                .entry(quic::version_name(initial.version))
+                .or_insert(0) += 1;
+        }
+    }
+    
+    pub fn display(&self) {
+        if self.quic_versions.is_empty() {
+            return;
+        }
+        
+        let mut sorted: Vec<_> = self.quic_versions.iter().collect();
+        sorted.sort_by(|a, b| b.1.cmp(a.1));
+        
+        println!("{}", "QUIC Versions:".bright_cyan());
+        for (version, count) in sorted {
+            println!("  {}: {}", version, format!("{:>8}", count).bright_green());
+        }
+    }
+}
+
 pub struct StatsMonitor {
     interface_name: Option<String>,
 }
@@ -68,10 +101,10 @@ pub fn display_historical_stats(storage: &Storage) -> Result<()> {
     println!("Total Alerts: {}", format!("{}", total_alerts).bright_red().bold());
     
     // Get alerts by severity
-    let critical = storage.get_alerts(Some("critical".to_string()), 1000)?;
-    let high = storage.get_alerts(Some("high".to_string()), 1000)?;
-    let medium = storage.get_alerts(Some("medium".to_string()), 1000)?;
-    let low = storage.get_alerts(Some("low".to_string()), 1000)?;
+    let critical = storage.get_alerts(Some("critical".to_string()), None, 1000)?;
+    let high = storage.get_alerts(Some("high".to_string()), None, 1000)?;
+    let medium = storage.get_alerts(Some("medium".to_string()), None, 1000)?;
+    let low = storage.get_alerts(Some("low".to_string()), None, 1000)?;
     
     println!();
     println!("{}", "Alerts by Severity:".bright_cyan());
@@ -81,7 +114,7 @@ pub fn display_historical_stats(storage: &Storage) -> Result<()> {
     println!("  Low:      {}", format!("{:>6}", low.len()).blue());
     
     // Count by type
-    let all_alerts = storage.get_alerts(None, 10000)?;
+    let all_alerts = storage.get_alerts(None, None, 10000)?;
     let mut type_counts: HashMap<String, usize> = HashMap::new();
     
     for alert in &all_alerts {
@@ -4,7 +4,9 @@ use colored::Colorize;
 use rusqlite::{params, Connection};
 use std::path::Path;
 
-use crate::detection::Alert;
+use crate::attack;
+use crate::detection::{Alert, HostRisk};
+use crate::geoip::{self, GeoInfo};
 
 pub struct Storage {
     conn: Connection,
@@ -23,7 +25,36 @@ impl Storage {
                 source_ip TEXT NOT NULL,
                 destination_ip TEXT,
                 details TEXT NOT NULL,
-                timestamp TEXT NOT NULL
+                timestamp TEXT NOT NULL,
+                source_geo TEXT,
+                destination_geo TEXT,
+                alert_id TEXT,
+                related TEXT,
+                attack TEXT
+            )",
+            [],
+        )?;
+        
+        // Databases created before GeoIP enrichment, correlation and ATT&CK
+        // mapping lack the geo, ID and technique columns
+        let columns: Vec<String> = conn
+            .prepare("SELECT name FROM pragma_table_info('alerts')")?
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        for column in ["source_geo", "destination_geo", "alert_id", "related", "attack"] {
+            if !columns.iter().any(|name| name == column) {
+                conn.execute(&format!("ALTER TABLE alerts ADD COLUMN {} TEXT", column), [])?;
+            }
+        }
+        
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS host_risk (
+                host TEXT PRIMARY KEY,
+                score REAL NOT NULL,
+                updated TEXT NOT NULL,
+                alerts INTEGER NOT NULL,
+                by_type TEXT NOT NULL,
+                escalated INTEGER NOT NULL
             )",
             [],
         )?;
@@ -33,14 +64,19 @@ impl Storage {
             "CREATE INDEX IF NOT EXISTS idx_timestamp ON alerts(timestamp)",
             [],
         )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_alert_id ON alerts(alert_id)",
+            [],
+        )?;
         
         Ok(Self { conn })
     }
     
     pub fn store_alert(&mut self, alert: &Alert) -> Result<()> {
         self.conn.execute(
-            "INSERT INTO alerts (alert_type, severity, source_ip, destination_ip, details, timestamp)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            "INSERT INTO alerts (alert_type, severity, source_ip, destination_ip, details, timestamp,
+                                 source_geo, destination_geo, alert_id, related, attack)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
             params![
                 alert.alert_type,
                 alert.severity,
@@ -48,43 +84,108 @@ impl Storage {
                 alert.destination_ip,
                 alert.details,
                 alert.timestamp.to_rfc3339(),
+                geo_to_json(alert.source_geo.as_ref())?,
+                geo_to_json(alert.destination_geo.as_ref())?,
+                alert.id,
+                list_to_json(&alert.related)?,
+                list_to_json(&alert.attack)?,
             ],
         )?;
         
         Ok(())
     }
     
+    /// The most recent alerts, optionally only those of a severity or
+    /// tagged with an ATT&CK technique (or one of its sub-techniques).
     pub fn get_alerts(
         &self,
         severity: Option<String>,
+        technique: Option<String>,
         limit: usize,
     ) -> Result<Vec<Alert>> {
-        let mut query = "SELECT alert_type, severity, source_ip, destination_ip, details, timestamp 
+        let mut conditions = Vec::new();
+        let mut values = Vec::new();
+        
+        if let Some(sev) = severity {
+            conditions.push("severity = ?".to_string());
+            values.push(sev);
+        }
+        if let Some(technique) = technique {
+            let technique = technique.to_ascii_uppercase();
+            conditions.push("(attack LIKE ? OR attack LIKE ?)".to_string());
+            values.push(format!("%\"technique\":\"{}\"%", technique));
+            values.push(format!("%\"technique\":\"{}.%", technique));
+        }
+        
+        self.query_alerts(&conditions, values, Some(limit))
+    }
+    
+    /// Alerts raised in `[from, to)`, oldest first; either end may be open.
+    pub fn get_alerts_between(
+        &self,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<Vec<Alert>> {
+        let mut conditions = Vec::new();
+        let mut values = Vec::new();
+        
+        if let Some(from) = from {
+            conditions.push("timestamp >= ?".to_string());
+            values.push(from.to_rfc3339());
+        }
+        if let Some(to) = to {
+            conditions.push("timestamp < ?".to_string());
+            values.push(to.to_rfc3339());
+        }
+        
+        let mut alerts = self.query_alerts(&conditions, values, None)?;
+        alerts.reverse();
+        Ok(alerts)
+    }
+    
+    /// Alerts matching every condition, newest first.
+    fn query_alerts(
+        &self,
+        conditions: &[String],
+        values: Vec<String>,
+        limit: Option<usize>,
+    ) -> Result<Vec<Alert>> {
+        let mut query = "SELECT alert_type, severity, source_ip, destination_ip, details, timestamp,
+                                source_geo, destination_geo, alert_id, related, attack
                          FROM alerts".to_string();
         
-        if let Some(sev) = &severity {
-            query.push_str(&format!(" WHERE severity = '{}'", sev));
+        if !conditions.is_empty() {
+            query.push_str(" WHERE ");
+            query.push_str(&conditions.join(" AND "));
         }
         
         query.push_str(" ORDER BY timestamp DESC");
-        query.push_str(&format!(" LIMIT {}", limit));
+        if let Some(limit) = limit {
+            query.push_str(&format!(" LIMIT {}", limit));
+        }
         
         let mut stmt = self.conn.prepare(&query)?;
         
-        let alerts = stmt.query_map([], |row| {
+        let alerts = stmt.query_map(rusqlite::params_from_iter(values), |row| {
             let timestamp_str: String = row.get(5)?;
             let timestamp = DateTime::parse_from_rfc3339(&timestamp_str)
                 .map(|dt| dt.with_timezone(&Utc))
                 .unwrap_or_else(|_| Utc::now());
             
-            Ok(Alert {
-                alert_type: row.get(0)?,
-                severity: row.get(1)?,
-                source_ip: row.get(2)?,
-                destination_ip: row.get(3)?,
-                details: row.get(4)?,
-                timestamp,
-            })
+            let mut alert = Alert::new(
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get(3)?,
+                row.get::<_, String>(4)?,
+            )
+            .at(timestamp)
+            .with_attack(list_from_json(row.get(10)?));
+            alert.source_geo = geo_from_json(row.get(6)?);
+            alert.destination_geo = geo_from_json(row.get(7)?);
+            alert.id = row.get(8)?;
+            alert.related = list_from_json(row.get(9)?);
+            Ok(alert)
         })?;
         
         let mut result = Vec::new();
@@ -95,6 +196,46 @@ impl Storage {
         Ok(result)
     }
     
+    pub fn store_host_risk(&mut self, risk: &HostRisk) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO host_risk (host, score, updated, alerts, by_type, escalated)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                risk.host,
+                risk.score,
+                risk.updated.to_rfc3339(),
+                risk.alerts as i64,
+                serde_json::to_string(&risk.by_type)?,
+                risk.escalated,
+            ],
+        )?;
+        
+        Ok(())
+    }
+    
+    pub fn get_host_risks(&self) -> Result<Vec<HostRisk>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT host, score, updated, alerts, by_type, escalated FROM host_risk",
+        )?;
+        
+        let risks = stmt.query_map([], |row| {
+            let updated: String = row.get(2)?;
+            let by_type: String = row.get(4)?;
+            Ok(HostRisk {
+                host: row.get(0)?,
+                score: row.get(1)?,
+                updated: DateTime::parse_from_rfc3339(&updated)
+                    .map(|dt| dt.with_timezone(&Utc))
+                    .unwrap_or_else(|_| Utc::now()),
+                alerts: row.get::<_, i64>(3)? as u64,
+                by_type: serde_json::from_str(&by_type).unwrap_or_default(),
+                escalated: row.get(5)?,
+            })
+        })?;
+        
+        Ok(risks.collect::<rusqlite::Result<_>>()?)
+    }
+    
     pub fn get_alert_count(&self) -> Result<usize> {
         let count: usize = self.conn.query_row(
             "SELECT COUNT(*) FROM alerts",
@@ -106,13 +247,34 @@ impl Storage {
     }
 }
 
+fn geo_to_json(geo: Option<&GeoInfo>) -> Result<Option<String>> {
+    Ok(geo.map(serde_json::to_string).transpose()?)
+}
+
+fn geo_from_json(json: Option<String>) -> Option<GeoInfo> {
+    json.and_then(|json| serde_json::from_str(&json).ok())
+}
+
+fn list_to_json<T: serde::Serialize>(list: &[T]) -> Result<Option<String>> {
+    if list.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::to_string(list)?))
+}
+
+fn list_from_json<T: serde::de::DeserializeOwned>(json: Option<String>) -> Vec<T> {
+    json.and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
 pub fn display_alerts(
     storage: &Storage,
     severity: Option<String>,
+    technique: Option<String>,
     export: Option<std::path::PathBuf>,
     limit: usize,
 ) -> Result<()> {
-    let alerts = storage.get_alerts(severity.clone(), limit)?;
+    let alerts = storage.get_alerts(severity.clone(), technique.clone(), limit)?;
     
     if alerts.is_empty() {
         println!("{}", "No alerts found.".yellow());
@@ -125,6 +287,9 @@ pub fn display_alerts(
     if let Some(sev) = &severity {
         println!("Filtered by severity: {}\n", sev.to_uppercase());
     }
+    if let Some(technique) = &technique {
+        println!("Filtered by ATT&CK technique: {}\n", technique.to_uppercase());
+    }
     
     for (idx, alert) in alerts.iter().enumerate() {
         let severity_color = match alert.severity.as_str() {
@@ -135,12 +300,22 @@ pub fn display_alerts(
         };
         
         println!("\n[{}] {} - {}", idx + 1, alert.alert_type.bright_cyan(), severity_color);
-        println!("    Source: {}", alert.source_ip);
+        println!("    Source: {}", geoip::describe(&alert.source_ip, alert.source_geo.as_ref()));
         if let Some(dest) = &alert.destination_ip {
-            println!("    Destination: {}", dest);
+            println!("    Destination: {}", geoip::describe(dest, alert.destination_geo.as_ref()));
         }
         println!("    Details: {}", alert.details);
+        if !alert.attack.is_empty() {
+            let techniques: Vec<String> = alert.attack.iter().map(|t| t.to_string()).collect();
+            println!("    ATT&CK: {}", techniques.join(", "));
+        }
         println!("    Time: {}", alert.timestamp.format("%Y-%m-%d %H:%M:%S UTC"));
+        if let Some(id) = &alert.id {
+            println!("    ID: {}", id.bright_black());
+        }
+        if !alert.related.is_empty() {
+            println!("    Correlated alerts: {}", alert.related.join(", "));
+        }
     }
     
     println!("\n{}", format!("Total alerts: {}", alerts.len()).bright_black());
@@ -154,3 +329,106 @@ pub fn display_alerts(
     
     Ok(())
 }
+
+/// Writes an ATT&CK Navigator layer of the enterprise techniques seen in
+/// alerts raised in `[from, to)` to `output`, and one of the ICS
+/// techniques, if any, next to it with `-ics` added to the file name.
+pub fn export_attack_layer(
+    storage: &Storage,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    output: &Path,
+) -> Result<()> {
+    let alerts = storage.get_alerts_between(from, to)?;
+    let range = |time: Option<DateTime<Utc>>, open: &str| {
+        time.map(|time| time.format("%Y-%m-%d %H:%M UTC").to_string())
+            .unwrap_or_else(|| open.to_string())
+    };
+    let description = format!(
+        "Techniques in NetGuard alerts from {} to {}",
+        range(from, "the first alert"),
+        range(to, "now")
+    );
+    let ics_output = output.with_file_name(format!(
+        "{}-ics{}",
+        output.file_stem().unwrap_or_default().to_string_lossy(),
+        output
+            .extension()
+            .map(|extension| format!(".{}", extension.to_string_lossy()))
+            .unwrap_or_default()
+    ));
+    for (domain, path) in [
+        (attack::Domain::Enterprise, output),
+        (attack::Domain::Ics, ics_output.as_path()),
+    ] {
+        let layer = attack::navigator_layer("NetGuard alerts", &description, domain, &alerts);
+        let techniques = layer["techniques"].as_array().map_or(0, Vec::len);
+        if domain == attack::Domain::Ics && techniques == 0 {
+            continue;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(&layer)?)?;
+
+        println!(
+            "{}",
+            format!(
+                "✅ {} {} techniques from {} alerts written to {}",
+                techniques,
+                domain.name(),
+                alerts.len(),
+                path.display()
+            )
+            .green()
+        );
+    }
+    Ok(())
+}
+
+/// Prints the `top` hosts by risk score, decayed to now with
+/// `half_life_hours`.
+pub fn display_hosts(storage: &Storage, top: usize, half_life_hours: f64) -> Result<()> {
+    let now = Utc::now();
+    let mut hosts: Vec<(DateTime<Utc>, HostRisk)> = storage
+        .get_host_risks()?
+        .into_iter()
+        .map(|mut risk| {
+            let last_alert = risk.updated;
+            risk.decay_to(now, half_life_hours);
+            (last_alert, risk)
+        })
+        .collect();
+    
+    if hosts.is_empty() {
+        println!("{}", "No host risk scores recorded.".yellow());
+        return Ok(());
+    }
+    
+    hosts.sort_by(|a, b| b.1.score.total_cmp(&a.1.score));
+    hosts.truncate(top);
+    
+    println!("{}", "🎯 Riskiest Hosts".bright_red().bold());
+    println!("{}", "━━━━━━━━━━━━━━━━━━".bright_black());
+    
+    for (idx, (last_alert, risk)) in hosts.iter().enumerate() {
+        let score_text = format!("{:.1}", risk.score);
+        let score_color = if risk.escalated {
+            score_text.bright_red().bold()
+        } else {
+            score_text.yellow()
+        };
+        
+        println!("\n[{}] {} - score {}", idx + 1, risk.host.bright_cyan(), score_color);
+        println!("    Alerts: {}", risk.alerts);
+        let types: Vec<String> = risk
+            .top_types(5)
+            .into_iter()
+            .map(|(alert_type, points)| format!("{} ({:.1})", alert_type, points))
+            .collect();
+        println!("    Top alert types: {}", types.join(", "));
+        println!("    Last alert: {}", last_alert.format("%Y-%m-%d %H:%M:%S UTC"));
+        if risk.escalated {
+            println!("    {}", "Escalated".bright_red());
+        }
+    }
+    
+    Ok(())
+}